@@ -0,0 +1,38 @@
+-- Per-day view counts for images, pastes and short links.
+--
+-- `images.views`, `paste.views` and `short_link.clicks` stay the running
+-- totals; this table is the breakdown behind them. One row per thing, UTC day
+-- and referring host, so a busy image costs a few rows a day rather than one
+-- per request. `ref` is `images.id`, `paste.id`, or `short_link.id` as text
+-- (a link's code can be edited, its id can't).
+--
+-- `referrer` is the bare host of the Referer header. It is '' for direct
+-- visits and for navigation within the site, and '*' once a thing has seen
+-- too many distinct hosts in one day; see `site::stats::record`.
+--
+-- Nothing references the source rows, so the delete triggers clean up.
+
+CREATE TABLE IF NOT EXISTS view_stat
+(
+    kind     TEXT    NOT NULL,
+    ref      TEXT    NOT NULL,
+    day      TEXT    NOT NULL,
+    referrer TEXT    NOT NULL DEFAULT '',
+    views    INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (kind, ref, day, referrer)
+) WITHOUT ROWID;
+
+CREATE TRIGGER IF NOT EXISTS images_view_stat_delete AFTER DELETE ON images
+BEGIN
+    DELETE FROM view_stat WHERE kind = 'image' AND ref = old.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS paste_view_stat_delete AFTER DELETE ON paste
+BEGIN
+    DELETE FROM view_stat WHERE kind = 'paste' AND ref = old.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS short_link_view_stat_delete AFTER DELETE ON short_link
+BEGIN
+    DELETE FROM view_stat WHERE kind = 'link' AND ref = CAST(old.id AS TEXT);
+END;
@@ -0,0 +1,10 @@
+-- Low-quality placeholders for the gallery.
+--
+-- `blurhash` is a BlurHash string (https://blurha.sh) of the image and
+-- `dominant_color` its most prominent colour as `#rrggbb`, both computed at
+-- upload time so listings can paint something before the thumbnail loads.
+-- Images uploaded earlier are filled in by a background backfill; formats the
+-- decoder can't read (AVIF) stay NULL.
+
+ALTER TABLE images ADD COLUMN blurhash TEXT;
+ALTER TABLE images ADD COLUMN dominant_color TEXT;
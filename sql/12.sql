@@ -0,0 +1,21 @@
+-- The trash: soft-deleted images, pastes and short links.
+--
+-- Deleting one of these as its owner now sets `deleted_at` instead of removing
+-- the row. A trashed row is invisible everywhere it used to be readable, can
+-- be restored by clearing `deleted_at`, and is removed for good by the hourly
+-- reapers once `trash.retention_days` have passed (see `site::trash`).
+--
+-- Nothing else changes for a trashed row: its id, code and blob stay taken,
+-- so a restore never collides, and the delete triggers (blob references,
+-- search documents, view stats, album membership) only fire on the purge.
+--
+-- The partial indexes keep the reapers' and the trash listing's scans to the
+-- handful of rows that are actually in the trash.
+
+ALTER TABLE images ADD COLUMN deleted_at TEXT;
+ALTER TABLE paste ADD COLUMN deleted_at TEXT;
+ALTER TABLE short_link ADD COLUMN deleted_at TEXT;
+
+CREATE INDEX IF NOT EXISTS images_deleted_at_idx ON images (deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX IF NOT EXISTS paste_deleted_at_idx ON paste (deleted_at) WHERE deleted_at IS NOT NULL;
+CREATE INDEX IF NOT EXISTS short_link_deleted_at_idx ON short_link (deleted_at) WHERE deleted_at IS NOT NULL;
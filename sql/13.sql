@@ -0,0 +1,49 @@
+-- Image revisions: the earlier versions of an image whose bytes were replaced.
+--
+-- Replacing an image (`PUT /api/v1/images/{id}`) keeps its id, links and
+-- settings and only swaps what it points at. The row it had is copied here
+-- first, so the owner can look at or go back to an earlier version; see
+-- `site::image_revision`. At most `REVISION_CAP` revisions are kept per image.
+--
+-- A revision holds a reference to its blob like an `images` row does, so the
+-- old bytes aren't collected while they are still in the history. The
+-- triggers mirror `images_blob_acquire` / `images_blob_release` from 3.sql;
+-- deleting the image drops its history, and those references, with it.
+--
+-- `replaced_at` is when the image's bytes last changed. Raw serving uses it
+-- for `Last-Modified`, which would otherwise stay at the upload time.
+
+CREATE TABLE IF NOT EXISTS image_revision
+(
+    id             INTEGER NOT NULL PRIMARY KEY,
+    image_id       TEXT    NOT NULL REFERENCES images (id) ON DELETE CASCADE,
+    blob_hash      TEXT    NOT NULL,
+    size           INTEGER NOT NULL,
+    mimetype       TEXT    NOT NULL,
+    original_name  TEXT,
+    phash          INTEGER,
+    blurhash       TEXT,
+    dominant_color TEXT,
+    created_at     TEXT    NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))
+);
+
+CREATE INDEX IF NOT EXISTS image_revision_image_idx ON image_revision (image_id, created_at DESC);
+
+ALTER TABLE images ADD COLUMN replaced_at TEXT;
+
+CREATE TRIGGER IF NOT EXISTS image_revision_blob_acquire
+    AFTER INSERT ON image_revision
+BEGIN
+    INSERT INTO image_blob (hash, size, refcount)
+    VALUES (NEW.blob_hash, NEW.size, 1)
+    ON CONFLICT (hash) DO UPDATE SET refcount = refcount + 1, released_at = NULL;
+END;
+
+CREATE TRIGGER IF NOT EXISTS image_revision_blob_release
+    AFTER DELETE ON image_revision
+BEGIN
+    UPDATE image_blob
+    SET refcount    = refcount - 1,
+        released_at = CASE WHEN refcount - 1 <= 0 THEN strftime('%Y-%m-%dT%H:%M:%fZ', 'now') END
+    WHERE hash = OLD.blob_hash;
+END;
@@ -0,0 +1,22 @@
+-- Upload malware scanning with a quarantine.
+--
+-- `scan_status` is where an image stands with the upload scan (see
+-- `site::image_scan`):
+--
+--   NULL          never scanned (no scanner configured, or the upload policy
+--                 left it out); served as usual
+--   'pending'     stored, waiting for the background scanner; not served
+--   'clean'       a scanner looked and found nothing
+--   'unknown'     the scanners couldn't say (an error, or VirusTotal has never
+--                 seen the file); served, as inline scanning always did
+--   'quarantined' a scanner flagged it; kept, but never served
+--
+-- `scan_report` is the JSON `ScanReport` the verdict came from, and
+-- `scanned_at` when it was made. The partial index keeps the scanner's queue
+-- query to the rows it has left to do.
+
+ALTER TABLE images ADD COLUMN scan_status TEXT;
+ALTER TABLE images ADD COLUMN scan_report TEXT;
+ALTER TABLE images ADD COLUMN scanned_at TEXT;
+
+CREATE INDEX IF NOT EXISTS images_scan_pending_idx ON images (id) WHERE scan_status = 'pending';
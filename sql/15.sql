@@ -0,0 +1,21 @@
+-- Per-day click breakdowns for short links.
+--
+-- `view_stat` (10.sql) already has a link's clicks per day and referring host.
+-- This adds who clicked, coarsely: the device class guessed from the
+-- User-Agent ('bot', 'mobile', 'tablet', 'desktop' or 'unknown') and the
+-- country the address is in, as an ISO 3166-1 alpha-2 code, or '' when there
+-- is no GeoIP database or it doesn't know the address; see
+-- `site::link_analytics`. Neither the address nor the User-Agent is stored.
+--
+-- A day has at most five device classes times some 250 countries, so a link
+-- costs a bounded handful of rows a day however busy it is.
+
+CREATE TABLE IF NOT EXISTS link_click_stat
+(
+    link_id INTEGER NOT NULL REFERENCES short_link (id) ON DELETE CASCADE,
+    day     TEXT    NOT NULL,
+    device  TEXT    NOT NULL,
+    country TEXT    NOT NULL DEFAULT '',
+    clicks  INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (link_id, day, device, country)
+) WITHOUT ROWID;
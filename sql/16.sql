@@ -0,0 +1,20 @@
+-- Short-link expiry, click limits and scheduled activation.
+--
+-- A link resolves from `active_from` (if set) until `expires_at` (if set) or
+-- until it has been followed `max_clicks` times (if set); before or after
+-- that it answers with a page saying so, not a redirect. See `site::links`.
+--
+-- `ended_at` is when a link stopped working for good. The redirect stamps it
+-- on the click that uses up the last one, and the hourly link reaper on links
+-- whose `expires_at` has passed; editing a link's limits clears and
+-- re-evaluates it. An ended link stays listed for its owner, and is deleted
+-- once it has been ended for as long as the trash keeps things.
+
+ALTER TABLE short_link ADD COLUMN active_from TEXT;
+ALTER TABLE short_link ADD COLUMN expires_at TEXT;
+ALTER TABLE short_link ADD COLUMN max_clicks INTEGER;
+ALTER TABLE short_link ADD COLUMN ended_at TEXT;
+
+CREATE INDEX IF NOT EXISTS short_link_expires_idx ON short_link (expires_at)
+    WHERE expires_at IS NOT NULL AND ended_at IS NULL;
+CREATE INDEX IF NOT EXISTS short_link_ended_idx ON short_link (ended_at) WHERE ended_at IS NOT NULL;
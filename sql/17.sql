@@ -0,0 +1,9 @@
+-- Password-protected and interstitial short links.
+--
+-- A link with a `password_hash` (Argon2, as for images) asks for the password
+-- before it redirects; getting it right earns a short-lived signed cookie for
+-- that link. An `interstitial` link shows where it goes and waits for the
+-- visitor to continue, every time. See `site::links`.
+
+ALTER TABLE short_link ADD COLUMN password_hash TEXT;
+ALTER TABLE short_link ADD COLUMN interstitial INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,35 @@
+-- Key short link search documents by the link's id rather than its code.
+--
+-- A link's code can change (renaming an alias), and `search_document.ref`
+-- following it meant the update trigger had to find the old document by a
+-- value that was no longer the link's. `short_link.id` is an INTEGER PRIMARY
+-- KEY, so unlike the TEXT keys of `paste` and `images` it never moves; search
+-- joins back to the link for its current code.
+
+DROP TRIGGER IF EXISTS short_link_search_insert;
+DROP TRIGGER IF EXISTS short_link_search_update;
+DROP TRIGGER IF EXISTS short_link_search_delete;
+
+DELETE FROM search_document WHERE kind = 'link';
+
+CREATE TRIGGER IF NOT EXISTS short_link_search_insert AFTER INSERT ON short_link
+BEGIN
+    INSERT INTO search_document (kind, ref, account_id, title, body)
+    VALUES ('link', new.id, new.account_id, new.code, new.target_url);
+END;
+
+CREATE TRIGGER IF NOT EXISTS short_link_search_update AFTER UPDATE OF account_id, code, target_url ON short_link
+BEGIN
+    DELETE FROM search_document WHERE kind = 'link' AND ref = old.id;
+    INSERT INTO search_document (kind, ref, account_id, title, body)
+    VALUES ('link', new.id, new.account_id, new.code, new.target_url);
+END;
+
+CREATE TRIGGER IF NOT EXISTS short_link_search_delete AFTER DELETE ON short_link
+BEGIN
+    DELETE FROM search_document WHERE kind = 'link' AND ref = old.id;
+END;
+
+INSERT INTO search_document (kind, ref, account_id, title, body)
+SELECT 'link', id, account_id, code, target_url
+FROM short_link;
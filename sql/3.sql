@@ -0,0 +1,89 @@
+-- Content-addressed image storage.
+--
+-- Image bytes move out of `images.image_data` into a blob store keyed by the
+-- SHA-256 of the bytes (see src/core/storage). `image_blob` is the refcount
+-- ledger for that store: one row per distinct blob, counting the `images` rows
+-- that point at it. The triggers below keep it in step with every INSERT,
+-- DELETE and re-point of `images.blob_hash`, so the many places that delete
+-- images (the reaper, bulk delete, account deletion, guild galleries) need no
+-- knowledge of the store. A blob whose count reaches zero is stamped with
+-- `released_at` and collected by the reaper after a grace period.
+
+CREATE TABLE IF NOT EXISTS image_blob
+(
+    hash        TEXT    NOT NULL PRIMARY KEY,
+    size        INTEGER NOT NULL,
+    refcount    INTEGER NOT NULL DEFAULT 0,
+    created_at  TEXT    NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%fZ', 'now')),
+    released_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS image_blob_released_idx ON image_blob (released_at) WHERE refcount <= 0;
+
+-- `images.size` was generated from `length(image_data)`, which stops meaning
+-- anything once the bytes live elsewhere, and `image_data` has to become
+-- nullable. Neither can be done with ALTER TABLE, so rebuild the table. Rows
+-- keep their inline BLOB here; the application moves them into the store on
+-- start-up (`storage::migrate_legacy_blobs`) and clears the column as it goes.
+CREATE TABLE images_new
+(
+    id            TEXT    NOT NULL PRIMARY KEY,
+    image_data    BLOB,
+    blob_hash     TEXT,
+    size          INTEGER NOT NULL DEFAULT 0,
+    mimetype      TEXT    NOT NULL,
+    uploaded_at   TEXT    NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    uploader_id   INTEGER REFERENCES account (id) ON DELETE SET NULL,
+    original_name TEXT,
+    views         INTEGER NOT NULL DEFAULT 0,
+    expires_at    TEXT,
+    guild_id      TEXT
+);
+
+INSERT INTO images_new (id, image_data, size, mimetype, uploaded_at, uploader_id, original_name, views, expires_at, guild_id)
+SELECT id, image_data, size, mimetype, uploaded_at, uploader_id, original_name, views, expires_at, guild_id
+FROM images;
+
+DROP TABLE images;
+ALTER TABLE images_new RENAME TO images;
+
+CREATE INDEX IF NOT EXISTS image_idx ON images (id);
+CREATE INDEX IF NOT EXISTS images_guild_idx ON images (guild_id);
+CREATE INDEX IF NOT EXISTS images_blob_idx ON images (blob_hash);
+
+
+CREATE TRIGGER IF NOT EXISTS images_blob_acquire
+    AFTER INSERT ON images
+    WHEN NEW.blob_hash IS NOT NULL
+BEGIN
+    INSERT INTO image_blob (hash, size, refcount)
+    VALUES (NEW.blob_hash, NEW.size, 1)
+    ON CONFLICT (hash) DO UPDATE SET refcount = refcount + 1, released_at = NULL;
+END;
+
+CREATE TRIGGER IF NOT EXISTS images_blob_release
+    AFTER DELETE ON images
+    WHEN OLD.blob_hash IS NOT NULL
+BEGIN
+    UPDATE image_blob
+    SET refcount    = refcount - 1,
+        released_at = CASE WHEN refcount - 1 <= 0 THEN strftime('%Y-%m-%dT%H:%M:%fZ', 'now') END
+    WHERE hash = OLD.blob_hash;
+END;
+
+-- Re-pointing a row (the legacy move, or replacing an image's bytes) releases
+-- the old blob and acquires the new one in the same statement.
+CREATE TRIGGER IF NOT EXISTS images_blob_repoint
+    AFTER UPDATE OF blob_hash ON images
+    WHEN OLD.blob_hash IS NOT NEW.blob_hash
+BEGIN
+    UPDATE image_blob
+    SET refcount    = refcount - 1,
+        released_at = CASE WHEN refcount - 1 <= 0 THEN strftime('%Y-%m-%dT%H:%M:%fZ', 'now') END
+    WHERE OLD.blob_hash IS NOT NULL AND hash = OLD.blob_hash;
+
+    INSERT INTO image_blob (hash, size, refcount)
+    SELECT NEW.blob_hash, NEW.size, 1
+    WHERE NEW.blob_hash IS NOT NULL
+    ON CONFLICT (hash) DO UPDATE SET refcount = refcount + 1, released_at = NULL;
+END;
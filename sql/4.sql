@@ -0,0 +1,32 @@
+-- Image albums.
+--
+-- An album is a named, ordered collection of one account's images with an
+-- optional cover image. `album_image.position` is the display order; appends
+-- take MAX + 1 and a reorder rewrites the whole album, so it never needs to be
+-- gapless. Deleting an image drops it from every album and clears it as a
+-- cover; deleting an album never touches the images in it.
+
+CREATE TABLE IF NOT EXISTS album
+(
+    id          TEXT    NOT NULL PRIMARY KEY,
+    account_id  INTEGER NOT NULL REFERENCES account (id) ON DELETE CASCADE,
+    title       TEXT    NOT NULL,
+    description TEXT,
+    visibility  TEXT    NOT NULL DEFAULT 'unlisted',
+    cover_id    TEXT REFERENCES images (id) ON DELETE SET NULL,
+    created_at  TEXT    NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    updated_at  TEXT    NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
+
+CREATE INDEX IF NOT EXISTS album_account_idx ON album (account_id);
+
+CREATE TABLE IF NOT EXISTS album_image
+(
+    album_id TEXT    NOT NULL REFERENCES album (id) ON DELETE CASCADE,
+    image_id TEXT    NOT NULL REFERENCES images (id) ON DELETE CASCADE,
+    position INTEGER NOT NULL,
+    added_at TEXT    NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (album_id, image_id)
+);
+
+CREATE INDEX IF NOT EXISTS album_image_image_idx ON album_image (image_id);
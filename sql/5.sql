@@ -0,0 +1,22 @@
+-- Perceptual hashes for near-duplicate detection.
+--
+-- `images.phash` is a 64-bit dHash of the decoded image, stored as its signed
+-- bit pattern; NULL until computed (uploads hash inline, older rows are filled
+-- in by a background pass on start, undecodable formats stay NULL). SQLite has
+-- no popcount, so Hamming-distance comparisons happen in the application.
+--
+-- `image_block` holds the hashes of images an admin has banned. An upload
+-- within a few bits of any of them is refused. The hash outlives the image it
+-- was taken from, which is deleted along with its near-duplicates when blocked.
+
+ALTER TABLE images ADD COLUMN phash INTEGER;
+
+CREATE TABLE IF NOT EXISTS image_block
+(
+    id              INTEGER NOT NULL PRIMARY KEY,
+    phash           INTEGER NOT NULL,
+    reason          TEXT,
+    source_image_id TEXT,
+    created_by      INTEGER REFERENCES account (id) ON DELETE SET NULL,
+    created_at      TEXT    NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
@@ -0,0 +1,27 @@
+-- Resumable uploads (tus 1.0).
+--
+-- One row per upload in progress. The bytes received so far live in a staging
+-- file named after `id` (see `site::api::uploads`); `upload_offset` is how many
+-- of them are committed. Once `upload_offset` reaches `upload_length` the file
+-- goes through the normal upload pipeline, `result` (or `error`) records the
+-- outcome, and the staging file is removed. Rows past `expires_at` are reaped
+-- together with their staging files, finished or not.
+
+CREATE TABLE IF NOT EXISTS upload_session
+(
+    id            TEXT    NOT NULL PRIMARY KEY,
+    account_id    INTEGER NOT NULL REFERENCES account (id) ON DELETE CASCADE,
+    filename      TEXT    NOT NULL,
+    upload_length INTEGER NOT NULL,
+    upload_offset INTEGER NOT NULL DEFAULT 0,
+    expires_in    INTEGER,
+    on_duplicate  TEXT,
+    result        TEXT,
+    error         TEXT,
+    created_at    TEXT    NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at    TEXT    NOT NULL,
+    completed_at  TEXT
+);
+
+CREATE INDEX IF NOT EXISTS upload_session_account_idx ON upload_session (account_id);
+CREATE INDEX IF NOT EXISTS upload_session_expires_idx ON upload_session (expires_at);
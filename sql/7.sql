@@ -0,0 +1,17 @@
+-- Presigned upload URLs.
+--
+-- A presigned URL carries its account, limits and expiry in its signature, so
+-- the only server-side state is which ones have been spent: a row is written
+-- when a URL is redeemed, and its primary key is what stops a second upload.
+-- Rows can be dropped once `expires_at` has passed, since the signature no
+-- longer verifies by then either.
+
+CREATE TABLE IF NOT EXISTS presigned_upload
+(
+    nonce      TEXT    NOT NULL PRIMARY KEY,
+    account_id INTEGER NOT NULL REFERENCES account (id) ON DELETE CASCADE,
+    used_at    TEXT    NOT NULL DEFAULT CURRENT_TIMESTAMP,
+    expires_at TEXT    NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS presigned_upload_expires_idx ON presigned_upload (expires_at);
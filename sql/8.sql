@@ -0,0 +1,128 @@
+-- Full-text search over what an account owns.
+--
+-- `search_document` holds one row per searchable thing: a paste (title and,
+-- unless it is encrypted, its body), an image (its original file name) or a
+-- short link (code and target). `search_fts` is an external-content FTS5 index
+-- over it. The document table exists so the index has a stable INTEGER rowid
+-- to point at — `paste` and `images` use TEXT keys, and their implicit rowids
+-- may be renumbered by VACUUM — and so deletes find their row through the
+-- (kind, ref) index instead of scanning.
+--
+-- Triggers on the source tables keep the documents in sync and triggers on
+-- the documents keep the index in sync; nothing in the application writes to
+-- either table. Anonymous pastes have no owner to search them and are left out.
+
+CREATE TABLE IF NOT EXISTS search_document
+(
+    id         INTEGER PRIMARY KEY,
+    kind       TEXT    NOT NULL,
+    ref        TEXT    NOT NULL,
+    account_id INTEGER NOT NULL,
+    title      TEXT,
+    body       TEXT,
+    UNIQUE (kind, ref)
+);
+
+CREATE INDEX IF NOT EXISTS search_document_account_idx ON search_document (account_id);
+
+CREATE VIRTUAL TABLE IF NOT EXISTS search_fts USING fts5
+(
+    title,
+    body,
+    content = 'search_document',
+    content_rowid = 'id',
+    tokenize = 'unicode61 remove_diacritics 2'
+);
+
+CREATE TRIGGER IF NOT EXISTS search_document_insert AFTER INSERT ON search_document
+BEGIN
+    INSERT INTO search_fts (rowid, title, body) VALUES (new.id, new.title, new.body);
+END;
+
+CREATE TRIGGER IF NOT EXISTS search_document_delete AFTER DELETE ON search_document
+BEGIN
+    INSERT INTO search_fts (search_fts, rowid, title, body) VALUES ('delete', old.id, old.title, old.body);
+END;
+
+-- Pastes. `views` and friends change constantly, so the update trigger only
+-- watches the columns that are indexed or decide whether the body is.
+
+CREATE TRIGGER IF NOT EXISTS paste_search_insert AFTER INSERT ON paste
+WHEN new.account_id IS NOT NULL
+BEGIN
+    INSERT INTO search_document (kind, ref, account_id, title, body)
+    VALUES ('paste', new.id, new.account_id, new.title,
+            CASE WHEN new.enc_nonce IS NULL THEN CAST(new.content AS TEXT) END);
+END;
+
+CREATE TRIGGER IF NOT EXISTS paste_search_update AFTER UPDATE OF account_id, title, content, enc_nonce ON paste
+BEGIN
+    DELETE FROM search_document WHERE kind = 'paste' AND ref = old.id;
+    INSERT INTO search_document (kind, ref, account_id, title, body)
+    SELECT 'paste', new.id, new.account_id, new.title,
+           CASE WHEN new.enc_nonce IS NULL THEN CAST(new.content AS TEXT) END
+    WHERE new.account_id IS NOT NULL;
+END;
+
+CREATE TRIGGER IF NOT EXISTS paste_search_delete AFTER DELETE ON paste
+BEGIN
+    DELETE FROM search_document WHERE kind = 'paste' AND ref = old.id;
+END;
+
+-- Images, by the file name they were uploaded with.
+
+CREATE TRIGGER IF NOT EXISTS images_search_insert AFTER INSERT ON images
+WHEN new.uploader_id IS NOT NULL AND new.original_name IS NOT NULL
+BEGIN
+    INSERT INTO search_document (kind, ref, account_id, title)
+    VALUES ('image', new.id, new.uploader_id, new.original_name);
+END;
+
+CREATE TRIGGER IF NOT EXISTS images_search_update AFTER UPDATE OF uploader_id, original_name ON images
+BEGIN
+    DELETE FROM search_document WHERE kind = 'image' AND ref = old.id;
+    INSERT INTO search_document (kind, ref, account_id, title)
+    SELECT 'image', new.id, new.uploader_id, new.original_name
+    WHERE new.uploader_id IS NOT NULL AND new.original_name IS NOT NULL;
+END;
+
+CREATE TRIGGER IF NOT EXISTS images_search_delete AFTER DELETE ON images
+BEGIN
+    DELETE FROM search_document WHERE kind = 'image' AND ref = old.id;
+END;
+
+-- Short links, by code and target URL.
+
+CREATE TRIGGER IF NOT EXISTS short_link_search_insert AFTER INSERT ON short_link
+BEGIN
+    INSERT INTO search_document (kind, ref, account_id, title, body)
+    VALUES ('link', new.code, new.account_id, new.code, new.target_url);
+END;
+
+CREATE TRIGGER IF NOT EXISTS short_link_search_update AFTER UPDATE OF account_id, code, target_url ON short_link
+BEGIN
+    DELETE FROM search_document WHERE kind = 'link' AND ref = old.code;
+    INSERT INTO search_document (kind, ref, account_id, title, body)
+    VALUES ('link', new.code, new.account_id, new.code, new.target_url);
+END;
+
+CREATE TRIGGER IF NOT EXISTS short_link_search_delete AFTER DELETE ON short_link
+BEGIN
+    DELETE FROM search_document WHERE kind = 'link' AND ref = old.code;
+END;
+
+-- Index everything that already exists.
+
+INSERT INTO search_document (kind, ref, account_id, title, body)
+SELECT 'paste', id, account_id, title, CASE WHEN enc_nonce IS NULL THEN CAST(content AS TEXT) END
+FROM paste
+WHERE account_id IS NOT NULL;
+
+INSERT INTO search_document (kind, ref, account_id, title)
+SELECT 'image', id, uploader_id, original_name
+FROM images
+WHERE uploader_id IS NOT NULL AND original_name IS NOT NULL;
+
+INSERT INTO search_document (kind, ref, account_id, title, body)
+SELECT 'link', code, account_id, code, target_url
+FROM short_link;
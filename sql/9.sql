@@ -0,0 +1,17 @@
+-- Per-image visibility, password protection and burn-after-N-views.
+--
+-- `visibility` takes the same values as `paste.visibility` and `album.visibility`.
+-- Existing images were reachable by anyone holding the id, which is what
+-- `unlisted` means, so that is the default. A `private` image is served to its
+-- uploader only.
+--
+-- `password_hash` is an Argon2 PHC string. Unlike a paste body, the bytes are
+-- not encrypted: thumbnails, variants and duplicate detection all need them.
+--
+-- `burn_after_views` counts reveals against `views`. Once they meet, the image
+-- is given a short `expires_at` (so the last viewer can still load it) and the
+-- expiry reaper removes it.
+
+ALTER TABLE images ADD COLUMN visibility TEXT NOT NULL DEFAULT 'unlisted';
+ALTER TABLE images ADD COLUMN password_hash TEXT;
+ALTER TABLE images ADD COLUMN burn_after_views INTEGER;
@@ -18,6 +18,7 @@ pub enum Command {
     Scrape { path: Option<PathBuf> },
     Fixtures { path: PathBuf },
     Move { path: PathBuf },
+    Thumbnails,
 }
 
 macro_rules! quick_exit {
@@ -32,6 +33,7 @@ const HELP_OUTPUT: &str = r#"usage: klapsptuhl_me <command>
 commands:
   run               Runs the server
   admin             Interactively creates an admin user
+  thumbnails        Rebuilds every gallery thumbnail in the rendition cache
 
 options:
   -h, --help   Prints this help output
@@ -102,6 +104,7 @@ impl Command {
             Some(s) => match s.as_str() {
                 "run" => Self::Run,
                 "admin" => Self::Admin,
+                "thumbnails" => Self::Thumbnails,
                 "scrape" => Self::Scrape {
                     path: args.next().map(PathBuf::from),
                 },
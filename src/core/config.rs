@@ -128,6 +128,205 @@ impl Default for PasteConfig {
     }
 }
 
+/// Per-account image quotas, which are unlimited unless set (admins are never
+/// limited), and how gallery thumbnails are made.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ImageConfig {
+    /// Maximum images a non-admin account may own.
+    #[serde(default)]
+    pub account_limit: Option<usize>,
+    /// Maximum total image bytes a non-admin account may store.
+    #[serde(default)]
+    pub account_max_total_bytes: Option<i64>,
+    /// Whether animated GIF and WebP uploads get animated thumbnails. Off by
+    /// default: they are much larger and slower to make than the first-frame
+    /// stills.
+    #[serde(default)]
+    pub animated_thumbnails: bool,
+}
+
+/// How long deleted images, pastes and short links stay restorable (see
+/// [`crate::site::trash`]).
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TrashConfig {
+    /// Days an item stays in the trash before the reapers remove it for good.
+    #[serde(default = "default_trash_retention_days")]
+    pub retention_days: i64,
+}
+
+fn default_trash_retention_days() -> i64 {
+    30
+}
+
+impl Default for TrashConfig {
+    fn default() -> Self {
+        Self {
+            retention_days: default_trash_retention_days(),
+        }
+    }
+}
+
+/// How uploads are checked for malware (see [`crate::site::image_scan`]).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadScan {
+    /// Scan each upload before storing it and refuse what's flagged. The
+    /// uploader waits for the scanners.
+    #[default]
+    Inline,
+    /// Store every upload straight away but hold it back until a background
+    /// scan clears it; what's flagged stays quarantined.
+    All,
+    /// Like `all`, but only for uploads the image decoder can't read, which
+    /// are the ones that could be something other than a picture.
+    NonImages,
+    /// Don't scan uploads.
+    Off,
+}
+
+/// Which [`crate::storage::BlobStore`] holds uploaded image bytes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StorageBackend {
+    /// Files on the local disk, under `storage.path`.
+    #[default]
+    Local,
+    /// An S3-compatible bucket, configured by `storage.s3`.
+    S3,
+}
+
+/// Where uploaded image bytes are kept (see [`crate::storage`]).
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct StorageConfig {
+    /// `"local"` (the default) or `"s3"`.
+    #[serde(default)]
+    pub backend: StorageBackend,
+    /// Directory for the content-addressed image blobs when `backend` is
+    /// `"local"`. Unset → `blobs/` next to `main.db`.
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    /// The bucket to use when `backend` is `"s3"`.
+    #[serde(default)]
+    pub s3: S3Config,
+}
+
+/// Limits on the resized/re-encoded variants `/gallery/raw/<id>` serves for
+/// `?w=`, `?h=`, `?fit=`, `?format=` and `?q=` (see [`crate::variant`]).
+///
+/// Requested sizes and qualities are snapped to these lists, so the number of
+/// distinct variants per image — and with it the cache and the CPU a client
+/// can make the server spend — stays bounded.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct VariantConfig {
+    /// Widths and heights, in pixels, a variant may have. A requested size is
+    /// rounded up to the next one in the list (or down to the largest). Empty
+    /// turns resizing off; format conversion still works.
+    #[serde(default = "default_variant_sizes")]
+    pub sizes: Vec<u32>,
+    /// JPEG qualities (1–100) a variant may be encoded at. `?q=` snaps to the
+    /// nearest one.
+    #[serde(default = "default_variant_qualities")]
+    pub qualities: Vec<u8>,
+    /// How many rendered variants to keep in memory.
+    #[serde(default = "default_variant_cache_entries")]
+    pub cache_entries: usize,
+}
+
+fn default_variant_sizes() -> Vec<u32> {
+    vec![64, 128, 256, 320, 480, 640, 800, 1024, 1280, 1600, 1920, 2560]
+}
+
+fn default_variant_qualities() -> Vec<u8> {
+    vec![50, 65, 80, 90]
+}
+
+fn default_variant_cache_entries() -> usize {
+    256
+}
+
+impl Default for VariantConfig {
+    fn default() -> Self {
+        Self {
+            sizes: default_variant_sizes(),
+            qualities: default_variant_qualities(),
+            cache_entries: default_variant_cache_entries(),
+        }
+    }
+}
+
+/// The on-disk cache of gallery thumbnails and resized variants (see
+/// [`crate::renditions`]), so a restart doesn't have to render them all again.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RenditionConfig {
+    /// Directory for the cache. Unset → `renditions/` next to `main.db`.
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    /// How many bytes of renditions to keep; the least recently used go first.
+    /// `0` turns the disk cache off.
+    #[serde(default = "default_rendition_max_bytes")]
+    pub max_bytes: u64,
+}
+
+fn default_rendition_max_bytes() -> u64 {
+    1024 * 1024 * 1024
+}
+
+impl Default for RenditionConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            max_bytes: default_rendition_max_bytes(),
+        }
+    }
+}
+
+/// Connection settings for an S3-compatible bucket (AWS S3, MinIO, R2, …).
+///
+/// `endpoint`, `bucket`, `access_key_id` and `secret_access_key` are required
+/// once `storage.backend` is `"s3"`; the server refuses to start without them.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct S3Config {
+    /// The service URL, e.g. `"https://s3.eu-central-1.amazonaws.com"` or
+    /// `"http://127.0.0.1:9000"` for a local MinIO.
+    #[serde(default)]
+    pub endpoint: Option<String>,
+    /// The bucket name.
+    #[serde(default)]
+    pub bucket: Option<String>,
+    /// The region requests are signed for. MinIO accepts the default.
+    #[serde(default = "default_s3_region")]
+    pub region: String,
+    #[serde(default)]
+    pub access_key_id: Option<String>,
+    #[serde(default)]
+    pub secret_access_key: Option<String>,
+    /// Prepended to every object key, e.g. `"images/"`.
+    #[serde(default)]
+    pub prefix: String,
+    /// Address the bucket as `<endpoint>/<bucket>/…` (what MinIO expects)
+    /// rather than `<bucket>.<endpoint host>/…`.
+    #[serde(default = "default_true")]
+    pub path_style: bool,
+}
+
+fn default_s3_region() -> String {
+    "us-east-1".to_string()
+}
+
+impl Default for S3Config {
+    fn default() -> Self {
+        Self {
+            endpoint: None,
+            bucket: None,
+            region: default_s3_region(),
+            access_key_id: None,
+            secret_access_key: None,
+            prefix: String::new(),
+            path_style: default_true(),
+        }
+    }
+}
+
 /// The server configuration.
 ///
 /// Field/declaration order is the canonical on-disk order: `load()` rewrites
@@ -163,6 +362,10 @@ pub struct Config {
     /// When set, an uploaded file's SHA-256 is looked up on VirusTotal.
     #[serde(default)]
     pub virustotal_api_key: Option<String>,
+    /// How uploads meet the scanners above; see [`UploadScan`]. Has no effect
+    /// while neither is configured.
+    #[serde(default)]
+    pub upload_scan: UploadScan,
     /// Path to a Chromium/Chrome binary for the screenshot and Markdown→PDF
     /// render endpoints. When unset, common names on `PATH` are tried; if none
     /// is found those endpoints return 503.
@@ -173,15 +376,41 @@ pub struct Config {
     /// return 503.
     #[serde(default)]
     pub ffmpeg_path: Option<String>,
+    /// Path to a MaxMind-format country database (`GeoLite2-Country.mmdb`, or
+    /// a City one) for the country breakdown of short-link clicks. Read once
+    /// at start-up; when unset or unreadable, clicks are counted without a
+    /// country.
+    #[serde(default)]
+    pub geoip_db_path: Option<String>,
+    /// Path to a file of destinations short links may not point at: domains,
+    /// and URL patterns with `*` wildcards (see
+    /// [`crate::site::link_blocklist`]). Re-read hourly; unset blocks nothing.
+    #[serde(default)]
+    pub link_blocklist_path: Option<String>,
     /// Maximum accepted size of a single uploaded image, in bytes. `0`/unset
     /// defaults to 10 MiB. The upload handler streams each field and aborts as
     /// soon as this is exceeded, so an oversized (or maliciously huge) upload
     /// can never be buffered whole in memory.
     #[serde(default)]
     pub max_upload_bytes: Option<u64>,
+    /// Per-account image count and storage quotas.
+    #[serde(default)]
+    pub images: ImageConfig,
+    /// Where uploaded image bytes are stored.
+    #[serde(default)]
+    pub storage: StorageConfig,
+    /// Allowed sizes and qualities for resized image variants.
+    #[serde(default)]
+    pub variants: VariantConfig,
+    /// Where rendered thumbnails and variants are kept between restarts.
+    #[serde(default)]
+    pub renditions: RenditionConfig,
     /// Pastebin limits and the anonymous-paste switch.
     #[serde(default)]
     pub paste: PasteConfig,
+    /// How long deleted content can be restored from the trash.
+    #[serde(default)]
+    pub trash: TrashConfig,
     /// Discord OAuth2 settings for identity linking (bot dashboard access).
     /// Off unless all three fields (`client_id`, `client_secret`, `redirect_uri`) are set.
     #[serde(default)]
@@ -217,10 +446,18 @@ impl Config {
             alerts: AlertsConfig::default(),
             clamav_addr: None,
             virustotal_api_key: None,
+            upload_scan: UploadScan::default(),
             chromium_path: None,
             ffmpeg_path: None,
+            geoip_db_path: None,
+            link_blocklist_path: None,
             max_upload_bytes: None,
+            images: ImageConfig::default(),
+            storage: StorageConfig::default(),
+            variants: VariantConfig::default(),
+            renditions: RenditionConfig::default(),
             paste: PasteConfig::default(),
+            trash: TrashConfig::default(),
             discord: DiscordConfig::default(),
             sso_secret: None,
             gallery_provision_token: None,
@@ -558,10 +795,18 @@ mod tests {
             "alerts",
             "clamav_addr",
             "virustotal_api_key",
+            "upload_scan",
             "chromium_path",
             "ffmpeg_path",
+            "geoip_db_path",
+            "link_blocklist_path",
             "max_upload_bytes",
+            "images",
+            "storage",
+            "variants",
+            "renditions",
             "paste",
+            "trash",
             "discord",
             "sso_secret",
             "gallery_provision_token",
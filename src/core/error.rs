@@ -101,6 +101,8 @@ pub enum ApiErrorCode {
     PayloadTooLarge = 10,
     /// The request's media type is not supported for this endpoint.
     UnsupportedMedia = 11,
+    /// The account is at its image count or storage quota.
+    QuotaExceeded = 12,
 }
 
 impl ApiErrorCode {
@@ -117,6 +119,7 @@ impl ApiErrorCode {
             9 => Some(Self::Validation),
             10 => Some(Self::PayloadTooLarge),
             11 => Some(Self::UnsupportedMedia),
+            12 => Some(Self::QuotaExceeded),
             _ => None,
         }
     }
@@ -231,6 +234,7 @@ impl ApiError {
             ApiErrorCode::EntryAlreadyExists => StatusCode::CONFLICT,
             ApiErrorCode::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
             ApiErrorCode::UnsupportedMedia => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            ApiErrorCode::QuotaExceeded => StatusCode::FORBIDDEN,
             _ => StatusCode::BAD_REQUEST,
         }
     }
@@ -105,6 +105,17 @@ mod tests {
             "storage",
             "audit_log",
             "images",
+            "image_blob",
+            "image_revision",
+            "album",
+            "album_image",
+            "image_block",
+            "upload_session",
+            "presigned_upload",
+            "search_document",
+            "search_fts",
+            "view_stat",
+            "link_click_stat",
             "short_link",
             "paste",
             "paste_revision",
@@ -134,6 +145,20 @@ mod tests {
         assert!(table_has_column(&conn, "session", "scopes"));
         assert!(table_has_column(&conn, "images", "expires_at"));
         assert!(table_has_column(&conn, "images", "guild_id"));
+        assert!(table_has_column(&conn, "images", "blob_hash"));
+        assert!(table_has_column(&conn, "images", "phash"));
+        assert!(table_has_column(&conn, "images", "blurhash"));
+        assert!(table_has_column(&conn, "images", "dominant_color"));
+        assert!(table_has_column(&conn, "images", "deleted_at"));
+        assert!(table_has_column(&conn, "images", "replaced_at"));
+        assert!(table_has_column(&conn, "image_revision", "blob_hash"));
+        assert!(table_has_column(&conn, "images", "scan_status"));
+        assert!(table_has_column(&conn, "images", "scan_report"));
+        assert!(table_has_column(&conn, "paste", "deleted_at"));
+        assert!(table_has_column(&conn, "short_link", "deleted_at"));
+        assert!(table_has_column(&conn, "short_link", "expires_at"));
+        assert!(table_has_column(&conn, "short_link", "max_clicks"));
+        assert!(table_has_column(&conn, "short_link", "password_hash"));
         assert!(table_has_column(&conn, "user_discord_links", "discord_avatar"));
         assert!(table_has_column(&conn, "paste", "enc_salt"));
         assert!(table_has_column(&conn, "paste", "burn_after_read"));
@@ -1,5 +1,6 @@
 //! Cross-cutting fundamentals: configuration, app state, the database layer,
-//! data models, error types, logging, CLI entry points, and shared utilities.
+//! data models, error types, logging, CLI entry points, image blob storage, and
+//! shared utilities.
 
 pub mod audit;
 pub mod cli;
@@ -11,4 +12,5 @@ pub mod logging;
 pub mod migrations;
 pub mod models;
 pub mod state;
+pub mod storage;
 pub mod utils;
@@ -1,5 +1,5 @@
 use rusqlite::{
-    types::{FromSql, FromSqlResult, ToSqlOutput, ValueRef},
+    types::{FromSql, FromSqlError, FromSqlResult, ToSqlOutput, ValueRef},
     ToSql,
 };
 use serde::{Deserialize, Serialize};
@@ -42,6 +42,12 @@ pub struct ImageFile {
     /// Number of times the image's landing page has been viewed.
     #[serde(default)]
     pub(crate) views: i64,
+    /// The image's BlurHash, shown while the thumbnail loads.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) blurhash: Option<String>,
+    /// The image's most prominent colour as `#rrggbb`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) dominant_color: Option<String>,
 }
 
 impl ImageFile {
@@ -95,6 +101,33 @@ pub struct ImageEntry {
     /// Number of times this image's landing page has been viewed.
     #[serde(default)]
     pub views: i64,
+    /// Who may see the image. `private` images are served to their uploader only.
+    #[serde(default)]
+    pub visibility: Visibility,
+    /// Argon2 hash of the image's password, if it has one. Never serialized.
+    #[serde(skip)]
+    pub password_hash: Option<String>,
+    /// Delete the image once it has been revealed this many times.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub burn_after_views: Option<i64>,
+    /// The image's BlurHash, for a placeholder while it loads. `None` until
+    /// computed, or for formats that can't be decoded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blurhash: Option<String>,
+    /// The image's most prominent colour as `#rrggbb`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dominant_color: Option<String>,
+    /// When the image's bytes were last replaced. `None` if they never were.
+    #[serde(
+        with = "time::serde::rfc3339::option",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub replaced_at: Option<OffsetDateTime>,
+    /// Where the image stands with the upload malware scan. `None` if it was
+    /// never scanned.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scan_status: Option<ScanStatus>,
 }
 
 impl ImageEntry {
@@ -110,14 +143,62 @@ impl ImageEntry {
             expires_at: None,
             original_name: None,
             views: 0,
+            visibility: Visibility::default(),
+            password_hash: None,
+            burn_after_views: None,
+            blurhash: None,
+            dominant_color: None,
+            replaced_at: None,
+            scan_status: None,
         }
     }
 
+    /// When the bytes served for this image last changed: the upload, or the
+    /// latest replacement.
+    pub fn modified_at(&self) -> OffsetDateTime {
+        self.replaced_at.unwrap_or(self.uploaded_at)
+    }
+
     /// Whether this image's expiry has passed.
     pub fn is_expired(&self) -> bool {
         self.expires_at.map(|e| OffsetDateTime::now_utc() > e).unwrap_or(false)
     }
 
+    /// Whether `account` uploaded this image (or is an admin).
+    pub fn owned_by(&self, account: &Account) -> bool {
+        self.uploader_id == Some(account.id) || account.flags.is_admin()
+    }
+
+    /// Whether `viewer` may know this image exists. Public and unlisted images
+    /// are readable by anyone with the link; private ones by their uploader
+    /// only. A password or a burn count still gates what is served.
+    pub fn visible_to(&self, viewer: Option<&Account>) -> bool {
+        match self.visibility {
+            Visibility::Private => viewer.is_some_and(|a| self.owned_by(a)),
+            Visibility::Public | Visibility::Unlisted => true,
+        }
+    }
+
+    /// Whether the image's bytes are held back from everyone, the uploader
+    /// included, until or because of a malware scan.
+    pub fn is_held(&self) -> bool {
+        matches!(self.scan_status, Some(ScanStatus::Pending | ScanStatus::Quarantined))
+    }
+
+    /// Whether the image has a password.
+    pub fn is_locked(&self) -> bool {
+        self.password_hash.is_some()
+    }
+
+    /// Whether anyone with the link gets the bytes straight away — no privacy,
+    /// no password, no burn count, and not held by the malware scan.
+    pub fn is_open(&self) -> bool {
+        self.visibility != Visibility::Private
+            && !self.is_locked()
+            && self.burn_after_views.is_none()
+            && !self.is_held()
+    }
+
     /// Returns data safe for embedding into the frontend
     pub fn data(&self) -> ImageEntryData<'_> {
         ImageEntryData {
@@ -166,7 +247,9 @@ impl Table for ImageEntry {
             id: row.get("id")?,
             mimetype: row.get("mimetype")?,
             size: row.get("size")?,
-            image_data: row.get("image_data")?,
+            // NULL once the bytes have moved into the blob store; the caller
+            // loads them through `AppState::resolve_image_data_for`.
+            image_data: row.get::<_, Option<Vec<u8>>>("image_data")?.unwrap_or_default(),
             uploaded_at: row.get("uploaded_at")?,
             uploader_id: row.get("uploader_id")?,
             // Tolerant: queries that don't SELECT this column (e.g. the
@@ -174,6 +257,80 @@ impl Table for ImageEntry {
             expires_at: row.get::<_, Option<OffsetDateTime>>("expires_at").unwrap_or(None),
             original_name: row.get::<_, Option<String>>("original_name").unwrap_or(None),
             views: row.get::<_, i64>("views").unwrap_or(0),
+            visibility: row.get::<_, Visibility>("visibility").unwrap_or_default(),
+            password_hash: row.get::<_, Option<String>>("password_hash").unwrap_or(None),
+            burn_after_views: row.get::<_, Option<i64>>("burn_after_views").unwrap_or(None),
+            blurhash: row.get::<_, Option<String>>("blurhash").unwrap_or(None),
+            dominant_color: row.get::<_, Option<String>>("dominant_color").unwrap_or(None),
+            replaced_at: row.get::<_, Option<OffsetDateTime>>("replaced_at").unwrap_or(None),
+            scan_status: row.get::<_, Option<ScanStatus>>("scan_status").unwrap_or(None),
+        })
+    }
+}
+
+/// An earlier version of an image, kept when its bytes were replaced.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ImageRevision {
+    /// Auto-increment primary key.
+    pub id: i64,
+    /// The image this revision belongs to.
+    #[serde(skip)]
+    pub image_id: String,
+    /// SHA-256 of the superseded bytes, the key of their blob.
+    #[serde(skip)]
+    pub blob_hash: String,
+    /// The superseded file's size in bytes.
+    pub size: i64,
+    /// The mime type as it was.
+    #[schema(example = "image/png")]
+    pub mimetype: String,
+    /// The original filename as it was.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub original_name: Option<String>,
+    /// The perceptual hash as it was.
+    #[serde(skip)]
+    pub phash: Option<i64>,
+    /// The BlurHash as it was.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blurhash: Option<String>,
+    /// The dominant colour as it was.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dominant_color: Option<String>,
+    /// When it was replaced.
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+}
+
+impl Table for ImageRevision {
+    const NAME: &'static str = "image_revision";
+
+    const COLUMNS: &'static [&'static str] = &[
+        "id",
+        "image_id",
+        "blob_hash",
+        "size",
+        "mimetype",
+        "original_name",
+        "phash",
+        "blurhash",
+        "dominant_color",
+        "created_at",
+    ];
+
+    type Id = i64;
+
+    fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get("id")?,
+            image_id: row.get("image_id")?,
+            blob_hash: row.get("blob_hash")?,
+            size: row.get("size")?,
+            mimetype: row.get("mimetype")?,
+            original_name: row.get("original_name")?,
+            phash: row.get("phash")?,
+            blurhash: row.get("blurhash")?,
+            dominant_color: row.get("dominant_color")?,
+            created_at: row.get("created_at")?,
         })
     }
 }
@@ -198,6 +355,72 @@ pub struct ShortLink {
     /// When the link was last edited.
     #[serde(with = "time::serde::rfc3339")]
     pub updated_at: OffsetDateTime,
+    /// The link doesn't resolve before this.
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub active_from: Option<OffsetDateTime>,
+    /// The link stops resolving at this point.
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub expires_at: Option<OffsetDateTime>,
+    /// The link stops resolving once it has been followed this many times.
+    pub max_clicks: Option<i64>,
+    /// When the link stopped resolving for good, once it has.
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub ended_at: Option<OffsetDateTime>,
+    /// Argon2 hash of the link's password, if it has one. Never serialized.
+    #[serde(skip)]
+    pub password_hash: Option<String>,
+    /// Show the destination and wait for the visitor to continue, instead of
+    /// redirecting straight away.
+    pub interstitial: bool,
+}
+
+/// Whether a short link resolves right now, and if not, why.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkStatus {
+    /// It redirects.
+    Active,
+    /// Its `active_from` is still to come.
+    Scheduled,
+    /// Its `expires_at` has passed.
+    Expired,
+    /// It has been followed `max_clicks` times.
+    UsedUp,
+    /// Its destination is on the blocklist. [`ShortLink::status`] can't tell
+    /// this; see `links::link_status`.
+    Blocked,
+}
+
+impl LinkStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Active => "active",
+            Self::Scheduled => "scheduled",
+            Self::Expired => "expired",
+            Self::UsedUp => "used_up",
+            Self::Blocked => "blocked",
+        }
+    }
+}
+
+impl ShortLink {
+    /// Whether the link has a password.
+    pub fn is_locked(&self) -> bool {
+        self.password_hash.is_some()
+    }
+
+    /// Where the link stands at the moment.
+    pub fn status(&self) -> LinkStatus {
+        let now = OffsetDateTime::now_utc();
+        if self.max_clicks.is_some_and(|max| self.clicks >= max) {
+            LinkStatus::UsedUp
+        } else if self.ended_at.is_some() || self.expires_at.is_some_and(|at| at <= now) {
+            LinkStatus::Expired
+        } else if self.active_from.is_some_and(|at| at > now) {
+            LinkStatus::Scheduled
+        } else {
+            LinkStatus::Active
+        }
+    }
 }
 
 impl Table for ShortLink {
@@ -211,6 +434,12 @@ impl Table for ShortLink {
         "clicks",
         "created_at",
         "updated_at",
+        "active_from",
+        "expires_at",
+        "max_clicks",
+        "ended_at",
+        "password_hash",
+        "interstitial",
     ];
 
     type Id = i64;
@@ -224,17 +453,27 @@ impl Table for ShortLink {
             clicks: row.get("clicks")?,
             created_at: row.get("created_at")?,
             updated_at: row.get("updated_at")?,
+            active_from: row.get("active_from")?,
+            expires_at: row.get("expires_at")?,
+            max_clicks: row.get("max_clicks")?,
+            ended_at: row.get("ended_at")?,
+            password_hash: row.get("password_hash")?,
+            interstitial: row.get("interstitial")?,
         })
     }
 }
 
-/// How discoverable a paste is.
+/// How discoverable a paste or an album is.
+///
+/// For a paste this is *not* an access-control mechanism on its own — every
+/// visibility is still readable by anyone holding the (unguessable) id, which
+/// is what makes a paste linkable. It controls indexing and listing: only
+/// `Public` pastes are indexable by crawlers and shown on the owner's
+/// `/user/:name` page. Real secrecy comes from password encryption and
+/// burn-after-read.
 ///
-/// This is *not* an access-control mechanism on its own — every visibility is
-/// still readable by anyone holding the (unguessable) id, which is what makes a
-/// paste linkable. It controls indexing and listing: only `Public` pastes are
-/// indexable by crawlers and shown on the owner's `/user/:name` page. Real
-/// secrecy comes from password encryption and burn-after-read.
+/// Albums follow the same rules, except that a `Private` album is shown to its
+/// owner only (see [`Album::visible_to`]).
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "lowercase")]
 pub enum Visibility {
@@ -285,6 +524,60 @@ impl FromSql for Visibility {
     }
 }
 
+/// Where an image stands with the upload malware scan (see
+/// [`crate::site::image_scan`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ScanStatus {
+    /// Stored and waiting for the background scanner. Not served yet.
+    Pending,
+    /// A scanner looked and found nothing.
+    Clean,
+    /// The scanners couldn't say either way. Served.
+    Unknown,
+    /// A scanner flagged it. Kept, but never served.
+    Quarantined,
+}
+
+impl ScanStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pending => "pending",
+            Self::Clean => "clean",
+            Self::Unknown => "unknown",
+            Self::Quarantined => "quarantined",
+        }
+    }
+
+    /// The status a finished scan's `verdict` (see
+    /// [`crate::scan::ScanReport`]) leaves an image in.
+    pub fn from_verdict(verdict: &str) -> Self {
+        match verdict {
+            "infected" => Self::Quarantined,
+            "clean" => Self::Clean,
+            _ => Self::Unknown,
+        }
+    }
+}
+
+impl ToSql for ScanStatus {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        Ok(ToSqlOutput::from(self.as_str()))
+    }
+}
+
+impl FromSql for ScanStatus {
+    fn column_result(value: ValueRef<'_>) -> FromSqlResult<Self> {
+        match value.as_str()? {
+            "pending" => Ok(Self::Pending),
+            "clean" => Ok(Self::Clean),
+            "unknown" => Ok(Self::Unknown),
+            "quarantined" => Ok(Self::Quarantined),
+            _ => Err(FromSqlError::InvalidType),
+        }
+    }
+}
+
 /// A hosted text/code paste, served at `/p/<id>` (highlighted) and
 /// `/p/<id>.txt` (raw), and managed from the browser (`/paste`, `/pastes`) or
 /// over the public API.
@@ -447,6 +740,76 @@ impl Table for PasteRevision {
     }
 }
 
+/// A named, ordered collection of one account's images, served at
+/// `/album/<id>` and managed over the API. The members live in `album_image`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Album {
+    /// The random short id that appears in the URL.
+    pub id: String,
+    /// Owner account id.
+    pub account_id: i64,
+    /// The album's title.
+    pub title: String,
+    /// Optional free-text description shown under the title.
+    pub description: Option<String>,
+    /// Indexing / listing visibility.
+    pub visibility: Visibility,
+    /// The image chosen as the cover. `None` falls back to the first image.
+    pub cover_id: Option<String>,
+    /// When the album was created.
+    #[serde(with = "time::serde::rfc3339")]
+    pub created_at: OffsetDateTime,
+    /// When the album (or its contents) last changed.
+    #[serde(with = "time::serde::rfc3339")]
+    pub updated_at: OffsetDateTime,
+}
+
+impl Album {
+    /// Whether `account` owns this album (admins own every album).
+    pub fn owned_by(&self, account: &Account) -> bool {
+        self.account_id == account.id || account.flags.is_admin()
+    }
+
+    /// Whether `viewer` may see the album page. Public and unlisted albums are
+    /// readable by anyone with the link; private ones by their owner only.
+    pub fn visible_to(&self, viewer: Option<&Account>) -> bool {
+        match self.visibility {
+            Visibility::Private => viewer.is_some_and(|a| self.owned_by(a)),
+            Visibility::Public | Visibility::Unlisted => true,
+        }
+    }
+}
+
+impl Table for Album {
+    const NAME: &'static str = "album";
+
+    const COLUMNS: &'static [&'static str] = &[
+        "id",
+        "account_id",
+        "title",
+        "description",
+        "visibility",
+        "cover_id",
+        "created_at",
+        "updated_at",
+    ];
+
+    type Id = String;
+
+    fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            id: row.get("id")?,
+            account_id: row.get("account_id")?,
+            title: row.get("title")?,
+            description: row.get("description")?,
+            visibility: row.get("visibility")?,
+            cover_id: row.get("cover_id")?,
+            created_at: row.get("created_at")?,
+            updated_at: row.get("updated_at")?,
+        })
+    }
+}
+
 #[derive(Debug, Serialize, PartialEq, Eq, Clone, ToSchema)]
 pub struct ResolvedImageData {
     /// encoded image bytes
@@ -721,6 +1084,10 @@ pub enum Scope {
     PastesRead,
     /// Create + delete your hosted pastes via the API.
     PastesWrite,
+    /// Read + list your image albums via the API.
+    AlbumsRead,
+    /// Create, edit, reorder + delete your image albums via the API.
+    AlbumsWrite,
 }
 
 impl Scope {
@@ -733,6 +1100,8 @@ impl Scope {
             Scope::LinksWrite => "links:write",
             Scope::PastesRead => "pastes:read",
             Scope::PastesWrite => "pastes:write",
+            Scope::AlbumsRead => "albums:read",
+            Scope::AlbumsWrite => "albums:write",
         }
     }
 
@@ -745,6 +1114,8 @@ impl Scope {
             "links:write" => Some(Scope::LinksWrite),
             "pastes:read" => Some(Scope::PastesRead),
             "pastes:write" => Some(Scope::PastesWrite),
+            "albums:read" => Some(Scope::AlbumsRead),
+            "albums:write" => Some(Scope::AlbumsWrite),
             _ => None,
         }
     }
@@ -759,6 +1130,8 @@ impl Scope {
             Scope::LinksWrite,
             Scope::PastesRead,
             Scope::PastesWrite,
+            Scope::AlbumsRead,
+            Scope::AlbumsWrite,
         ]
     }
 
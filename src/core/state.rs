@@ -6,11 +6,15 @@ use crate::{
     database::Table,
     logging::RequestLogger,
     models::{Account, ImageEntry, Session},
+    storage::{BlobStore, BlobStream},
     token::MAX_TOKEN_AGE,
     Config, Database,
 };
 use quick_cache::sync::Cache;
-use std::{sync::Arc, time::Duration};
+use std::{
+    sync::{Arc, OnceLock, RwLock},
+    time::{Duration, Instant},
+};
 use tokio::sync::RwLockReadGuard;
 
 /// A processed media result kept around for a shareable short link.
@@ -18,6 +22,9 @@ use tokio::sync::RwLockReadGuard;
 pub struct ProcessedMedia {
     pub bytes: Vec<u8>,
     pub content_type: String,
+    /// SHA-256 of `bytes`, served as the `ETag`.
+    pub hash: String,
+    pub created_at: time::OffsetDateTime,
 }
 
 /// Username of the dedicated, non-personal account that owns every per-guild
@@ -62,6 +69,27 @@ pub struct Thumbnail {
     pub content_type: &'static str,
 }
 
+/// A cached resized/re-encoded rendition of an image (see [`crate::variant`]).
+#[derive(Clone)]
+pub struct ImageVariant {
+    pub bytes: bytes::Bytes,
+    pub content_type: &'static str,
+    /// SHA-256 of the original the variant was rendered from; with the
+    /// variant key it makes the `ETag`.
+    pub source_hash: String,
+}
+
+/// A short link's destination as unfurled for its preview page. A failed
+/// unfurl is kept too (`None`), so a dead target isn't re-fetched per view.
+#[derive(Debug, Clone)]
+pub struct LinkPreview {
+    pub unfurl: Option<crate::site::api::unfurl::UnfurlResult>,
+    pub fetched_at: Instant,
+}
+
+/// How long a [`LinkPreview`] is reused before the target is fetched again.
+const LINK_PREVIEW_TTL: Duration = Duration::from_secs(60 * 60);
+
 struct InnerState {
     config: Config,
     database: Database,
@@ -73,10 +101,30 @@ struct InnerState {
     /// callers asked to share via a short link. Capacity-bounded rather than
     /// TTL'd — old entries are evicted as new ones arrive.
     processed_media: Cache<String, ProcessedMedia>,
-    /// Bounded LRU of generated gallery thumbnails, keyed by image id. Image
-    /// bytes are immutable per id (ids are random and never reused), so entries
-    /// never go stale; they're simply evicted under capacity pressure.
+    /// Bounded LRU of generated gallery thumbnails, keyed by image id. Ids are
+    /// random and never reused; the one way an entry goes stale is its image's
+    /// bytes being replaced, which drops it (see [`AppState::forget_renditions`]).
     thumbnails: Cache<String, Thumbnail>,
+    /// Bounded LRU of rendered image variants, keyed by image id plus the
+    /// resolved variant parameters. Dropped along with the thumbnails.
+    variants: Cache<String, ImageVariant>,
+    /// The on-disk cache behind `thumbnails` and `variants`, which outlives a
+    /// restart (see [`crate::renditions`]). `None` when turned off.
+    renditions: Option<crate::renditions::RenditionCache>,
+    /// Hands new uploads to the thumbnail worker, once it runs.
+    thumbnail_queue: OnceLock<tokio::sync::mpsc::Sender<String>>,
+    /// Wakes the background scanner for held uploads (see
+    /// [`crate::site::image_scan`]).
+    upload_scans: tokio::sync::Notify,
+    /// Where image bytes live, addressed by their SHA-256 (see [`crate::storage`]).
+    blobs: Arc<dyn BlobStore>,
+    /// Country lookups for link analytics. `None` without a database.
+    geoip: Option<crate::geoip::GeoIp>,
+    /// Destinations short links may not point at, swapped out whole on reload
+    /// (see [`crate::site::link_blocklist`]).
+    link_blocklist: RwLock<Arc<crate::site::link_blocklist::Blocklist>>,
+    /// Bounded LRU of unfurled short-link targets, keyed by target URL.
+    link_previews: Cache<String, LinkPreview>,
 }
 
 /// Global application state for the axum Router.
@@ -98,6 +146,14 @@ impl AppState {
             .expect("could not build HTTP client");
 
         let requests = RequestLogger::new().expect("could not build request logger");
+        let blobs = crate::storage::from_config(&config).expect("could not open blob store");
+        let renditions = crate::renditions::from_config(&config).unwrap_or_else(|e| {
+            tracing::warn!(error = %e, "could not open the rendition cache, keeping renditions in memory only");
+            None
+        });
+        let variants = Cache::new(config.variants.cache_entries.max(1));
+        let geoip = crate::geoip::GeoIp::from_config(&config);
+        let link_blocklist = crate::site::link_blocklist::Blocklist::from_config(&config);
 
         Self {
             inner: Arc::new(InnerState {
@@ -109,6 +165,14 @@ impl AppState {
                 valid_sessions: Cache::new(1000),
                 processed_media: Cache::new(512),
                 thumbnails: Cache::new(1024),
+                variants,
+                renditions,
+                thumbnail_queue: OnceLock::new(),
+                upload_scans: tokio::sync::Notify::new(),
+                blobs,
+                geoip,
+                link_blocklist: RwLock::new(Arc::new(link_blocklist)),
+                link_previews: Cache::new(512),
             }),
             client,
             requests,
@@ -143,6 +207,12 @@ impl AppState {
         let secret_key = crate::key::SecretKey::random().expect("random secret key");
         let config: Config = serde_json::from_value(serde_json::json!({ "secret_key": secret_key.hex() }))
             .expect("a config from just a secret key");
+        // A throwaway blob directory per state, so tests never share blobs.
+        let blobs = crate::storage::LocalBlobStore::open(
+            std::env::temp_dir().join(format!("klappstuhl-test-blobs-{}", nanoid::nanoid!(8))),
+        )
+        .expect("a scratch blob store");
+        let variants = Cache::new(config.variants.cache_entries.max(1));
         Self {
             inner: Arc::new(InnerState {
                 config,
@@ -153,6 +223,14 @@ impl AppState {
                 valid_sessions: Cache::new(1000),
                 processed_media: Cache::new(512),
                 thumbnails: Cache::new(1024),
+                variants,
+                renditions: None,
+                thumbnail_queue: OnceLock::new(),
+                upload_scans: tokio::sync::Notify::new(),
+                blobs: Arc::new(blobs),
+                geoip: None,
+                link_blocklist: RwLock::default(),
+                link_previews: Cache::new(512),
             }),
             client: reqwest::Client::new(),
             requests: RequestLogger::null(),
@@ -168,8 +246,10 @@ impl AppState {
         self.inner.processed_media.insert(
             id.clone(),
             ProcessedMedia {
+                hash: crate::scan::sha256_hex(&bytes),
                 bytes,
                 content_type: content_type.into(),
+                created_at: time::OffsetDateTime::now_utc(),
             },
         );
         id
@@ -194,6 +274,11 @@ impl AppState {
         &self.inner.database
     }
 
+    /// The blob store holding image bytes.
+    pub fn blobs(&self) -> &dyn BlobStore {
+        self.inner.blobs.as_ref()
+    }
+
     /// Sends an alert to the configured Discord webhook (if any).
     /// Delivery happens in the background — failures are not surfaced.
     pub fn send_alert<T: serde::Serialize + Send + 'static>(&self, payload: T) {
@@ -220,6 +305,93 @@ impl AppState {
         self.inner.cached_image_files.invalidate().await;
     }
 
+    /// Drops the cached thumbnail and variants of image `id`, after its bytes
+    /// were replaced. Variant keys can't be enumerated per image in memory, so
+    /// all of those go; replacements are rare enough that re-rendering is
+    /// cheap. On disk only `id`'s are removed.
+    pub async fn forget_renditions(&self, id: &str) {
+        self.inner.thumbnails.remove(id);
+        self.inner.variants.clear();
+        if let Some(disk) = &self.inner.renditions {
+            disk.forget(id).await;
+        }
+    }
+
+    /// The on-disk rendition cache, if it is on.
+    pub fn renditions(&self) -> Option<&crate::renditions::RenditionCache> {
+        self.inner.renditions.as_ref()
+    }
+
+    /// Connects the thumbnail worker. Returns `false` if one already is.
+    pub(crate) fn attach_thumbnail_queue(&self, queue: tokio::sync::mpsc::Sender<String>) -> bool {
+        self.inner.thumbnail_queue.set(queue).is_ok()
+    }
+
+    /// Asks the background worker to make the thumbnail of a new upload `id`
+    /// ahead of its first view. A no-op when no worker runs (the CLI, tests)
+    /// or its queue is full; the thumbnail is then made on demand.
+    pub fn pregenerate_thumbnail(&self, id: &str) {
+        if let Some(queue) = self.inner.thumbnail_queue.get() {
+            let _ = queue.try_send(id.to_string());
+        }
+    }
+
+    /// Tells the background scanner that an upload is waiting for it.
+    pub fn wake_upload_scanner(&self) {
+        self.inner.upload_scans.notify_one();
+    }
+
+    /// The GeoIP database, if one is configured and could be read.
+    pub fn geoip(&self) -> Option<&crate::geoip::GeoIp> {
+        self.inner.geoip.as_ref()
+    }
+
+    /// The current short-link destination blocklist.
+    pub fn link_blocklist(&self) -> Arc<crate::site::link_blocklist::Blocklist> {
+        self.inner
+            .link_blocklist
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
+    /// Re-reads the blocklist file. A file that has become unreadable keeps
+    /// the blocklist already loaded rather than blocking nothing.
+    pub fn reload_link_blocklist(&self) {
+        let Some(path) = self.config().link_blocklist_path.as_deref() else {
+            return;
+        };
+        match crate::site::link_blocklist::Blocklist::load(path) {
+            Ok(blocklist) => {
+                *self.inner.link_blocklist.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(blocklist);
+            }
+            Err(e) => tracing::warn!(path, error = %e, "could not re-read the link blocklist, keeping the old one"),
+        }
+    }
+
+    /// The cached preview of `target`, if one was fetched within the last hour.
+    pub fn link_preview(&self, target: &str) -> Option<LinkPreview> {
+        self.inner
+            .link_previews
+            .get(target)
+            .filter(|preview| preview.fetched_at.elapsed() < LINK_PREVIEW_TTL)
+    }
+
+    pub fn store_link_preview(&self, target: &str, unfurl: Option<crate::site::api::unfurl::UnfurlResult>) {
+        self.inner.link_previews.insert(
+            target.to_string(),
+            LinkPreview {
+                unfurl,
+                fetched_at: Instant::now(),
+            },
+        );
+    }
+
+    /// Resolves once [`Self::wake_upload_scanner`] has been called.
+    pub(crate) async fn upload_scan_requested(&self) {
+        self.inner.upload_scans.notified().await;
+    }
+
     pub async fn get_account(&self, id: i64) -> Option<Account> {
         match self.inner.cached_users.get_value_or_guard_async(&id).await {
             Ok(acc) => Some(acc),
@@ -471,6 +643,19 @@ impl AppState {
         Ok(id)
     }
 
+    /// Whether `account_id` acts for Discord guild `guild_id`: it holds that
+    /// guild's `images:guild` key (see [`Self::ensure_guild_api_key`]).
+    pub async fn is_guild_member(&self, account_id: i64, guild_id: &str) -> bool {
+        self.database()
+            .get_row(
+                "SELECT EXISTS (SELECT 1 FROM guild_api_key WHERE guild_id = ? AND account_id = ?)",
+                boxed_params![guild_id.to_string(), account_id],
+                |row| row.get::<_, bool>(0),
+            )
+            .await
+            .unwrap_or(false)
+    }
+
     /// Get-or-create the `images:guild`-scoped API key for a Discord guild.
     ///
     /// Returns the stored key when it's still a live session (so a revoked key —
@@ -545,7 +730,7 @@ impl AppState {
         let files: Vec<ImageEntry> = self
             .database()
             .all(
-                "SELECT id, size, X'' AS image_data, mimetype, uploader_id, uploaded_at, expires_at, original_name, views FROM images ORDER BY id ASC",
+                "SELECT id, size, X'' AS image_data, mimetype, uploader_id, uploaded_at, expires_at, original_name, views, visibility, password_hash, burn_after_views, blurhash, dominant_color, replaced_at, scan_status FROM images WHERE deleted_at IS NULL ORDER BY id ASC",
                 [],
             )
             .await
@@ -567,6 +752,8 @@ impl AppState {
                 expires_at: entry.expires_at,
                 original_name: entry.original_name,
                 views: entry.views,
+                blurhash: entry.blurhash,
+                dominant_color: entry.dominant_color,
             });
         }
 
@@ -593,40 +780,203 @@ impl AppState {
             .ok()
     }
 
-    /// Returns a cached thumbnail for `id`, generating it from `bytes` on a
-    /// cache miss. Returns `None` when the bytes can't be decoded (e.g. AVIF),
-    /// signalling the caller to fall back to the original image.
+    /// The name a thumbnail has in the rendition cache. Animated thumbnails
+    /// are their own entries, so flipping the setting doesn't serve stale ones.
+    fn thumbnail_name(&self) -> &'static str {
+        if self.config().images.animated_thumbnails {
+            "thumb-animated"
+        } else {
+            "thumb"
+        }
+    }
+
+    /// The SHA-256 of image `id`'s current bytes (its blob key), without
+    /// opening them. Renditions record it as their source and raw serving
+    /// answers revalidations with it. `None` for a row the legacy move hasn't
+    /// reached yet, or one that is gone.
+    pub async fn image_blob_hash(&self, id: &str) -> Option<String> {
+        self.database()
+            .get_row(
+                "SELECT blob_hash FROM images WHERE id = ?",
+                boxed_params![id.to_string()],
+                |row| row.get::<_, Option<String>>(0),
+            )
+            .await
+            .ok()
+            .flatten()
+    }
+
+    /// Whether a rendition made from the bytes hashing to `source_hash` is
+    /// still of image `id`. One whose render finished after the bytes were
+    /// replaced (and its renditions forgotten) isn't. Those of a row with no
+    /// blob yet can't be checked and are taken as they are.
+    async fn is_current(&self, id: &str, source_hash: &str) -> bool {
+        self.image_blob_hash(id)
+            .await
+            .map_or(true, |current| current == source_hash)
+    }
+
+    /// Returns the thumbnail for `id` if one is already cached, in memory or
+    /// on disk, without touching the image bytes.
+    pub async fn cached_thumbnail(&self, id: &str) -> Option<Thumbnail> {
+        if let Some(thumb) = self.inner.thumbnails.get(id) {
+            return Some(thumb);
+        }
+        let stored = self.inner.renditions.as_ref()?.get(id, self.thumbnail_name()).await?;
+        if !self.is_current(id, &stored.source_hash).await {
+            return None;
+        }
+        let thumb = Thumbnail {
+            bytes: Arc::new(stored.bytes),
+            content_type: stored.content_type,
+        };
+        self.inner.thumbnails.insert(id.to_string(), thumb.clone());
+        Some(thumb)
+    }
+
+    /// Returns a cached thumbnail for `id`, generating it from `bytes` (and
+    /// keeping it on disk) on a cache miss. Returns `None` when the bytes can't
+    /// be decoded (e.g. AVIF), signalling the caller to fall back to the
+    /// original image.
     ///
     /// Generation (decode + resize + encode) runs on the blocking pool so it
     /// never stalls the async runtime.
     pub async fn thumbnail_for(&self, id: &str, bytes: &[u8]) -> Option<Thumbnail> {
-        if let Some(thumb) = self.inner.thumbnails.get(id) {
+        if let Some(thumb) = self.cached_thumbnail(id).await {
             return Some(thumb);
         }
         let owned = bytes.to_vec();
-        let (data, content_type) = tokio::task::spawn_blocking(move || crate::thumbnail::generate(&owned))
-            .await
-            .ok()
-            .flatten()?;
+        let animated = self.config().images.animated_thumbnails;
+        let (source_hash, (data, content_type)) = tokio::task::spawn_blocking(move || {
+            let generated = if animated {
+                crate::thumbnail::generate_animated(&owned)
+            } else {
+                crate::thumbnail::generate(&owned)
+            }?;
+            Some((crate::scan::sha256_hex(&owned), generated))
+        })
+        .await
+        .ok()
+        .flatten()?;
         let thumb = Thumbnail {
             bytes: Arc::new(data),
             content_type,
         };
+        if !self.is_current(id, &source_hash).await {
+            return Some(thumb);
+        }
+        if let Some(disk) = &self.inner.renditions {
+            disk.put(id, self.thumbnail_name(), content_type, &source_hash, &thumb.bytes)
+                .await;
+        }
         self.inner.thumbnails.insert(id.to_string(), thumb.clone());
         Some(thumb)
     }
 
+    /// Returns the `variant` of image `id`, rendering it from the original on
+    /// a cache miss. `None` when the image is gone or can't be decoded (e.g.
+    /// AVIF), signalling the caller to serve the original instead.
+    pub async fn image_variant(&self, id: &str, variant: &crate::variant::Variant) -> Option<ImageVariant> {
+        let key = format!("{id}/{}", variant.key());
+        if let Some(cached) = self.inner.variants.get(&key) {
+            return Some(cached);
+        }
+        let name = format!("v-{}", variant.key());
+        if let Some(disk) = &self.inner.renditions {
+            // One rendered from bytes since replaced is made again below.
+            if let Some(stored) = disk.get(id, &name).await {
+                if self.is_current(id, &stored.source_hash).await {
+                    let rendered = ImageVariant {
+                        bytes: stored.bytes.into(),
+                        content_type: stored.content_type,
+                        source_hash: stored.source_hash,
+                    };
+                    self.inner.variants.insert(key, rendered.clone());
+                    return Some(rendered);
+                }
+            }
+        }
+        let bytes = self.resolve_image_data_for(id).await?;
+        let owned = variant.clone();
+        let (source_hash, (data, content_type)) = tokio::task::spawn_blocking(move || {
+            let rendered = crate::variant::render(&bytes, &owned)?;
+            Some((crate::scan::sha256_hex(&bytes), rendered))
+        })
+        .await
+        .ok()
+        .flatten()?;
+        let rendered = ImageVariant {
+            bytes: data.into(),
+            content_type,
+            source_hash,
+        };
+        if !self.is_current(id, &rendered.source_hash).await {
+            return Some(rendered);
+        }
+        if let Some(disk) = &self.inner.renditions {
+            disk.put(id, &name, content_type, &rendered.source_hash, &rendered.bytes)
+                .await;
+        }
+        self.inner.variants.insert(key, rendered.clone());
+        Some(rendered)
+    }
+
+    /// Loads the bytes of image `id`: from the blob store when the row points
+    /// at a blob, otherwise from the inline `image_data` column of a row the
+    /// legacy move (see [`crate::storage::migrate_legacy_blobs`]) hasn't
+    /// reached yet.
     pub async fn resolve_image_data_for(&self, id: &str) -> Option<Vec<u8>> {
         let id = id.to_string(); // owned
 
-        self.database()
+        let (hash, inline) = self
+            .database()
             .get_row(
-                "SELECT image_data FROM images WHERE id = ?",
+                "SELECT blob_hash, image_data FROM images WHERE id = ?",
                 boxed_params![id], // <--- use boxed_params! to satisfy Send + 'static
-                |row| row.get::<_, Vec<u8>>(0),
+                |row| Ok((row.get::<_, Option<String>>(0)?, row.get::<_, Option<Vec<u8>>>(1)?)),
             )
             .await
-            .ok()
+            .ok()?;
+
+        match hash {
+            Some(hash) => match self.blobs().get(&hash).await {
+                Ok(data) => data,
+                Err(e) => {
+                    tracing::warn!(error = %e, hash = %hash, "could not read image blob");
+                    None
+                }
+            },
+            None => inline,
+        }
+    }
+
+    /// Opens the bytes of image `id` for streaming, along with their SHA-256
+    /// (the blob key, which raw serving uses as the `ETag`). Like
+    /// [`Self::resolve_image_data_for`], but a blob-backed image is read from
+    /// the store chunk by chunk instead of being loaded whole.
+    pub async fn image_stream(&self, id: &str) -> Option<(String, BlobStream)> {
+        let id = id.to_string();
+
+        let (hash, inline) = self
+            .database()
+            .get_row(
+                "SELECT blob_hash, image_data FROM images WHERE id = ?",
+                boxed_params![id],
+                |row| Ok((row.get::<_, Option<String>>(0)?, row.get::<_, Option<Vec<u8>>>(1)?)),
+            )
+            .await
+            .ok()?;
+
+        match hash {
+            Some(hash) => match self.blobs().get_stream(&hash).await {
+                Ok(stream) => stream.map(|stream| (hash, stream)),
+                Err(e) => {
+                    tracing::warn!(error = %e, hash = %hash, "could not open image blob");
+                    None
+                }
+            },
+            None => inline.map(|bytes| (crate::scan::sha256_hex(&bytes), BlobStream::from_bytes(bytes))),
+        }
     }
 
     pub async fn resolve_image_files(&self) -> RwLockReadGuard<'_, Vec<ImageFile>> {
@@ -641,35 +991,40 @@ impl AppState {
         self.inner.cached_image_files.get().await.unwrap()
     }
 
-    /// Gets the image by the given ID.
+    /// Gets the image's metadata by the given ID, *without* its bytes
+    /// (`image_data` is left empty). Serving paths pair this with
+    /// [`Self::image_stream`] so nothing is buffered they don't need.
+    ///
+    /// All errors are coerced into None.
+    pub async fn get_image_meta(&self, id: String) -> Option<ImageEntry> {
+        let cached = match self.cached_images().get().await {
+            // Clone the entry so the lock is released before any I/O below.
+            Some(guard) => guard.iter().find(|x| x.id == id).cloned(),
+            None => None,
+        };
+        if cached.is_some() {
+            return cached;
+        }
+
+        self.database()
+            .get(
+                "SELECT id, size, X'' AS image_data, mimetype, uploader_id, uploaded_at, expires_at, original_name, views, visibility, password_hash, burn_after_views, blurhash, dominant_color, replaced_at, scan_status FROM images WHERE id = ? AND deleted_at IS NULL",
+                boxed_params![id],
+            )
+            .await
+            .ok()
+            .flatten()
+    }
+
+    /// Gets the image by the given ID, bytes included.
     ///
     /// If not found in cache then it calls the database.
     /// This incurs the cost of one clone regardless of the case.
     ///
     /// All errors are coerced into None.
     pub async fn get_image(&self, id: String) -> Option<ImageEntry> {
-        if let Some(guard) = self.cached_images().get().await {
-            if let Some(found_ref) = guard.iter().find(|x| x.id == id) {
-                // Clone the entry first
-                let mut entry = found_ref.clone();
-
-                // Now we can drop the lock safely
-                drop(guard);
-
-                // If image_data is missing, fetch it from DB
-                if entry.image_data.is_empty() {
-                    if let Some(data) = self.resolve_image_data_for(&id).await {
-                        entry.image_data = data;
-                        return Some(entry);
-                    } else {
-                        return None;
-                    }
-                }
-
-                return Some(entry);
-            }
-        }
-
-        self.database().get_by_id(id).await.ok().flatten()
+        let mut entry = self.get_image_meta(id.clone()).await?;
+        entry.image_data = self.resolve_image_data_for(&id).await?;
+        Some(entry)
     }
 }
@@ -0,0 +1,152 @@
+//! The filesystem [`BlobStore`]: one file per blob under a root directory.
+//!
+//! Blobs are fanned out by the first two bytes of their hash
+//! (`<root>/ab/cd/abcd…`) so no single directory grows to hundreds of
+//! thousands of entries. Writes go to `<root>/tmp/` first and are renamed into
+//! place, so a reader never sees a half-written blob and a crash mid-write
+//! leaves only a stray temp file behind.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use bytes::Bytes;
+use futures_util::StreamExt;
+use tokio::io::AsyncReadExt;
+
+use super::{is_valid_hash, BlobStore, BlobStream};
+
+/// Read size for streamed blobs.
+const CHUNK: usize = 64 * 1024;
+
+pub struct LocalBlobStore {
+    root: PathBuf,
+}
+
+impl LocalBlobStore {
+    /// Opens (and creates, if needed) a store rooted at `root`.
+    pub fn open(root: impl Into<PathBuf>) -> anyhow::Result<Self> {
+        let root = root.into();
+        std::fs::create_dir_all(root.join("tmp"))
+            .with_context(|| format!("could not create blob directory {}", root.display()))?;
+        Ok(Self { root })
+    }
+
+    /// The root directory blobs are stored under.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn path_for(&self, hash: &str) -> anyhow::Result<PathBuf> {
+        if !is_valid_hash(hash) {
+            anyhow::bail!("invalid blob hash {hash:?}");
+        }
+        Ok(self.root.join(&hash[0..2]).join(&hash[2..4]).join(hash))
+    }
+}
+
+#[async_trait::async_trait]
+impl BlobStore for LocalBlobStore {
+    fn name(&self) -> &'static str {
+        "local"
+    }
+
+    async fn put(&self, hash: &str, bytes: &[u8]) -> anyhow::Result<()> {
+        let path = self.path_for(hash)?;
+        if tokio::fs::try_exists(&path).await.unwrap_or(false) {
+            return Ok(());
+        }
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let tmp = self.root.join("tmp").join(format!("{hash}.{}", nanoid::nanoid!(8)));
+        tokio::fs::write(&tmp, bytes)
+            .await
+            .with_context(|| format!("could not write blob {hash}"))?;
+        if let Err(e) = tokio::fs::rename(&tmp, &path).await {
+            let _ = tokio::fs::remove_file(&tmp).await;
+            return Err(e).with_context(|| format!("could not move blob {hash} into place"));
+        }
+        Ok(())
+    }
+
+    async fn get(&self, hash: &str) -> anyhow::Result<Option<Vec<u8>>> {
+        let path = self.path_for(hash)?;
+        match tokio::fs::read(&path).await {
+            Ok(bytes) => Ok(Some(bytes)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e).with_context(|| format!("could not read blob {hash}")),
+        }
+    }
+
+    async fn get_stream(&self, hash: &str) -> anyhow::Result<Option<BlobStream>> {
+        let path = self.path_for(hash)?;
+        let file = match tokio::fs::File::open(&path).await {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e).with_context(|| format!("could not open blob {hash}")),
+        };
+        let len = file.metadata().await.ok().map(|m| m.len());
+        let chunks = futures_util::stream::try_unfold(file, |mut file| async move {
+            let mut buf = vec![0u8; CHUNK];
+            let n = file.read(&mut buf).await?;
+            if n == 0 {
+                return Ok(None);
+            }
+            buf.truncate(n);
+            Ok(Some((Bytes::from(buf), file)))
+        })
+        .boxed();
+        Ok(Some(BlobStream { len, chunks }))
+    }
+
+    async fn exists(&self, hash: &str) -> anyhow::Result<bool> {
+        let path = self.path_for(hash)?;
+        Ok(tokio::fs::try_exists(&path).await?)
+    }
+
+    async fn delete(&self, hash: &str) -> anyhow::Result<()> {
+        let path = self.path_for(hash)?;
+        match tokio::fs::remove_file(&path).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(e).with_context(|| format!("could not delete blob {hash}")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch() -> LocalBlobStore {
+        let dir = std::env::temp_dir().join(format!("klappstuhl-blobs-{}", nanoid::nanoid!(8)));
+        LocalBlobStore::open(dir).unwrap()
+    }
+
+    #[tokio::test]
+    async fn round_trips_and_deduplicates() {
+        let store = scratch();
+        let hash = crate::scan::sha256_hex(b"pixels");
+
+        assert_eq!(store.get(&hash).await.unwrap(), None);
+        store.put(&hash, b"pixels").await.unwrap();
+        // A second put of the same content is a no-op, not an error.
+        store.put(&hash, b"pixels").await.unwrap();
+        assert_eq!(store.get(&hash).await.unwrap().as_deref(), Some(&b"pixels"[..]));
+        assert!(store.exists(&hash).await.unwrap());
+
+        store.delete(&hash).await.unwrap();
+        store.delete(&hash).await.unwrap();
+        assert!(!store.exists(&hash).await.unwrap());
+
+        let _ = std::fs::remove_dir_all(store.root());
+    }
+
+    #[tokio::test]
+    async fn refuses_hashes_that_would_escape_the_root() {
+        let store = scratch();
+        assert!(store.get("../../../../etc/passwd").await.is_err());
+        assert!(store.put("..", b"x").await.is_err());
+        let _ = std::fs::remove_dir_all(store.root());
+    }
+}
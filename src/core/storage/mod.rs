@@ -0,0 +1,368 @@
+//! Content-addressed blob storage for uploaded image bytes.
+//!
+//! Image rows in `images` no longer carry their bytes. They point at a blob by
+//! `blob_hash` — the lowercase hex SHA-256 of the bytes — and the bytes live in
+//! a [`BlobStore`]. Two uploads of the same file share one blob: the
+//! `image_blob` table counts the rows referencing each hash, kept in step by
+//! triggers (see `sql/3.sql`), so deduplication costs callers nothing beyond
+//! hashing before they insert.
+//!
+//! Two backends implement the store: [`LocalBlobStore`] (files under the data
+//! directory, the default) and [`S3BlobStore`] (any S3-compatible bucket),
+//! picked by `storage.backend` in the config.
+//!
+//! A blob whose refcount drops to zero is not deleted straight away. It is
+//! stamped with `released_at` and removed by [`collect_garbage`] once
+//! [`GC_GRACE`] has passed, which gives an upload of identical bytes that raced
+//! the delete time to take the reference back. Writers go through
+//! [`store_blob`], which restarts that grace period before writing, so the
+//! bytes are still there by the time the row that references them is inserted.
+
+use std::sync::{Arc, OnceLock};
+
+use bytes::Bytes;
+use futures_util::stream::BoxStream;
+use futures_util::StreamExt;
+
+use crate::config::StorageBackend;
+use crate::{boxed_params, AppState, Config};
+
+mod local;
+mod s3;
+
+pub use local::LocalBlobStore;
+pub use s3::S3BlobStore;
+
+/// How long a blob must have been unreferenced before the reaper deletes it.
+pub const GC_GRACE: &str = "-1 hour";
+
+/// Rows moved per round-trip by [`migrate_legacy_blobs`]. Small enough that a
+/// batch of 10 MiB images doesn't balloon memory.
+const MIGRATION_BATCH: i64 = 16;
+
+/// A blob being read: its bytes as a stream of chunks, plus the total length
+/// when the store knows it up front.
+pub struct BlobStream {
+    pub len: Option<u64>,
+    pub chunks: BoxStream<'static, std::io::Result<Bytes>>,
+}
+
+impl BlobStream {
+    /// Wraps bytes that are already in memory.
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        Self {
+            len: Some(bytes.len() as u64),
+            chunks: futures_util::stream::once(async move { Ok(Bytes::from(bytes)) }).boxed(),
+        }
+    }
+
+    /// Turns the stream into a response body.
+    pub fn into_body(self) -> axum::body::Body {
+        axum::body::Body::from_stream(self.chunks)
+    }
+}
+
+/// A place image bytes can be kept, addressed by their SHA-256.
+///
+/// Implementations must be idempotent: `put` of a hash that already exists is a
+/// no-op success, and `delete` of a missing hash is not an error.
+#[async_trait::async_trait]
+pub trait BlobStore: Send + Sync {
+    /// A short name for logs (`"local"`, …).
+    fn name(&self) -> &'static str;
+
+    /// Stores `bytes` under `hash`. Callers compute the hash with
+    /// [`crate::scan::sha256_hex`]; the store does not re-verify it.
+    async fn put(&self, hash: &str, bytes: &[u8]) -> anyhow::Result<()>;
+
+    /// Reads the blob stored under `hash`, or `None` if there is none.
+    async fn get(&self, hash: &str) -> anyhow::Result<Option<Vec<u8>>>;
+
+    /// Opens the blob stored under `hash` for streaming, or `None` if there is
+    /// none. Serving paths use this so a large image is never buffered whole;
+    /// the default reads it into memory, for stores that can't do better.
+    async fn get_stream(&self, hash: &str) -> anyhow::Result<Option<BlobStream>> {
+        Ok(self.get(hash).await?.map(BlobStream::from_bytes))
+    }
+
+    /// Whether a blob is stored under `hash`.
+    async fn exists(&self, hash: &str) -> anyhow::Result<bool>;
+
+    /// Removes the blob stored under `hash`.
+    async fn delete(&self, hash: &str) -> anyhow::Result<()>;
+}
+
+/// Whether `hash` has the shape of a lowercase hex SHA-256. Stores use this to
+/// refuse anything that could escape their keyspace (`../`, separators, …).
+pub fn is_valid_hash(hash: &str) -> bool {
+    hash.len() == 64 && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+/// Builds the blob store selected by `config`.
+pub fn from_config(config: &Config) -> anyhow::Result<Arc<dyn BlobStore>> {
+    match config.storage.backend {
+        StorageBackend::Local => {
+            let root = match &config.storage.path {
+                Some(path) => path.clone(),
+                None => default_directory()?,
+            };
+            Ok(Arc::new(LocalBlobStore::open(root)?))
+        }
+        StorageBackend::S3 => Ok(Arc::new(S3BlobStore::new(&config.storage.s3)?)),
+    }
+}
+
+/// The default blob directory: `blobs/` next to `main.db`.
+pub fn default_directory() -> anyhow::Result<std::path::PathBuf> {
+    Ok(crate::database::directory()?.with_file_name("blobs"))
+}
+
+/// Held shared by [`store_blob`] while it pins a blob and exclusively by
+/// [`collect_garbage`] while it deletes, so a pin can't land between the
+/// collector picking a blob and removing it from the store.
+fn collection() -> &'static tokio::sync::RwLock<()> {
+    static COLLECTION: OnceLock<tokio::sync::RwLock<()>> = OnceLock::new();
+    COLLECTION.get_or_init(Default::default)
+}
+
+/// Stores `bytes` under `hash` for a row about to reference them.
+///
+/// The blob's ledger row is pinned first: created unreferenced if it's new,
+/// and if it's unreferenced, stamped as released just now. Either way
+/// [`collect_garbage`] leaves it alone for [`GC_GRACE`], long enough for the
+/// caller to insert its row, and collects it after that if the insert never
+/// happened.
+pub async fn store_blob(state: &AppState, hash: &str, bytes: &[u8]) -> anyhow::Result<()> {
+    {
+        let _pin = collection().read().await;
+        state
+            .database()
+            .execute(
+                "INSERT INTO image_blob (hash, size, refcount, released_at) \
+                 VALUES (?1, ?2, 0, strftime('%Y-%m-%dT%H:%M:%fZ', 'now')) \
+                 ON CONFLICT (hash) DO UPDATE SET released_at = \
+                     CASE WHEN refcount <= 0 THEN excluded.released_at ELSE released_at END",
+                boxed_params![hash.to_string(), bytes.len() as i64],
+            )
+            .await?;
+    }
+    state.blobs().put(hash, bytes).await
+}
+
+/// Deletes every blob that has been unreferenced for longer than [`GC_GRACE`],
+/// returning how many were removed.
+///
+/// The ledger row goes first, so a blob that fails to delete from the store is
+/// leaked rather than left referenced by a row that claims it's gone.
+pub async fn collect_garbage(state: &AppState) -> anyhow::Result<usize> {
+    let _collecting = collection().write().await;
+    let hashes: Vec<String> = state
+        .database()
+        .call(|conn| {
+            let mut stmt = conn.prepare(
+                "DELETE FROM image_blob WHERE refcount <= 0 AND released_at IS NOT NULL \
+                 AND datetime(released_at) <= datetime('now', ?) RETURNING hash",
+            )?;
+            let hashes: rusqlite::Result<Vec<String>> = stmt.query_map([GC_GRACE], |row| row.get(0))?.collect();
+            hashes
+        })
+        .await?;
+
+    let mut removed = 0usize;
+    for hash in hashes {
+        match state.blobs().delete(&hash).await {
+            Ok(()) => removed += 1,
+            Err(e) => tracing::warn!(error = %e, hash = %hash, "could not delete unreferenced blob"),
+        }
+    }
+    Ok(removed)
+}
+
+/// One-shot move of image bytes still stored inline in `main.db` into the blob
+/// store.
+///
+/// Rows written before the blob store existed keep their bytes in
+/// `images.image_data`. This walks them in small batches, writes each into the
+/// store, then re-points the row at its hash and clears the column — the
+/// `images_blob_repoint` trigger takes the reference. Reads fall back to the
+/// inline column until a row has moved, so this can run in the background
+/// while the server is already serving. Once anything has moved the database
+/// is vacuumed to hand the freed pages back to the filesystem.
+///
+/// Safe to call on every start: with nothing left inline it is a single
+/// indexed query.
+pub async fn migrate_legacy_blobs(state: &AppState) -> anyhow::Result<usize> {
+    let mut moved = 0usize;
+    loop {
+        let batch: Vec<(String, Vec<u8>)> = state
+            .database()
+            .call(|conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT id, image_data FROM images \
+                     WHERE blob_hash IS NULL AND image_data IS NOT NULL LIMIT ?",
+                )?;
+                let rows: rusqlite::Result<Vec<(String, Vec<u8>)>> = stmt
+                    .query_map([MIGRATION_BATCH], |row| Ok((row.get(0)?, row.get(1)?)))?
+                    .collect();
+                rows
+            })
+            .await?;
+
+        if batch.is_empty() {
+            break;
+        }
+
+        for (id, data) in batch {
+            let hash = crate::scan::sha256_hex(&data);
+            store_blob(state, &hash, &data).await?;
+            let size = data.len() as i64;
+            state
+                .database()
+                .execute(
+                    "UPDATE images SET blob_hash = ?, size = ?, image_data = NULL WHERE id = ? AND blob_hash IS NULL",
+                    boxed_params![hash, size, id],
+                )
+                .await?;
+            moved += 1;
+        }
+    }
+
+    if moved > 0 {
+        tracing::info!(
+            count = moved,
+            store = state.blobs().name(),
+            "moved inline image bytes into the blob store"
+        );
+        state.database().execute_batch("VACUUM;").await?;
+        state.invalidate_image_caches().await;
+    }
+    Ok(moved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_lowercase_hex_sha256_is_a_valid_hash() {
+        assert!(is_valid_hash(&crate::scan::sha256_hex(b"hello")));
+        assert!(!is_valid_hash("../../etc/passwd"));
+        assert!(!is_valid_hash(&"A".repeat(64)));
+        assert!(!is_valid_hash(&"a".repeat(63)));
+    }
+
+    fn refcount(conn: &rusqlite::Connection, hash: &str) -> Option<i64> {
+        conn.query_row("SELECT refcount FROM image_blob WHERE hash = ?", [hash], |row| {
+            row.get(0)
+        })
+        .ok()
+    }
+
+    #[test]
+    fn triggers_keep_the_refcount_in_step_with_images() {
+        let mut conn = rusqlite::Connection::open_in_memory().unwrap();
+        crate::migrations::migrate(&mut conn).unwrap();
+
+        let insert = "INSERT INTO images (id, blob_hash, size, mimetype) VALUES (?, ?, 3, 'image/png')";
+        conn.execute(insert, ["a", "h1"]).unwrap();
+        conn.execute(insert, ["b", "h1"]).unwrap();
+        assert_eq!(refcount(&conn, "h1"), Some(2));
+
+        conn.execute("DELETE FROM images WHERE id = 'a'", []).unwrap();
+        assert_eq!(refcount(&conn, "h1"), Some(1));
+
+        // Re-pointing moves the reference from one blob to the other.
+        conn.execute("UPDATE images SET blob_hash = 'h2' WHERE id = 'b'", [])
+            .unwrap();
+        assert_eq!(refcount(&conn, "h1"), Some(0));
+        assert_eq!(refcount(&conn, "h2"), Some(1));
+
+        let released: Option<String> = conn
+            .query_row("SELECT released_at FROM image_blob WHERE hash = 'h1'", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert!(released.is_some(), "an unreferenced blob is stamped for collection");
+
+        // Taking the reference back clears the stamp.
+        conn.execute(insert, ["c", "h1"]).unwrap();
+        let released: Option<String> = conn
+            .query_row("SELECT released_at FROM image_blob WHERE hash = 'h1'", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert!(released.is_none());
+    }
+
+    #[tokio::test]
+    async fn storing_a_released_blob_again_keeps_it_from_collection() {
+        let db = crate::Database::file(":memory:")
+            .connections(1)
+            .with_init(crate::migrations::migrate)
+            .open()
+            .await
+            .unwrap();
+        let state = AppState::for_tests(db).await;
+        let bytes = b"same bytes, uploaded twice";
+        let hash = crate::scan::sha256_hex(bytes);
+
+        // Released long ago, so due for collection...
+        store_blob(&state, &hash, bytes).await.unwrap();
+        state
+            .database()
+            .execute(
+                "UPDATE image_blob SET released_at = '2000-01-01T00:00:00.000Z' WHERE hash = ?1",
+                boxed_params![hash.clone()],
+            )
+            .await
+            .unwrap();
+        // ...until an upload of the same bytes stores it on the way to its insert.
+        store_blob(&state, &hash, bytes).await.unwrap();
+        assert_eq!(collect_garbage(&state).await.unwrap(), 0);
+        assert!(state.blobs().exists(&hash).await.unwrap());
+
+        // An insert that never happens leaves the blob to be collected later.
+        state
+            .database()
+            .execute(
+                "UPDATE image_blob SET released_at = '2000-01-01T00:00:00.000Z' WHERE hash = ?1",
+                boxed_params![hash.clone()],
+            )
+            .await
+            .unwrap();
+        assert_eq!(collect_garbage(&state).await.unwrap(), 1);
+        assert!(!state.blobs().exists(&hash).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn legacy_inline_bytes_move_into_the_store() {
+        let db = crate::Database::file(":memory:")
+            .connections(1)
+            .with_init(crate::migrations::migrate)
+            .open()
+            .await
+            .unwrap();
+        db.execute(
+            "INSERT INTO images (id, image_data, size, mimetype) VALUES ('legacy', x'010203', 3, 'image/png')",
+            [],
+        )
+        .await
+        .unwrap();
+        let state = AppState::for_tests(db).await;
+
+        assert_eq!(migrate_legacy_blobs(&state).await.unwrap(), 1);
+        assert_eq!(state.resolve_image_data_for("legacy").await, Some(vec![1, 2, 3]));
+
+        let inline: Option<Vec<u8>> = state
+            .database()
+            .get_row("SELECT image_data FROM images WHERE id = 'legacy'", [], |row| {
+                row.get(0)
+            })
+            .await
+            .unwrap();
+        assert!(inline.is_none(), "the inline copy is cleared once moved");
+
+        // Nothing left to move the second time round.
+        assert_eq!(migrate_legacy_blobs(&state).await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,42 @@
+//! Country lookups against a local MaxMind database (`geoip_db_path`).
+//!
+//! The database is an offline `.mmdb` file the operator downloads (GeoLite2
+//! Country or City both work); nothing is ever sent to a third party. It is
+//! read into memory once at start-up, so updating the file needs a restart.
+
+use std::net::IpAddr;
+
+use anyhow::Context;
+use maxminddb::{geoip2, Reader};
+
+pub struct GeoIp {
+    reader: Reader<Vec<u8>>,
+}
+
+impl GeoIp {
+    /// Opens the database at `path`.
+    pub fn open(path: &str) -> anyhow::Result<Self> {
+        let reader = Reader::open_readfile(path).with_context(|| format!("could not read GeoIP database {path}"))?;
+        Ok(Self { reader })
+    }
+
+    /// The configured database, or `None` when there is none or it can't be
+    /// read (logged).
+    pub fn from_config(config: &crate::Config) -> Option<Self> {
+        let path = config.geoip_db_path.as_deref()?;
+        match Self::open(path) {
+            Ok(geoip) => Some(geoip),
+            Err(e) => {
+                tracing::warn!(error = %e, "GeoIP lookups are off");
+                None
+            }
+        }
+    }
+
+    /// The ISO 3166-1 alpha-2 code of the country `ip` is in, if the database
+    /// knows it.
+    pub fn country(&self, ip: IpAddr) -> Option<String> {
+        let record: geoip2::Country = self.reader.lookup(ip).ok()?;
+        record.country?.iso_code.map(str::to_string)
+    }
+}
@@ -1,4 +1,5 @@
-//! Third-party integrations: Discord and external tools.
+//! Third-party integrations: Discord, external tools and the GeoIP database.
 
 pub mod discord;
 pub mod exttools;
+pub mod geoip;
@@ -17,11 +17,11 @@ pub mod site;
 // call sites are unchanged.
 pub use auth::{token, totp};
 // `audit` is cross-cutting — it lives in `core`.
-pub use core::{audit, cli, config, database, error, filters, logging, migrations, models, state, utils};
-pub use integrations::{discord, exttools};
+pub use core::{audit, cli, config, database, error, filters, logging, migrations, models, state, storage, utils};
+pub use integrations::{discord, exttools, geoip};
 pub use kls_web_core::key;
-pub use platform::{cached, cookies, flash, headers, ratelimit, scope};
-pub use site::media::{codeimage, metadata, scan, thumbnail};
+pub use platform::{cached, conditional, cookies, flash, headers, ratelimit, scope};
+pub use site::media::{animation, codeimage, metadata, phash, placeholder, renditions, scan, thumbnail, variant};
 
 /// The running version, taken from `Cargo.toml` — the single source of truth for
 /// it. The site footer, the changelog page and the OpenAPI docs all derive from
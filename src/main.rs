@@ -155,12 +155,40 @@ async fn run_server(state: klappstuhl_me::AppState) -> anyhow::Result<()> {
         }
     });
 
+    // Move any image bytes still stored inline in main.db into the blob store.
+    // Reads fall back to the inline copy until a row has moved, so this runs
+    // alongside serving rather than holding up start-up.
+    let migration_state = state.clone();
+    tokio::spawn(async move {
+        if let Err(e) = klappstuhl_me::storage::migrate_legacy_blobs(&migration_state).await {
+            error!(error = %e, "moving inline image bytes into the blob store failed");
+        }
+        // Then hash images uploaded before duplicate detection existed.
+        if let Err(e) = klappstuhl_me::phash::backfill(&migration_state).await {
+            error!(error = %e, "computing perceptual hashes for existing images failed");
+        }
+        // And give them loading placeholders.
+        if let Err(e) = klappstuhl_me::placeholder::backfill(&migration_state).await {
+            error!(error = %e, "computing placeholders for existing images failed");
+        }
+    });
+
+    // Make thumbnails for new uploads before anyone asks for them.
+    klappstuhl_me::renditions::spawn_worker(state.clone());
+
+    // Scan uploads held by the `upload_scan` policy, and whatever a restart
+    // left pending.
+    klappstuhl_me::routes::spawn_upload_scanner(state.clone());
+
     // Reap expired image uploads (TTL) hourly.
     klappstuhl_me::routes::spawn_expiry_reaper(state.clone());
 
     // Reap expired pastes (TTL) hourly.
     klappstuhl_me::routes::spawn_paste_reaper(state.clone());
 
+    // Expire, and eventually delete, dead short links hourly.
+    klappstuhl_me::routes::spawn_link_reaper(state.clone());
+
     // Middleware order for request processing is bottom to top
     // and for response processing it's top to bottom
     let router = klappstuhl_me::routes::all()
@@ -351,6 +379,11 @@ async fn run(command: klappstuhl_me::Command) -> anyhow::Result<()> {
             info!("successfully created account {}", credentials.username);
             Ok(())
         }
+        klappstuhl_me::Command::Thumbnails => {
+            let made = klappstuhl_me::renditions::rebuild(&state).await?;
+            println!("rebuilt {made} thumbnails");
+            Ok(())
+        }
         _ => Err(anyhow::anyhow!("unknown command")),
     }
 }
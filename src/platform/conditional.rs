@@ -0,0 +1,506 @@
+//! Conditional GET and byte-range responses for the raw media routes.
+//!
+//! `/gallery/raw/:id`, the content-negotiated `/gallery/:id`, `/m/:id` and the
+//! raw paste views all serve an immutable-ish blob of bytes. [`respond`] wraps
+//! such a body with the HTTP caching and partial-content machinery:
+//!
+//! - a strong `ETag` (the content hash) and `Last-Modified`,
+//! - `304 Not Modified` for a matching `If-None-Match` / `If-Modified-Since`,
+//! - `206 Partial Content` for single and multiple `Range`s (the latter as
+//!   `multipart/byteranges`), `If-Range` included, and `416` for a range past
+//!   the end.
+//!
+//! A malformed `Range` header is ignored and the whole body sent, as RFC 9110
+//! allows. Bodies whose length isn't known up front are always sent whole.
+
+use axum::body::Body;
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use bytes::{Bytes, BytesMut};
+use futures_util::StreamExt;
+use time::{format_description::FormatItem, macros::format_description, OffsetDateTime, PrimitiveDateTime};
+
+use crate::storage::BlobStream;
+
+/// More ranges than this in one request is treated as no `Range` at all; a
+/// real client never needs that many, and each one costs a multipart section.
+const MAX_RANGES: usize = 16;
+
+/// The IMF-fixdate form of an HTTP-date (`Sun, 06 Nov 1994 08:49:37 GMT`). The
+/// obsolete RFC 850 and asctime forms aren't accepted; a validator in either
+/// is just ignored.
+const HTTP_DATE: &[FormatItem<'static>] =
+    format_description!("[weekday repr:short], [day] [month repr:short] [year] [hour]:[minute]:[second] GMT");
+
+/// The validators of the representation being served.
+#[derive(Debug, Clone)]
+pub struct Validators {
+    etag: String,
+    last_modified: Option<OffsetDateTime>,
+}
+
+impl Validators {
+    /// Validators for a body whose content hash is `hash` (any string unique to
+    /// the bytes, e.g. the blob's SHA-256), last changed at `last_modified`.
+    pub fn new(hash: &str, last_modified: Option<OffsetDateTime>) -> Self {
+        Self {
+            etag: format!("\"{hash}\""),
+            // HTTP-dates have whole-second precision; truncating here keeps an
+            // echoed `If-Modified-Since` comparing equal.
+            last_modified: last_modified.and_then(|t| t.replace_nanosecond(0).ok()),
+        }
+    }
+
+    /// Whether the request's conditional headers say the client's copy is
+    /// current. `If-None-Match` wins over `If-Modified-Since` when both are sent.
+    fn not_modified(&self, request: &HeaderMap) -> bool {
+        if let Some(value) = request.get(header::IF_NONE_MATCH) {
+            let Ok(value) = value.to_str() else {
+                return false;
+            };
+            // Weak comparison: a `W/` prefix on the client's tag still matches.
+            return value
+                .split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || tag.trim_start_matches("W/") == self.etag);
+        }
+        match (self.last_modified, header_date(request, header::IF_MODIFIED_SINCE)) {
+            (Some(modified), Some(since)) => modified <= since,
+            _ => false,
+        }
+    }
+
+    /// Whether an `If-Range` precondition (if any) allows a partial response.
+    /// An entity tag must match strongly; a date must equal `Last-Modified`.
+    fn range_applies(&self, request: &HeaderMap) -> bool {
+        let Some(value) = request.get(header::IF_RANGE) else {
+            return true;
+        };
+        let Ok(value) = value.to_str() else {
+            return false;
+        };
+        let value = value.trim();
+        if value.starts_with('"') {
+            return value == self.etag;
+        }
+        match (self.last_modified, parse_http_date(value)) {
+            (Some(modified), Some(date)) => modified == date,
+            _ => false,
+        }
+    }
+
+    fn apply(&self, headers: &mut HeaderMap) {
+        if let Ok(value) = HeaderValue::from_str(&self.etag) {
+            headers.insert(header::ETAG, value);
+        }
+        if let Some(value) = self.last_modified.and_then(format_http_date) {
+            headers.insert(header::LAST_MODIFIED, value);
+        }
+    }
+}
+
+/// The `304 Not Modified` [`respond`] would answer with, if the request's
+/// validators match, or `None` if the body is needed. Lets a caller whose body
+/// is costly to open (a blob in storage) skip opening it for a revalidation.
+pub fn not_modified(request: &HeaderMap, validators: &Validators, mut headers: HeaderMap) -> Option<Response> {
+    if !validators.not_modified(request) {
+        return None;
+    }
+    validators.apply(&mut headers);
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+    headers.remove(header::CONTENT_TYPE);
+    Some((StatusCode::NOT_MODIFIED, headers).into_response())
+}
+
+/// A body [`respond`] can serve.
+pub enum Payload {
+    /// Bytes already in memory.
+    Bytes(Bytes),
+    /// A blob being streamed from storage.
+    Stream(BlobStream),
+}
+
+impl From<Bytes> for Payload {
+    fn from(bytes: Bytes) -> Self {
+        Self::Bytes(bytes)
+    }
+}
+
+impl From<Vec<u8>> for Payload {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::Bytes(bytes.into())
+    }
+}
+
+impl From<String> for Payload {
+    fn from(text: String) -> Self {
+        Self::Bytes(text.into())
+    }
+}
+
+impl From<BlobStream> for Payload {
+    fn from(stream: BlobStream) -> Self {
+        Self::Stream(stream)
+    }
+}
+
+impl Payload {
+    fn len(&self) -> Option<u64> {
+        match self {
+            Self::Bytes(bytes) => Some(bytes.len() as u64),
+            Self::Stream(stream) => stream.len,
+        }
+    }
+
+    /// The bytes in `first..=last`. A stream is read up to `last` and the
+    /// prefix discarded, so no store needs to support ranged reads.
+    fn slice(self, first: u64, last: u64) -> Body {
+        match self {
+            Self::Bytes(bytes) => Body::from(bytes.slice(first as usize..=last as usize)),
+            Self::Stream(stream) => {
+                let chunks = stream
+                    .chunks
+                    .scan(0u64, move |offset, chunk| {
+                        let start = *offset;
+                        let item = match chunk {
+                            Err(e) => Some(Some(Err(e))),
+                            // Past the range: stop reading the blob.
+                            Ok(_) if start > last => None,
+                            Ok(chunk) => {
+                                let end = start + chunk.len() as u64;
+                                *offset = end;
+                                if end <= first {
+                                    Some(None)
+                                } else {
+                                    let from = first.saturating_sub(start) as usize;
+                                    let to = ((last + 1).min(end) - start) as usize;
+                                    Some(Some(Ok(chunk.slice(from..to))))
+                                }
+                            }
+                        };
+                        std::future::ready(item)
+                    })
+                    .filter_map(std::future::ready);
+                Body::from_stream(chunks)
+            }
+        }
+    }
+
+    /// The whole body in memory. Only multi-range responses need this.
+    async fn collect(self) -> std::io::Result<Bytes> {
+        match self {
+            Self::Bytes(bytes) => Ok(bytes),
+            Self::Stream(mut stream) => {
+                let mut buf = BytesMut::with_capacity(stream.len.unwrap_or_default() as usize);
+                while let Some(chunk) = stream.chunks.next().await {
+                    buf.extend_from_slice(&chunk?);
+                }
+                Ok(buf.freeze())
+            }
+        }
+    }
+
+    fn into_body(self) -> Body {
+        match self {
+            Self::Bytes(bytes) => Body::from(bytes),
+            Self::Stream(stream) => stream.into_body(),
+        }
+    }
+}
+
+/// What a `Range` header asks for, resolved against the body length.
+#[derive(Debug, PartialEq, Eq)]
+enum RangeRequest {
+    /// No usable `Range`: send everything.
+    Full,
+    /// Inclusive `(first, last)` byte ranges, sorted and non-overlapping.
+    Partial(Vec<(u64, u64)>),
+    /// Every range starts past the end.
+    Unsatisfiable,
+}
+
+fn parse_range(value: &str, len: u64) -> RangeRequest {
+    let Some(specs) = value.trim().strip_prefix("bytes=") else {
+        return RangeRequest::Full;
+    };
+
+    let mut ranges = Vec::new();
+    for spec in specs.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let Some((first, last)) = spec.split_once('-') else {
+            return RangeRequest::Full;
+        };
+        let range = match (first.trim(), last.trim()) {
+            ("", suffix) => match suffix.parse::<u64>() {
+                Ok(0) => None,
+                Ok(n) if len > 0 => Some((len.saturating_sub(n), len - 1)),
+                Ok(_) => None,
+                Err(_) => return RangeRequest::Full,
+            },
+            (first, last) => {
+                let Ok(first) = first.parse::<u64>() else {
+                    return RangeRequest::Full;
+                };
+                let last = match last {
+                    "" => u64::MAX,
+                    last => match last.parse::<u64>() {
+                        Ok(last) if last >= first => last,
+                        _ => return RangeRequest::Full,
+                    },
+                };
+                (first < len).then(|| (first, last.min(len - 1)))
+            }
+        };
+        ranges.extend(range);
+    }
+
+    if ranges.is_empty() {
+        return RangeRequest::Unsatisfiable;
+    }
+    if ranges.len() > MAX_RANGES {
+        return RangeRequest::Full;
+    }
+
+    // Coalesce overlapping and adjacent ranges so a client can't ask for the
+    // same bytes many times over.
+    ranges.sort_unstable();
+    let mut merged: Vec<(u64, u64)> = Vec::with_capacity(ranges.len());
+    for (first, last) in ranges {
+        match merged.last_mut() {
+            Some(prev) if first <= prev.1.saturating_add(1) => prev.1 = prev.1.max(last),
+            _ => merged.push((first, last)),
+        }
+    }
+    RangeRequest::Partial(merged)
+}
+
+fn parse_http_date(value: &str) -> Option<OffsetDateTime> {
+    PrimitiveDateTime::parse(value.trim(), HTTP_DATE)
+        .ok()
+        .map(PrimitiveDateTime::assume_utc)
+}
+
+/// Formats `date` as an HTTP-date (`Sun, 06 Nov 1994 08:49:37 GMT`).
+pub(crate) fn format_http_date(date: OffsetDateTime) -> Option<HeaderValue> {
+    let date = date.to_offset(time::UtcOffset::UTC);
+    HeaderValue::from_str(&date.format(HTTP_DATE).ok()?).ok()
+}
+
+fn header_date(request: &HeaderMap, name: header::HeaderName) -> Option<OffsetDateTime> {
+    parse_http_date(request.get(name)?.to_str().ok()?)
+}
+
+/// Serves `payload` for a GET carrying the `request` headers.
+///
+/// `headers` are the representation headers (`Content-Type`,
+/// `Content-Disposition`, `Cache-Control`, …); they go on every response,
+/// including 304s, so a revalidated cache entry keeps them. `Content-Type`
+/// moves into the parts of a multi-range response.
+pub async fn respond(request: &HeaderMap, validators: &Validators, headers: HeaderMap, payload: Payload) -> Response {
+    if let Some(response) = not_modified(request, validators, headers.clone()) {
+        return response;
+    }
+    let mut headers = headers;
+    validators.apply(&mut headers);
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+
+    let Some(len) = payload.len() else {
+        headers.remove(header::ACCEPT_RANGES);
+        return (headers, payload.into_body()).into_response();
+    };
+
+    let range = match request.get(header::RANGE).and_then(|v| v.to_str().ok()) {
+        Some(value) if validators.range_applies(request) => parse_range(value, len),
+        _ => RangeRequest::Full,
+    };
+
+    match range {
+        RangeRequest::Full => {
+            headers.insert(header::CONTENT_LENGTH, len.into());
+            (headers, payload.into_body()).into_response()
+        }
+        RangeRequest::Unsatisfiable => {
+            if let Ok(value) = HeaderValue::from_str(&format!("bytes */{len}")) {
+                headers.insert(header::CONTENT_RANGE, value);
+            }
+            headers.remove(header::CONTENT_TYPE);
+            headers.remove(header::CONTENT_DISPOSITION);
+            (StatusCode::RANGE_NOT_SATISFIABLE, headers).into_response()
+        }
+        RangeRequest::Partial(ranges) if ranges.len() == 1 => {
+            let (first, last) = ranges[0];
+            if let Ok(value) = HeaderValue::from_str(&format!("bytes {first}-{last}/{len}")) {
+                headers.insert(header::CONTENT_RANGE, value);
+            }
+            headers.insert(header::CONTENT_LENGTH, (last - first + 1).into());
+            (StatusCode::PARTIAL_CONTENT, headers, payload.slice(first, last)).into_response()
+        }
+        RangeRequest::Partial(ranges) => {
+            let bytes = match payload.collect().await {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    tracing::warn!(error = %e, "could not read body for a multi-range response");
+                    return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+                }
+            };
+            let content_type = headers
+                .remove(header::CONTENT_TYPE)
+                .and_then(|v| v.to_str().ok().map(str::to_string))
+                .unwrap_or_else(|| "application/octet-stream".to_string());
+            let boundary = nanoid::nanoid!(24, &nanoid::alphabet::SAFE);
+            let body = multipart_byteranges(&bytes, &ranges, &content_type, &boundary);
+            if let Ok(value) = HeaderValue::from_str(&format!("multipart/byteranges; boundary={boundary}")) {
+                headers.insert(header::CONTENT_TYPE, value);
+            }
+            headers.insert(header::CONTENT_LENGTH, body.len().into());
+            (StatusCode::PARTIAL_CONTENT, headers, body).into_response()
+        }
+    }
+}
+
+fn multipart_byteranges(bytes: &Bytes, ranges: &[(u64, u64)], content_type: &str, boundary: &str) -> Vec<u8> {
+    let len = bytes.len();
+    let mut body = Vec::new();
+    for &(first, last) in ranges {
+        body.extend_from_slice(
+            format!(
+                "\r\n--{boundary}\r\nContent-Type: {content_type}\r\nContent-Range: bytes {first}-{last}/{len}\r\n\r\n"
+            )
+            .as_bytes(),
+        );
+        body.extend_from_slice(&bytes[first as usize..=last as usize]);
+    }
+    body.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(pairs: &[(header::HeaderName, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in pairs {
+            headers.insert(name.clone(), HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    async fn body_of(response: Response) -> Vec<u8> {
+        axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap()
+            .to_vec()
+    }
+
+    fn validators() -> Validators {
+        Validators::new("abc", Some(time::macros::datetime!(2026-01-02 03:04:05.678 UTC)))
+    }
+
+    #[test]
+    fn ranges_resolve_against_the_length() {
+        assert_eq!(parse_range("bytes=0-9", 100), RangeRequest::Partial(vec![(0, 9)]));
+        assert_eq!(parse_range("bytes=90-", 100), RangeRequest::Partial(vec![(90, 99)]));
+        assert_eq!(parse_range("bytes=-10", 100), RangeRequest::Partial(vec![(90, 99)]));
+        assert_eq!(parse_range("bytes=50-500", 100), RangeRequest::Partial(vec![(50, 99)]));
+        assert_eq!(parse_range("bytes=-500", 100), RangeRequest::Partial(vec![(0, 99)]));
+        assert_eq!(parse_range("bytes=100-", 100), RangeRequest::Unsatisfiable);
+        assert_eq!(parse_range("bytes=9-0", 100), RangeRequest::Full);
+        assert_eq!(parse_range("items=0-1", 100), RangeRequest::Full);
+        assert_eq!(parse_range("bytes=a-b", 100), RangeRequest::Full);
+    }
+
+    #[test]
+    fn overlapping_ranges_are_coalesced() {
+        assert_eq!(
+            parse_range("bytes=20-29, 0-9, 5-14, 15-15", 100),
+            RangeRequest::Partial(vec![(0, 15), (20, 29)])
+        );
+        let many = (0..=MAX_RANGES)
+            .map(|i| format!("{}-{}", i * 10, i * 10 + 1))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            parse_range(&format!("bytes={}", many.join(",")), 1000),
+            RangeRequest::Full
+        );
+    }
+
+    #[test]
+    fn http_dates_round_trip() {
+        let date = parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT").unwrap();
+        assert_eq!(date, time::macros::datetime!(1994-11-06 08:49:37 UTC));
+        assert_eq!(format_http_date(date).unwrap(), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT").is_none());
+    }
+
+    #[tokio::test]
+    async fn matching_validators_give_not_modified() {
+        let v = validators();
+        let by_tag = request(&[(header::IF_NONE_MATCH, "\"zzz\", W/\"abc\"")]);
+        let res = respond(&by_tag, &v, HeaderMap::new(), Payload::from(b"hello".to_vec())).await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(res.headers()[header::ETAG], "\"abc\"");
+
+        let by_date = request(&[(header::IF_MODIFIED_SINCE, "Fri, 02 Jan 2026 03:04:05 GMT")]);
+        let res = respond(&by_date, &v, HeaderMap::new(), Payload::from(b"hello".to_vec())).await;
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+
+        // A stale tag wins over a fresh date.
+        let stale = request(&[
+            (header::IF_NONE_MATCH, "\"old\""),
+            (header::IF_MODIFIED_SINCE, "Fri, 02 Jan 2026 03:04:05 GMT"),
+        ]);
+        let res = respond(&stale, &v, HeaderMap::new(), Payload::from(b"hello".to_vec())).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(body_of(res).await, b"hello");
+
+        // The same answer without a body to hand.
+        let res = not_modified(&by_tag, &v, HeaderMap::new()).unwrap();
+        assert_eq!(res.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(res.headers()[header::ETAG], "\"abc\"");
+        assert!(not_modified(&stale, &v, HeaderMap::new()).is_none());
+    }
+
+    #[tokio::test]
+    async fn single_range_from_a_stream() {
+        let chunks = ["hel", "lo w", "orld"].map(|s| Ok(Bytes::from(s)));
+        let stream = BlobStream {
+            len: Some(11),
+            chunks: futures_util::stream::iter(chunks).boxed(),
+        };
+        let req = request(&[(header::RANGE, "bytes=2-7")]);
+        let res = respond(&req, &validators(), HeaderMap::new(), stream.into()).await;
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes 2-7/11");
+        assert_eq!(res.headers()[header::CONTENT_LENGTH], "6");
+        assert_eq!(body_of(res).await, b"llo wo");
+    }
+
+    #[tokio::test]
+    async fn multiple_ranges_are_multipart() {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("text/plain"));
+        let req = request(&[(header::RANGE, "bytes=0-1,-2")]);
+        let res = respond(&req, &validators(), headers, Payload::from(b"0123456789".to_vec())).await;
+        assert_eq!(res.status(), StatusCode::PARTIAL_CONTENT);
+        let content_type = res.headers()[header::CONTENT_TYPE].to_str().unwrap().to_string();
+        let boundary = content_type.strip_prefix("multipart/byteranges; boundary=").unwrap();
+        let body = String::from_utf8(body_of(res).await).unwrap();
+        assert!(body.contains("Content-Range: bytes 0-1/10\r\n\r\n01\r\n"));
+        assert!(body.contains("Content-Range: bytes 8-9/10\r\n\r\n89\r\n"));
+        assert!(body.ends_with(&format!("--{boundary}--\r\n")));
+    }
+
+    #[tokio::test]
+    async fn unsatisfiable_and_stale_if_range() {
+        let req = request(&[(header::RANGE, "bytes=50-")]);
+        let res = respond(&req, &validators(), HeaderMap::new(), Payload::from(b"short".to_vec())).await;
+        assert_eq!(res.status(), StatusCode::RANGE_NOT_SATISFIABLE);
+        assert_eq!(res.headers()[header::CONTENT_RANGE], "bytes */5");
+
+        // The client's copy is out of date, so it gets the whole new body.
+        let req = request(&[(header::RANGE, "bytes=0-1"), (header::IF_RANGE, "\"old\"")]);
+        let res = respond(&req, &validators(), HeaderMap::new(), Payload::from(b"short".to_vec())).await;
+        assert_eq!(res.status(), StatusCode::OK);
+        assert_eq!(body_of(res).await, b"short");
+    }
+}
@@ -1,8 +1,10 @@
 //! Cross-cutting HTTP plumbing shared by every surface: response caching, flash
-//! messages, header/client-IP helpers, rate limiting, and permission scopes.
+//! messages, header/client-IP helpers, conditional/range responses, rate
+//! limiting, and permission scopes.
 //! These wrap the route handlers in `site` and `admin` but belong to neither.
 
 pub mod cached;
+pub mod conditional;
 pub mod cookies;
 pub mod flash;
 pub mod headers;
@@ -204,6 +204,20 @@ impl IntoResponse for RateLimitInfo {
 }
 
 #[derive(Clone)]
+impl<T: KeyExtractor> RateLimitLayer<T> {
+    /// Counts `request` against the limit outside of a service, for a handler
+    /// that limits only some of what it takes (a fallback can't have a layer
+    /// per method). The `Err` is the 429 to answer with.
+    pub fn check(&self, request: &Request) -> Result<(), Response> {
+        let info = self.process(request);
+        if info.is_ratelimited() {
+            Err(info.into_response())
+        } else {
+            Ok(())
+        }
+    }
+}
+
 pub struct RateLimitService<S, T: KeyExtractor> {
     layer: RateLimitLayer<T>,
     inner: S,
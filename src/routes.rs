@@ -7,6 +7,8 @@ use axum::Router;
 
 pub use crate::site::api::{copy_api_token, ApiToken};
 pub use crate::site::image::spawn_expiry_reaper;
+pub use crate::site::image_scan::spawn_upload_scanner;
+pub use crate::site::links::spawn_link_reaper;
 pub use crate::site::paste::spawn_paste_reaper;
 
 /// Builds the complete application router.
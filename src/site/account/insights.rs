@@ -72,7 +72,7 @@ fn window(range: Option<&str>) -> (i64, i64) {
 /// Hand-rolled because the crate has no URL parser and this needs no more than
 /// the authority: referrers are attacker-supplied strings, so anything that
 /// doesn't look like `scheme://host/…` is rejected rather than guessed at.
-fn host_of(url: &str) -> Option<String> {
+pub(crate) fn host_of(url: &str) -> Option<String> {
     let rest = url.split_once("://")?.1;
     let authority = rest.split(['/', '?', '#']).next()?;
     // Strip userinfo and port: `user@host:443` → `host`.
@@ -97,7 +97,7 @@ fn search_engine(host: &str) -> Option<&'static str> {
 
 /// True when a referrer host is one of ours (the apex or any subdomain of a
 /// configured domain). Those are internal navigation, not a referring site.
-fn is_own_host(host: &str, domains: &[String]) -> bool {
+pub(crate) fn is_own_host(host: &str, domains: &[String]) -> bool {
     domains.iter().any(|d| {
         let d = d.to_ascii_lowercase();
         host == d || host.ends_with(&format!(".{d}"))
@@ -14,6 +14,7 @@
 //!   signup shares).
 //! - [`api_keys`] — API-token generation and the ShareX uploader config.
 //! - [`sessions`] — revoking and renaming sessions.
+//! - [`trash`] — the page listing deleted content, with restore and purge.
 //! - [`delete`] — the data export and the permanent account-deletion flow.
 //!
 //! Everything an authenticated page needs to read about its own account lives
@@ -28,6 +29,7 @@ pub(crate) mod lockout;
 pub mod pages;
 pub mod security;
 pub mod sessions;
+pub mod trash;
 pub mod username;
 
 use crate::{models::Account, ratelimit::RateLimit, AppState};
@@ -284,6 +286,7 @@ pub fn routes() -> Router<AppState> {
         .route("/account/sessions", get(pages::sessions_page))
         .route("/account/api", get(pages::api_page))
         .route("/account/content", get(pages::content))
+        .route("/account/trash", get(trash::page))
         .route("/account/danger", get(pages::danger))
         // Admin-only; both handlers check the flag themselves (there is no
         // admin extractor — the shell gates in the sidebar, not the router).
@@ -319,6 +322,9 @@ pub fn routes() -> Router<AppState> {
         .route("/account/sharex.sxcu", get(api_keys::sharex_config))
         .route("/account/invalidate", post(sessions::invalidate_session))
         .route("/account/sessions/rename", post(sessions::rename_session))
+        .route("/account/trash/:kind/:id/restore", post(trash::restore))
+        .route("/account/trash/:kind/:id/purge", post(trash::purge))
+        .route("/account/trash/empty", post(trash::empty))
         .route(
             "/account/export",
             get(delete::export).layer(RateLimit::default().quota(5, 600.0).build()),
@@ -14,10 +14,10 @@ use crate::{
 };
 use askama::Template;
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, State},
     response::{IntoResponse, Redirect, Response},
 };
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use time::OffsetDateTime;
 
 use super::{
@@ -334,16 +334,49 @@ struct ContentTemplate {
     pastes: i64,
     recent_images: Vec<ImageSummary>,
     recent_pastes: Vec<PasteSummary>,
+    /// What was typed into the search box; empty when nothing was searched.
+    query: String,
+    results: Vec<crate::site::search::Hit>,
 }
 
-pub async fn content(State(state): State<AppState>, flashes: Flashes, account: Account) -> Response {
+#[derive(Deserialize)]
+pub struct ContentQuery {
+    #[serde(default)]
+    q: String,
+}
+
+pub async fn content(
+    State(state): State<AppState>,
+    flashes: Flashes,
+    account: Account,
+    Query(search): Query<ContentQuery>,
+) -> Response {
     let account_id = account.id;
+    let query: String = search
+        .q
+        .trim()
+        .chars()
+        .take(crate::site::search::MAX_QUERY_LEN)
+        .collect();
+    let results = if query.is_empty() {
+        Vec::new()
+    } else {
+        crate::site::search::search(
+            &state,
+            account_id,
+            &query,
+            &crate::site::search::SearchKind::ALL,
+            crate::site::search::DEFAULT_LIMIT,
+        )
+        .await
+        .unwrap_or_default()
+    };
     let recent_images = state
         .database()
         .call(move |conn| -> rusqlite::Result<Vec<ImageSummary>> {
             let mut stmt = conn.prepare_cached(
                 "SELECT id, size, uploaded_at, views FROM images
-                  WHERE uploader_id = ? ORDER BY uploaded_at DESC LIMIT 8",
+                  WHERE uploader_id = ? AND deleted_at IS NULL ORDER BY uploaded_at DESC LIMIT 8",
             )?;
             let rows: rusqlite::Result<Vec<ImageSummary>> = stmt
                 .query_map([account_id], |row| {
@@ -365,7 +398,7 @@ pub async fn content(State(state): State<AppState>, flashes: Flashes, account: A
         .call(move |conn| -> rusqlite::Result<Vec<PasteSummary>> {
             let mut stmt = conn.prepare_cached(
                 "SELECT id, language, views, created_at, expires_at FROM paste
-                  WHERE account_id = ? ORDER BY created_at DESC LIMIT 8",
+                  WHERE account_id = ? AND deleted_at IS NULL ORDER BY created_at DESC LIMIT 8",
             )?;
             let rows: rusqlite::Result<Vec<PasteSummary>> = stmt
                 .query_map([account_id], |row| {
@@ -394,6 +427,8 @@ pub async fn content(State(state): State<AppState>, flashes: Flashes, account: A
         pastes: counts.pastes,
         recent_images,
         recent_pastes,
+        query,
+        results,
         account: Some(account),
     }
     .into_response()
@@ -456,6 +491,8 @@ struct UserPublicTemplate {
     /// the only pastes that show on a public profile (unlisted/private never do).
     public_pastes: usize,
     recent_public_pastes: Vec<PublicPasteRow>,
+    /// The user's `public` albums, newest first.
+    public_albums: Vec<crate::models::Album>,
     /// True when you're looking at your own public page — the template then
     /// offers a link back into the account shell.
     is_self: bool,
@@ -501,18 +538,19 @@ pub async fn user_public(
         .flatten()
         .ok_or_else(|| Redirect::to("/"))?;
 
-    let ((images, image_bytes), links, public_pastes) = tokio::join!(
+    let ((images, image_bytes), links, public_pastes, public_albums) = tokio::join!(
         image_totals(&state, user.id),
         short_link_count(&state, user.id),
         // `public` visibility means *indexable + listed on your own profile*,
         // and nothing more — there is still no global discover feed.
         crate::site::paste::service::list_public(&state, user.id, 6),
+        crate::site::album::list_public(&state, user.id, 12),
     );
 
     let public_count: i64 = state
         .database()
         .get_row(
-            "SELECT COUNT(*) FROM paste WHERE account_id = ?1 AND visibility = 'public' \
+            "SELECT COUNT(*) FROM paste WHERE account_id = ?1 AND visibility = 'public' AND deleted_at IS NULL \
              AND (expires_at IS NULL OR datetime(expires_at) > datetime('now'))",
             [user.id],
             |row| row.get::<_, i64>(0),
@@ -543,6 +581,7 @@ pub async fn user_public(
         links,
         public_pastes: public_count.max(0) as usize,
         recent_public_pastes,
+        public_albums,
         account: Some(account),
     }
     .into_response())
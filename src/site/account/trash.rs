@@ -0,0 +1,145 @@
+//! The trash page at `/account/trash`.
+//!
+//! - `GET  /account/trash`                    the page
+//! - `POST /account/trash/:kind/:id/restore`  put an item back
+//! - `POST /account/trash/:kind/:id/purge`    delete an item for good, now
+//! - `POST /account/trash/empty`              purge everything
+//!
+//! The soft-delete model itself lives in [`crate::site::trash`]; this is only
+//! the browser surface over it, alongside `/api/v1/trash`.
+
+use askama::Template;
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Response},
+};
+
+use crate::{
+    filters,
+    flash::{FlashMessage, Flasher, Flashes},
+    headers::ClientIp,
+    models::Account,
+    site::trash::{self, TrashItem, TrashKind},
+    AppState,
+};
+
+#[derive(Template)]
+#[template(path = "auth/account/trash.html")]
+struct TrashTemplate {
+    account: Option<Account>,
+    flashes: Flashes,
+    active_page: &'static str,
+    items: Vec<TrashItem>,
+    retention_days: i64,
+}
+
+pub async fn page(State(state): State<AppState>, flashes: Flashes, account: Account) -> Response {
+    let items = trash::list(&state, account.id, &TrashKind::ALL)
+        .await
+        .unwrap_or_default();
+    TrashTemplate {
+        active_page: "trash",
+        flashes,
+        items,
+        retention_days: trash::retention_days(&state),
+        account: Some(account),
+    }
+    .into_response()
+}
+
+pub async fn restore(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    account: Account,
+    flasher: Flasher,
+    Path((kind, id)): Path<(TrashKind, String)>,
+) -> Response {
+    match trash::restore(&state, kind, account.id, &id).await {
+        Ok(true) => {
+            state
+                .audit("trash.restore")
+                .actor(&account)
+                .target(id)
+                .ip_opt(client_ip)
+                .meta(serde_json::json!({ "kind": kind }))
+                .fire();
+            flasher
+                .add(FlashMessage::success(format!("Restored the {}.", kind.as_str())))
+                .bail("/account/trash")
+        }
+        Ok(false) => flasher
+            .add(FlashMessage::error("That is no longer in your trash."))
+            .bail("/account/trash"),
+        Err(e) => {
+            tracing::warn!(error = %e, "failed to restore from the trash");
+            flasher
+                .add(FlashMessage::error("Could not restore that item."))
+                .bail("/account/trash")
+        }
+    }
+}
+
+pub async fn purge(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    account: Account,
+    flasher: Flasher,
+    Path((kind, id)): Path<(TrashKind, String)>,
+) -> Response {
+    match trash::purge(&state, kind, account.id, &id).await {
+        Ok(true) => {
+            state
+                .audit("trash.purge")
+                .actor(&account)
+                .target(id)
+                .ip_opt(client_ip)
+                .meta(serde_json::json!({ "kind": kind }))
+                .fire();
+            flasher
+                .add(FlashMessage::success(format!(
+                    "Deleted the {} for good.",
+                    kind.as_str()
+                )))
+                .bail("/account/trash")
+        }
+        Ok(false) => flasher
+            .add(FlashMessage::error("That is no longer in your trash."))
+            .bail("/account/trash"),
+        Err(e) => {
+            tracing::warn!(error = %e, "failed to purge from the trash");
+            flasher
+                .add(FlashMessage::error("Could not delete that item."))
+                .bail("/account/trash")
+        }
+    }
+}
+
+pub async fn empty(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    account: Account,
+    flasher: Flasher,
+) -> Response {
+    match trash::empty(&state, account.id, &TrashKind::ALL).await {
+        Ok(count) => {
+            state
+                .audit("trash.empty")
+                .actor(&account)
+                .ip_opt(client_ip)
+                .meta(serde_json::json!({ "count": count }))
+                .fire();
+            flasher
+                .add(FlashMessage::success(format!(
+                    "Deleted {count} item{} for good.",
+                    if count == 1 { "" } else { "s" }
+                )))
+                .bail("/account/trash")
+        }
+        Err(e) => {
+            tracing::warn!(error = %e, "failed to empty the trash");
+            flasher
+                .add(FlashMessage::error("Could not empty the trash."))
+                .bail("/account/trash")
+        }
+    }
+}
@@ -0,0 +1,777 @@
+//! Image albums: named, ordered collections of one account's images with an
+//! optional cover, viewable at `/album/<id>` and managed over the JSON API
+//! ([`crate::site::api::albums`]).
+//!
+//! Like the paste core, every album read and write goes through this module, so
+//! the ownership and visibility rules live in exactly one place. An album only
+//! ever holds images its owner uploaded; membership and order live in
+//! `album_image` (see `sql/4.sql`), which cascades away when an image is
+//! deleted, so nothing else in the image code needs to know albums exist.
+
+use std::collections::HashSet;
+use std::net::IpAddr;
+
+use askama::Template;
+use axum::{
+    extract::{Path, State},
+    response::{IntoResponse, Redirect, Response},
+    routing::get,
+    Router,
+};
+
+use crate::error::InternalError;
+use crate::filters;
+use crate::flash::Flashes;
+use crate::models::{Account, Album, ImageEntry, Visibility};
+use crate::utils::get_new_image_id;
+use crate::{boxed_params, AppState};
+
+/// Maximum length of an album title.
+pub const MAX_TITLE_LEN: usize = 120;
+/// Maximum length of an album description.
+pub const MAX_DESCRIPTION_LEN: usize = 2000;
+/// Maximum number of images one album may hold.
+pub const MAX_ALBUM_IMAGES: i64 = 500;
+
+// ─── Errors ──────────────────────────────────────────────────────────────────
+
+/// Every way an album operation can be refused.
+#[derive(Debug, Clone)]
+pub enum AlbumError {
+    /// No such album, or it isn't yours — deliberately the same answer.
+    NotFound,
+    /// The title was empty or whitespace-only.
+    TitleEmpty,
+    /// The title exceeded [`MAX_TITLE_LEN`].
+    TitleTooLong,
+    /// The description exceeded [`MAX_DESCRIPTION_LEN`].
+    DescriptionTooLong,
+    /// An image id that is not one of the owner's (live) images.
+    ImageNotFound(String),
+    /// An image id that is not in this album.
+    NotInAlbum(String),
+    /// A reorder that didn't list every image in the album exactly once.
+    BadOrder,
+    /// Adding the images would exceed [`MAX_ALBUM_IMAGES`].
+    Full,
+    /// The database refused.
+    Db,
+}
+
+impl AlbumError {
+    /// A message safe to show the owner.
+    pub fn message(&self) -> String {
+        match self {
+            Self::NotFound => "Album not found.".to_string(),
+            Self::TitleEmpty => "The album needs a title.".to_string(),
+            Self::TitleTooLong => format!("Title is too long (max {MAX_TITLE_LEN} characters)."),
+            Self::DescriptionTooLong => format!("Description is too long (max {MAX_DESCRIPTION_LEN} characters)."),
+            Self::ImageNotFound(id) => format!("Image `{id}` was not found."),
+            Self::NotInAlbum(id) => format!("Image `{id}` is not in this album."),
+            Self::BadOrder => "The new order must list every image in the album exactly once.".to_string(),
+            Self::Full => format!("An album can hold at most {MAX_ALBUM_IMAGES} images."),
+            Self::Db => "Could not save the album. Please try again.".to_string(),
+        }
+    }
+}
+
+fn db_error(e: impl std::fmt::Display) -> AlbumError {
+    tracing::error!(error = %e, "album query failed");
+    AlbumError::Db
+}
+
+// ─── Writes ──────────────────────────────────────────────────────────────────
+
+/// A new album, as submitted.
+#[derive(Debug, Default)]
+pub struct NewAlbum {
+    pub title: String,
+    pub description: Option<String>,
+    pub visibility: Visibility,
+}
+
+/// Changes to an album's details. `None` leaves a field as it is; an empty
+/// `description` or `cover_id` clears it.
+#[derive(Debug, Default)]
+pub struct EditAlbum {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    pub visibility: Option<Visibility>,
+    pub cover_id: Option<String>,
+}
+
+/// Creates an empty album owned by `account`.
+pub async fn create(
+    state: &AppState,
+    account: &Account,
+    client_ip: Option<IpAddr>,
+    new: NewAlbum,
+) -> Result<Album, AlbumError> {
+    let title = normalize_title(&new.title)?;
+    let description = normalize_description(new.description)?;
+
+    let id = get_new_image_id();
+    let album: Album = state
+        .database()
+        .get(
+            "INSERT INTO album (id, account_id, title, description, visibility) \
+             VALUES (?1, ?2, ?3, ?4, ?5) RETURNING *",
+            boxed_params![id, account.id, title, description, new.visibility],
+        )
+        .await
+        .map_err(db_error)?
+        .ok_or(AlbumError::Db)?;
+
+    state
+        .audit("album.create")
+        .actor(account)
+        .target(album.id.clone())
+        .ip_opt(client_ip)
+        .meta(serde_json::json!({ "visibility": album.visibility }))
+        .fire();
+    Ok(album)
+}
+
+/// Applies `edit` to an album the account owns.
+pub async fn edit(
+    state: &AppState,
+    album: &Album,
+    account: &Account,
+    client_ip: Option<IpAddr>,
+    edit: EditAlbum,
+) -> Result<Album, AlbumError> {
+    if !album.owned_by(account) {
+        return Err(AlbumError::NotFound);
+    }
+
+    let title = match edit.title.as_deref() {
+        Some(title) => normalize_title(title)?,
+        None => album.title.clone(),
+    };
+    let description = match edit.description {
+        Some(description) => normalize_description(Some(description))?,
+        None => album.description.clone(),
+    };
+    let visibility = edit.visibility.unwrap_or(album.visibility);
+    let cover_id = match edit.cover_id.as_deref().map(str::trim) {
+        Some("") => None,
+        Some(cover) => {
+            let cover = strip_extension(cover);
+            if !image_ids(state, &album.id).await?.contains(cover) {
+                return Err(AlbumError::NotInAlbum(cover.to_string()));
+            }
+            Some(cover.to_string())
+        }
+        None => album.cover_id.clone(),
+    };
+
+    let updated: Album = state
+        .database()
+        .get(
+            "UPDATE album SET title = ?1, description = ?2, visibility = ?3, cover_id = ?4, \
+             updated_at = CURRENT_TIMESTAMP WHERE id = ?5 RETURNING *",
+            boxed_params![title, description, visibility, cover_id, album.id.clone()],
+        )
+        .await
+        .map_err(db_error)?
+        .ok_or(AlbumError::NotFound)?;
+
+    state
+        .audit("album.edit")
+        .actor(account)
+        .target(album.id.clone())
+        .ip_opt(client_ip)
+        .fire();
+    Ok(updated)
+}
+
+/// Deletes an album. Its images are untouched; the memberships go with it via
+/// `ON DELETE CASCADE`.
+pub async fn delete(
+    state: &AppState,
+    album: &Album,
+    account: &Account,
+    client_ip: Option<IpAddr>,
+) -> Result<(), AlbumError> {
+    if !album.owned_by(account) {
+        return Err(AlbumError::NotFound);
+    }
+
+    state
+        .database()
+        .execute("DELETE FROM album WHERE id = ?1", [album.id.clone()])
+        .await
+        .map_err(db_error)?;
+
+    state
+        .audit("album.delete")
+        .actor(account)
+        .target(album.id.clone())
+        .ip_opt(client_ip)
+        .fire();
+    Ok(())
+}
+
+/// Appends images to the end of an album, in the order given, returning how
+/// many were newly added. Ids may carry their extension (`abc.png`). Images
+/// already in the album are left where they are.
+///
+/// Every id must be one of the album owner's live images; the first that isn't
+/// refuses the whole request, so a typo never half-applies.
+pub async fn add_images(
+    state: &AppState,
+    album: &Album,
+    account: &Account,
+    client_ip: Option<IpAddr>,
+    ids: &[String],
+) -> Result<usize, AlbumError> {
+    if !album.owned_by(account) {
+        return Err(AlbumError::NotFound);
+    }
+
+    let present = image_ids(state, &album.id).await?;
+    let mut seen = HashSet::new();
+    let mut wanted = Vec::new();
+    for raw in ids {
+        let id = strip_extension(raw.trim()).to_string();
+        if present.contains(&id) || !seen.insert(id.clone()) {
+            continue;
+        }
+        if !is_live_image_of(state, &id, album.account_id).await? {
+            return Err(AlbumError::ImageNotFound(id));
+        }
+        wanted.push(id);
+    }
+
+    if wanted.is_empty() {
+        return Ok(0);
+    }
+    if present.len() as i64 + wanted.len() as i64 > MAX_ALBUM_IMAGES {
+        return Err(AlbumError::Full);
+    }
+
+    let album_id = album.id.clone();
+    let added = wanted.len();
+    let audit_ids = wanted.clone();
+    state
+        .database()
+        .call(move |conn| {
+            let tx = conn.transaction()?;
+            {
+                let mut stmt = tx.prepare(
+                    "INSERT OR IGNORE INTO album_image (album_id, image_id, position) \
+                     VALUES (?1, ?2, (SELECT COALESCE(MAX(position), -1) + 1 FROM album_image WHERE album_id = ?1))",
+                )?;
+                for id in &wanted {
+                    stmt.execute((&album_id, id))?;
+                }
+            }
+            tx.execute(
+                "UPDATE album SET updated_at = CURRENT_TIMESTAMP WHERE id = ?1",
+                [&album_id],
+            )?;
+            tx.commit()
+        })
+        .await
+        .map_err(db_error)?;
+
+    state
+        .audit("album.images.add")
+        .actor(account)
+        .target(album.id.clone())
+        .ip_opt(client_ip)
+        .meta(serde_json::json!({ "images": audit_ids }))
+        .fire();
+    Ok(added)
+}
+
+/// Takes one image out of an album (the image itself is kept). If it was the
+/// cover, the album falls back to its first image.
+pub async fn remove_image(
+    state: &AppState,
+    album: &Album,
+    account: &Account,
+    client_ip: Option<IpAddr>,
+    image_id: &str,
+) -> Result<(), AlbumError> {
+    if !album.owned_by(account) {
+        return Err(AlbumError::NotFound);
+    }
+
+    let image_id = strip_extension(image_id.trim()).to_string();
+    let album_id = album.id.clone();
+    let id = image_id.clone();
+    let removed = state
+        .database()
+        .call(move |conn| {
+            let tx = conn.transaction()?;
+            let removed = tx.execute(
+                "DELETE FROM album_image WHERE album_id = ?1 AND image_id = ?2",
+                (&album_id, &id),
+            )?;
+            tx.execute(
+                "UPDATE album SET cover_id = CASE WHEN cover_id = ?2 THEN NULL ELSE cover_id END, \
+                 updated_at = CURRENT_TIMESTAMP WHERE id = ?1",
+                (&album_id, &id),
+            )?;
+            tx.commit()?;
+            Ok(removed)
+        })
+        .await
+        .map_err(db_error)?;
+
+    if removed == 0 {
+        return Err(AlbumError::NotInAlbum(image_id));
+    }
+
+    state
+        .audit("album.images.remove")
+        .actor(account)
+        .target(album.id.clone())
+        .ip_opt(client_ip)
+        .meta(serde_json::json!({ "image": image_id }))
+        .fire();
+    Ok(())
+}
+
+/// Puts an album's images in the order given. `order` must name every image in
+/// the album exactly once — anything else is [`AlbumError::BadOrder`], so a
+/// client working from a stale listing can't silently drop or duplicate one.
+pub async fn reorder(
+    state: &AppState,
+    album: &Album,
+    account: &Account,
+    client_ip: Option<IpAddr>,
+    order: &[String],
+) -> Result<(), AlbumError> {
+    if !album.owned_by(account) {
+        return Err(AlbumError::NotFound);
+    }
+
+    let order: Vec<String> = order.iter().map(|id| strip_extension(id.trim()).to_string()).collect();
+    let present = image_ids(state, &album.id).await?;
+    let listed: HashSet<&String> = order.iter().collect();
+    if order.len() != present.len() || listed.len() != order.len() || !order.iter().all(|id| present.contains(id)) {
+        return Err(AlbumError::BadOrder);
+    }
+
+    let album_id = album.id.clone();
+    state
+        .database()
+        .call(move |conn| {
+            let tx = conn.transaction()?;
+            {
+                let mut stmt =
+                    tx.prepare("UPDATE album_image SET position = ?1 WHERE album_id = ?2 AND image_id = ?3")?;
+                for (position, id) in order.iter().enumerate() {
+                    stmt.execute((position as i64, &album_id, id))?;
+                }
+            }
+            tx.execute(
+                "UPDATE album SET updated_at = CURRENT_TIMESTAMP WHERE id = ?1",
+                [&album_id],
+            )?;
+            tx.commit()
+        })
+        .await
+        .map_err(db_error)?;
+
+    state
+        .audit("album.images.reorder")
+        .actor(account)
+        .target(album.id.clone())
+        .ip_opt(client_ip)
+        .fire();
+    Ok(())
+}
+
+// ─── Reads ───────────────────────────────────────────────────────────────────
+
+/// Loads an album by id.
+pub async fn load(state: &AppState, id: &str) -> Option<Album> {
+    state
+        .database()
+        .get("SELECT * FROM album WHERE id = ?1", [id.to_string()])
+        .await
+        .ok()
+        .flatten()
+}
+
+/// Loads an album only if `account` owns it. "Not yours" and "doesn't exist"
+/// are both [`AlbumError::NotFound`].
+pub async fn load_for(state: &AppState, id: &str, account: &Account) -> Result<Album, AlbumError> {
+    let album = load(state, id).await.ok_or(AlbumError::NotFound)?;
+    if !album.owned_by(account) {
+        return Err(AlbumError::NotFound);
+    }
+    Ok(album)
+}
+
+/// The account's `public` albums, newest first — the only ones listed on
+/// `/user/:name`.
+pub async fn list_public(state: &AppState, account_id: i64, limit: i64) -> Vec<Album> {
+    state
+        .database()
+        .all(
+            "SELECT * FROM album WHERE account_id = ?1 AND visibility = 'public' \
+             ORDER BY created_at DESC LIMIT ?2",
+            (account_id, limit),
+        )
+        .await
+        .unwrap_or_default()
+}
+
+/// An album's live images, in album order. Rows carry no bytes.
+pub async fn images(state: &AppState, album_id: &str) -> Vec<ImageEntry> {
+    state
+        .database()
+        .all(
+            "SELECT i.id, i.mimetype, i.size, X'' AS image_data, i.uploaded_at, i.uploader_id, \
+                    i.expires_at, i.original_name, i.views, i.visibility, i.password_hash, i.burn_after_views, \
+                    i.scan_status \
+             FROM album_image a JOIN images i ON i.id = a.image_id \
+             WHERE a.album_id = ?1 AND i.deleted_at IS NULL \
+               AND (i.expires_at IS NULL OR datetime(i.expires_at) > datetime('now')) \
+             ORDER BY a.position, a.added_at",
+            [album_id.to_string()],
+        )
+        .await
+        .unwrap_or_default()
+}
+
+/// The image to show for an album: its chosen cover, else its first image.
+pub fn cover<'a>(album: &Album, images: &'a [ImageEntry]) -> Option<&'a ImageEntry> {
+    album
+        .cover_id
+        .as_deref()
+        .and_then(|cover| images.iter().find(|i| i.id == cover))
+        .or_else(|| images.first())
+}
+
+/// The ids of every image in an album, expired or not.
+async fn image_ids(state: &AppState, album_id: &str) -> Result<HashSet<String>, AlbumError> {
+    let album_id = album_id.to_string();
+    state
+        .database()
+        .call(move |conn| {
+            let mut stmt = conn.prepare("SELECT image_id FROM album_image WHERE album_id = ?1")?;
+            let ids: rusqlite::Result<HashSet<String>> = stmt.query_map([album_id], |row| row.get(0))?.collect();
+            ids
+        })
+        .await
+        .map_err(db_error)
+}
+
+/// Whether `id` is an unexpired image uploaded by `account_id`.
+async fn is_live_image_of(state: &AppState, id: &str, account_id: i64) -> Result<bool, AlbumError> {
+    state
+        .database()
+        .get_row(
+            "SELECT EXISTS(SELECT 1 FROM images WHERE id = ?1 AND uploader_id = ?2 AND deleted_at IS NULL \
+             AND (expires_at IS NULL OR datetime(expires_at) > datetime('now')))",
+            (id.to_string(), account_id),
+            |row| row.get::<_, bool>(0),
+        )
+        .await
+        .map_err(db_error)
+}
+
+fn normalize_title(title: &str) -> Result<String, AlbumError> {
+    let title = title.trim();
+    if title.is_empty() {
+        return Err(AlbumError::TitleEmpty);
+    }
+    if title.chars().count() > MAX_TITLE_LEN {
+        return Err(AlbumError::TitleTooLong);
+    }
+    Ok(title.to_string())
+}
+
+fn normalize_description(description: Option<String>) -> Result<Option<String>, AlbumError> {
+    let Some(description) = description.map(|d| d.trim().to_string()).filter(|d| !d.is_empty()) else {
+        return Ok(None);
+    };
+    if description.chars().count() > MAX_DESCRIPTION_LEN {
+        return Err(AlbumError::DescriptionTooLong);
+    }
+    Ok(Some(description))
+}
+
+/// `abc.png` → `abc`, matching how the gallery routes accept image ids.
+fn strip_extension(id: &str) -> &str {
+    id.split('.').next().unwrap_or(id)
+}
+
+// ─── Page ────────────────────────────────────────────────────────────────────
+
+#[derive(Template)]
+#[template(path = "images/album.html")]
+struct AlbumTemplate {
+    account: Option<Account>,
+    flashes: Flashes,
+    album: Album,
+    images: Vec<ImageEntry>,
+    /// The cover's raw URL, for the link-preview image.
+    cover_url: Option<String>,
+    url: String,
+    /// The owner's username, when the account still exists.
+    owner: Option<String>,
+    /// True when the viewer owns the album.
+    is_owner: bool,
+}
+
+/// `GET /album/:id` — the public album page. A private album is a 404 for
+/// everyone but its owner; an unlisted one is `noindex`.
+async fn album_page(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    account: Option<Account>,
+    flashes: Flashes,
+) -> Result<Response, InternalError> {
+    let Some(album) = load(&state, &id).await else {
+        return Ok(Redirect::to("/").into_response());
+    };
+    if !album.visible_to(account.as_ref()) {
+        return Ok(Redirect::to("/").into_response());
+    }
+
+    let is_owner = account.as_ref().is_some_and(|a| album.owned_by(a));
+    let mut images = images(&state, &album.id).await;
+    // Visitors only get the images anyone with a link could open. A private,
+    // password-protected, burning or scan-held one would be a broken tile at
+    // best, and must not end up as the cover either.
+    if !is_owner {
+        images.retain(ImageEntry::is_open);
+    }
+    let cover_url =
+        cover(&album, &images).map(|img| state.config().url_to(format!("/gallery/raw/{}.{}", img.id, img.ext())));
+    let owner = state
+        .database()
+        .get_row("SELECT name FROM account WHERE id = ?1", [album.account_id], |row| {
+            row.get::<_, String>(0)
+        })
+        .await
+        .ok();
+
+    Ok(AlbumTemplate {
+        is_owner,
+        url: state.config().url_to(format!("/album/{}", album.id)),
+        account,
+        flashes,
+        album,
+        images,
+        cover_url,
+        owner,
+    }
+    .into_response())
+}
+
+pub fn routes() -> Router<AppState> {
+    Router::new().route("/album/:id", get(album_page))
+}
+
+#[cfg(test)]
+mod tests {
+    use time::OffsetDateTime;
+
+    use super::*;
+
+    async fn state_with_images(ids: &[&str]) -> (AppState, Account) {
+        let db = crate::Database::file(":memory:")
+            .connections(1)
+            .with_init(crate::migrations::migrate)
+            .open()
+            .await
+            .unwrap();
+        db.execute("INSERT INTO account (id, name, password) VALUES (1, 'owner', 'x')", [])
+            .await
+            .unwrap();
+        db.execute("INSERT INTO account (id, name, password) VALUES (2, 'other', 'x')", [])
+            .await
+            .unwrap();
+        for id in ids {
+            db.execute(
+                "INSERT INTO images (id, mimetype, uploader_id) VALUES (?1, 'image/png', 1)",
+                [id.to_string()],
+            )
+            .await
+            .unwrap();
+        }
+        let account: Account = db.get("SELECT * FROM account WHERE id = 1", []).await.unwrap().unwrap();
+        (AppState::for_tests(db).await, account)
+    }
+
+    fn order(state_images: &[ImageEntry]) -> Vec<&str> {
+        state_images.iter().map(|i| i.id.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn images_keep_their_order_and_the_cover_falls_back() {
+        let (state, account) = state_with_images(&["a", "b", "c"]).await;
+        let album = create(
+            &state,
+            &account,
+            None,
+            NewAlbum {
+                title: "  Holiday ".into(),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(album.title, "Holiday");
+        assert_eq!(album.visibility, Visibility::Unlisted);
+
+        let ids = ["b.png".to_string(), "a".to_string(), "b".to_string()];
+        assert_eq!(add_images(&state, &album, &account, None, &ids).await.unwrap(), 2);
+        assert_eq!(order(&images(&state, &album.id).await), ["b", "a"]);
+
+        reorder(&state, &album, &account, None, &["a".into(), "b".into()])
+            .await
+            .unwrap();
+        let listed = images(&state, &album.id).await;
+        assert_eq!(order(&listed), ["a", "b"]);
+        assert_eq!(cover(&album, &listed).map(|i| i.id.as_str()), Some("a"));
+
+        let album = edit(
+            &state,
+            &album,
+            &account,
+            None,
+            EditAlbum {
+                cover_id: Some("b".into()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(cover(&album, &listed).map(|i| i.id.as_str()), Some("b"));
+
+        // Deleting the cover image drops it from the album and clears the cover.
+        state
+            .database()
+            .execute("DELETE FROM images WHERE id = 'b'", [])
+            .await
+            .unwrap();
+        let album = load(&state, &album.id).await.unwrap();
+        assert_eq!(album.cover_id, None);
+        assert_eq!(order(&images(&state, &album.id).await), ["a"]);
+    }
+
+    #[tokio::test]
+    async fn a_held_cover_is_left_out_for_visitors() {
+        let (state, account) = state_with_images(&["a", "b"]).await;
+        let album = create(
+            &state,
+            &account,
+            None,
+            NewAlbum {
+                title: "Scanned".into(),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        add_images(&state, &album, &account, None, &["a".into(), "b".into()])
+            .await
+            .unwrap();
+        let album = edit(
+            &state,
+            &album,
+            &account,
+            None,
+            EditAlbum {
+                cover_id: Some("b".into()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        // The chosen cover is still waiting on the malware scan.
+        state
+            .database()
+            .execute("UPDATE images SET scan_status = 'pending' WHERE id = 'b'", [])
+            .await
+            .unwrap();
+
+        let mut listed = images(&state, &album.id).await;
+        assert!(listed.iter().any(|i| i.id == "b" && i.is_held()));
+        listed.retain(ImageEntry::is_open);
+        assert_eq!(order(&listed), ["a"]);
+        assert_eq!(cover(&album, &listed).map(|i| i.id.as_str()), Some("a"));
+    }
+
+    #[tokio::test]
+    async fn refuses_foreign_images_and_partial_orders() {
+        let (state, account) = state_with_images(&["a", "b"]).await;
+        state
+            .database()
+            .execute(
+                "INSERT INTO images (id, mimetype, uploader_id) VALUES ('theirs', 'image/png', 2)",
+                [],
+            )
+            .await
+            .unwrap();
+        let album = create(
+            &state,
+            &account,
+            None,
+            NewAlbum {
+                title: "Mine".into(),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        let result = add_images(&state, &album, &account, None, &["a".into(), "theirs".into()]).await;
+        assert!(matches!(result, Err(AlbumError::ImageNotFound(id)) if id == "theirs"));
+        assert!(
+            images(&state, &album.id).await.is_empty(),
+            "a refused add applies nothing"
+        );
+
+        add_images(&state, &album, &account, None, &["a".into(), "b".into()])
+            .await
+            .unwrap();
+        let result = reorder(&state, &album, &account, None, &["b".into()]).await;
+        assert!(matches!(result, Err(AlbumError::BadOrder)));
+        let result = reorder(&state, &album, &account, None, &["a".into(), "a".into()]).await;
+        assert!(matches!(result, Err(AlbumError::BadOrder)));
+    }
+
+    #[test]
+    fn private_albums_are_only_visible_to_their_owner() {
+        let owner = Account {
+            id: 1,
+            name: "owner".into(),
+            password: String::new(),
+            flags: Default::default(),
+            created_at: OffsetDateTime::UNIX_EPOCH,
+            totp_secret: None,
+            totp_enabled: false,
+            discord_id: None,
+        };
+        let stranger = Account {
+            id: 2,
+            name: "stranger".into(),
+            ..owner.clone()
+        };
+        let mut album = Album {
+            id: "x".into(),
+            account_id: 1,
+            title: "t".into(),
+            description: None,
+            visibility: Visibility::Unlisted,
+            cover_id: None,
+            created_at: OffsetDateTime::UNIX_EPOCH,
+            updated_at: OffsetDateTime::UNIX_EPOCH,
+        };
+        assert!(album.visible_to(None));
+
+        album.visibility = Visibility::Private;
+        assert!(!album.visible_to(None));
+        assert!(!album.visible_to(Some(&stranger)));
+        assert!(album.visible_to(Some(&owner)));
+    }
+}
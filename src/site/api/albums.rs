@@ -0,0 +1,468 @@
+//! Public API for image albums.
+//!
+//! Create, list, read, edit and delete albums, and add, reorder and remove the
+//! images in them, gated by the `albums:read` / `albums:write` scopes. Albums
+//! are viewable without auth at `/album/<id>` (unless private) — see
+//! [`crate::site::album`], which owns every rule; this module is the HTTP shell.
+
+use axum::extract::{Path, Query, State};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+use super::{
+    auth::ApiToken,
+    utils::{ApiJson as Json, Page, RateLimitResponse},
+};
+use crate::site::album::{self, AlbumError, EditAlbum, NewAlbum};
+use crate::{
+    error::ApiError,
+    headers::ClientIp,
+    models::{Album, ImageEntry, Scope, Visibility},
+    AppState,
+};
+
+/// An image as listed in an album.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiAlbumImage {
+    /// The image's id.
+    pub id: String,
+    /// The image's landing-page URL.
+    pub url: String,
+    /// The image's raw URL.
+    pub raw_url: String,
+}
+
+/// An album as returned by the API.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ApiAlbum {
+    /// The album's short id.
+    pub id: String,
+    /// The public album page (`/album/{id}`).
+    pub url: String,
+    /// The album's title.
+    pub title: String,
+    /// The album's description, if it has one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// `public`, `unlisted` (the default) or `private`. Public albums are
+    /// listed on your profile; private ones are only viewable by you.
+    pub visibility: Visibility,
+    /// The id of the image chosen as the cover, if one was picked. Without one
+    /// the first image is used.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cover_id: Option<String>,
+    /// The raw URL of the effective cover image, if the album has any images.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cover_url: Option<String>,
+    /// How many (unexpired) images the album holds.
+    pub image_count: usize,
+    /// The album's images, in order. Omitted from list responses.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub images: Option<Vec<ApiAlbumImage>>,
+    /// Creation timestamp (RFC 3339).
+    pub created_at: String,
+    /// Last-change timestamp (RFC 3339).
+    pub updated_at: String,
+}
+
+fn rfc3339(t: OffsetDateTime) -> String {
+    t.format(&time::format_description::well_known::Rfc3339)
+        .unwrap_or_default()
+}
+
+impl ApiAlbum {
+    fn new(state: &AppState, album: Album, images: Vec<ImageEntry>, with_images: bool) -> Self {
+        let config = state.config();
+        let raw_url = |img: &ImageEntry| config.url_to(format!("/gallery/raw/{}.{}", img.id, img.ext()));
+        let cover_url = album::cover(&album, &images).map(raw_url);
+        Self {
+            url: config.url_to(format!("/album/{}", album.id)),
+            cover_url,
+            image_count: images.len(),
+            images: with_images.then(|| {
+                images
+                    .iter()
+                    .map(|img| ApiAlbumImage {
+                        id: img.id.clone(),
+                        url: config.url_to(format!("/gallery/{}.{}", img.id, img.ext())),
+                        raw_url: raw_url(img),
+                    })
+                    .collect()
+            }),
+            id: album.id,
+            title: album.title,
+            description: album.description,
+            visibility: album.visibility,
+            cover_id: album.cover_id,
+            created_at: rfc3339(album.created_at),
+            updated_at: rfc3339(album.updated_at),
+        }
+    }
+
+    /// Loads the album's images and builds the full response.
+    async fn load(state: &AppState, album: Album) -> Self {
+        let images = album::images(state, &album.id).await;
+        Self::new(state, album, images, true)
+    }
+}
+
+/// Maps a service refusal onto the API's error shape.
+fn api_error(error: AlbumError) -> ApiError {
+    match error {
+        AlbumError::NotFound => ApiError::not_found(error.message()),
+        AlbumError::TitleEmpty | AlbumError::TitleTooLong => ApiError::validation("title", error.message()),
+        AlbumError::DescriptionTooLong => ApiError::validation("description", error.message()),
+        AlbumError::ImageNotFound(_) | AlbumError::BadOrder | AlbumError::Full => {
+            ApiError::validation("images", error.message())
+        }
+        AlbumError::NotInAlbum(_) => ApiError::not_found(error.message()),
+        AlbumError::Db => ApiError::new(error.message()),
+    }
+}
+
+/// Body of a create-album request.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateAlbumBody {
+    /// The album's title (≤120 characters).
+    pub title: String,
+    /// Optional description (≤2000 characters).
+    #[serde(default)]
+    pub description: Option<String>,
+    /// `public`, `unlisted` (the default) or `private`.
+    #[serde(default)]
+    pub visibility: Option<Visibility>,
+}
+
+/// Body of an edit-album request. Omitted fields are left unchanged.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct UpdateAlbumBody {
+    /// The new title.
+    #[serde(default)]
+    pub title: Option<String>,
+    /// The new description. An empty string removes it.
+    #[serde(default)]
+    pub description: Option<String>,
+    /// The new visibility.
+    #[serde(default)]
+    pub visibility: Option<Visibility>,
+    /// The id of an image in the album to use as the cover. An empty string
+    /// goes back to using the first image.
+    #[serde(default)]
+    pub cover_id: Option<String>,
+}
+
+/// A list of image ids, for adding to or reordering an album.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct AlbumImagesBody {
+    /// Image ids, with or without their extension (`abc12` or `abc12.png`).
+    pub images: Vec<String>,
+}
+
+/// Create an album
+#[utoipa::path(
+    post,
+    path = "/albums",
+    request_body(content = CreateAlbumBody, content_type = "application/json"),
+    responses(
+        (status = 200, description = "The created album", body = ApiAlbum),
+        (status = 400, description = "Missing or oversized title or description", body = ApiError),
+        (status = 401, description = "Unauthenticated", body = ApiError),
+        (status = 403, description = "Missing the albums:write scope", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(("api_key" = ["albums:write"])),
+    tag = "albums"
+)]
+pub async fn create_album(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    auth: ApiToken,
+    Json(body): Json<CreateAlbumBody>,
+) -> Result<Json<ApiAlbum>, ApiError> {
+    let account = auth.require_account(&state, Scope::AlbumsWrite).await?;
+
+    let album = album::create(
+        &state,
+        &account,
+        client_ip,
+        NewAlbum {
+            title: body.title,
+            description: body.description,
+            visibility: body.visibility.unwrap_or_default(),
+        },
+    )
+    .await
+    .map_err(api_error)?;
+    Ok(Json(ApiAlbum::new(&state, album, Vec::new(), true)))
+}
+
+/// List albums
+///
+/// Lists the authenticated account's albums, newest first, with Discord-style
+/// cursor pagination. Images are not included; fetch an album for those.
+#[utoipa::path(
+    get,
+    path = "/albums",
+    params(Page),
+    responses(
+        (status = 200, description = "The account's albums", body = [ApiAlbum]),
+        (status = 401, description = "Unauthenticated", body = ApiError),
+        (status = 403, description = "Missing the albums:read scope", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(("api_key" = ["albums:read"])),
+    tag = "albums"
+)]
+pub async fn list_albums(
+    State(state): State<AppState>,
+    Query(page): Query<Page>,
+    auth: ApiToken,
+) -> Result<Json<Vec<ApiAlbum>>, ApiError> {
+    let account = auth.require_account(&state, Scope::AlbumsRead).await?;
+
+    let limit = page.effective_limit() as i64;
+    // Same keyset shape as the links listing: an unknown/foreign cursor is
+    // forgiven (behaves as "no bound").
+    let albums: Vec<Album> = state
+        .database()
+        .all(
+            "SELECT * FROM album \
+             WHERE account_id = ?1 \
+               AND (?2 IS NULL \
+                    OR NOT EXISTS (SELECT 1 FROM album WHERE id = ?2 AND account_id = ?1) \
+                    OR created_at < (SELECT created_at FROM album WHERE id = ?2 AND account_id = ?1)) \
+               AND (?3 IS NULL \
+                    OR NOT EXISTS (SELECT 1 FROM album WHERE id = ?3 AND account_id = ?1) \
+                    OR created_at > (SELECT created_at FROM album WHERE id = ?3 AND account_id = ?1)) \
+             ORDER BY created_at DESC \
+             LIMIT ?4",
+            (account.id, page.after.clone(), page.before.clone(), limit),
+        )
+        .await
+        .map_err(|_| ApiError::new("failed to list albums"))?;
+
+    let mut out = Vec::with_capacity(albums.len());
+    for album in albums {
+        let images = album::images(&state, &album.id).await;
+        out.push(ApiAlbum::new(&state, album, images, false));
+    }
+    Ok(Json(out))
+}
+
+/// Get an album
+#[utoipa::path(
+    get,
+    path = "/albums/{id}",
+    params(("id" = String, Path, description = "The album's id.")),
+    responses(
+        (status = 200, description = "The album, with its images in order", body = ApiAlbum),
+        (status = 401, description = "Unauthenticated", body = ApiError),
+        (status = 403, description = "Missing the albums:read scope", body = ApiError),
+        (status = 404, description = "No such album owned by this account", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(("api_key" = ["albums:read"])),
+    tag = "albums"
+)]
+pub async fn get_album(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    auth: ApiToken,
+) -> Result<Json<ApiAlbum>, ApiError> {
+    let account = auth.require_account(&state, Scope::AlbumsRead).await?;
+
+    let album = album::load_for(&state, &id, &account).await.map_err(api_error)?;
+    Ok(Json(ApiAlbum::load(&state, album).await))
+}
+
+/// Edit an album
+///
+/// Renames an album, changes its description or visibility, or picks its
+/// cover. Omitted fields are left as they are.
+#[utoipa::path(
+    patch,
+    path = "/albums/{id}",
+    params(("id" = String, Path, description = "The album's id.")),
+    request_body(content = UpdateAlbumBody, content_type = "application/json"),
+    responses(
+        (status = 200, description = "The updated album", body = ApiAlbum),
+        (status = 400, description = "Missing or oversized title or description", body = ApiError),
+        (status = 401, description = "Unauthenticated", body = ApiError),
+        (status = 403, description = "Missing the albums:write scope", body = ApiError),
+        (status = 404, description = "No such album, or the cover is not in it", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(("api_key" = ["albums:write"])),
+    tag = "albums"
+)]
+pub async fn update_album(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    Path(id): Path<String>,
+    auth: ApiToken,
+    Json(body): Json<UpdateAlbumBody>,
+) -> Result<Json<ApiAlbum>, ApiError> {
+    let account = auth.require_account(&state, Scope::AlbumsWrite).await?;
+
+    let album = album::load_for(&state, &id, &account).await.map_err(api_error)?;
+    let album = album::edit(
+        &state,
+        &album,
+        &account,
+        client_ip,
+        EditAlbum {
+            title: body.title,
+            description: body.description,
+            visibility: body.visibility,
+            cover_id: body.cover_id,
+        },
+    )
+    .await
+    .map_err(api_error)?;
+    Ok(Json(ApiAlbum::load(&state, album).await))
+}
+
+/// Delete an album
+///
+/// Deletes the album. The images in it are kept.
+#[utoipa::path(
+    delete,
+    path = "/albums/{id}",
+    params(("id" = String, Path, description = "The album's id.")),
+    responses(
+        (status = 200, description = "The deleted album", body = ApiAlbum),
+        (status = 401, description = "Unauthenticated", body = ApiError),
+        (status = 403, description = "Missing the albums:write scope", body = ApiError),
+        (status = 404, description = "No such album owned by this account", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(("api_key" = ["albums:write"])),
+    tag = "albums"
+)]
+pub async fn delete_album(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    Path(id): Path<String>,
+    auth: ApiToken,
+) -> Result<Json<ApiAlbum>, ApiError> {
+    let account = auth.require_account(&state, Scope::AlbumsWrite).await?;
+
+    let album = album::load_for(&state, &id, &account).await.map_err(api_error)?;
+    let response = ApiAlbum::load(&state, album.clone()).await;
+    album::delete(&state, &album, &account, client_ip)
+        .await
+        .map_err(api_error)?;
+    Ok(Json(response))
+}
+
+/// Add images to an album
+///
+/// Appends your images to the end of the album, in the order given. Images
+/// already in the album stay where they are. If any id is not one of your
+/// images, nothing is added.
+#[utoipa::path(
+    post,
+    path = "/albums/{id}/images",
+    params(("id" = String, Path, description = "The album's id.")),
+    request_body(content = AlbumImagesBody, content_type = "application/json"),
+    responses(
+        (status = 200, description = "The updated album", body = ApiAlbum),
+        (status = 400, description = "An unknown image, or the album would exceed 500 images", body = ApiError),
+        (status = 401, description = "Unauthenticated", body = ApiError),
+        (status = 403, description = "Missing the albums:write scope", body = ApiError),
+        (status = 404, description = "No such album owned by this account", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(("api_key" = ["albums:write"])),
+    tag = "albums"
+)]
+pub async fn add_album_images(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    Path(id): Path<String>,
+    auth: ApiToken,
+    Json(body): Json<AlbumImagesBody>,
+) -> Result<Json<ApiAlbum>, ApiError> {
+    let account = auth.require_account(&state, Scope::AlbumsWrite).await?;
+
+    let album = album::load_for(&state, &id, &account).await.map_err(api_error)?;
+    album::add_images(&state, &album, &account, client_ip, &body.images)
+        .await
+        .map_err(api_error)?;
+    let album = album::load_for(&state, &id, &account).await.map_err(api_error)?;
+    Ok(Json(ApiAlbum::load(&state, album).await))
+}
+
+/// Reorder an album
+///
+/// Puts the album's images in the order given. The list must name every image
+/// in the album exactly once.
+#[utoipa::path(
+    patch,
+    path = "/albums/{id}/images",
+    params(("id" = String, Path, description = "The album's id.")),
+    request_body(content = AlbumImagesBody, content_type = "application/json"),
+    responses(
+        (status = 200, description = "The reordered album", body = ApiAlbum),
+        (status = 400, description = "The list is not exactly the album's images", body = ApiError),
+        (status = 401, description = "Unauthenticated", body = ApiError),
+        (status = 403, description = "Missing the albums:write scope", body = ApiError),
+        (status = 404, description = "No such album owned by this account", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(("api_key" = ["albums:write"])),
+    tag = "albums"
+)]
+pub async fn reorder_album_images(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    Path(id): Path<String>,
+    auth: ApiToken,
+    Json(body): Json<AlbumImagesBody>,
+) -> Result<Json<ApiAlbum>, ApiError> {
+    let account = auth.require_account(&state, Scope::AlbumsWrite).await?;
+
+    let album = album::load_for(&state, &id, &account).await.map_err(api_error)?;
+    album::reorder(&state, &album, &account, client_ip, &body.images)
+        .await
+        .map_err(api_error)?;
+    let album = album::load_for(&state, &id, &account).await.map_err(api_error)?;
+    Ok(Json(ApiAlbum::load(&state, album).await))
+}
+
+/// Remove an image from an album
+///
+/// Takes the image out of the album. The image itself is kept.
+#[utoipa::path(
+    delete,
+    path = "/albums/{id}/images/{image_id}",
+    params(
+        ("id" = String, Path, description = "The album's id."),
+        ("image_id" = String, Path, description = "The image's id."),
+    ),
+    responses(
+        (status = 200, description = "The updated album", body = ApiAlbum),
+        (status = 401, description = "Unauthenticated", body = ApiError),
+        (status = 403, description = "Missing the albums:write scope", body = ApiError),
+        (status = 404, description = "No such album, or the image is not in it", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(("api_key" = ["albums:write"])),
+    tag = "albums"
+)]
+pub async fn remove_album_image(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    Path((id, image_id)): Path<(String, String)>,
+    auth: ApiToken,
+) -> Result<Json<ApiAlbum>, ApiError> {
+    let account = auth.require_account(&state, Scope::AlbumsWrite).await?;
+
+    let album = album::load_for(&state, &id, &account).await.map_err(api_error)?;
+    album::remove_image(&state, &album, &account, client_ip, &image_id)
+        .await
+        .map_err(api_error)?;
+    let album = album::load_for(&state, &id, &account).await.map_err(api_error)?;
+    Ok(Json(ApiAlbum::load(&state, album).await))
+}
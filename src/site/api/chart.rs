@@ -428,7 +428,7 @@ fn text_w(s: &str, font_px: f64) -> f64 {
 
 /// Pure render: chart spec → SVG document. Returns `(field, message)` on
 /// invalid specs so the handler can shape a validation error.
-fn render_svg(req: &ChartRequest) -> Result<String, RenderError> {
+pub(crate) fn render_svg(req: &ChartRequest) -> Result<String, RenderError> {
     let series = normalize(req)?;
     let theme = req.theme.resolve();
     let w = req.width.unwrap_or(DEFAULT_W).clamp(MIN_W, MAX_W) as f64;
@@ -28,6 +28,7 @@ use crate::{
     headers::ClientIp,
     models::Scope,
     site::image::{expiry_from_params, raw_upload_file, DeleteResult, UploadParams, UploadResult},
+    site::image_lock::Protection,
     AppState,
 };
 
@@ -53,8 +54,28 @@ pub struct GuildImageInfo {
     pub url: String,
     /// Raw-bytes URL (`/gallery/raw/{id}.{ext}`).
     pub raw_url: String,
+    /// The image's BlurHash, to paint while it loads. Absent for images that
+    /// can't be decoded (AVIF) or haven't been processed yet.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub blurhash: Option<String>,
+    /// The image's most prominent colour as `#rrggbb`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(example = "#4f6d8a")]
+    pub dominant_color: Option<String>,
 }
 
+/// A row of a guild gallery listing: id, mimetype, size, original name, upload
+/// time, BlurHash and dominant colour.
+type GuildImageRow = (
+    String,
+    String,
+    i64,
+    Option<String>,
+    String,
+    Option<String>,
+    Option<String>,
+);
+
 /// The listing of a guild's gallery.
 #[derive(Debug, Serialize, ToSchema)]
 pub struct GuildImagesResult {
@@ -95,7 +116,7 @@ struct UploadedFiles {
         (status = 200, description = "Upload processed", body = UploadResult),
         (status = 400, description = "An error occurred", body = ApiError),
         (status = 401, description = "User is unauthenticated", body = ApiError),
-        (status = 403, description = "The API key is missing the images:guild scope", body = ApiError),
+        (status = 403, description = "The API key is missing the images:guild scope, or (code 12) the guild's gallery is over the image quota", body = ApiError),
         (status = 429, response = RateLimitResponse),
     ),
     security(("api_key" = ["images:guild"])),
@@ -112,7 +133,19 @@ pub async fn upload_guild_images(
     let account = auth.require_account(&state, Scope::GuildImages).await?;
 
     let expires_at = expiry_from_params(&params);
-    let result = raw_upload_file(state, account, client_ip, multipart, true, expires_at, Some(guild_id)).await?;
+    let result = raw_upload_file(
+        state,
+        account,
+        client_ip,
+        multipart,
+        true,
+        expires_at,
+        Some(guild_id),
+        params.on_duplicate,
+        params.keep_metadata,
+        Protection::default(),
+    )
+    .await?;
     if result.is_error() {
         return Err(ApiError::new("Upload failed"));
     }
@@ -157,61 +190,73 @@ pub async fn list_guild_images(
 
     let rows = state
         .database()
-        .call(
-            move |conn| -> rusqlite::Result<Vec<(String, String, i64, Option<String>, String)>> {
-                // Keyset pagination over (uploaded_at DESC). The cursors are image
-                // ids resolved to their `uploaded_at` via a scalar subquery:
-                // `after` walks to older rows, `before` to newer ones. An unknown
-                // cursor id (no such row) is forgiven — the `NOT EXISTS` guard
-                // makes it behave as "no bound" instead of erroring or returning
-                // an empty page.
-                let mut stmt = conn.prepare(
-                    "SELECT id, mimetype, size, original_name, uploaded_at FROM images \
-                     WHERE guild_id = :guild \
-                       AND (expires_at IS NULL OR datetime(expires_at) > datetime('now')) \
-                       AND (:after IS NULL \
-                            OR NOT EXISTS (SELECT 1 FROM images WHERE id = :after) \
-                            OR uploaded_at < (SELECT uploaded_at FROM images WHERE id = :after)) \
-                       AND (:before IS NULL \
-                            OR NOT EXISTS (SELECT 1 FROM images WHERE id = :before) \
-                            OR uploaded_at > (SELECT uploaded_at FROM images WHERE id = :before)) \
-                     ORDER BY uploaded_at DESC \
-                     LIMIT :limit",
-                )?;
-                let rows = stmt
-                    .query_map(
-                        rusqlite::named_params! {
-                            ":guild": guild_id,
-                            ":after": after,
-                            ":before": before,
-                            ":limit": limit,
-                        },
-                        |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?)),
-                    )?
-                    .collect::<rusqlite::Result<Vec<_>>>()?;
-                Ok(rows)
-            },
-        )
+        .call(move |conn| -> rusqlite::Result<Vec<GuildImageRow>> {
+            // Keyset pagination over (uploaded_at DESC). The cursors are image
+            // ids resolved to their `uploaded_at` via a scalar subquery:
+            // `after` walks to older rows, `before` to newer ones. An unknown
+            // cursor id (no such row) is forgiven — the `NOT EXISTS` guard
+            // makes it behave as "no bound" instead of erroring or returning
+            // an empty page.
+            let mut stmt = conn.prepare(
+                "SELECT id, mimetype, size, original_name, uploaded_at, blurhash, dominant_color FROM images \
+                 WHERE guild_id = :guild AND deleted_at IS NULL \
+                   AND (expires_at IS NULL OR datetime(expires_at) > datetime('now')) \
+                   AND (:after IS NULL \
+                        OR NOT EXISTS (SELECT 1 FROM images WHERE id = :after) \
+                        OR uploaded_at < (SELECT uploaded_at FROM images WHERE id = :after)) \
+                   AND (:before IS NULL \
+                        OR NOT EXISTS (SELECT 1 FROM images WHERE id = :before) \
+                        OR uploaded_at > (SELECT uploaded_at FROM images WHERE id = :before)) \
+                 ORDER BY uploaded_at DESC \
+                 LIMIT :limit",
+            )?;
+            let rows = stmt
+                .query_map(
+                    rusqlite::named_params! {
+                        ":guild": guild_id,
+                        ":after": after,
+                        ":before": before,
+                        ":limit": limit,
+                    },
+                    |r| {
+                        Ok((
+                            r.get(0)?,
+                            r.get(1)?,
+                            r.get(2)?,
+                            r.get(3)?,
+                            r.get(4)?,
+                            r.get(5)?,
+                            r.get(6)?,
+                        ))
+                    },
+                )?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(rows)
+        })
         .await
         .map_err(|_| ApiError::new("Failed to list gallery"))?;
 
     let images: Vec<GuildImageInfo> = rows
         .into_iter()
-        .map(|(id, mimetype, size, original_name, uploaded_at)| {
-            let ext = mimetype.split('/').last().unwrap_or("png").to_string();
-            let url = canonical_url(format!("/gallery/{id}.{ext}")).unwrap_or_default();
-            let raw_url = canonical_url(format!("/gallery/raw/{id}.{ext}")).unwrap_or_default();
-            GuildImageInfo {
-                id,
-                ext,
-                mimetype,
-                size,
-                original_name,
-                uploaded_at,
-                url,
-                raw_url,
-            }
-        })
+        .map(
+            |(id, mimetype, size, original_name, uploaded_at, blurhash, dominant_color)| {
+                let ext = mimetype.split('/').last().unwrap_or("png").to_string();
+                let url = canonical_url(format!("/gallery/{id}.{ext}")).unwrap_or_default();
+                let raw_url = canonical_url(format!("/gallery/raw/{id}.{ext}")).unwrap_or_default();
+                GuildImageInfo {
+                    id,
+                    ext,
+                    mimetype,
+                    size,
+                    original_name,
+                    uploaded_at,
+                    url,
+                    raw_url,
+                    blurhash,
+                    dominant_color,
+                }
+            },
+        )
         .collect();
 
     let total = images.len();
@@ -1,17 +1,28 @@
 use axum::extract::{Multipart, Path, Query, State};
 use axum::http::header;
 use axum::response::{IntoResponse, Response};
-use utoipa::ToSchema;
+use axum::Extension;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
 use super::{
     auth::ApiToken,
+    presign,
     utils::{ApiJson as Json, RateLimitResponse},
 };
-use crate::site::image::{delete_image, raw_upload_file};
+use crate::site::album;
+use crate::site::image::{delete_image, raw_upload_file, single_file};
+use crate::site::image_import::{self, ImportResult};
+use crate::site::image_lock::{self, ImageSettings};
+use crate::site::image_revision;
+use crate::site::stats::{self, StatsQuery, ViewKind, ViewStats};
 use crate::{
     error::ApiError,
+    filters::canonical_url,
     headers::ClientIp,
-    models::Scope,
+    key::SecretKey,
+    models::{Account, ImageEntry, ImageRevision, Scope, Visibility},
+    phash, renditions,
     site::image::{build_images_zip, BulkFilesPayload, DeleteResult, UploadParams, UploadResult},
     AppState,
 };
@@ -36,11 +47,35 @@ struct UploadedFiles {
 ///
 /// Pass `?expires_in=<seconds>` to make the upload auto-delete after a TTL
 /// (capped at 365 days). Omit it for a permanent upload.
+///
+/// Pass `?album=<id>` to add the uploaded images to the end of one of your
+/// albums. This also needs the `albums:write` scope.
+///
+/// Pass `?visibility=private` (or `public`) and `?burn_after_views=<n>` to
+/// set who may see the uploads; a password can be added afterwards with
+/// `PATCH /images/{id}`.
+///
+/// Metadata (EXIF, GPS, XMP, comments, colour profiles) is stripped from every
+/// upload, and JPEGs are first turned upright by their EXIF orientation. Pass
+/// `?keep_metadata=color` to keep the colour profile and orientation.
+///
+/// Accounts may have a quota on how many images, and how many bytes, they
+/// keep. Files that don't fit are counted in `over_quota`; if none fit the
+/// request fails with error code 12 and a message saying which limit was hit.
+///
+/// With `?signature=<signature>` from `/images/presign` no API key is needed;
+/// the upload is limited to what the signature allows and the other parameters
+/// are ignored.
 #[utoipa::path(
     post,
     path = "/images/upload",
     params(
         ("expires_in" = Option<i64>, Query, description = "Optional time-to-live in seconds; the upload is auto-deleted afterwards (max 365 days)."),
+        ("album" = Option<String>, Query, description = "Optional id of one of your albums to add the uploads to."),
+        ("visibility" = Option<Visibility>, Query, description = "Who may see the uploads: `public`, `unlisted` (the default) or `private`."),
+        ("burn_after_views" = Option<i64>, Query, description = "Delete each upload after it has been opened this many times (max 1000)."),
+        ("keep_metadata" = Option<crate::metadata::Keep>, Query, description = "`color` keeps each upload's colour profile and orientation; by default all metadata is stripped."),
+        ("signature" = Option<String>, Query, description = "A presigned upload signature, in place of an API key."),
     ),
     request_body(
         content = inline(UploadedFiles),
@@ -51,7 +86,8 @@ struct UploadedFiles {
         (status = 200, description = "Upload processed", body = UploadResult),
         (status = 400, description = "An error occurred", body = ApiError),
         (status = 401, description = "User is unauthenticated", body = ApiError),
-        (status = 403, description = "The API key is missing the images:write scope", body = ApiError),
+        (status = 403, description = "The API key is missing the images:write scope (or albums:write, with `album`), or the signature is invalid, expired or spent, or (code 12) the account is over its image quota", body = ApiError),
+        (status = 404, description = "The album does not exist or is not yours", body = ApiError),
         (status = 429, response = RateLimitResponse),
     ),
     security(
@@ -62,17 +98,166 @@ struct UploadedFiles {
 pub async fn upload_files(
     State(state): State<AppState>,
     ClientIp(client_ip): ClientIp,
+    Extension(secret): Extension<SecretKey>,
     Query(params): Query<UploadParams>,
-    auth: ApiToken,
+    Query(presigned): Query<presign::SignatureQuery>,
+    auth: Option<ApiToken>,
     multipart: Multipart,
 ) -> Result<Json<UploadResult>, ApiError> {
+    if let Some(signature) = presigned.signature.as_deref() {
+        return presign::redeem(state, client_ip, &secret, signature, multipart)
+            .await
+            .map(Json);
+    }
+    let auth = auth.ok_or_else(ApiError::unauthorized)?;
     let account = auth.require_account(&state, Scope::ImagesWrite).await?;
 
+    // Resolve the album before accepting any bytes, so a bad id fails fast
+    // instead of leaving the uploads stranded outside it.
+    let target = match params.album.as_deref().map(str::trim).filter(|id| !id.is_empty()) {
+        Some(id) => {
+            auth.require(Scope::AlbumsWrite)?;
+            Some(
+                album::load_for(&state, id, &account)
+                    .await
+                    .map_err(|e| ApiError::not_found(e.message()))?,
+            )
+        }
+        None => None,
+    };
+
+    let protection = params.settings().for_upload()?;
+
     let expires_at = crate::site::image::expiry_from_params(&params);
-    let result = raw_upload_file(state, account, client_ip, multipart, true, expires_at, None).await?;
+    let result = raw_upload_file(
+        state.clone(),
+        account.clone(),
+        client_ip,
+        multipart,
+        true,
+        expires_at,
+        None,
+        params.on_duplicate,
+        params.keep_metadata,
+        protection,
+    )
+    .await?;
     if result.is_error() {
         return Err(ApiError::new("Upload failed"));
     }
+
+    if let Some(target) = target {
+        // The images are already stored; a full album shouldn't turn a good
+        // upload into an error, so this is logged rather than returned.
+        if let Err(e) = album::add_images(&state, &target, &account, client_ip, &result.ids).await {
+            tracing::warn!(album = %target.id, error = %e.message(), "could not add uploads to album");
+        }
+    }
+    Ok(Json(result))
+}
+
+#[derive(ToSchema)]
+struct ImportArchive {
+    #[schema(format = Binary)]
+    #[allow(dead_code)]
+    file: String,
+}
+
+/// Import
+///
+/// Upload every image in a ZIP archive — the inverse of `/images/download`.
+///
+/// Each file in the archive goes through the same checks as a `file` field on
+/// `/images/upload`: the extension allow-list, metadata stripping, the malware
+/// scan and the account's quota. `entries` reports what became of each one, in
+/// archive order; directories are left out. The upload's query parameters
+/// (`expires_in`, `album`, `on_duplicate`, `visibility`, `burn_after_views`,
+/// `keep_metadata`) apply to every image.
+///
+/// Archives may hold at most 250 entries and unpack to at most 256 MiB. An
+/// entry larger than the upload size limit, or one that is compressed more
+/// than a hundredfold, is skipped.
+#[utoipa::path(
+    post,
+    path = "/images/import",
+    params(
+        ("expires_in" = Option<i64>, Query, description = "Optional time-to-live in seconds for every image (max 365 days)."),
+        ("album" = Option<String>, Query, description = "Optional id of one of your albums to add the images to."),
+        ("on_duplicate" = Option<crate::site::image::DuplicatePolicy>, Query, description = "`dedupe` returns an existing image's links instead of storing a copy."),
+        ("visibility" = Option<Visibility>, Query, description = "Who may see the images: `public`, `unlisted` (the default) or `private`."),
+        ("burn_after_views" = Option<i64>, Query, description = "Delete each image after it has been opened this many times (max 1000)."),
+        ("keep_metadata" = Option<crate::metadata::Keep>, Query, description = "`color` keeps each image's colour profile and orientation; by default all metadata is stripped."),
+    ),
+    request_body(
+        content = inline(ImportArchive),
+        content_type = "multipart/form-data",
+        description = "The ZIP archive, as a `file` field."
+    ),
+    responses(
+        (status = 200, description = "Import processed", body = ImportResult),
+        (status = 400, description = "No archive, an unreadable or oversized one, or none of its entries could be uploaded", body = ApiError),
+        (status = 401, description = "User is unauthenticated", body = ApiError),
+        (status = 403, description = "The API key is missing the images:write scope (or albums:write, with `album`), or (code 12) the account is over its image quota", body = ApiError),
+        (status = 404, description = "The album does not exist or is not yours", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(
+        ("api_key" = ["images:write"])
+    ),
+    tag = "images"
+)]
+pub async fn import_images(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    Query(params): Query<UploadParams>,
+    auth: ApiToken,
+    mut multipart: Multipart,
+) -> Result<Json<ImportResult>, ApiError> {
+    let account = auth.require_account(&state, Scope::ImagesWrite).await?;
+
+    let target = match params.album.as_deref().map(str::trim).filter(|id| !id.is_empty()) {
+        Some(id) => {
+            auth.require(Scope::AlbumsWrite)?;
+            Some(
+                album::load_for(&state, id, &account)
+                    .await
+                    .map_err(|e| ApiError::not_found(e.message()))?,
+            )
+        }
+        None => None,
+    };
+
+    let protection = params.settings().for_upload()?;
+
+    let mut archive = None;
+    while let Some(field) = multipart.next_field().await.map_err(|e| ApiError::new(e.to_string()))? {
+        if field.name() == Some("file") {
+            archive = Some(field.bytes().await.map_err(|e| ApiError::new(e.to_string()))?);
+            break;
+        }
+    }
+    let Some(archive) = archive.filter(|bytes| !bytes.is_empty()) else {
+        return Err(ApiError::new("no `file` field in upload"));
+    };
+
+    let expires_at = crate::site::image::expiry_from_params(&params);
+    let result = image_import::import(
+        state.clone(),
+        account.clone(),
+        client_ip,
+        archive,
+        expires_at,
+        params.on_duplicate,
+        params.keep_metadata,
+        protection,
+    )
+    .await?;
+
+    if let Some(target) = target {
+        if let Err(e) = album::add_images(&state, &target, &account, client_ip, &result.upload.ids).await {
+            tracing::warn!(album = %target.id, error = %e.message(), "could not add imported images to album");
+        }
+    }
     Ok(Json(result))
 }
 
@@ -114,6 +299,351 @@ pub async fn delete_image_by_id(
     Ok(Json(result))
 }
 
+/// Who may see one of your images, as [`update_image`] leaves it.
+#[derive(Serialize, ToSchema)]
+pub struct ImageAccess {
+    /// The image's ID.
+    pub id: String,
+    pub visibility: Visibility,
+    /// Whether opening the image needs a password.
+    pub password_protected: bool,
+    /// The image is deleted once `views` reaches this. Absent when it isn't
+    /// burning.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub burn_after_views: Option<i64>,
+    /// How often the image has been viewed.
+    pub views: i64,
+}
+
+/// Update
+///
+/// Change who may see one of your images. Fields left out stay as they are.
+///
+/// - `visibility`: `public` images may be indexed by search engines;
+///   `unlisted` ones can be opened by anyone with the link; `private` ones
+///   only by you.
+/// - `password`: viewers have to enter it before the image is served. An
+///   empty string removes it.
+/// - `burn_after_views`: the image is deleted after this many more views.
+///   Each view is an explicit "open" in a browser, so link previews don't
+///   count; your own views never do. `0` stops it burning.
+#[utoipa::path(
+    patch,
+    path = "/images/{id}",
+    params(
+        ("id" = String, Path, description = "The image's ID")
+    ),
+    request_body = ImageSettings,
+    responses(
+        (status = 200, description = "The image's new settings", body = ImageAccess),
+        (status = 400, description = "The password or view count is out of range", body = ApiError),
+        (status = 401, description = "User is unauthenticated", body = ApiError),
+        (status = 404, description = "Image not found", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(
+        ("api_key" = ["images:write"])
+    ),
+    tag = "images"
+)]
+pub async fn update_image(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    auth: ApiToken,
+    Path(id): Path<String>,
+    Json(settings): Json<ImageSettings>,
+) -> Result<Json<ImageAccess>, ApiError> {
+    let account = auth.require_account(&state, Scope::ImagesWrite).await?;
+    let id = bare_id(&id).to_string();
+    let not_found = || ApiError::not_found(format!("Image `{id}` was not found"));
+
+    let entry = state.get_image_meta(id.clone()).await.ok_or_else(not_found)?;
+    if entry.uploader_id != Some(account.id) || entry.is_expired() {
+        return Err(not_found());
+    }
+    image_lock::apply(&state, &account, &[id.clone()], &settings).await?;
+
+    state
+        .audit("image.settings")
+        .actor(&account)
+        .target(id.clone())
+        .ip_opt(client_ip)
+        .meta(serde_json::json!({
+            "visibility":       settings.visibility,
+            "password_changed": settings.password.is_some(),
+            "burn_after_views": settings.burn_after_views,
+            "via_api":          true,
+        }))
+        .fire();
+
+    let entry = state.get_image_meta(id).await.ok_or_else(not_found)?;
+    Ok(Json(ImageAccess {
+        password_protected: entry.is_locked(),
+        id: entry.id,
+        visibility: entry.visibility,
+        burn_after_views: entry.burn_after_views,
+        views: entry.views,
+    }))
+}
+
+#[derive(ToSchema)]
+struct ReplacementFile {
+    #[schema(format = Binary)]
+    #[allow(dead_code)]
+    file: String,
+}
+
+#[derive(Deserialize)]
+pub struct ReplaceParams {
+    #[serde(default)]
+    keep_metadata: crate::metadata::Keep,
+}
+
+/// One of your images after its bytes changed, with the versions it had.
+#[derive(Serialize, ToSchema)]
+pub struct ImageVersions {
+    /// The image's ID.
+    pub id: String,
+    /// The image's page, the same as before.
+    pub link: String,
+    /// The image's raw bytes, the same as before.
+    pub raw_link: String,
+    /// The current file's size in bytes.
+    pub size: u64,
+    /// When the bytes last changed.
+    #[serde(with = "time::serde::rfc3339::option")]
+    pub replaced_at: Option<time::OffsetDateTime>,
+    /// The earlier versions kept, newest first.
+    pub revisions: Vec<ImageRevision>,
+}
+
+impl ImageVersions {
+    async fn of(state: &AppState, entry: ImageEntry) -> Self {
+        let ext = entry.ext();
+        Self {
+            link: canonical_url(format!("/gallery/{}.{ext}", entry.id))
+                .unwrap_or_default()
+                .to_string(),
+            raw_link: canonical_url(format!("/gallery/raw/{}.{ext}", entry.id))
+                .unwrap_or_default()
+                .to_string(),
+            revisions: image_revision::list(state, &entry.id).await,
+            id: entry.id,
+            size: entry.size,
+            replaced_at: entry.replaced_at,
+        }
+    }
+}
+
+/// Replace
+///
+/// Swap one of your images for a new file while keeping its id, so every link
+/// to it keeps working and shows the new file. Its settings, views and album
+/// places stay as they are.
+///
+/// The file must be the same format as the image. It is scanned and checked
+/// against the block list like an upload, metadata is stripped the same way,
+/// and it has to fit in your storage quota in place of the old one.
+///
+/// The previous file is kept as a revision: list them with
+/// `GET /images/{id}/revisions` and put one back with
+/// `POST /images/{id}/revisions/{revision}/restore`. At most 10 are kept; the
+/// oldest go first.
+#[utoipa::path(
+    put,
+    path = "/images/{id}",
+    params(
+        ("id" = String, Path, description = "The image's ID"),
+        ("keep_metadata" = Option<crate::metadata::Keep>, Query, description = "`color` keeps the colour profile and orientation; by default all metadata is stripped."),
+    ),
+    request_body(
+        content = inline(ReplacementFile),
+        content_type = "multipart/form-data",
+        description = "The new file, as a `file` field."
+    ),
+    responses(
+        (status = 200, description = "The image was replaced", body = ImageVersions),
+        (status = 400, description = "No file, a different format, or a file that failed the malware scan or is blocked", body = ApiError),
+        (status = 401, description = "User is unauthenticated", body = ApiError),
+        (status = 403, description = "(code 12) The new file doesn't fit in the account's storage quota", body = ApiError),
+        (status = 404, description = "Image not found", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(
+        ("api_key" = ["images:write"])
+    ),
+    tag = "images"
+)]
+pub async fn replace_image(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    auth: ApiToken,
+    Path(id): Path<String>,
+    Query(params): Query<ReplaceParams>,
+    multipart: Multipart,
+) -> Result<Json<ImageVersions>, ApiError> {
+    let account = auth.require_account(&state, Scope::ImagesWrite).await?;
+    let id = bare_id(&id).to_string();
+
+    let max_bytes = state.config().effective_max_upload_bytes();
+    let file = single_file(multipart, max_bytes, params.keep_metadata).await?;
+    let entry = image_revision::replace(&state, &account, client_ip, &id, file, true).await?;
+    Ok(Json(ImageVersions::of(&state, entry).await))
+}
+
+/// List revisions
+///
+/// The earlier versions of one of your images, newest first, from each time it
+/// was replaced.
+#[utoipa::path(
+    get,
+    path = "/images/{id}/revisions",
+    params(
+        ("id" = String, Path, description = "The image's ID")
+    ),
+    responses(
+        (status = 200, description = "The image's revision history", body = [ImageRevision]),
+        (status = 401, description = "User is unauthenticated", body = ApiError),
+        (status = 404, description = "Image not found", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(
+        ("api_key" = ["images:read"])
+    ),
+    tag = "images"
+)]
+pub async fn list_image_revisions(
+    State(state): State<AppState>,
+    auth: ApiToken,
+    Path(id): Path<String>,
+) -> Result<Json<Vec<ImageRevision>>, ApiError> {
+    let account = auth.require_account(&state, Scope::ImagesRead).await?;
+    let entry = owned_image(&state, &account, bare_id(&id)).await?;
+    Ok(Json(image_revision::list(&state, &entry.id).await))
+}
+
+/// Download a revision
+///
+/// The bytes of an earlier version of one of your images.
+#[utoipa::path(
+    get,
+    path = "/images/{id}/revisions/{revision}",
+    params(
+        ("id" = String, Path, description = "The image's ID"),
+        ("revision" = i64, Path, description = "The revision's ID"),
+    ),
+    responses(
+        (status = 200, description = "The revision's bytes", content_type = "image/*", body = Vec<u8>),
+        (status = 401, description = "User is unauthenticated", body = ApiError),
+        (status = 404, description = "Image or revision not found", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(
+        ("api_key" = ["images:read"])
+    ),
+    tag = "images"
+)]
+pub async fn get_image_revision(
+    State(state): State<AppState>,
+    auth: ApiToken,
+    headers: axum::http::HeaderMap,
+    Path((id, revision)): Path<(String, i64)>,
+) -> Result<Response, ApiError> {
+    let account = auth.require_account(&state, Scope::ImagesRead).await?;
+    let entry = owned_image(&state, &account, bare_id(&id)).await?;
+    let not_found = || ApiError::not_found(format!("Image `{}` has no revision {revision}", entry.id));
+    let revision = image_revision::get(&state, &entry.id, revision)
+        .await
+        .ok_or_else(not_found)?;
+    image_revision::respond(&state, &headers, &revision)
+        .await
+        .ok_or_else(not_found)
+}
+
+/// Restore a revision
+///
+/// Put an earlier version of one of your images back. The version it replaces
+/// becomes a revision in turn, so a restore can itself be undone.
+#[utoipa::path(
+    post,
+    path = "/images/{id}/revisions/{revision}/restore",
+    params(
+        ("id" = String, Path, description = "The image's ID"),
+        ("revision" = i64, Path, description = "The revision's ID"),
+    ),
+    responses(
+        (status = 200, description = "The revision was restored", body = ImageVersions),
+        (status = 400, description = "The revision has since been blocked", body = ApiError),
+        (status = 401, description = "User is unauthenticated", body = ApiError),
+        (status = 403, description = "(code 12) The revision doesn't fit in the account's storage quota", body = ApiError),
+        (status = 404, description = "Image or revision not found", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(
+        ("api_key" = ["images:write"])
+    ),
+    tag = "images"
+)]
+pub async fn restore_image_revision(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    auth: ApiToken,
+    Path((id, revision)): Path<(String, i64)>,
+) -> Result<Json<ImageVersions>, ApiError> {
+    let account = auth.require_account(&state, Scope::ImagesWrite).await?;
+    let id = bare_id(&id).to_string();
+    let entry = image_revision::restore(&state, &account, client_ip, &id, revision, true).await?;
+    Ok(Json(ImageVersions::of(&state, entry).await))
+}
+
+/// One of `account`'s live images, or a 404.
+async fn owned_image(state: &AppState, account: &Account, id: &str) -> Result<ImageEntry, ApiError> {
+    state
+        .get_image_meta(id.to_string())
+        .await
+        .filter(|entry| entry.uploader_id == Some(account.id) && !entry.is_expired())
+        .ok_or_else(|| ApiError::not_found(format!("Image `{id}` was not found")))
+}
+
+/// Image view statistics
+///
+/// Landing-page views of one of your images over the last `days` days (30 by
+/// default), zero-filled per UTC day, plus the sites that sent them. The
+/// `series` object drops straight into `POST /render/chart`. Raw and embedded
+/// loads aren't counted, as with the image's `views` total.
+#[utoipa::path(
+    get,
+    path = "/images/{id}/stats",
+    params(
+        ("id" = String, Path, description = "The image's ID"),
+        StatsQuery,
+    ),
+    responses(
+        (status = 200, description = "The image's view statistics", body = ViewStats),
+        (status = 400, description = "days is out of range", body = ApiError),
+        (status = 401, description = "User is unauthenticated", body = ApiError),
+        (status = 404, description = "Image not found", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(
+        ("api_key" = ["images:read"])
+    ),
+    tag = "images"
+)]
+pub async fn image_stats(
+    State(state): State<AppState>,
+    auth: ApiToken,
+    Path(id): Path<String>,
+    Query(query): Query<StatsQuery>,
+) -> Result<Json<ViewStats>, ApiError> {
+    let account = auth.require_account(&state, Scope::ImagesRead).await?;
+    let days = query.days().map_err(|msg| ApiError::validation("days", msg))?;
+
+    let entry = owned_image(&state, &account, bare_id(&id)).await?;
+
+    Ok(Json(stats::load(&state, ViewKind::Image, entry.id, days).await?))
+}
+
 /// Download
 ///
 /// Bundle one or more of your images into a ZIP archive.
@@ -187,3 +717,345 @@ pub async fn download_images(
     )
         .into_response())
 }
+
+/// Query parameters for [`similar_images`].
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct SimilarQuery {
+    /// The largest Hamming distance between perceptual hashes to count as
+    /// similar, from 0 (visually identical) to 20. Defaults to 10.
+    pub max_distance: Option<u32>,
+}
+
+/// One of your images that looks like the requested one.
+#[derive(Serialize, ToSchema)]
+pub struct SimilarImage {
+    /// The image's ID.
+    pub id: String,
+    /// Canonical URL of the image.
+    pub link: String,
+    /// Canonical raw URL of the image.
+    pub raw_link: String,
+    /// How many bits of the two perceptual hashes differ (0 = identical).
+    pub distance: u32,
+}
+
+/// The result of a "find similar" search.
+#[derive(Serialize, ToSchema)]
+pub struct SimilarImages {
+    /// The image that was searched for.
+    pub id: String,
+    /// Matching images, closest first. Empty if the image could not be
+    /// hashed (e.g. AVIF).
+    pub results: Vec<SimilarImage>,
+}
+
+const DEFAULT_SIMILAR_DISTANCE: u32 = 10;
+
+/// Strips an optional file extension from an image ID in the path.
+fn bare_id(id: &str) -> &str {
+    id.split_once('.').map(|(id, _)| id).unwrap_or(id)
+}
+
+/// The stored perceptual hash and uploader of an image, or `None` if there is
+/// no such image.
+async fn image_hash(state: &AppState, id: &str) -> Option<(Option<i64>, Option<i64>)> {
+    state
+        .database()
+        .get_row(
+            "SELECT phash, uploader_id FROM images WHERE id = ?1 AND deleted_at IS NULL",
+            [id.to_string()],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .await
+        .ok()
+}
+
+/// Find similar
+///
+/// List your images that look like the given one, by comparing perceptual
+/// hashes. Catches re-encoded, rescaled and lightly edited copies of the same
+/// picture, not just byte-identical ones.
+#[utoipa::path(
+    get,
+    path = "/images/{id}/similar",
+    params(
+        ("id" = String, Path, description = "The image's ID"),
+        SimilarQuery,
+    ),
+    responses(
+        (status = 200, description = "Similar images, closest first", body = SimilarImages),
+        (status = 400, description = "max_distance is out of range", body = ApiError),
+        (status = 401, description = "User is unauthenticated", body = ApiError),
+        (status = 404, description = "Image not found", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(
+        ("api_key" = ["images:read"])
+    ),
+    tag = "images"
+)]
+pub async fn similar_images(
+    State(state): State<AppState>,
+    auth: ApiToken,
+    Path(id): Path<String>,
+    Query(query): Query<SimilarQuery>,
+) -> Result<Json<SimilarImages>, ApiError> {
+    let account = auth.require_account(&state, Scope::ImagesRead).await?;
+
+    let max_distance = query.max_distance.unwrap_or(DEFAULT_SIMILAR_DISTANCE);
+    if max_distance > phash::MAX_SEARCH_DISTANCE {
+        return Err(ApiError::validation(
+            "max_distance",
+            format!("must be between 0 and {}", phash::MAX_SEARCH_DISTANCE),
+        ));
+    }
+
+    let id = bare_id(&id).to_string();
+    let Some((hash, _)) = image_hash(&state, &id)
+        .await
+        .filter(|(_, owner)| *owner == Some(account.id))
+    else {
+        return Err(ApiError::not_found(format!("Image `{id}` was not found")));
+    };
+
+    let matches = match hash {
+        Some(hash) => phash::similar(&state, account.id, hash, max_distance, Some(id.as_str()))
+            .await
+            .map_err(|e| ApiError::new(e.to_string()))?,
+        None => Vec::new(),
+    };
+    let results = matches
+        .into_iter()
+        .map(|m| {
+            let ext = m.mimetype.split('/').last().unwrap_or("png");
+            SimilarImage {
+                link: canonical_url(format!("/gallery/{}.{ext}", m.id))
+                    .unwrap_or_default()
+                    .to_string(),
+                raw_link: canonical_url(format!("/gallery/raw/{}.{ext}", m.id))
+                    .unwrap_or_default()
+                    .to_string(),
+                id: m.id,
+                distance: m.distance,
+            }
+        })
+        .collect();
+
+    Ok(Json(SimilarImages { id, results }))
+}
+
+/// Request body for [`block_image`].
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct BlockImageBody {
+    /// Why the image is being blocked, kept in the audit log.
+    #[serde(default)]
+    pub reason: Option<String>,
+    /// How many images the block is expected to delete. Required when it
+    /// would delete more than the image itself; a block that would delete a
+    /// different number is refused with the actual count, so it can be checked
+    /// and sent again.
+    #[serde(default)]
+    pub confirm: Option<usize>,
+}
+
+/// The result of blocking an image.
+#[derive(Serialize, ToSchema)]
+pub struct BlockResult {
+    /// The ID of the new block-list entry.
+    pub block_id: i64,
+    /// The IDs of the images deleted: the blocked one and every near-duplicate
+    /// of it on the site.
+    pub deleted: Vec<String>,
+}
+
+/// Block
+///
+/// **Admin only.** Ban an image and everything that looks like it.
+///
+/// Records the image's perceptual hash on the block list, deletes it and
+/// every near-duplicate already on the site (whoever uploaded them), and
+/// refuses future uploads that match. Images that can't be hashed (AVIF),
+/// and flat ones whose hash would match every other plain image, can't be
+/// blocked this way.
+///
+/// When near-duplicates would go too, the block has to be confirmed by
+/// sending their total in `confirm`; without it the request fails with a
+/// validation error on `confirm` that says how many images would be deleted.
+#[utoipa::path(
+    post,
+    path = "/images/{id}/block",
+    params(
+        ("id" = String, Path, description = "The image's ID")
+    ),
+    request_body(content = BlockImageBody, content_type = "application/json"),
+    responses(
+        (status = 200, description = "The image was blocked", body = BlockResult),
+        (status = 400, description = "The image could not be hashed, is too plain to block, or the block needs confirming", body = ApiError),
+        (status = 401, description = "User is unauthenticated", body = ApiError),
+        (status = 403, description = "The account is not an admin", body = ApiError),
+        (status = 404, description = "Image not found", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(
+        ("api_key" = ["images:write"])
+    ),
+    tag = "images"
+)]
+pub async fn block_image(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    auth: ApiToken,
+    Path(id): Path<String>,
+    body: Option<Json<BlockImageBody>>,
+) -> Result<Json<BlockResult>, ApiError> {
+    let account = auth.require_account(&state, Scope::ImagesWrite).await?;
+    if !account.flags.is_admin() {
+        return Err(ApiError::forbidden());
+    }
+    let Json(body) = body.unwrap_or_default();
+    let reason = body.reason.map(|r| r.trim().to_string()).filter(|r| !r.is_empty());
+
+    let id = bare_id(&id).to_string();
+    let Some((stored, uploader_id)) = image_hash(&state, &id).await else {
+        return Err(ApiError::not_found(format!("Image `{id}` was not found")));
+    };
+    let hash = match stored {
+        Some(hash) => Some(hash),
+        None => match state.resolve_image_data_for(&id).await {
+            Some(bytes) => phash::compute(&bytes).await,
+            None => None,
+        },
+    };
+    let Some(hash) = hash else {
+        return Err(ApiError::new(
+            "This image can't be decoded, so it can't be blocked by hash.",
+        ));
+    };
+    if phash::is_degenerate(hash) {
+        return Err(ApiError::new(
+            "This image is too plain to block by hash; the block would match every other plain image.",
+        ));
+    }
+
+    let mut doomed = phash::near_duplicates_sitewide(&state, hash)
+        .await
+        .map_err(|e| ApiError::new(e.to_string()))?;
+    if !doomed.contains(&id) {
+        doomed.push(id.clone());
+    }
+    if doomed.len() > 1 && body.confirm != Some(doomed.len()) {
+        return Err(ApiError::validation(
+            "confirm",
+            format!(
+                "This block deletes {} images across the site, this one and its near-duplicates. Send `confirm: {}` to go ahead.",
+                doomed.len(),
+                doomed.len()
+            ),
+        ));
+    }
+
+    let block_reason = reason.clone();
+    let source = id.clone();
+    let admin_id = account.id;
+    let to_delete = doomed.clone();
+    let block_id = state
+        .database()
+        .call(move |conn| {
+            let tx = conn.transaction()?;
+            let block_id: i64 = tx.query_row(
+                "INSERT INTO image_block (phash, reason, source_image_id, created_by) VALUES (?1, ?2, ?3, ?4) RETURNING id",
+                crate::boxed_params![hash, block_reason, source, admin_id],
+                |row| row.get(0),
+            )?;
+            {
+                let mut stmt = tx.prepare("DELETE FROM images WHERE id = ?1")?;
+                for image_id in &to_delete {
+                    stmt.execute([image_id])?;
+                }
+            }
+            tx.commit()?;
+            Ok(block_id)
+        })
+        .await
+        .map_err(|e| ApiError::new(e.to_string()))?;
+
+    state.invalidate_image_caches().await;
+
+    state
+        .audit("image.block")
+        .actor(&account)
+        .target(id)
+        .ip_opt(client_ip)
+        .meta(serde_json::json!({
+            "block_id": block_id,
+            "reason":   reason,
+            "uploader": uploader_id,
+            "deleted":  doomed,
+        }))
+        .fire();
+
+    Ok(Json(BlockResult {
+        block_id,
+        deleted: doomed,
+    }))
+}
+
+/// The state of a thumbnail rebuild.
+#[derive(Serialize, ToSchema)]
+pub struct ThumbnailRebuild {
+    /// Whether this request started one. `false` when a rebuild was already
+    /// running; it carries on.
+    pub started: bool,
+    /// Renditions (thumbnails and resized variants) in the disk cache right
+    /// now. `0` when the disk cache is turned off.
+    pub cached: usize,
+    /// Their total size in bytes.
+    pub cached_bytes: u64,
+}
+
+/// Rebuild thumbnails
+///
+/// **Admin only.** Remake every gallery thumbnail.
+///
+/// Drops the cached thumbnail and resized variants of every image and renders
+/// the thumbnails again, in the background; variants come back as they are
+/// asked for. Useful after changing how thumbnails are made (e.g.
+/// `images.animated_thumbnails`). Only one rebuild runs at a time.
+#[utoipa::path(
+    post,
+    path = "/images/thumbnails/rebuild",
+    responses(
+        (status = 200, description = "The rebuild is running", body = ThumbnailRebuild),
+        (status = 401, description = "User is unauthenticated", body = ApiError),
+        (status = 403, description = "The account is not an admin", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(
+        ("api_key" = ["images:write"])
+    ),
+    tag = "images"
+)]
+pub async fn rebuild_thumbnails(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    auth: ApiToken,
+) -> Result<Json<ThumbnailRebuild>, ApiError> {
+    let account = auth.require_account(&state, Scope::ImagesWrite).await?;
+    if !account.flags.is_admin() {
+        return Err(ApiError::forbidden());
+    }
+    let (cached, cached_bytes) = state.renditions().map(|disk| disk.usage()).unwrap_or_default();
+    let started = renditions::start_rebuild(state.clone());
+    if started {
+        state
+            .audit("image.thumbnails.rebuild")
+            .actor(&account)
+            .ip_opt(client_ip)
+            .fire();
+    }
+    Ok(Json(ThumbnailRebuild {
+        started,
+        cached,
+        cached_bytes,
+    }))
+}
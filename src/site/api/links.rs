@@ -4,7 +4,9 @@
 //! web UI (see [`crate::site::links`]) over the JSON API, gated by the
 //! `links:read` / `links:write` scopes. Validation, the per-account free-tier
 //! cap, and code generation are shared with the web form so both surfaces behave
-//! identically.
+//! identically. That includes a link's limits: when it starts and stops
+//! redirecting, and after how many clicks; and its gates: a password, and an
+//! interstitial that shows the destination first.
 
 use axum::extract::{Path, Query, State};
 use serde::{Deserialize, Serialize};
@@ -18,7 +20,14 @@ use crate::{
     error::ApiError,
     headers::ClientIp,
     models::{Scope, ShortLink},
-    site::links::{count_links, insert_link, normalize_target, validate_code, InsertError, FREE_LINK_LIMIT},
+    site::link_analytics::{self, LinkAnalytics},
+    site::link_lock::LinkGate,
+    site::links::{
+        count_links, insert_link, link_status, normalize_target, update_link as apply_update, validate_code,
+        InsertError, LinkLimits, FREE_LINK_LIMIT,
+    },
+    site::stats::{self, StatsQuery, ViewKind, ViewStats},
+    site::trash::{self, TrashKind},
     utils::get_new_image_id,
     AppState,
 };
@@ -36,6 +45,26 @@ pub struct ApiShortLink {
     pub clicks: i64,
     /// Creation timestamp (RFC 3339).
     pub created_at: String,
+    /// When the link starts redirecting (RFC 3339), if it was scheduled.
+    pub active_from: Option<String>,
+    /// When the link stops redirecting (RFC 3339), if ever.
+    pub expires_at: Option<String>,
+    /// How many clicks the link allows, if it is limited.
+    pub max_clicks: Option<i64>,
+    /// `active`, or why the link doesn't redirect right now: `scheduled`,
+    /// `expired`, `used_up` or `blocked` (its destination was put on the
+    /// site's blocklist).
+    pub status: String,
+    /// Whether the link asks for a password before redirecting.
+    pub has_password: bool,
+    /// Whether the link shows its destination and waits for the visitor to
+    /// continue.
+    pub interstitial: bool,
+}
+
+fn rfc3339(at: time::OffsetDateTime) -> String {
+    at.format(&time::format_description::well_known::Rfc3339)
+        .unwrap_or_default()
 }
 
 impl ApiShortLink {
@@ -43,13 +72,16 @@ impl ApiShortLink {
         let short_url = state.config().short_link_url(&link.code);
         Self {
             short_url,
+            status: link_status(state, &link).as_str().to_string(),
+            created_at: rfc3339(link.created_at),
+            active_from: link.active_from.map(rfc3339),
+            expires_at: link.expires_at.map(rfc3339),
+            max_clicks: link.max_clicks,
+            has_password: link.is_locked(),
+            interstitial: link.interstitial,
             code: link.code,
             target_url: link.target_url,
             clicks: link.clicks,
-            created_at: link
-                .created_at
-                .format(&time::format_description::well_known::Rfc3339)
-                .unwrap_or_default(),
         }
     }
 }
@@ -63,19 +95,64 @@ pub struct CreateLinkBody {
     /// code.
     #[serde(default)]
     pub code: Option<String>,
+    /// When the link starts redirecting (RFC 3339). Omit to start right away.
+    #[serde(default)]
+    pub active_from: Option<String>,
+    /// When the link stops redirecting (RFC 3339). Omit for never.
+    #[serde(default)]
+    pub expires_at: Option<String>,
+    /// How many clicks the link allows before it stops redirecting. Omit for
+    /// no limit.
+    #[serde(default)]
+    pub max_clicks: Option<i64>,
+    /// A password visitors must enter before the link redirects.
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Show visitors the destination, and wait for them to continue.
+    #[serde(default)]
+    pub interstitial: Option<bool>,
+}
+
+/// Reads the limits of a create or update body. An empty time or a `0` click
+/// limit removes that limit.
+fn body_limits(
+    active_from: Option<&str>,
+    expires_at: Option<&str>,
+    max_clicks: Option<i64>,
+    link: Option<&ShortLink>,
+) -> Result<LinkLimits, ApiError> {
+    let limits = LinkLimits {
+        active_from: LinkLimits::time(active_from).map_err(|e| ApiError::validation("active_from", e))?,
+        expires_at: LinkLimits::time(expires_at).map_err(|e| ApiError::validation("expires_at", e))?,
+        max_clicks: LinkLimits::clicks(max_clicks).map_err(|e| ApiError::validation("max_clicks", e))?,
+    };
+    limits
+        .validate(link)
+        .map_err(|(field, e)| ApiError::validation(field, e))?;
+    Ok(limits)
+}
+
+/// Reads the gates of a create or update body. An empty password removes it.
+fn body_gate(password: Option<&str>, interstitial: Option<bool>) -> Result<LinkGate, ApiError> {
+    LinkGate::new(password, interstitial).map_err(|e| ApiError::validation("password", e))
 }
 
 /// Create a short link
 ///
 /// Creates a short link for the authenticated account. Non-admin accounts are
-/// capped at 10 links (delete one to make room).
+/// capped at 10 links (delete one and empty it from the trash to make room).
+/// The link can be scheduled to start later, to expire, and to stop after a
+/// number of clicks; outside of
+/// those limits it answers with a page saying so instead of redirecting. A
+/// link with a password asks for it, and one with `interstitial` set shows
+/// where it goes, before redirecting.
 #[utoipa::path(
     post,
     path = "/links",
     request_body(content = CreateLinkBody, content_type = "application/json"),
     responses(
         (status = 200, description = "The created short link", body = ApiShortLink),
-        (status = 400, description = "Invalid URL or alias", body = ApiError),
+        (status = 400, description = "Invalid or blocked URL, alias, limits or password", body = ApiError),
         (status = 401, description = "Unauthenticated", body = ApiError),
         (status = 403, description = "Missing the links:write scope, or link limit reached", body = ApiError),
         (status = 409, description = "The alias is already taken", body = ApiError),
@@ -94,11 +171,18 @@ pub async fn create_link(
 
     if !account.flags.is_admin() && count_links(&state, account.id).await >= FREE_LINK_LIMIT {
         return Err(ApiError::forbidden().with_message(format!(
-            "short-link limit reached ({FREE_LINK_LIMIT}); delete one to create another"
+            "short-link limit reached ({FREE_LINK_LIMIT}); delete one and empty it from the trash to create another"
         )));
     }
 
-    let target = normalize_target(&body.url).map_err(|e| ApiError::validation("url", e))?;
+    let target = normalize_target(&body.url, &state.link_blocklist()).map_err(|e| ApiError::validation("url", e))?;
+    let limits = body_limits(
+        body.active_from.as_deref(),
+        body.expires_at.as_deref(),
+        body.max_clicks,
+        None,
+    )?;
+    let gate = body_gate(body.password.as_deref(), body.interstitial)?;
 
     let alias = body.code.as_deref().map(str::trim).filter(|c| !c.is_empty());
     let custom_alias = alias.is_some();
@@ -107,7 +191,7 @@ pub async fn create_link(
         None => get_new_image_id(),
     };
 
-    let final_code = insert_link(&state, code, &target, account.id, custom_alias)
+    let final_code = insert_link(&state, code, &target, account.id, custom_alias, &limits, &gate)
         .await
         .map_err(|e| match e {
             InsertError::Taken => {
@@ -161,7 +245,7 @@ pub async fn list_links(
         .database()
         .all(
             "SELECT * FROM short_link \
-             WHERE account_id = ?1 \
+             WHERE account_id = ?1 AND deleted_at IS NULL \
                AND (?2 IS NULL \
                     OR NOT EXISTS (SELECT 1 FROM short_link WHERE code = ?2 AND account_id = ?1) \
                     OR created_at < (SELECT created_at FROM short_link WHERE code = ?2 AND account_id = ?1)) \
@@ -208,18 +292,113 @@ pub async fn get_link(
     Ok(Json(ApiShortLink::from_link(&state, link)))
 }
 
-/// Body of an update-link request.
+/// Short link click statistics
+///
+/// Clicks on one of your short links over the last `days` days (30 by
+/// default), zero-filled per UTC day, plus the sites the clicks came from. The
+/// `series` object drops straight into `POST /render/chart`.
+#[utoipa::path(
+    get,
+    path = "/links/{code}/stats",
+    params(("code" = String, Path, description = "The link's short code / alias."), StatsQuery),
+    responses(
+        (status = 200, description = "The link's click statistics", body = ViewStats),
+        (status = 400, description = "days is out of range", body = ApiError),
+        (status = 401, description = "Unauthenticated", body = ApiError),
+        (status = 403, description = "Missing the links:read scope", body = ApiError),
+        (status = 404, description = "No such link owned by this account", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(("api_key" = ["links:read"])),
+    tag = "links"
+)]
+pub async fn link_stats(
+    State(state): State<AppState>,
+    Path(code): Path<String>,
+    Query(query): Query<StatsQuery>,
+    auth: ApiToken,
+) -> Result<Json<ViewStats>, ApiError> {
+    let account = auth.require_account(&state, Scope::LinksRead).await?;
+    let days = query.days().map_err(|msg| ApiError::validation("days", msg))?;
+
+    let link = fetch_owned_link(&state, &code, account.id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(format!("no short link `{code}`")))?;
+    // Stats are keyed by id, which survives the code being changed on the web.
+    Ok(Json(
+        stats::load(&state, ViewKind::Link, link.id.to_string(), days).await?,
+    ))
+}
+
+/// Short link click analytics
+///
+/// Everything `/links/{code}/stats` returns, plus the clicks broken down by
+/// device class (from the User-Agent: `bot`, `mobile`, `tablet`, `desktop` or
+/// `unknown`) and by country (an ISO 3166-1 alpha-2 code). Countries are
+/// `unknown` unless the site is configured with a GeoIP database. Counted per
+/// UTC day over the last `days` days (30 by default).
+#[utoipa::path(
+    get,
+    path = "/links/{code}/analytics",
+    params(("code" = String, Path, description = "The link's short code / alias."), StatsQuery),
+    responses(
+        (status = 200, description = "The link's click analytics", body = LinkAnalytics),
+        (status = 400, description = "days is out of range", body = ApiError),
+        (status = 401, description = "Unauthenticated", body = ApiError),
+        (status = 403, description = "Missing the links:read scope", body = ApiError),
+        (status = 404, description = "No such link owned by this account", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(("api_key" = ["links:read"])),
+    tag = "links"
+)]
+pub async fn link_analytics(
+    State(state): State<AppState>,
+    Path(code): Path<String>,
+    Query(query): Query<StatsQuery>,
+    auth: ApiToken,
+) -> Result<Json<LinkAnalytics>, ApiError> {
+    let account = auth.require_account(&state, Scope::LinksRead).await?;
+    let days = query.days().map_err(|msg| ApiError::validation("days", msg))?;
+
+    let link = fetch_owned_link(&state, &code, account.id)
+        .await?
+        .ok_or_else(|| ApiError::not_found(format!("no short link `{code}`")))?;
+    Ok(Json(link_analytics::load(&state, link.id, days).await?))
+}
+
+/// Body of an update-link request. Fields left out stay as they are.
 #[derive(Debug, Deserialize, ToSchema)]
 pub struct UpdateLinkBody {
     /// The new destination URL. A missing scheme defaults to `https://`. The
     /// short code itself is immutable — delete and recreate to change it.
-    pub url: String,
+    #[serde(default)]
+    pub url: Option<String>,
+    /// When the link starts redirecting (RFC 3339), or `""` to start right
+    /// away.
+    #[serde(default)]
+    pub active_from: Option<String>,
+    /// When the link stops redirecting (RFC 3339), or `""` for never.
+    #[serde(default)]
+    pub expires_at: Option<String>,
+    /// How many clicks the link allows in all, or `0` for no limit.
+    #[serde(default)]
+    pub max_clicks: Option<i64>,
+    /// A new password, or `""` to remove it.
+    #[serde(default)]
+    pub password: Option<String>,
+    /// Whether to show visitors the destination, and wait for them to
+    /// continue.
+    #[serde(default)]
+    pub interstitial: Option<bool>,
 }
 
 /// Update a short link
 ///
-/// Repoints an existing short link at a new destination. The code stays the
-/// same, so anything already sharing the short URL keeps working.
+/// Repoints an existing short link at a new destination, and changes its
+/// limits and gates. The code stays the same, so anything already sharing the short URL
+/// keeps working. A link that had expired or been used up redirects again if
+/// its new limits allow it.
 #[utoipa::path(
     patch,
     path = "/links/{code}",
@@ -227,7 +406,7 @@ pub struct UpdateLinkBody {
     request_body(content = UpdateLinkBody, content_type = "application/json"),
     responses(
         (status = 200, description = "The updated short link", body = ApiShortLink),
-        (status = 400, description = "Invalid URL", body = ApiError),
+        (status = 400, description = "Invalid or blocked URL, limits or password", body = ApiError),
         (status = 401, description = "Unauthenticated", body = ApiError),
         (status = 403, description = "Missing the links:write scope", body = ApiError),
         (status = 404, description = "No such link owned by this account", body = ApiError),
@@ -245,18 +424,26 @@ pub async fn update_link(
 ) -> Result<Json<ApiShortLink>, ApiError> {
     let account = auth.require_account(&state, Scope::LinksWrite).await?;
 
-    let mut link = fetch_owned_link(&state, &code, account.id)
+    let link = fetch_owned_link(&state, &code, account.id)
         .await?
         .ok_or_else(|| ApiError::not_found(format!("no short link `{code}`")))?;
 
-    let target = normalize_target(&body.url).map_err(|e| ApiError::validation("url", e))?;
+    let blocklist = state.link_blocklist();
+    let target = body
+        .url
+        .as_deref()
+        .map(|url| normalize_target(url, &blocklist))
+        .transpose()
+        .map_err(|e| ApiError::validation("url", e))?;
+    let limits = body_limits(
+        body.active_from.as_deref(),
+        body.expires_at.as_deref(),
+        body.max_clicks,
+        Some(&link),
+    )?;
+    let gate = body_gate(body.password.as_deref(), body.interstitial)?;
 
-    state
-        .database()
-        .execute(
-            "UPDATE short_link SET target_url = ?1, updated_at = CURRENT_TIMESTAMP WHERE id = ?2",
-            (target.clone(), link.id),
-        )
+    apply_update(&state, link.id, target.clone(), None, &limits, &gate)
         .await
         .map_err(|_| ApiError::new("could not update the short link"))?;
 
@@ -265,14 +452,24 @@ pub async fn update_link(
         .actor(&account)
         .target(link.code.clone())
         .ip_opt(client_ip)
-        .meta(serde_json::json!({ "via_api": true, "from": link.target_url, "to": target }))
+        .meta(serde_json::json!({
+            "via_api": true,
+            "from": link.target_url,
+            "to": target.unwrap_or_else(|| link.target_url.clone()),
+        }))
         .fire();
 
-    link.target_url = target;
+    let link = fetch_owned_link(&state, &link.code, account.id)
+        .await?
+        .ok_or_else(|| ApiError::new("link vanished after the update"))?;
     Ok(Json(ApiShortLink::from_link(&state, link)))
 }
 
 /// Delete a short link
+///
+/// The link moves to the account's trash and stops resolving; it can be
+/// restored with `POST /trash/link/{code}/restore` until the restore window
+/// runs out. Its code stays taken meanwhile.
 #[utoipa::path(
     delete,
     path = "/links/{code}",
@@ -299,9 +496,7 @@ pub async fn delete_link(
         .await?
         .ok_or_else(|| ApiError::not_found(format!("no short link `{code}`")))?;
 
-    state
-        .database()
-        .execute("DELETE FROM short_link WHERE id = ?1", [link.id])
+    trash::discard(&state, TrashKind::Link, account.id, &link.code)
         .await
         .map_err(|_| ApiError::new("could not delete the short link"))?;
 
@@ -316,12 +511,12 @@ pub async fn delete_link(
     Ok(Json(ApiShortLink::from_link(&state, link)))
 }
 
-/// Loads a link by code only if the account owns it.
+/// Loads a live link by code only if the account owns it.
 async fn fetch_owned_link(state: &AppState, code: &str, account_id: i64) -> Result<Option<ShortLink>, ApiError> {
     state
         .database()
         .get(
-            "SELECT * FROM short_link WHERE code = ?1 AND account_id = ?2",
+            "SELECT * FROM short_link WHERE code = ?1 AND account_id = ?2 AND deleted_at IS NULL",
             (code.to_string(), account_id),
         )
         .await
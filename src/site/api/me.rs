@@ -15,6 +15,7 @@ use utoipa::ToSchema;
 
 use super::auth::ApiToken;
 use super::utils::{ApiJson as Json, RateLimitResponse};
+use crate::site::image_quota::{Quota, QuotaHeadroom, Usage};
 use crate::{error::ApiError, models::Scope, AppState};
 
 /// How many days of history `/me/usage` returns.
@@ -104,14 +105,17 @@ pub struct ApiUsage {
     pub links: ResourceUsage,
     /// Pastes: count and total views.
     pub pastes: ResourceUsage,
+    /// What's left of the account's image quota. Both fields are `null` for
+    /// admins and when the server sets no limit.
+    pub image_quota: QuotaHeadroom,
     /// Upload activity over the last 30 days, ready to plot.
     pub series: UsageSeries,
 }
 
 /// Get account usage
 ///
-/// Returns resource totals (images, short links, pastes) plus a zero-filled
-/// 30-day upload series. The `series` object is shaped to drop straight into
+/// Returns resource totals (images, short links, pastes), the headroom left
+/// under the image quota, and a zero-filled 30-day upload series. The `series` object is shaped to drop straight into
 /// `POST /render/chart`: use `days` as `labels` and `uploads` /
 /// `upload_bytes` as series data.
 #[utoipa::path(
@@ -206,8 +210,14 @@ pub async fn get_usage(State(state): State<AppState>, auth: ApiToken) -> Result<
         }
     }
 
+    let image_quota = Quota::for_account(&state, &account).headroom(Usage {
+        count: images.count,
+        bytes: images.bytes,
+    });
+
     Ok(Json(ApiUsage {
         images,
+        image_quota,
         links,
         pastes,
         series,
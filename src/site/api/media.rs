@@ -4,14 +4,16 @@
 //!   invert, grayscale) and return a PNG.
 //! - `POST /api/v1/convert` — transcode an image between raster formats
 //!   (PNG → WebP, and friends).
+//! - `POST /api/v1/metadata` and `/metadata/exif` — inspect an image's
+//!   dimensions and format, or its embedded EXIF/XMP/ICC metadata.
 //!
-//! Both accept the source image either as a multipart `file` upload or as a
+//! All accept the source image either as a multipart `file` upload or as a
 //! `url` form field pointing at a public http(s) image. URL fetches are
 //! SSRF-guarded (private/reserved addresses are refused, redirects disabled,
 //! and the download is size-capped).
 
 use axum::extract::{Multipart, Path, Query, State};
-use axum::http::{header, StatusCode};
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
 use axum::response::{IntoResponse, Response};
 use axum::Json;
 use image::imageops::FilterType;
@@ -19,6 +21,9 @@ use image::{DynamicImage, ImageFormat};
 use serde::{Deserialize, Serialize};
 use utoipa::{IntoParams, ToSchema};
 
+use crate::animation::{self, AnimatedFormat, AnimationError, Budget};
+use crate::conditional::{self, Validators};
+use crate::metadata::{self, MetadataReport};
 use crate::{error::ApiError, headers::ClientIp, models::Scope, AppState};
 
 use super::auth::ApiToken;
@@ -95,6 +100,12 @@ fn decode_image(bytes: &[u8]) -> Result<DynamicImage, ApiError> {
         .map_err(|e| ApiError::new(format!("could not decode image: {e}")))
 }
 
+/// Over-budget animations are the caller's problem (400); anything else the
+/// codec trips over is reported the same way a bad still is.
+fn animation_error(e: AnimationError) -> ApiError {
+    ApiError::new(e.to_string())
+}
+
 fn encode_png(img: &DynamicImage) -> Result<Vec<u8>, ApiError> {
     let mut buf = std::io::Cursor::new(Vec::new());
     img.write_to(&mut buf, ImageFormat::Png)
@@ -161,7 +172,11 @@ fn apply_op(op: &str, img: DynamicImage, amount: Option<f32>) -> Result<DynamicI
     })
 }
 
-fn encode_to(img: &DynamicImage, to: &str, quality: u8) -> Result<(Vec<u8>, &'static str, &'static str), ApiError> {
+pub(crate) fn encode_to(
+    img: &DynamicImage,
+    to: &str,
+    quality: u8,
+) -> Result<(Vec<u8>, &'static str, &'static str), ApiError> {
     let mut buf = std::io::Cursor::new(Vec::new());
     let enc = |buf: &mut std::io::Cursor<Vec<u8>>, fmt: ImageFormat, img: &DynamicImage| {
         img.write_to(buf, fmt)
@@ -247,7 +262,9 @@ pub struct ShareResult {
 
 /// Manipulate
 ///
-/// Apply a visual effect to an image and get a PNG back.
+/// Apply a visual effect to an image and get a PNG back. Animated GIFs and
+/// WebPs get the effect on every frame and come back animated, in their own
+/// format.
 ///
 /// The `{op}` path segment selects the effect:
 ///
@@ -258,7 +275,8 @@ pub struct ShareResult {
 /// - `grayscale` — desaturate to gray.
 ///
 /// Supply the source as a multipart `file` upload or a `url` form field. The
-/// result is returned as `image/png`, or — with `share=true` — as a JSON
+/// result is returned as `image/png` (`image/gif` or `image/webp` for an
+/// animation), or — with `share=true` — as a JSON
 /// `ShareResult` carrying a short `/m/:id` link to the stored image.
 #[utoipa::path(
     post,
@@ -273,8 +291,8 @@ pub struct ShareResult {
         ManipulateParams,
     ),
     responses(
-        (status = 200, description = "The processed image", content_type = "image/png", body = Vec<u8>),
-        (status = 400, description = "Bad input or unknown operation", body = ApiError),
+        (status = 200, description = "The processed image (GIF or WebP for an animation)", content_type = "image/png", body = Vec<u8>),
+        (status = 400, description = "Bad input, unknown operation, or an animation over the size limits", body = ApiError),
         (status = 401, description = "User is unauthenticated", body = ApiError),
         (status = 429, response = RateLimitResponse),
     ),
@@ -304,10 +322,16 @@ pub async fn manipulate_image(
     let amount = params.amount;
     let share = params.share.unwrap_or(false);
     let op_for_task = op.clone();
-    let out = tokio::task::spawn_blocking(move || -> Result<Vec<u8>, ApiError> {
+    let (out, mime) = tokio::task::spawn_blocking(move || -> Result<(Vec<u8>, &'static str), ApiError> {
+        // Animations get the effect on every frame and keep their format.
+        if let Some(anim) = animation::decode(&bytes, Budget::default()).map_err(animation_error)? {
+            let format = anim.format;
+            let anim = anim.map(|frame| apply_op(&op_for_task, frame, amount))?;
+            return Ok((anim.encode(format).map_err(animation_error)?, format.mime()));
+        }
         let img = decode_image(&bytes)?;
         let img = apply_op(&op_for_task, img, amount)?;
-        encode_png(&img)
+        Ok((encode_png(&img)?, "image/png"))
     })
     .await
     .map_err(|_| ApiError::new("image processing task failed"))??;
@@ -320,9 +344,9 @@ pub async fn manipulate_image(
         .fire();
 
     if share {
-        return Ok(Json(share_result(&state, out, "image/png")).into_response());
+        return Ok(Json(share_result(&state, out, mime)).into_response());
     }
-    Ok(([(header::CONTENT_TYPE, "image/png".to_string())], out).into_response())
+    Ok(([(header::CONTENT_TYPE, mime.to_string())], out).into_response())
 }
 
 /// Convert
@@ -331,7 +355,12 @@ pub async fn manipulate_image(
 ///
 /// Supported targets (`to` query parameter): `png`, `jpeg` (alias `jpg`),
 /// `webp`, `gif`, `bmp`, `tiff`. WebP output is lossless; JPEG honours the
-/// optional `quality` parameter (1–100, default 85).
+/// optional `quality` parameter (1–100, default 85). An animated GIF or WebP
+/// converted to `gif` or `webp` keeps all its frames; other targets get the
+/// first frame.
+///
+/// Animations are limited to 500 frames and 32 million pixels across all
+/// frames, here and for effects.
 ///
 /// Supply the source as a multipart `file` upload or a `url` form field. The
 /// response carries the matching `Content-Type` and a `Content-Disposition`
@@ -371,11 +400,20 @@ pub async fn convert_file(
     let quality = params.quality.unwrap_or(85).clamp(1, 100);
     let share = params.share.unwrap_or(false);
     let to_for_task = to.clone();
-    let (data, mime, ext) = tokio::task::spawn_blocking(move || {
-        decode_image(&bytes).and_then(|img| encode_to(&img, &to_for_task, quality))
-    })
-    .await
-    .map_err(|_| ApiError::new("image conversion task failed"))??;
+    let (data, mime, ext) =
+        tokio::task::spawn_blocking(move || -> Result<(Vec<u8>, &'static str, &'static str), ApiError> {
+            // GIF ⇄ WebP keeps an animation moving; any other target gets its
+            // first frame.
+            if let Some(target) = AnimatedFormat::from_name(&to_for_task) {
+                if let Some(anim) = animation::decode(&bytes, Budget::default()).map_err(animation_error)? {
+                    let data = anim.encode(target).map_err(animation_error)?;
+                    return Ok((data, target.mime(), target.ext()));
+                }
+            }
+            decode_image(&bytes).and_then(|img| encode_to(&img, &to_for_task, quality))
+        })
+        .await
+        .map_err(|_| ApiError::new("image conversion task failed"))??;
 
     state
         .audit("api.convert")
@@ -485,6 +523,50 @@ pub async fn image_info(
     Ok(Json(info))
 }
 
+/// EXIF
+///
+/// Read an image's embedded metadata without storing anything: EXIF fields
+/// (camera, lens, exposure, timestamps, orientation and GPS position), the XMP
+/// packet, the ICC colour profile, and text chunks and comments.
+///
+/// JPEG, PNG, WebP, GIF and TIFF are understood. Supply the source as a
+/// multipart `file` upload or a `url` form field.
+#[utoipa::path(
+    post,
+    path = "/metadata/exif",
+    request_body(
+        content = inline(ImageInput),
+        content_type = "multipart/form-data",
+        description = "The source image, as a `file` upload or a `url` field."
+    ),
+    responses(
+        (status = 200, description = "The image's metadata", body = MetadataReport),
+        (status = 400, description = "Bad input or not a supported image format", body = ApiError),
+        (status = 401, description = "User is unauthenticated", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(
+        ("api_key" = ["images:read"])
+    ),
+    tag = "media"
+)]
+pub async fn image_exif(
+    State(state): State<AppState>,
+    auth: ApiToken,
+    multipart: Multipart,
+) -> Result<Json<MetadataReport>, ApiError> {
+    auth.require_account(&state, Scope::ImagesRead).await?;
+
+    let bytes = read_image_input(multipart).await?;
+    let report = tokio::task::spawn_blocking(move || metadata::inspect(&bytes))
+        .await
+        .map_err(|_| ApiError::new("image inspection task failed"))?;
+
+    report
+        .map(Json)
+        .ok_or_else(|| ApiError::new("not a JPEG, PNG, WebP, GIF or TIFF image"))
+}
+
 // ─── Color palette extraction ──────────────────────────────────────────────────
 
 /// One extracted palette color.
@@ -570,7 +652,7 @@ pub async fn color_palette(
 /// 4 bits per channel, average each bucket's true colors, then merge buckets
 /// that are perceptually close so near-identical shades don't crowd out the
 /// rest of the palette. Deterministic (no k-means seeding).
-fn extract_palette(img: &DynamicImage, count: usize) -> PaletteResult {
+pub(crate) fn extract_palette(img: &DynamicImage, count: usize) -> PaletteResult {
     // Downscale for a bounded, structure-preserving sample.
     let small = img.resize(96, 96, FilterType::Triangle).to_rgba8();
 
@@ -646,19 +728,19 @@ fn share_result(state: &AppState, bytes: Vec<u8>, content_type: &str) -> ShareRe
 
 /// Serves a previously shared processed image by its short id. Public (no
 /// auth) so the `/m/:id` links can be embedded anywhere. Returns 404 once the
-/// entry has been evicted from the bounded cache.
-pub async fn serve_media(State(state): State<AppState>, Path(id): Path<String>) -> Response {
-    match state.get_media(&id) {
-        Some(media) => (
-            [
-                (header::CONTENT_TYPE, media.content_type),
-                (header::CACHE_CONTROL, "public, max-age=86400".to_string()),
-            ],
-            media.bytes,
-        )
-            .into_response(),
-        None => (StatusCode::NOT_FOUND, "media not found or expired").into_response(),
+/// entry has been evicted from the bounded cache. Supports revalidation and
+/// `Range` requests like the gallery's raw route.
+pub async fn serve_media(State(state): State<AppState>, Path(id): Path<String>, request: HeaderMap) -> Response {
+    let Some(media) = state.get_media(&id) else {
+        return (StatusCode::NOT_FOUND, "media not found or expired").into_response();
+    };
+    let mut headers = HeaderMap::new();
+    if let Ok(value) = HeaderValue::from_str(&media.content_type) {
+        headers.insert(header::CONTENT_TYPE, value);
     }
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("public, max-age=86400"));
+    let validators = Validators::new(&media.hash, Some(media.created_at));
+    conditional::respond(&request, &validators, headers, media.bytes.into()).await
 }
 
 #[cfg(test)]
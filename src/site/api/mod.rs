@@ -1,16 +1,21 @@
+mod albums;
 mod auth;
-mod chart;
+pub(crate) mod chart;
 mod code;
 mod external;
 mod guild_images;
 mod images;
 mod links;
 mod me;
-mod media;
+pub(crate) mod media;
 mod pastes;
+pub(crate) mod presign;
 mod qr;
 mod scan;
-mod unfurl;
+mod search;
+mod trash;
+pub(crate) mod unfurl;
+pub(crate) mod uploads;
 pub mod utils;
 
 use crate::{models::Account, ratelimit::RateLimit, AppState};
@@ -19,7 +24,7 @@ use axum::routing::delete;
 use axum::{
     extract::State,
     http::{
-        header::{AUTHORIZATION, USER_AGENT},
+        header::{AUTHORIZATION, CONTENT_TYPE, LOCATION, USER_AGENT},
         HeaderValue, Method,
     },
     middleware::map_response,
@@ -48,14 +53,33 @@ pub use media::serve_media;
     ),
     paths(
         images::upload_files,
+        images::import_images,
+        presign::presign_upload,
         images::delete_image_by_id,
+        images::update_image,
+        images::replace_image,
+        images::list_image_revisions,
+        images::get_image_revision,
+        images::restore_image_revision,
         images::download_images,
+        images::similar_images,
+        images::image_stats,
+        images::block_image,
+        images::rebuild_thumbnails,
+        uploads::upload_options,
+        uploads::create_upload,
+        uploads::upload_offset,
+        uploads::upload_chunk,
+        uploads::upload_status,
+        uploads::delete_upload,
         guild_images::upload_guild_images,
         guild_images::list_guild_images,
         guild_images::delete_guild_image,
         links::create_link,
         links::list_links,
         links::get_link,
+        links::link_stats,
+        links::link_analytics,
         links::update_link,
         links::delete_link,
         pastes::create_paste,
@@ -64,16 +88,31 @@ pub use media::serve_media;
         pastes::update_paste,
         pastes::fork_paste,
         pastes::list_revisions,
+        pastes::paste_stats,
         pastes::delete_paste,
+        albums::create_album,
+        albums::list_albums,
+        albums::get_album,
+        albums::update_album,
+        albums::delete_album,
+        albums::add_album_images,
+        albums::reorder_album_images,
+        albums::remove_album_image,
         media::manipulate_image,
         media::convert_file,
         media::image_info,
+        media::image_exif,
         media::color_palette,
         code::render_code,
         qr::render_qr,
         chart::render_chart,
         me::get_me,
         me::get_usage,
+        search::search_content,
+        trash::list_trash,
+        trash::restore_item,
+        trash::purge_item,
+        trash::empty_trash,
         external::screenshot,
         external::markdown_pdf,
         external::transcode,
@@ -84,9 +123,33 @@ pub use media::serve_media;
         schemas(
             ApiError,
             crate::models::ImageEntry,
+            crate::models::ScanStatus,
             crate::site::image::UploadResult,
             crate::site::image::DeleteResult,
             crate::site::image::BulkFilesPayload,
+            crate::site::image::DuplicateUpload,
+            crate::site::image::DuplicatePolicy,
+            crate::placeholder::Placeholder,
+            presign::PresignBody,
+            presign::PresignedUpload,
+            images::SimilarImage,
+            images::SimilarImages,
+            images::BlockImageBody,
+            images::BlockResult,
+            images::ThumbnailRebuild,
+            images::ImageAccess,
+            images::ImageVersions,
+            crate::models::ImageRevision,
+            crate::site::image_import::ImportResult,
+            crate::site::image_import::ImportEntry,
+            crate::site::image::UploadOutcome,
+            crate::site::image_lock::ImageSettings,
+            crate::site::stats::ViewStats,
+            crate::site::stats::ViewSeries,
+            crate::site::stats::ReferrerViews,
+            crate::site::link_analytics::LinkAnalytics,
+            crate::site::link_analytics::ClickShare,
+            uploads::UploadStatus,
             guild_images::GuildImageInfo,
             guild_images::GuildImagesResult,
             links::ApiShortLink,
@@ -96,8 +159,19 @@ pub use media::serve_media;
             pastes::ApiRevision,
             pastes::CreatePasteBody,
             pastes::UpdatePasteBody,
+            albums::ApiAlbum,
+            albums::ApiAlbumImage,
+            albums::CreateAlbumBody,
+            albums::UpdateAlbumBody,
+            albums::AlbumImagesBody,
             crate::scan::ScanReport,
             media::ImageInfo,
+            crate::metadata::MetadataReport,
+            crate::metadata::ExifData,
+            crate::metadata::GpsPosition,
+            crate::metadata::IccProfile,
+            crate::metadata::TextEntry,
+            crate::metadata::Keep,
             media::ShareResult,
             media::PaletteColor,
             media::PaletteResult,
@@ -114,6 +188,14 @@ pub use media::serve_media;
             me::ApiUsage,
             me::ResourceUsage,
             me::UsageSeries,
+            crate::site::image_quota::QuotaHeadroom,
+            search::SearchResults,
+            crate::site::search::Hit,
+            crate::site::search::SearchKind,
+            trash::TrashList,
+            trash::TrashEmptied,
+            crate::site::trash::TrashItem,
+            crate::site::trash::TrashKind,
             external::ScreenshotRequest,
             external::MarkdownRequest,
             unfurl::UnfurlResult,
@@ -123,11 +205,13 @@ pub use media::serve_media;
     modifiers(&RequiredAuthentication),
     tags(
         (name = "images", description = "Endpoints for uploading/deleting and getting images at the server."),
+        (name = "uploads", description = "Resumable uploads for large files over the tus 1.0 protocol."),
         (name = "links", description = "Create, list, and delete your short links (URL shortener)."),
         (name = "pastes", description = "Create, list, read, and delete hosted text/code pastes."),
+        (name = "albums", description = "Group your images into ordered albums with a cover, viewable at `/album/{id}`."),
         (name = "media", description = "Image manipulation and format conversion. Accepts a `file` upload or a public image `url`."),
         (name = "render", description = "Render content to images (syntax-highlighted code screenshots, QR codes, charts, …)."),
-        (name = "account", description = "Introspect the calling account: identity, key scopes, and resource usage."),
+        (name = "account", description = "Introspect the calling account: identity, key scopes, and resource usage, search its content, and manage its trash."),
         (name = "web", description = "Web utilities: unfurl a URL into Open Graph / link-preview metadata."),
         (name = "scan", description = "Scan uploaded files for malware via ClamAV and VirusTotal.")
     )
@@ -279,23 +363,47 @@ mod tests {
         let base = api_base_path();
         for suffix in [
             "/scan",
+            "/images/import",
             "/convert",
             "/image/{op}",
             "/metadata",
+            "/metadata/exif",
             "/render/code",
             "/render/qr",
             "/render/chart",
             "/color/palette",
             "/me",
             "/me/usage",
+            "/search",
+            "/trash",
+            "/trash/{kind}/{id}",
+            "/trash/{kind}/{id}/restore",
             "/render/screenshot",
             "/render/markdown-pdf",
             "/convert/transcode",
             "/unfurl",
             "/links",
             "/links/{code}",
+            "/links/{code}/stats",
+            "/links/{code}/analytics",
             "/pastes",
             "/pastes/{id}",
+            "/pastes/{id}/stats",
+            "/albums",
+            "/albums/{id}",
+            "/albums/{id}/images",
+            "/albums/{id}/images/{image_id}",
+            "/images/presign",
+            "/images/{id}",
+            "/images/{id}/similar",
+            "/images/{id}/stats",
+            "/images/{id}/block",
+            "/images/thumbnails/rebuild",
+            "/images/{id}/revisions",
+            "/images/{id}/revisions/{revision}",
+            "/images/{id}/revisions/{revision}/restore",
+            "/uploads",
+            "/uploads/{id}",
         ] {
             let expected = format!("{base}{suffix}");
             assert!(paths.contains_key(&expected), "missing {expected} in OpenAPI spec");
@@ -326,8 +434,26 @@ mod tests {
 fn v1() -> Router<AppState> {
     Router::new()
         .route("/images/upload", post(images::upload_files))
+        .route("/images/import", post(images::import_images))
+        .route("/images/presign", post(presign::presign_upload))
         .route("/images/download", post(images::download_images))
-        .route("/images/:id", delete(images::delete_image_by_id))
+        .route(
+            "/images/:id",
+            delete(images::delete_image_by_id)
+                .patch(images::update_image)
+                .put(images::replace_image),
+        )
+        .route("/images/:id/revisions", get(images::list_image_revisions))
+        .route("/images/:id/revisions/:revision", get(images::get_image_revision))
+        .route(
+            "/images/:id/revisions/:revision/restore",
+            post(images::restore_image_revision),
+        )
+        .route("/images/:id/similar", get(images::similar_images))
+        .route("/images/:id/stats", get(images::image_stats))
+        .route("/images/:id/block", post(images::block_image))
+        .route("/images/thumbnails/rebuild", post(images::rebuild_thumbnails))
+        .merge(uploads::routes())
         .route(
             "/guilds/:guild_id/images/upload",
             post(guild_images::upload_guild_images),
@@ -348,6 +474,8 @@ fn v1() -> Router<AppState> {
                 .patch(links::update_link)
                 .delete(links::delete_link),
         )
+        .route("/links/:code/stats", get(links::link_stats))
+        .route("/links/:code/analytics", get(links::link_analytics))
         .route("/pastes", post(pastes::create_paste).get(pastes::list_pastes))
         .route(
             "/pastes/:id",
@@ -357,10 +485,28 @@ fn v1() -> Router<AppState> {
         )
         .route("/pastes/:id/fork", post(pastes::fork_paste))
         .route("/pastes/:id/revisions", get(pastes::list_revisions))
+        .route("/pastes/:id/stats", get(pastes::paste_stats))
+        .route("/albums", post(albums::create_album).get(albums::list_albums))
+        .route(
+            "/albums/:id",
+            get(albums::get_album)
+                .patch(albums::update_album)
+                .delete(albums::delete_album),
+        )
+        .route(
+            "/albums/:id/images",
+            post(albums::add_album_images).patch(albums::reorder_album_images),
+        )
+        .route("/albums/:id/images/:image_id", delete(albums::remove_album_image))
         .route("/scan", post(scan::scan_file))
         .route("/me", get(me::get_me))
         .route("/me/usage", get(me::get_usage))
+        .route("/search", get(search::search_content))
+        .route("/trash", get(trash::list_trash).delete(trash::empty_trash))
+        .route("/trash/:kind/:id", delete(trash::purge_item))
+        .route("/trash/:kind/:id/restore", post(trash::restore_item))
         .route("/metadata", post(media::image_info))
+        .route("/metadata/exif", post(media::image_exif))
         .route("/image/:op", post(media::manipulate_image))
         .route("/convert", post(media::convert_file))
         .route("/color/palette", post(media::color_palette))
@@ -393,9 +539,28 @@ pub fn routes() -> Router<AppState> {
         .route_layer(RateLimit::default().quota(25, 60.0).build())
         .route_layer(
             CorsLayer::new()
-                .allow_methods([Method::GET, Method::POST, Method::PATCH, Method::DELETE])
+                .allow_methods([Method::GET, Method::HEAD, Method::POST, Method::PATCH, Method::DELETE])
                 .allow_credentials(true)
                 .allow_origin(AllowOrigin::mirror_request())
-                .allow_headers([AUTHORIZATION, USER_AGENT]),
+                .allow_headers([
+                    AUTHORIZATION,
+                    USER_AGENT,
+                    CONTENT_TYPE,
+                    uploads::TUS_RESUMABLE,
+                    uploads::UPLOAD_LENGTH,
+                    uploads::UPLOAD_OFFSET,
+                    uploads::UPLOAD_METADATA,
+                ])
+                // Browser tus clients have to be able to read these back.
+                .expose_headers([
+                    LOCATION,
+                    uploads::TUS_RESUMABLE,
+                    uploads::TUS_VERSION_HEADER,
+                    uploads::TUS_MAX_SIZE,
+                    uploads::TUS_EXTENSION,
+                    uploads::UPLOAD_LENGTH,
+                    uploads::UPLOAD_OFFSET,
+                    uploads::UPLOAD_EXPIRES,
+                ]),
         )
 }
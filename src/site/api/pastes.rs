@@ -22,6 +22,7 @@ use super::{
 };
 use crate::site::paste::crypto;
 use crate::site::paste::service::{self, Actor, Creator, EditPaste, NewPaste, PasteError};
+use crate::site::stats::{self, StatsQuery, ViewKind, ViewStats};
 use crate::{
     error::ApiError,
     headers::ClientIp,
@@ -276,7 +277,7 @@ pub async fn list_pastes(
         .database()
         .all(
             "SELECT * FROM paste \
-             WHERE account_id = ?1 \
+             WHERE account_id = ?1 AND deleted_at IS NULL \
                AND (expires_at IS NULL OR datetime(expires_at) > datetime('now')) \
                AND (?2 IS NULL \
                     OR NOT EXISTS (SELECT 1 FROM paste WHERE id = ?2 AND account_id = ?1) \
@@ -492,7 +493,46 @@ pub async fn list_revisions(
     Ok(Json(revisions))
 }
 
+/// Paste view statistics
+///
+/// Views of one of your pastes over the last `days` days (30 by default),
+/// zero-filled per UTC day, plus the sites that sent them. The `series` object
+/// drops straight into `POST /render/chart`. Only views that showed the body
+/// count, as with the paste's `views` total.
+#[utoipa::path(
+    get,
+    path = "/pastes/{id}/stats",
+    params(("id" = String, Path, description = "The paste's id."), StatsQuery),
+    responses(
+        (status = 200, description = "The paste's view statistics", body = ViewStats),
+        (status = 400, description = "days is out of range", body = ApiError),
+        (status = 401, description = "Unauthenticated", body = ApiError),
+        (status = 403, description = "Missing the pastes:read scope", body = ApiError),
+        (status = 404, description = "No such paste owned by this account", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(("api_key" = ["pastes:read"])),
+    tag = "pastes"
+)]
+pub async fn paste_stats(
+    State(state): State<AppState>,
+    Path(id): Path<String>,
+    Query(query): Query<StatsQuery>,
+    auth: ApiToken,
+) -> Result<Json<ViewStats>, ApiError> {
+    let account = auth.require_account(&state, Scope::PastesRead).await?;
+    let days = query.days().map_err(|msg| ApiError::validation("days", msg))?;
+    let paste = service::load_for(&state, &id, &Actor::account(&account))
+        .await
+        .map_err(api_error)?;
+
+    Ok(Json(stats::load(&state, ViewKind::Paste, paste.id, days).await?))
+}
+
 /// Delete a paste
+///
+/// The paste moves to the account's trash, from which it can be restored with
+/// `POST /trash/paste/{id}/restore` until the restore window runs out.
 #[utoipa::path(
     delete,
     path = "/pastes/{id}",
@@ -0,0 +1,399 @@
+//! Presigned upload URLs.
+//!
+//! Lets a frontend hand a browser a URL it can upload one image to without the
+//! browser ever seeing an API key. `POST /images/presign` signs a [`Grant`] —
+//! the account, an expiry, and the limits the upload has to fit — with the app
+//! [`SecretKey`] (the primitive the session and paste-unlock cookies use), and
+//! `POST /images/upload?signature=…` verifies it in place of an
+//! `Authorization` header. The grant's nonce is recorded in `presigned_upload`
+//! when it's redeemed, so each URL uploads exactly once.
+//!
+//! Nothing else is stored: revoking the API key that minted a URL doesn't
+//! revoke the URL, which is why lifetimes are short.
+
+use axum::extract::{Multipart, State};
+use axum::Extension;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+use super::{
+    auth::ApiToken,
+    utils::{ApiJson as Json, RateLimitResponse},
+};
+use crate::site::album;
+use crate::site::image::{self, UploadGrant, UploadResult};
+use crate::{boxed_params, error::ApiError, headers::ClientIp, key::SecretKey, models::Scope, AppState};
+
+/// How long a URL stays valid when the request doesn't say (15 minutes).
+const DEFAULT_TTL_SECONDS: i64 = 15 * 60;
+/// The longest a URL can stay valid (one day).
+const MAX_TTL_SECONDS: i64 = 24 * 3600;
+/// The most MIME types one URL can list.
+const MAX_TYPES: usize = 16;
+
+/// The signed contents of a presigned upload URL.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Grant {
+    /// Random id, recorded when the URL is redeemed so it only works once.
+    nonce: String,
+    /// The account the upload is attributed to.
+    account: i64,
+    /// Unix timestamp the URL stops working at.
+    exp: i64,
+    max_size: u64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    types: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expires_in: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    album: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    guild: Option<String>,
+}
+
+/// Signs a grant into the URL-safe form used in `?signature=`.
+fn sign(secret: &SecretKey, grant: &Grant) -> anyhow::Result<String> {
+    Ok(URL_SAFE_NO_PAD.encode(secret.sign(grant)?))
+}
+
+/// Verifies a `?signature=` value, rejecting one that has expired by `now`.
+fn verify(secret: &SecretKey, signature: &str, now: OffsetDateTime) -> Option<Grant> {
+    let token = String::from_utf8(URL_SAFE_NO_PAD.decode(signature).ok()?).ok()?;
+    let grant: Grant = secret.verify(&token)?;
+    (grant.exp > now.unix_timestamp()).then_some(grant)
+}
+
+/// The query parameter a presigned upload carries.
+#[derive(Debug, Default, Deserialize)]
+pub(super) struct SignatureQuery {
+    pub signature: Option<String>,
+}
+
+/// What a presigned upload URL should allow.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct PresignBody {
+    /// How long the URL stays valid, in seconds (default 900, max 86400).
+    pub ttl: Option<i64>,
+    /// The largest file accepted, in bytes. Defaults to, and can't exceed, the
+    /// server's upload limit.
+    pub max_size: Option<u64>,
+    /// The MIME types the file may be, e.g. `["image/png", "image/jpeg"]`.
+    /// Every image type the site hosts when omitted.
+    #[serde(default)]
+    pub allowed_types: Vec<String>,
+    /// Time-to-live of the uploaded image in seconds, as `expires_in` on
+    /// `/images/upload`. Permanent when omitted.
+    pub expires_in: Option<i64>,
+    /// One of your albums to add the image to. Needs `albums:write`.
+    pub album: Option<String>,
+    /// A guild gallery to tag the image with. Needs `images:guild`, and the
+    /// account has to be the one that guild's key belongs to.
+    pub guild_id: Option<String>,
+}
+
+/// A presigned upload URL.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct PresignedUpload {
+    /// `POST` a multipart `file` here; no `Authorization` header needed.
+    pub url: String,
+    /// The signature on its own, for clients that build the URL themselves.
+    pub signature: String,
+    /// When the URL stops working (RFC 3339).
+    pub expires_at: String,
+    /// The largest file the URL accepts, in bytes.
+    pub max_size: u64,
+}
+
+/// Presign upload
+///
+/// Create a URL that lets whoever holds it upload **one** image to your
+/// account, without an API key — for handing to a browser. The URL expires
+/// after `ttl` seconds and can carry a size limit, a list of allowed MIME
+/// types, an expiry for the uploaded image, and an album or guild gallery to
+/// file it in.
+///
+/// Upload to it like `/images/upload`, as multipart with a `file` field. Only
+/// the first file that fits is stored. A URL stays usable if its upload is
+/// rejected (wrong type, too large, …) and is spent once an image is stored.
+#[utoipa::path(
+    post,
+    path = "/images/presign",
+    request_body = PresignBody,
+    responses(
+        (status = 200, description = "The presigned URL", body = PresignedUpload),
+        (status = 400, description = "A limit is out of range", body = ApiError),
+        (status = 401, description = "User is unauthenticated", body = ApiError),
+        (status = 403, description = "The API key is missing the images:write scope (or albums:write / images:guild), or the account doesn't belong to the guild", body = ApiError),
+        (status = 404, description = "The album does not exist or is not yours", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(
+        ("api_key" = ["images:write"])
+    ),
+    tag = "images"
+)]
+pub async fn presign_upload(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    Extension(secret): Extension<SecretKey>,
+    auth: ApiToken,
+    Json(body): Json<PresignBody>,
+) -> Result<Json<PresignedUpload>, ApiError> {
+    let account = auth.require_account(&state, Scope::ImagesWrite).await?;
+
+    let ttl = body.ttl.unwrap_or(DEFAULT_TTL_SECONDS);
+    if !(1..=MAX_TTL_SECONDS).contains(&ttl) {
+        return Err(ApiError::validation(
+            "ttl",
+            format!("must be between 1 and {MAX_TTL_SECONDS} seconds"),
+        ));
+    }
+
+    let limit = state.config().effective_max_upload_bytes();
+    let max_size = match body.max_size {
+        None => limit,
+        Some(size) if (1..=limit).contains(&size) => size,
+        Some(_) => {
+            return Err(ApiError::validation(
+                "max_size",
+                format!("must be between 1 and {limit} bytes"),
+            ))
+        }
+    };
+
+    let types: Vec<String> = body
+        .allowed_types
+        .iter()
+        .map(|t| t.trim().to_ascii_lowercase())
+        .collect();
+    if types.len() > MAX_TYPES || types.iter().any(|t| !t.starts_with("image/")) {
+        return Err(ApiError::validation(
+            "allowed_types",
+            format!("must be at most {MAX_TYPES} image MIME types"),
+        ));
+    }
+
+    let album = match body.album.as_deref().map(str::trim).filter(|id| !id.is_empty()) {
+        Some(id) => {
+            auth.require(Scope::AlbumsWrite)?;
+            let album = album::load_for(&state, id, &account)
+                .await
+                .map_err(|e| ApiError::not_found(e.message()))?;
+            Some(album.id)
+        }
+        None => None,
+    };
+    let guild = body.guild_id.filter(|id| !id.trim().is_empty());
+    if let Some(guild_id) = &guild {
+        auth.require(Scope::GuildImages)?;
+        if !state.is_guild_member(account.id, guild_id).await {
+            return Err(ApiError::forbidden().with_message("This account doesn't belong to that guild."));
+        }
+    }
+
+    let expires_at = OffsetDateTime::now_utc() + time::Duration::seconds(ttl);
+    let grant = Grant {
+        nonce: nanoid::nanoid!(),
+        account: account.id,
+        exp: expires_at.unix_timestamp(),
+        max_size,
+        types,
+        expires_in: body.expires_in,
+        album,
+        guild,
+    };
+    let signature = sign(&secret, &grant)?;
+    let url = format!(
+        "{}?signature={signature}",
+        state
+            .config()
+            .url_to(format!("{}/images/upload", super::api_base_path()))
+    );
+
+    state
+        .audit("image.presign")
+        .actor(&account)
+        .target(grant.nonce.clone())
+        .ip_opt(client_ip)
+        .meta(serde_json::json!({
+            "ttl":      ttl,
+            "max_size": max_size,
+            "types":    grant.types,
+            "album":    grant.album,
+            "guild":    grant.guild,
+        }))
+        .fire();
+
+    Ok(Json(PresignedUpload {
+        url,
+        signature,
+        expires_at: expires_at
+            .format(&time::format_description::well_known::Rfc3339)
+            .unwrap_or_default(),
+        max_size,
+    }))
+}
+
+/// Uploads through a presigned URL: checks the signature, spends the grant and
+/// uploads as the account that minted it. An upload that stores nothing hands
+/// the grant back, so the browser can try again with a file that fits.
+pub(super) async fn redeem(
+    state: AppState,
+    client_ip: Option<std::net::IpAddr>,
+    secret: &SecretKey,
+    signature: &str,
+    multipart: Multipart,
+) -> Result<UploadResult, ApiError> {
+    let invalid = || ApiError::forbidden().with_message("this upload URL is invalid or has expired");
+    let grant = verify(secret, signature, OffsetDateTime::now_utc()).ok_or_else(invalid)?;
+    let account = state.get_account(grant.account).await.ok_or_else(invalid)?;
+
+    // The album may have gone since the URL was minted; fail before the bytes
+    // are stored rather than leave them outside it.
+    let target = match grant.album.as_deref() {
+        Some(id) => Some(
+            album::load_for(&state, id, &account)
+                .await
+                .map_err(|e| ApiError::not_found(e.message()))?,
+        ),
+        None => None,
+    };
+
+    let expires_at = OffsetDateTime::from_unix_timestamp(grant.exp)?;
+    let spent = state
+        .database()
+        .execute(
+            "INSERT INTO presigned_upload (nonce, account_id, expires_at) VALUES (?1, ?2, ?3) \
+             ON CONFLICT (nonce) DO NOTHING",
+            boxed_params![grant.nonce.clone(), account.id, expires_at],
+        )
+        .await?;
+    if spent == 0 {
+        return Err(ApiError::forbidden().with_message("this upload URL has already been used"));
+    }
+
+    let outcome = image::raw_upload_presigned(
+        state.clone(),
+        account.clone(),
+        client_ip,
+        multipart,
+        UploadGrant {
+            max_bytes: grant.max_size,
+            mimetypes: grant.types,
+            expires_at: image::expiry_from(grant.expires_in),
+            guild_id: grant.guild,
+        },
+    )
+    .await;
+    let result = match outcome {
+        Ok(result) if result.successful() > 0 => result,
+        outcome => {
+            state
+                .database()
+                .execute("DELETE FROM presigned_upload WHERE nonce = ?1", [grant.nonce])
+                .await?;
+            return Err(match outcome {
+                Err(e) => e,
+                Ok(_) => ApiError::new("Upload failed"),
+            });
+        }
+    };
+
+    if let Some(target) = target {
+        // As on a keyed upload, a full album doesn't undo a stored image.
+        if let Err(e) = album::add_images(&state, &target, &account, client_ip, &result.ids).await {
+            tracing::warn!(album = %target.id, error = %e.message(), "could not add presigned upload to album");
+        }
+    }
+    Ok(result)
+}
+
+/// Drops spent grants whose URLs have expired, returning how many went.
+pub(crate) async fn reap(state: &AppState) -> anyhow::Result<usize> {
+    Ok(state
+        .database()
+        .execute(
+            "DELETE FROM presigned_upload WHERE datetime(expires_at) <= datetime('now')",
+            [],
+        )
+        .await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grant(exp: i64) -> Grant {
+        Grant {
+            nonce: "n0nce".into(),
+            account: 7,
+            exp,
+            max_size: 1024,
+            types: vec!["image/png".into()],
+            expires_in: Some(3600),
+            album: Some("alb".into()),
+            guild: None,
+        }
+    }
+
+    #[test]
+    fn signatures_round_trip_until_they_expire() {
+        let secret = SecretKey::random().unwrap();
+        let now = OffsetDateTime::now_utc();
+        let grant = grant(now.unix_timestamp() + 60);
+        let signature = sign(&secret, &grant).unwrap();
+
+        assert!(signature
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_'));
+        assert_eq!(verify(&secret, &signature, now), Some(grant));
+        assert_eq!(verify(&secret, &signature, now + time::Duration::seconds(61)), None);
+    }
+
+    #[test]
+    fn foreign_or_tampered_signatures_are_rejected() {
+        let secret = SecretKey::random().unwrap();
+        let now = OffsetDateTime::now_utc();
+        let signature = sign(&secret, &grant(now.unix_timestamp() + 60)).unwrap();
+
+        let other = SecretKey::random().unwrap();
+        assert_eq!(verify(&other, &signature, now), None);
+
+        let mut tampered = signature.into_bytes();
+        let last = tampered.len() - 2;
+        tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+        assert_eq!(verify(&secret, &String::from_utf8(tampered).unwrap(), now), None);
+        assert_eq!(verify(&secret, "not a signature", now), None);
+    }
+
+    #[tokio::test]
+    async fn only_a_guilds_own_account_belongs_to_it() {
+        let db = crate::Database::file(":memory:")
+            .connections(1)
+            .with_init(crate::migrations::migrate)
+            .open()
+            .await
+            .unwrap();
+        for (id, name) in [(1, "gallery"), (2, "someone")] {
+            db.execute(
+                "INSERT INTO account (id, name, password) VALUES (?1, ?2, 'x')",
+                crate::boxed_params![id, name],
+            )
+            .await
+            .unwrap();
+        }
+        db.execute(
+            "INSERT INTO guild_api_key (guild_id, token, account_id) VALUES ('1234', 'tok', 1)",
+            [],
+        )
+        .await
+        .unwrap();
+        let state = AppState::for_tests(db).await;
+
+        assert!(state.is_guild_member(1, "1234").await);
+        assert!(!state.is_guild_member(1, "5678").await);
+        assert!(!state.is_guild_member(2, "1234").await);
+    }
+}
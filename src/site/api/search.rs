@@ -0,0 +1,109 @@
+//! Full-text search over the calling account's content (`/api/v1/search`).
+//!
+//! A thin shell over [`crate::site::search`]: it picks the kinds the key's
+//! scopes may read and hands the query on. Only the caller's own images, pastes
+//! and links are ever searched.
+
+use axum::extract::{Query, State};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use super::auth::ApiToken;
+use super::utils::{ApiJson as Json, RateLimitResponse};
+use crate::site::search::{self, Hit, SearchKind};
+use crate::{error::ApiError, models::Scope, AppState};
+
+/// Query parameters for [`search_content`].
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SearchQuery {
+    /// What to look for. Every word has to match; words match as prefixes.
+    pub q: String,
+    /// Only search one kind of content.
+    #[param(inline)]
+    pub kind: Option<SearchKind>,
+    /// How many hits to return (1–100, default 25).
+    pub limit: Option<u32>,
+}
+
+/// The hits for a search, best match first.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SearchResults {
+    /// The query as given.
+    pub query: String,
+    pub hits: Vec<Hit>,
+}
+
+/// The scope a key needs to see hits of `kind`.
+fn scope_for(kind: SearchKind) -> Scope {
+    match kind {
+        SearchKind::Image => Scope::ImagesRead,
+        SearchKind::Paste => Scope::PastesRead,
+        SearchKind::Link => Scope::LinksRead,
+    }
+}
+
+/// Search
+///
+/// Search your images (by original file name), pastes (by title and body) and
+/// short links (by code and target URL). Encrypted paste bodies are never
+/// indexed, only their titles.
+///
+/// Each kind is only searched if the key has its read scope (`images:read`,
+/// `pastes:read`, `links:read`); pass `kind` to search just one.
+#[utoipa::path(
+    get,
+    path = "/search",
+    params(SearchQuery),
+    responses(
+        (status = 200, description = "Matching content, best match first", body = SearchResults),
+        (status = 400, description = "The query is empty or too long", body = ApiError),
+        (status = 401, description = "User is unauthenticated", body = ApiError),
+        (status = 403, description = "The API key can't read any (or the requested) kind of content", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(
+        ("api_key" = ["images:read", "pastes:read", "links:read"])
+    ),
+    tag = "account"
+)]
+pub async fn search_content(
+    State(state): State<AppState>,
+    auth: ApiToken,
+    Query(params): Query<SearchQuery>,
+) -> Result<Json<SearchResults>, ApiError> {
+    let account = auth.account(&state).await?;
+
+    let query = params.q.trim();
+    if query.len() > search::MAX_QUERY_LEN {
+        return Err(ApiError::validation(
+            "q",
+            format!("must be at most {} bytes", search::MAX_QUERY_LEN),
+        ));
+    }
+    if search::fts_query(query).is_none() {
+        return Err(ApiError::validation("q", "must contain a word to search for"));
+    }
+
+    let kinds: Vec<SearchKind> = match params.kind {
+        Some(kind) => {
+            auth.require(scope_for(kind))?;
+            vec![kind]
+        }
+        None => SearchKind::ALL
+            .into_iter()
+            .filter(|&kind| auth.has_scope(scope_for(kind)))
+            .collect(),
+    };
+    if kinds.is_empty() {
+        return Err(ApiError::forbidden()
+            .with_message("this API key needs `images:read`, `pastes:read` or `links:read` to search"));
+    }
+
+    let limit = params.limit.unwrap_or(search::DEFAULT_LIMIT);
+    let hits = search::search(&state, account.id, query, &kinds, limit).await?;
+    Ok(Json(SearchResults {
+        query: query.to_string(),
+        hits,
+    }))
+}
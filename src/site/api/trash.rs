@@ -0,0 +1,217 @@
+//! The calling account's trash (`/api/v1/trash`).
+//!
+//! A thin shell over [`crate::site::trash`], like the search endpoint: each
+//! kind of content is only listed with its read scope and only restored or
+//! purged with its write scope.
+
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use super::auth::ApiToken;
+use super::utils::{ApiJson as Json, RateLimitResponse};
+use crate::site::trash::{self, TrashItem, TrashKind};
+use crate::{error::ApiError, headers::ClientIp, models::Scope, AppState};
+
+/// The account's trash, most recently deleted first.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TrashList {
+    pub items: Vec<TrashItem>,
+    /// How many days a deleted item stays restorable.
+    pub retention_days: i64,
+}
+
+/// How many items an empty-trash call deleted.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TrashEmptied {
+    pub purged: usize,
+}
+
+fn read_scope(kind: TrashKind) -> Scope {
+    match kind {
+        TrashKind::Image => Scope::ImagesRead,
+        TrashKind::Paste => Scope::PastesRead,
+        TrashKind::Link => Scope::LinksRead,
+    }
+}
+
+fn write_scope(kind: TrashKind) -> Scope {
+    match kind {
+        TrashKind::Image => Scope::ImagesWrite,
+        TrashKind::Paste => Scope::PastesWrite,
+        TrashKind::Link => Scope::LinksWrite,
+    }
+}
+
+fn not_in_trash(kind: TrashKind, id: &str) -> ApiError {
+    ApiError::not_found(format!("no {} `{id}` in the trash", kind.as_str()))
+}
+
+/// List trash
+///
+/// The images, pastes and short links you deleted that can still be restored,
+/// with when each will be removed for good. Each kind is only listed if the key
+/// has its read scope.
+#[utoipa::path(
+    get,
+    path = "/trash",
+    responses(
+        (status = 200, description = "The trash, most recently deleted first", body = TrashList),
+        (status = 401, description = "User is unauthenticated", body = ApiError),
+        (status = 403, description = "The API key can't read any kind of content", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(
+        ("api_key" = ["images:read", "pastes:read", "links:read"])
+    ),
+    tag = "account"
+)]
+pub async fn list_trash(State(state): State<AppState>, auth: ApiToken) -> Result<Json<TrashList>, ApiError> {
+    let account = auth.account(&state).await?;
+    let kinds: Vec<TrashKind> = TrashKind::ALL
+        .into_iter()
+        .filter(|&kind| auth.has_scope(read_scope(kind)))
+        .collect();
+    if kinds.is_empty() {
+        return Err(ApiError::forbidden()
+            .with_message("this API key needs `images:read`, `pastes:read` or `links:read` to list the trash"));
+    }
+
+    let items = trash::list(&state, account.id, &kinds).await?;
+    Ok(Json(TrashList {
+        items,
+        retention_days: trash::retention_days(&state),
+    }))
+}
+
+/// Restore from trash
+///
+/// Put a deleted image, paste or short link back where it was. Links are
+/// addressed by code.
+#[utoipa::path(
+    post,
+    path = "/trash/{kind}/{id}/restore",
+    params(
+        ("kind" = TrashKind, Path, description = "What was deleted"),
+        ("id" = String, Path, description = "The image id, paste id or link code"),
+    ),
+    responses(
+        (status = 204, description = "The item was restored"),
+        (status = 401, description = "User is unauthenticated", body = ApiError),
+        (status = 403, description = "Missing the write scope for this kind", body = ApiError),
+        (status = 404, description = "No such item in the trash", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(
+        ("api_key" = ["images:write", "pastes:write", "links:write"])
+    ),
+    tag = "account"
+)]
+pub async fn restore_item(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    auth: ApiToken,
+    Path((kind, id)): Path<(TrashKind, String)>,
+) -> Result<StatusCode, ApiError> {
+    let account = auth.require_account(&state, write_scope(kind)).await?;
+    if !trash::restore(&state, kind, account.id, &id).await? {
+        return Err(not_in_trash(kind, &id));
+    }
+
+    state
+        .audit("trash.restore")
+        .actor(&account)
+        .target(id)
+        .ip_opt(client_ip)
+        .meta(serde_json::json!({ "kind": kind, "via_api": true }))
+        .fire();
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Purge from trash
+///
+/// Delete an item in the trash for good, without waiting for its restore
+/// window to run out.
+#[utoipa::path(
+    delete,
+    path = "/trash/{kind}/{id}",
+    params(
+        ("kind" = TrashKind, Path, description = "What was deleted"),
+        ("id" = String, Path, description = "The image id, paste id or link code"),
+    ),
+    responses(
+        (status = 204, description = "The item was deleted for good"),
+        (status = 401, description = "User is unauthenticated", body = ApiError),
+        (status = 403, description = "Missing the write scope for this kind", body = ApiError),
+        (status = 404, description = "No such item in the trash", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(
+        ("api_key" = ["images:write", "pastes:write", "links:write"])
+    ),
+    tag = "account"
+)]
+pub async fn purge_item(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    auth: ApiToken,
+    Path((kind, id)): Path<(TrashKind, String)>,
+) -> Result<StatusCode, ApiError> {
+    let account = auth.require_account(&state, write_scope(kind)).await?;
+    if !trash::purge(&state, kind, account.id, &id).await? {
+        return Err(not_in_trash(kind, &id));
+    }
+
+    state
+        .audit("trash.purge")
+        .actor(&account)
+        .target(id)
+        .ip_opt(client_ip)
+        .meta(serde_json::json!({ "kind": kind, "via_api": true }))
+        .fire();
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Empty trash
+///
+/// Delete everything in the trash for good — of the kinds the key has the
+/// write scope for.
+#[utoipa::path(
+    delete,
+    path = "/trash",
+    responses(
+        (status = 200, description = "How many items were deleted", body = TrashEmptied),
+        (status = 401, description = "User is unauthenticated", body = ApiError),
+        (status = 403, description = "The API key can't write any kind of content", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(
+        ("api_key" = ["images:write", "pastes:write", "links:write"])
+    ),
+    tag = "account"
+)]
+pub async fn empty_trash(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    auth: ApiToken,
+) -> Result<Json<TrashEmptied>, ApiError> {
+    let account = auth.account(&state).await?;
+    let kinds: Vec<TrashKind> = TrashKind::ALL
+        .into_iter()
+        .filter(|&kind| auth.has_scope(write_scope(kind)))
+        .collect();
+    if kinds.is_empty() {
+        return Err(ApiError::forbidden()
+            .with_message("this API key needs `images:write`, `pastes:write` or `links:write` to empty the trash"));
+    }
+
+    let purged = trash::empty(&state, account.id, &kinds).await?;
+    state
+        .audit("trash.empty")
+        .actor(&account)
+        .ip_opt(client_ip)
+        .meta(serde_json::json!({ "count": purged, "via_api": true }))
+        .fire();
+    Ok(Json(TrashEmptied { purged }))
+}
@@ -2,7 +2,9 @@
 //!
 //! Fetches a caller-supplied URL (SSRF-guarded, see [`fetch_guarded`]) and
 //! extracts its Open Graph / `<meta>` preview data — the same information a chat
-//! client shows in a rich link embed. Gated by the `images:read` scope.
+//! client shows in a rich link embed. Gated by the `images:read` scope. The
+//! short-link preview page ([`crate::site::links`]) unfurls destinations with
+//! the same [`unfurl_url`].
 
 use axum::extract::{Query, State};
 use serde::{Deserialize, Serialize};
@@ -24,7 +26,7 @@ pub struct UnfurlQuery {
 }
 
 /// The extracted preview metadata for a URL.
-#[derive(Debug, Default, Serialize, ToSchema)]
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
 pub struct UnfurlResult {
     /// The URL that was unfurled (as requested).
     pub url: String,
@@ -69,8 +71,15 @@ pub async fn unfurl(
     auth: ApiToken,
 ) -> Result<Json<UnfurlResult>, ApiError> {
     let account = auth.require_account(&state, Scope::ImagesRead).await?;
+    let result = unfurl_url(&query.url).await?;
+    state.audit("api.unfurl").actor(&account).ip_opt(client_ip).fire();
+    Ok(Json(result))
+}
 
-    let body = fetch_guarded(&query.url, MAX_HTML_BYTES, "klappstuhl.me link unfurler").await?;
+/// Fetches `url` and extracts its preview metadata, with relative image and
+/// favicon URLs made absolute.
+pub(crate) async fn unfurl_url(url: &str) -> Result<UnfurlResult, ApiError> {
+    let body = fetch_guarded(url, MAX_HTML_BYTES, "klappstuhl.me link unfurler").await?;
 
     // Only parse things that look like HTML; refuse binary/other payloads early.
     if let Some(ct) = &body.content_type {
@@ -87,7 +96,7 @@ pub async fn unfurl(
         .map_err(|_| ApiError::new("unfurl task failed"))?;
 
     // Resolve relative image/favicon URLs against the requested URL.
-    let base = reqwest::Url::parse(&query.url).ok();
+    let base = reqwest::Url::parse(url).ok();
     let resolve = |value: Option<String>| -> Option<String> {
         let value = value?;
         match &base {
@@ -96,16 +105,14 @@ pub async fn unfurl(
         }
     };
 
-    state.audit("api.unfurl").actor(&account).ip_opt(client_ip).fire();
-
-    Ok(Json(UnfurlResult {
-        url: query.url,
+    Ok(UnfurlResult {
+        url: url.to_string(),
         title: raw.title,
         description: raw.description,
         image: resolve(raw.image),
         site_name: raw.site_name,
         favicon: resolve(raw.favicon).or_else(|| base.and_then(|b| b.join("/favicon.ico").ok()).map(|u| u.to_string())),
-    }))
+    })
 }
 
 /// The un-resolved extraction result (relative URLs not yet absolutised).
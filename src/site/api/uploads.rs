@@ -0,0 +1,842 @@
+//! Resumable uploads over the [tus 1.0](https://tus.io/protocols/resumable-upload) protocol.
+//!
+//! A plain `/images/upload` has to fit in one request body and finish inside the
+//! request timeout, which large files on a flaky connection don't. Here a client
+//! creates an upload with its total length, sends the bytes in as many `PATCH`
+//! chunks as it likes, and after a dropped connection asks (`HEAD`) how far the
+//! server got and carries on from there.
+//!
+//! Supported extensions: `creation`, `termination` and `expiration`. Partial
+//! bytes are kept in a staging file per upload under `uploads/` next to
+//! `main.db`; the `upload_session` row tracks how many of them are committed.
+//! Once the last byte lands the file goes through the normal upload pipeline
+//! ([`image::raw_upload_bytes`]) — validation, metadata stripping, scanning,
+//! duplicate checks and expiry — in the background, since a large file can take
+//! longer than the request timeout, and the outcome is kept on the session for
+//! `GET /uploads/{id}` until the session expires.
+
+use std::collections::{HashMap, HashSet};
+use std::io::SeekFrom;
+use std::path::PathBuf;
+use std::sync::{Mutex, OnceLock};
+
+use axum::body::Body;
+use axum::extract::{FromRequestParts, Path, State};
+use axum::http::{header, request::Parts, HeaderMap, HeaderName, HeaderValue, StatusCode};
+use axum::middleware::map_response;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::Router;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bytes::Bytes;
+use futures_util::StreamExt;
+use serde::Serialize;
+use time::OffsetDateTime;
+use tokio::io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt};
+use utoipa::ToSchema;
+
+use super::{
+    auth::ApiToken,
+    utils::{ApiJson as Json, RateLimitResponse},
+};
+use crate::{
+    boxed_params, conditional,
+    error::{ApiError, ApiErrorCode},
+    headers::ClientIp,
+    models::{Account, Scope},
+    site::image::{self, DuplicatePolicy},
+    AppState,
+};
+
+/// The only protocol version spoken here.
+const TUS_VERSION: &str = "1.0.0";
+/// The tus extensions advertised by `OPTIONS /uploads`.
+const TUS_EXTENSIONS: &str = "creation,termination,expiration";
+/// How long an upload can take from creation to its last byte. The finished
+/// session (and its result) is kept until the same deadline.
+const SESSION_TTL: time::Duration = time::Duration::hours(24);
+/// Staging files with no session are only removed once they're this old, so the
+/// reaper can't race an upload that is being created.
+const ORPHAN_GRACE: std::time::Duration = std::time::Duration::from_secs(3600);
+
+pub(super) const TUS_RESUMABLE: HeaderName = HeaderName::from_static("tus-resumable");
+pub(super) const TUS_VERSION_HEADER: HeaderName = HeaderName::from_static("tus-version");
+pub(super) const TUS_MAX_SIZE: HeaderName = HeaderName::from_static("tus-max-size");
+pub(super) const TUS_EXTENSION: HeaderName = HeaderName::from_static("tus-extension");
+pub(super) const UPLOAD_LENGTH: HeaderName = HeaderName::from_static("upload-length");
+pub(super) const UPLOAD_OFFSET: HeaderName = HeaderName::from_static("upload-offset");
+pub(super) const UPLOAD_METADATA: HeaderName = HeaderName::from_static("upload-metadata");
+pub(super) const UPLOAD_EXPIRES: HeaderName = HeaderName::from_static("upload-expires");
+
+/// The content type every `PATCH` body must be sent as.
+const CHUNK_CONTENT_TYPE: &str = "application/offset+octet-stream";
+
+/// Rejects requests that don't carry `Tus-Resumable: 1.0.0` with
+/// `412 Precondition Failed`, as the protocol requires.
+struct Tus;
+
+#[async_trait::async_trait]
+impl<S> FromRequestParts<S> for Tus
+where
+    S: Send + Sync,
+{
+    type Rejection = Response;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        if parts.headers.get(TUS_RESUMABLE).is_some_and(|v| v == TUS_VERSION) {
+            Ok(Self)
+        } else {
+            Err((
+                StatusCode::PRECONDITION_FAILED,
+                [(TUS_VERSION_HEADER, TUS_VERSION)],
+                ApiError::new("this server only speaks tus 1.0.0 (`Tus-Resumable: 1.0.0`)"),
+            )
+                .into_response())
+        }
+    }
+}
+
+/// Stamps `Tus-Resumable` onto every response from this module, errors
+/// included, which tus clients check before trusting anything else.
+async fn stamp_resumable(mut response: Response) -> Response {
+    response
+        .headers_mut()
+        .insert(TUS_RESUMABLE, HeaderValue::from_static(TUS_VERSION));
+    response
+}
+
+/// Where partial uploads are kept: `uploads/` next to `main.db`.
+fn staging_directory() -> anyhow::Result<PathBuf> {
+    use anyhow::Context;
+
+    let dir = crate::database::directory()?.with_file_name("uploads");
+    std::fs::create_dir_all(&dir).context("could not create upload staging directory")?;
+    Ok(dir)
+}
+
+fn staging_path(id: &str) -> anyhow::Result<PathBuf> {
+    Ok(staging_directory()?.join(id))
+}
+
+/// Upload ids with a `PATCH` or `DELETE` in flight. tus has no locking of its
+/// own, and two writers at the same offset would interleave their bytes.
+fn in_flight() -> &'static Mutex<HashSet<String>> {
+    static IN_FLIGHT: OnceLock<Mutex<HashSet<String>>> = OnceLock::new();
+    IN_FLIGHT.get_or_init(Default::default)
+}
+
+/// Exclusive hold on one upload, released on drop.
+struct Claim(String);
+
+impl Claim {
+    fn take(id: &str) -> Result<Self, ApiError> {
+        let mut ids = in_flight().lock().unwrap_or_else(|e| e.into_inner());
+        if ids.insert(id.to_string()) {
+            Ok(Self(id.to_string()))
+        } else {
+            Err(ApiError::new("another request for this upload is still in progress")
+                .with_code(ApiErrorCode::EntryAlreadyExists))
+        }
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        in_flight().lock().unwrap_or_else(|e| e.into_inner()).remove(&self.0);
+    }
+}
+
+/// Decodes an `Upload-Metadata` header: comma-separated `key value` pairs with
+/// base64 values, where the value may be left out. Returns `None` if the header
+/// is malformed or repeats a key.
+fn parse_metadata(header: &str) -> Option<HashMap<String, String>> {
+    let mut metadata = HashMap::new();
+    for pair in header.split(',').map(str::trim).filter(|pair| !pair.is_empty()) {
+        let (key, value) = match pair.split_once(' ') {
+            Some((key, value)) => (key, String::from_utf8(STANDARD.decode(value.trim()).ok()?).ok()?),
+            None => (pair, String::new()),
+        };
+        if key.is_empty() || metadata.insert(key.to_string(), value).is_some() {
+            return None;
+        }
+    }
+    Some(metadata)
+}
+
+/// Reads an `Upload-Length` / `Upload-Offset` style header: a plain
+/// non-negative integer, no sign or whitespace.
+fn header_number(headers: &HeaderMap, name: HeaderName) -> Option<u64> {
+    let value = headers.get(name)?.to_str().ok()?;
+    if value.is_empty() || !value.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    value.parse().ok()
+}
+
+fn parse_policy(value: &str) -> Option<DuplicatePolicy> {
+    match value {
+        "warn" => Some(DuplicatePolicy::Warn),
+        "dedupe" => Some(DuplicatePolicy::Dedupe),
+        _ => None,
+    }
+}
+
+/// An `upload_session` row.
+struct Session {
+    account_id: i64,
+    filename: String,
+    upload_length: u64,
+    upload_offset: u64,
+    expires_in: Option<i64>,
+    on_duplicate: Option<String>,
+    result: Option<String>,
+    error: Option<String>,
+    expires_at: OffsetDateTime,
+    completed_at: Option<OffsetDateTime>,
+}
+
+impl Session {
+    fn from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<Self> {
+        Ok(Self {
+            account_id: row.get(0)?,
+            filename: row.get(1)?,
+            upload_length: row.get::<_, i64>(2)? as u64,
+            upload_offset: row.get::<_, i64>(3)? as u64,
+            expires_in: row.get(4)?,
+            on_duplicate: row.get(5)?,
+            result: row.get(6)?,
+            error: row.get(7)?,
+            expires_at: row.get(8)?,
+            completed_at: row.get(9)?,
+        })
+    }
+
+    /// The headers `HEAD` and `PATCH` report progress with.
+    fn progress_headers(&self, offset: u64) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(UPLOAD_OFFSET, HeaderValue::from(offset));
+        headers.insert(UPLOAD_LENGTH, HeaderValue::from(self.upload_length));
+        if let Some(expires) = conditional::format_http_date(self.expires_at) {
+            headers.insert(UPLOAD_EXPIRES, expires);
+        }
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        headers
+    }
+}
+
+/// Loads one of `account`'s unexpired upload sessions. Someone else's upload is
+/// reported as missing, the same as one that never existed.
+async fn load(state: &AppState, account: &Account, id: &str) -> Result<Session, ApiError> {
+    let session = state
+        .database()
+        .get_row(
+            "SELECT account_id, filename, upload_length, upload_offset, expires_in, on_duplicate, \
+                    result, error, expires_at, completed_at \
+             FROM upload_session WHERE id = ?1 AND datetime(expires_at) > datetime('now')",
+            [id.to_string()],
+            Session::from_row,
+        )
+        .await
+        .ok();
+    match session {
+        Some(session) if session.account_id == account.id => Ok(session),
+        _ => Err(ApiError::not_found("upload not found or expired")),
+    }
+}
+
+/// Writes a `PATCH` body into the staging file at `offset`, accepting at most
+/// `remaining` bytes. Returns how many bytes landed along with the error that
+/// cut the body short, if any: bytes received before a dropped connection
+/// still count, which is the whole point of resuming.
+async fn write_chunk(
+    path: &std::path::Path,
+    offset: u64,
+    body: Body,
+    remaining: u64,
+) -> anyhow::Result<(u64, Option<ApiError>)> {
+    let mut file = tokio::fs::OpenOptions::new().write(true).open(path).await?;
+    // Anything past the committed offset is from a write that never got
+    // recorded; drop it so the file only ever holds acknowledged bytes.
+    file.set_len(offset).await?;
+    file.seek(SeekFrom::Start(offset)).await?;
+
+    let mut written = 0u64;
+    let mut cut_short = None;
+    let mut stream = body.into_data_stream();
+    while let Some(frame) = stream.next().await {
+        let chunk = match frame {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                cut_short = Some(ApiError::new(format!("upload interrupted: {e}")));
+                break;
+            }
+        };
+        if written + chunk.len() as u64 > remaining {
+            cut_short = Some(
+                ApiError::new("the chunk runs past the upload's declared length")
+                    .with_code(ApiErrorCode::PayloadTooLarge),
+            );
+            break;
+        }
+        file.write_all(&chunk).await?;
+        written += chunk.len() as u64;
+    }
+    file.flush().await?;
+    Ok((written, cut_short))
+}
+
+/// Reads a finished staging file in chunks, giving up once it holds more than
+/// `cap` bytes (the upload limit may have been lowered since it was created).
+async fn read_staged(path: &std::path::Path, length: u64, cap: u64) -> anyhow::Result<Bytes> {
+    let file = tokio::fs::File::open(path).await?;
+    let mut buf = Vec::with_capacity(length.min(cap) as usize);
+    file.take(cap + 1).read_to_end(&mut buf).await?;
+    if buf.len() as u64 > cap {
+        anyhow::bail!("file exceeds the maximum upload size");
+    }
+    Ok(Bytes::from(buf))
+}
+
+/// Runs a fully received upload through the normal upload pipeline and records
+/// the outcome on its session. The staging file is removed either way: a
+/// rejected file won't get any better by resending the same bytes.
+async fn finish(
+    state: &AppState,
+    account: Account,
+    client_ip: Option<std::net::IpAddr>,
+    id: &str,
+    session: &Session,
+) -> anyhow::Result<()> {
+    let path = staging_path(id)?;
+    let cap = state.config().effective_max_upload_bytes();
+    let outcome = match read_staged(&path, session.upload_length, cap).await {
+        Ok(bytes) => {
+            let policy = session
+                .on_duplicate
+                .as_deref()
+                .and_then(parse_policy)
+                .unwrap_or_default();
+            image::raw_upload_bytes(
+                state.clone(),
+                account,
+                client_ip,
+                &session.filename,
+                bytes,
+                image::expiry_from(session.expires_in),
+                policy,
+            )
+            .await
+        }
+        Err(e) => Err(ApiError::from(e)),
+    };
+
+    let (result, error) = match &outcome {
+        Ok(result) => (Some(serde_json::to_string(result)?), None),
+        Err(e) => (None, Some(e.message.to_string())),
+    };
+    state
+        .database()
+        .execute(
+            "UPDATE upload_session SET result = ?1, error = ?2, completed_at = CURRENT_TIMESTAMP WHERE id = ?3",
+            boxed_params![result, error, id.to_string()],
+        )
+        .await?;
+    let _ = tokio::fs::remove_file(&path).await;
+    Ok(())
+}
+
+/// Deletes expired upload sessions with their staging files, plus staging files
+/// whose session is gone (account deletion cascades the rows away). Returns how
+/// many sessions expired.
+pub(crate) async fn reap(state: &AppState) -> anyhow::Result<usize> {
+    let expired: Vec<String> = state
+        .database()
+        .call(|conn| {
+            let mut stmt =
+                conn.prepare("DELETE FROM upload_session WHERE datetime(expires_at) <= datetime('now') RETURNING id")?;
+            let ids: rusqlite::Result<Vec<String>> = stmt.query_map([], |row| row.get(0))?.collect();
+            ids
+        })
+        .await?;
+    let live: HashSet<String> = state
+        .database()
+        .call(|conn| {
+            let mut stmt = conn.prepare("SELECT id FROM upload_session")?;
+            let ids: rusqlite::Result<HashSet<String>> = stmt.query_map([], |row| row.get(0))?.collect();
+            ids
+        })
+        .await?;
+
+    let mut entries = tokio::fs::read_dir(staging_directory()?).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        if live.contains(entry.file_name().to_string_lossy().as_ref()) {
+            continue;
+        }
+        let age = entry
+            .metadata()
+            .await
+            .ok()
+            .and_then(|m| m.modified().ok())
+            .and_then(|modified| modified.elapsed().ok());
+        if age.is_some_and(|age| age >= ORPHAN_GRACE) {
+            let _ = tokio::fs::remove_file(entry.path()).await;
+        }
+    }
+    Ok(expired.len())
+}
+
+/// The state of a resumable upload.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct UploadStatus {
+    /// The upload's id.
+    pub id: String,
+    /// The file name given in `Upload-Metadata`.
+    pub filename: String,
+    /// The total size of the file, in bytes.
+    pub length: u64,
+    /// How many bytes the server has received.
+    pub offset: u64,
+    /// Whether every byte has arrived and the file has been processed. The
+    /// file is processed in the background after the last `PATCH`, so this can
+    /// stay `false` for a moment after `offset` reaches `length`.
+    pub complete: bool,
+    /// When the upload session (and this status) expires (RFC 3339).
+    pub expires_at: String,
+    /// The upload's result, the same as `/images/upload` returns, once complete.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<crate::site::image::UploadResult>)]
+    pub result: Option<serde_json::Value>,
+    /// Why the finished file was rejected, if it was.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Upload capabilities
+///
+/// Report the tus version, extensions and maximum upload size this server
+/// supports. Needs no authentication.
+#[utoipa::path(
+    options,
+    path = "/uploads",
+    responses(
+        (status = 204, description = "Server capabilities", headers(
+            ("Tus-Version" = String, description = "Supported protocol versions (`1.0.0`)"),
+            ("Tus-Max-Size" = u64, description = "The largest upload accepted, in bytes"),
+            ("Tus-Extension" = String, description = "Supported extensions (`creation,termination,expiration`)"),
+        )),
+    ),
+    tag = "uploads"
+)]
+pub async fn upload_options(State(state): State<AppState>) -> Response {
+    let max_size = state.config().effective_max_upload_bytes();
+    (
+        StatusCode::NO_CONTENT,
+        [
+            (TUS_VERSION_HEADER, HeaderValue::from_static(TUS_VERSION)),
+            (TUS_MAX_SIZE, HeaderValue::from(max_size)),
+            (TUS_EXTENSION, HeaderValue::from_static(TUS_EXTENSIONS)),
+        ],
+    )
+        .into_response()
+}
+
+/// Create upload
+///
+/// Start a resumable upload (tus `creation`). Send the file's total size in
+/// `Upload-Length` and its name in `Upload-Metadata` as `filename <base64>`;
+/// the optional `expires_in` (seconds) and `on_duplicate` (`warn` or `dedupe`)
+/// keys work as on `/images/upload`.
+///
+/// The returned `Location` is the upload's URL: `PATCH` the bytes to it, and
+/// `HEAD` it to find out where to resume. An upload has 24 hours to finish.
+#[utoipa::path(
+    post,
+    path = "/uploads",
+    params(
+        ("Tus-Resumable" = String, Header, description = "Must be `1.0.0`"),
+        ("Upload-Length" = u64, Header, description = "The file's total size in bytes"),
+        ("Upload-Metadata" = String, Header, description = "Comma-separated `key base64value` pairs; `filename` is required"),
+    ),
+    responses(
+        (status = 201, description = "Upload created", headers(
+            ("Location" = String, description = "The upload's URL"),
+            ("Upload-Expires" = String, description = "When the upload must be finished by (HTTP date)"),
+        )),
+        (status = 400, description = "Missing or malformed length or metadata, or an unsupported file type", body = ApiError),
+        (status = 401, description = "User is unauthenticated", body = ApiError),
+        (status = 403, description = "The API key is missing the images:write scope", body = ApiError),
+        (status = 412, description = "Unsupported `Tus-Resumable` version", body = ApiError),
+        (status = 413, description = "The file is larger than the server accepts", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(
+        ("api_key" = ["images:write"])
+    ),
+    tag = "uploads"
+)]
+pub async fn create_upload(
+    State(state): State<AppState>,
+    _tus: Tus,
+    auth: ApiToken,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let account = auth.require_account(&state, Scope::ImagesWrite).await?;
+
+    let length = header_number(&headers, UPLOAD_LENGTH)
+        .ok_or_else(|| ApiError::validation("Upload-Length", "must be given as a number of bytes"))?;
+    if length == 0 {
+        return Err(ApiError::validation("Upload-Length", "empty files can't be uploaded"));
+    }
+    let max_size = state.config().effective_max_upload_bytes();
+    if length > max_size {
+        return Err(
+            ApiError::new(format!("uploads are limited to {max_size} bytes")).with_code(ApiErrorCode::PayloadTooLarge)
+        );
+    }
+
+    let metadata = match headers.get(UPLOAD_METADATA) {
+        Some(value) => value
+            .to_str()
+            .ok()
+            .and_then(parse_metadata)
+            .ok_or_else(|| ApiError::validation("Upload-Metadata", "is malformed"))?,
+        None => HashMap::new(),
+    };
+    let filename = metadata
+        .get("filename")
+        .or_else(|| metadata.get("name"))
+        .ok_or_else(|| ApiError::validation("filename", "must be given in Upload-Metadata"))?;
+    let (filename, _) = image::validate_name(filename).map_err(|e| ApiError::validation("filename", e.to_string()))?;
+    let expires_in = metadata
+        .get("expires_in")
+        .map(|value| value.parse::<i64>())
+        .transpose()
+        .map_err(|_| ApiError::validation("expires_in", "must be a number of seconds"))?;
+    let on_duplicate = metadata.get("on_duplicate").cloned();
+    if on_duplicate
+        .as_deref()
+        .is_some_and(|value| parse_policy(value).is_none())
+    {
+        return Err(ApiError::validation("on_duplicate", "must be `warn` or `dedupe`"));
+    }
+
+    let id = nanoid::nanoid!();
+    let expires_at = OffsetDateTime::now_utc() + SESSION_TTL;
+    state
+        .database()
+        .execute(
+            "INSERT INTO upload_session (id, account_id, filename, upload_length, expires_in, on_duplicate, expires_at) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            boxed_params![id.clone(), account.id, filename, length as i64, expires_in, on_duplicate, expires_at],
+        )
+        .await?;
+    tokio::fs::File::create(staging_path(&id)?).await?;
+
+    let location = state
+        .config()
+        .url_to(format!("{}/uploads/{id}", super::api_base_path()));
+    let mut response = (StatusCode::CREATED, [(header::LOCATION, location)]).into_response();
+    if let Some(expires) = conditional::format_http_date(expires_at) {
+        response.headers_mut().insert(UPLOAD_EXPIRES, expires);
+    }
+    Ok(response)
+}
+
+/// Upload offset
+///
+/// Find out how many bytes of an upload the server has, to resume from there.
+#[utoipa::path(
+    head,
+    path = "/uploads/{id}",
+    params(
+        ("id" = String, Path, description = "The upload's ID"),
+        ("Tus-Resumable" = String, Header, description = "Must be `1.0.0`"),
+    ),
+    responses(
+        (status = 200, description = "The upload's progress", headers(
+            ("Upload-Offset" = u64, description = "Bytes received so far"),
+            ("Upload-Length" = u64, description = "The file's total size"),
+            ("Upload-Expires" = String, description = "When the upload must be finished by (HTTP date)"),
+        )),
+        (status = 401, description = "User is unauthenticated"),
+        (status = 403, description = "The API key is missing the images:write scope"),
+        (status = 404, description = "Upload not found or expired"),
+        (status = 412, description = "Unsupported `Tus-Resumable` version"),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(
+        ("api_key" = ["images:write"])
+    ),
+    tag = "uploads"
+)]
+pub async fn upload_offset(
+    State(state): State<AppState>,
+    _tus: Tus,
+    auth: ApiToken,
+    Path(id): Path<String>,
+) -> Result<Response, ApiError> {
+    let account = auth.require_account(&state, Scope::ImagesWrite).await?;
+    let session = load(&state, &account, &id).await?;
+    Ok((StatusCode::OK, session.progress_headers(session.upload_offset)).into_response())
+}
+
+/// Upload chunk
+///
+/// Append bytes to an upload. `Upload-Offset` must match the server's offset
+/// (ask with `HEAD`) and the body must be sent as
+/// `application/offset+octet-stream`. Each chunk has to fit in one request, so
+/// keep chunks to a few MiB.
+///
+/// When the last byte arrives the file is processed like a normal upload; the
+/// outcome is available from `GET /uploads/{id}`.
+#[utoipa::path(
+    patch,
+    path = "/uploads/{id}",
+    params(
+        ("id" = String, Path, description = "The upload's ID"),
+        ("Tus-Resumable" = String, Header, description = "Must be `1.0.0`"),
+        ("Upload-Offset" = u64, Header, description = "The offset this chunk starts at"),
+    ),
+    request_body(
+        content = Vec<u8>,
+        content_type = "application/offset+octet-stream",
+        description = "The next bytes of the file."
+    ),
+    responses(
+        (status = 204, description = "Chunk stored", headers(
+            ("Upload-Offset" = u64, description = "The new offset"),
+        )),
+        (status = 400, description = "Missing `Upload-Offset`, or the body was cut short", body = ApiError),
+        (status = 401, description = "User is unauthenticated", body = ApiError),
+        (status = 403, description = "The API key is missing the images:write scope", body = ApiError),
+        (status = 404, description = "Upload not found or expired", body = ApiError),
+        (status = 409, description = "`Upload-Offset` doesn't match, or another chunk is still being written", body = ApiError),
+        (status = 412, description = "Unsupported `Tus-Resumable` version", body = ApiError),
+        (status = 413, description = "The chunk runs past the declared length", body = ApiError),
+        (status = 415, description = "Wrong content type", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(
+        ("api_key" = ["images:write"])
+    ),
+    tag = "uploads"
+)]
+pub async fn upload_chunk(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    _tus: Tus,
+    auth: ApiToken,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+    body: Body,
+) -> Result<Response, ApiError> {
+    let account = auth.require_account(&state, Scope::ImagesWrite).await?;
+
+    if headers
+        .get(header::CONTENT_TYPE)
+        .map_or(true, |v| v != CHUNK_CONTENT_TYPE)
+    {
+        return Err(ApiError::new(format!("chunks must be sent as {CHUNK_CONTENT_TYPE}"))
+            .with_code(ApiErrorCode::UnsupportedMedia));
+    }
+    let offset = header_number(&headers, UPLOAD_OFFSET)
+        .ok_or_else(|| ApiError::validation("Upload-Offset", "must be given as a number of bytes"))?;
+
+    let claim = Claim::take(&id)?;
+    let session = load(&state, &account, &id).await?;
+    if offset != session.upload_offset {
+        return Err(ApiError::new(format!(
+            "Upload-Offset is {offset} but the server has {} bytes",
+            session.upload_offset
+        ))
+        .with_code(ApiErrorCode::EntryAlreadyExists));
+    }
+
+    let remaining = session.upload_length - offset;
+    let (written, cut_short) = write_chunk(&staging_path(&id)?, offset, body, remaining).await?;
+    let new_offset = offset + written;
+    if written > 0 {
+        state
+            .database()
+            .execute(
+                "UPDATE upload_session SET upload_offset = ?1 WHERE id = ?2",
+                boxed_params![new_offset as i64, id.clone()],
+            )
+            .await?;
+    }
+    if let Some(error) = cut_short {
+        return Err(error);
+    }
+
+    let headers = session.progress_headers(new_offset);
+    if new_offset == session.upload_length && session.completed_at.is_none() {
+        // Processing can outlast the request timeout, which would drop it
+        // half done, so it runs on its own. It keeps the claim until it's
+        // finished; clients poll `GET /uploads/{id}` for the result, and a
+        // `PATCH` at the final offset tries again if it failed.
+        tokio::spawn(async move {
+            let _claim = claim;
+            if let Err(e) = finish(&state, account, client_ip, &id, &session).await {
+                tracing::error!(error = %e, id = %id, "could not finish resumable upload");
+            }
+        });
+    }
+    Ok((StatusCode::NO_CONTENT, headers).into_response())
+}
+
+/// Upload status
+///
+/// Get an upload's progress and, once it's complete, its result: the same
+/// body `/images/upload` returns, with the image's links.
+#[utoipa::path(
+    get,
+    path = "/uploads/{id}",
+    params(
+        ("id" = String, Path, description = "The upload's ID"),
+    ),
+    responses(
+        (status = 200, description = "The upload's state", body = UploadStatus),
+        (status = 401, description = "User is unauthenticated", body = ApiError),
+        (status = 403, description = "The API key is missing the images:write scope", body = ApiError),
+        (status = 404, description = "Upload not found or expired", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(
+        ("api_key" = ["images:write"])
+    ),
+    tag = "uploads"
+)]
+pub async fn upload_status(
+    State(state): State<AppState>,
+    auth: ApiToken,
+    Path(id): Path<String>,
+) -> Result<Json<UploadStatus>, ApiError> {
+    let account = auth.require_account(&state, Scope::ImagesWrite).await?;
+    let session = load(&state, &account, &id).await?;
+    Ok(Json(UploadStatus {
+        id,
+        complete: session.completed_at.is_some(),
+        expires_at: session
+            .expires_at
+            .format(&time::format_description::well_known::Rfc3339)
+            .unwrap_or_default(),
+        result: session.result.as_deref().and_then(|r| serde_json::from_str(r).ok()),
+        error: session.error,
+        filename: session.filename,
+        length: session.upload_length,
+        offset: session.upload_offset,
+    }))
+}
+
+/// Cancel upload
+///
+/// Abandon an upload and discard the bytes received so far (tus
+/// `termination`). Images from an already completed upload are not affected;
+/// delete those through `/images/{id}`.
+#[utoipa::path(
+    delete,
+    path = "/uploads/{id}",
+    params(
+        ("id" = String, Path, description = "The upload's ID"),
+        ("Tus-Resumable" = String, Header, description = "Must be `1.0.0`"),
+    ),
+    responses(
+        (status = 204, description = "Upload discarded"),
+        (status = 401, description = "User is unauthenticated", body = ApiError),
+        (status = 403, description = "The API key is missing the images:write scope", body = ApiError),
+        (status = 404, description = "Upload not found or expired", body = ApiError),
+        (status = 409, description = "A chunk for this upload is still being written", body = ApiError),
+        (status = 412, description = "Unsupported `Tus-Resumable` version", body = ApiError),
+        (status = 429, response = RateLimitResponse),
+    ),
+    security(
+        ("api_key" = ["images:write"])
+    ),
+    tag = "uploads"
+)]
+pub async fn delete_upload(
+    State(state): State<AppState>,
+    _tus: Tus,
+    auth: ApiToken,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let account = auth.require_account(&state, Scope::ImagesWrite).await?;
+    let _claim = Claim::take(&id)?;
+    load(&state, &account, &id).await?;
+
+    state
+        .database()
+        .execute("DELETE FROM upload_session WHERE id = ?1", [id.clone()])
+        .await?;
+    let _ = tokio::fs::remove_file(staging_path(&id)?).await;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// The tus endpoints, relative to the API version prefix.
+pub(super) fn routes() -> Router<AppState> {
+    Router::new()
+        .route("/uploads", post(create_upload).options(upload_options))
+        .route(
+            "/uploads/:id",
+            get(upload_status)
+                .head(upload_offset)
+                .patch(upload_chunk)
+                .delete(delete_upload),
+        )
+        .layer(map_response(stamp_resumable))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(value: &str) -> String {
+        STANDARD.encode(value)
+    }
+
+    #[test]
+    fn metadata_decodes_base64_values_and_bare_keys() {
+        let header = format!(
+            "filename {},expires_in {}, is_confidential",
+            encode("cat.png"),
+            encode("3600")
+        );
+        let metadata = parse_metadata(&header).unwrap();
+        assert_eq!(metadata["filename"], "cat.png");
+        assert_eq!(metadata["expires_in"], "3600");
+        assert_eq!(metadata["is_confidential"], "");
+        assert!(parse_metadata("").unwrap().is_empty());
+    }
+
+    #[test]
+    fn malformed_metadata_is_rejected() {
+        assert!(parse_metadata("filename not*base64").is_none());
+        assert!(parse_metadata(&format!("filename {0},filename {0}", encode("a.png"))).is_none());
+        // Values must be UTF-8 once decoded.
+        assert!(parse_metadata(&format!("filename {}", STANDARD.encode([0xff, 0xfe]))).is_none());
+    }
+
+    #[test]
+    fn offsets_and_lengths_must_be_plain_integers() {
+        let mut headers = HeaderMap::new();
+        for (value, expected) in [
+            ("0", Some(0)),
+            ("1048576", Some(1_048_576)),
+            ("+5", None),
+            ("-1", None),
+            (" 5", None),
+            ("", None),
+        ] {
+            headers.insert(UPLOAD_OFFSET, HeaderValue::from_static(value));
+            assert_eq!(header_number(&headers, UPLOAD_OFFSET), expected, "{value:?}");
+        }
+        assert_eq!(header_number(&HeaderMap::new(), UPLOAD_OFFSET), None);
+    }
+
+    #[test]
+    fn duplicate_policies_match_the_upload_endpoint() {
+        assert_eq!(parse_policy("warn"), Some(DuplicatePolicy::Warn));
+        assert_eq!(parse_policy("dedupe"), Some(DuplicatePolicy::Dedupe));
+        assert_eq!(parse_policy("Dedupe"), None);
+    }
+}
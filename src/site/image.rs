@@ -1,24 +1,41 @@
 //! Routes for image upload, viewing, deletion, and raw serving.
 
+use crate::conditional::{self, Validators};
+use crate::config::UploadScan;
+use crate::cookies::set_cookie;
 use crate::error::{ApiError, InternalError};
 use crate::filters::canonical_url;
 use crate::flash::{FlashMessage, Flasher, Flashes};
 use crate::headers::{ClientIp, Referrer};
-use crate::models::{Account, ImageEntry, ImageFile};
+use crate::key::SecretKey;
+use crate::metadata::{self, Keep};
+use crate::models::{Account, ImageEntry, ImageFile, ImageRevision, ScanStatus, Visibility};
+use crate::placeholder::Placeholder;
 use crate::ratelimit::RateLimit;
+use crate::site::image_lock::{self, Access, ImageSettings, Protection};
+use crate::site::image_quota::{self, Quota};
+use crate::site::image_revision;
+use crate::site::image_scan;
+use crate::site::stats::{self, ViewKind};
+use crate::site::trash::{self, TrashKind};
+use crate::state::{ImageVariant, Thumbnail};
+use crate::storage::BlobStream;
 use crate::utils::get_new_image_id;
-use crate::{database::is_unique_constraint_violation, filters, AppState};
+use crate::variant::{Variant, VariantQuery};
+use crate::{boxed_params, database::is_unique_constraint_violation, filters, AppState};
 use askama::Template;
 use axum::extract::multipart::Field;
 use axum::extract::Multipart;
 use axum::http::{header, HeaderMap, StatusCode};
 use axum::routing::{delete, get, post};
 use axum::{
-    extract::{Path, State},
+    extract::{Path, Query, RawQuery, State},
     response::{IntoResponse, Redirect, Response},
-    Json, Router,
+    Extension, Form, Json, Router,
 };
 use bytes::Bytes;
+use cookie::Cookie;
+use futures_util::stream::{self, Stream, StreamExt};
 use serde::{Deserialize, Serialize};
 use std::net::IpAddr;
 use time::OffsetDateTime;
@@ -46,11 +63,54 @@ pub struct UploadParams {
     /// auto-deleted by the reaper after this many seconds. Capped at 365 days.
     #[serde(default)]
     pub expires_in: Option<i64>,
+    /// An album of the uploader's to add the uploads to (API only), so a
+    /// ShareX profile can file screenshots straight into one.
+    #[serde(default)]
+    pub album: Option<String>,
+    /// What to do with an upload that looks like one of the uploader's
+    /// existing images.
+    #[serde(default)]
+    pub on_duplicate: DuplicatePolicy,
+    /// Who may see the uploads. Unlisted when left out.
+    #[serde(default)]
+    pub visibility: Option<Visibility>,
+    /// Delete each upload after it has been revealed this many times.
+    #[serde(default)]
+    pub burn_after_views: Option<i64>,
+    /// Which metadata to leave on the uploads: everything is stripped unless
+    /// this is `color`, which keeps the colour profile and orientation.
+    #[serde(default)]
+    pub keep_metadata: Keep,
+}
+
+impl UploadParams {
+    /// The visibility and burn count asked for, as settings to apply to the
+    /// stored uploads. Passwords aren't taken here: they'd end up in URLs and
+    /// logs.
+    pub fn settings(&self) -> ImageSettings {
+        ImageSettings {
+            visibility: self.visibility,
+            password: None,
+            burn_after_views: self.burn_after_views,
+        }
+    }
+}
+
+/// How an upload that is a near-duplicate (by perceptual hash) of one of the
+/// uploader's existing images is handled.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DuplicatePolicy {
+    /// Store the upload anyway and list the match in `duplicates`.
+    #[default]
+    Warn,
+    /// Don't store the upload; return the existing image's links in its place.
+    Dedupe,
 }
 
 /// Resolves a requested TTL into an absolute expiry timestamp, clamped to
 /// `MAX_TTL_SECONDS`. `None`/zero/negative means "never expires".
-fn expiry_from(expires_in: Option<i64>) -> Option<OffsetDateTime> {
+pub(crate) fn expiry_from(expires_in: Option<i64>) -> Option<OffsetDateTime> {
     let secs = expires_in.filter(|&s| s > 0)?.min(MAX_TTL_SECONDS);
     Some(OffsetDateTime::now_utc() + time::Duration::seconds(secs))
 }
@@ -65,18 +125,18 @@ pub fn expiry_from_params(params: &UploadParams) -> Option<OffsetDateTime> {
 // ---------------------------------------------------------------------------
 
 /// A single validated file ready for insertion into the database.
-struct ValidatedFile {
+pub(crate) struct ValidatedFile {
     /// The randomly generated image ID (no extension).
     id: String,
     /// The original file extension (lowercase).
     ext: String,
     /// Raw image bytes.
-    bytes: Bytes,
+    pub(crate) bytes: Bytes,
     /// MIME type detected from the byte content.
-    mimetype: String,
+    pub(crate) mimetype: String,
     /// The uploader's sanitised original filename (e.g. `holiday.png`),
     /// preserved for human-friendly downloads. The public URL still uses `id`.
-    original_name: String,
+    pub(crate) original_name: String,
 }
 
 /// Reads a multipart field body, aborting as soon as it exceeds `cap` bytes.
@@ -96,11 +156,10 @@ async fn read_capped(mut field: Field<'_>, cap: u64) -> anyhow::Result<Bytes> {
     Ok(Bytes::from(buf))
 }
 
-async fn validate_field(field: Field<'_>, max_bytes: u64) -> anyhow::Result<ValidatedFile> {
-    let filename = field
-        .file_name()
-        .map(sanitise_file_name::sanitise)
-        .ok_or_else(|| anyhow::anyhow!("missing filename"))?;
+/// Sanitises an uploaded file's name and checks its extension is one we host,
+/// returning the sanitised name and the lowercase extension.
+pub(crate) fn validate_name(name: &str) -> anyhow::Result<(String, String)> {
+    let filename = sanitise_file_name::sanitise(name);
 
     let path = std::path::Path::new(&filename);
     let ext = path
@@ -112,15 +171,27 @@ async fn validate_field(field: Field<'_>, max_bytes: u64) -> anyhow::Result<Vali
     if !is_allowed_extension(&ext) {
         anyhow::bail!("unsupported file extension: {ext}");
     }
+    Ok((filename, ext))
+}
 
-    let bytes = read_capped(field, max_bytes).await?;
+/// Turns an upload's bytes into a [`ValidatedFile`]: strips metadata, sniffs
+/// the MIME type and assigns a fresh id.
+async fn validate_bytes(filename: String, ext: String, bytes: Bytes, keep: Keep) -> anyhow::Result<ValidatedFile> {
     if bytes.is_empty() {
         anyhow::bail!("empty file");
     }
 
-    // Strip EXIF/XMP/text metadata (GPS, camera, etc.) before the bytes are
-    // stored or served. Pixel data and animation are preserved.
-    let bytes = Bytes::from(crate::metadata::strip(&ext, &bytes));
+    // Turn sideways JPEGs upright while the EXIF orientation still says how,
+    // then strip EXIF/XMP/text metadata (GPS, camera, etc.) before the bytes
+    // are stored or served. Pixel data and animation are preserved.
+    let bytes = {
+        let ext = ext.clone();
+        tokio::task::spawn_blocking(move || {
+            let upright = metadata::auto_orient(&ext, &bytes);
+            Bytes::from(metadata::strip(&ext, upright.as_deref().unwrap_or(&bytes), keep))
+        })
+        .await?
+    };
 
     let mimetype = tree_magic::from_u8(&bytes);
 
@@ -133,12 +204,43 @@ async fn validate_field(field: Field<'_>, max_bytes: u64) -> anyhow::Result<Vali
     })
 }
 
-async fn collect_fields(mut multipart: Multipart, max_bytes: u64) -> (Vec<ValidatedFile>, usize) {
+async fn validate_field(field: Field<'_>, max_bytes: u64, keep: Keep) -> anyhow::Result<ValidatedFile> {
+    let name = field.file_name().ok_or_else(|| anyhow::anyhow!("missing filename"))?;
+    let (filename, ext) = validate_name(name)?;
+    let bytes = read_capped(field, max_bytes).await?;
+    validate_bytes(filename, ext, bytes, keep).await
+}
+
+/// Validates a file whose name and bytes arrived some other way than a
+/// multipart field (a resumable upload, an archive entry).
+pub(crate) async fn validate_named(name: &str, bytes: Bytes, keep: Keep) -> anyhow::Result<ValidatedFile> {
+    let (filename, ext) = validate_name(name)?;
+    validate_bytes(filename, ext, bytes, keep).await
+}
+
+/// Reads and validates the `file` field of a form that takes exactly one image
+/// (replacing one). Other fields are ignored.
+pub(crate) async fn single_file(
+    mut multipart: Multipart,
+    max_bytes: u64,
+    keep: Keep,
+) -> Result<ValidatedFile, ApiError> {
+    while let Some(field) = multipart.next_field().await.map_err(|e| ApiError::new(e.to_string()))? {
+        if field.name() == Some("file") {
+            return validate_field(field, max_bytes, keep)
+                .await
+                .map_err(|e| ApiError::validation("file", e.to_string()));
+        }
+    }
+    Err(ApiError::new("no `file` field in upload"))
+}
+
+async fn collect_fields(mut multipart: Multipart, max_bytes: u64, keep: Keep) -> (Vec<ValidatedFile>, usize) {
     let mut files = Vec::new();
     let mut skipped = 0usize;
 
     while let Ok(Some(field)) = multipart.next_field().await {
-        match validate_field(field, max_bytes).await {
+        match validate_field(field, max_bytes, keep).await {
             Ok(f) => files.push(f),
             Err(e) => {
                 tracing::debug!(error = %e, "skipped upload field");
@@ -166,23 +268,112 @@ pub struct UploadResult {
     /// Number of files rejected because a malware scan flagged them.
     #[serde(default)]
     pub infected: usize,
+    /// Number of stored files held back until a background malware scan
+    /// clears them. Their links answer `503` until then, and `404` if the scan
+    /// quarantines them.
+    #[serde(default)]
+    pub pending: usize,
     /// Canonical URLs of the successfully uploaded files.
     pub links: Vec<String>,
     /// Canonical raw URLs of the successfully uploaded files.
     pub raw_links: Vec<String>,
+    /// Loading placeholders for the successfully uploaded files, in the same
+    /// order as `links`. `null` for an image that couldn't be decoded (AVIF).
+    #[serde(default)]
+    pub placeholders: Vec<Option<Placeholder>>,
+    /// Number of files rejected because they match an image an admin has
+    /// blocked.
+    #[serde(default)]
+    pub blocked: usize,
+    /// Number of files refused because they would put the account over its
+    /// image count or storage quota.
+    #[serde(default)]
+    pub over_quota: usize,
+    /// Uploads that look like one of your existing images.
+    #[serde(default)]
+    pub duplicates: Vec<DuplicateUpload>,
+    /// IDs of the successfully uploaded files, for callers that file them
+    /// somewhere afterwards (e.g. into an album).
+    #[serde(skip)]
+    pub ids: Vec<String>,
+    /// What became of each validated file, in the order they were given. The
+    /// `Stored` and `Deduplicated` ones line up with `links`.
+    #[serde(skip)]
+    pub outcomes: Vec<UploadOutcome>,
+}
+
+/// What became of one file in an upload.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum UploadOutcome {
+    /// It was stored as a new image.
+    Stored,
+    /// It matched one of the uploader's images and its links were returned in
+    /// its place (`on_duplicate=dedupe`).
+    Deduplicated,
+    /// It was never looked at: not an image we host, empty, or too large.
+    Skipped,
+    /// A storage or database error.
+    Error,
+    /// A malware scan flagged it.
+    Infected,
+    /// It matches an image an admin has blocked.
+    Blocked,
+    /// It would have put the account over its image quota.
+    OverQuota,
+}
+
+/// An upload that matched one of the uploader's existing images.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DuplicateUpload {
+    /// The uploaded file's original name.
+    pub file: String,
+    /// The ID of the existing image it matched.
+    pub existing_id: String,
+    /// Canonical URL of the existing image.
+    pub existing_link: String,
+    /// How many bits of the two perceptual hashes differ (0 = identical).
+    pub distance: u32,
+    /// Whether the upload was stored anyway (`on_duplicate=warn`) or replaced
+    /// by the existing image's links (`on_duplicate=dedupe`).
+    pub stored: bool,
+}
+
+impl DuplicateUpload {
+    fn of(file: &ValidatedFile, existing: &crate::phash::Match, stored: bool) -> Self {
+        let ext = existing.mimetype.split('/').last().unwrap_or("png");
+        Self {
+            file: file.original_name.clone(),
+            existing_id: existing.id.clone(),
+            existing_link: canonical_url(format!("/gallery/{}.{ext}", existing.id))
+                .unwrap_or_default()
+                .to_string(),
+            distance: existing.distance,
+            stored,
+        }
+    }
 }
 
 impl UploadResult {
     pub fn is_success(&self) -> bool {
-        self.total > 0 && self.errors == 0 && self.skipped == 0 && self.infected == 0
+        self.total > 0
+            && self.errors == 0
+            && self.skipped == 0
+            && self.infected == 0
+            && self.blocked == 0
+            && self.over_quota == 0
     }
 
     pub fn is_error(&self) -> bool {
-        self.total > 0 && self.total == self.errors + self.infected
+        self.total > 0 && self.total == self.errors + self.infected + self.blocked + self.over_quota
     }
 
     pub fn successful(&self) -> usize {
-        self.total.saturating_sub(self.errors).saturating_sub(self.infected)
+        self.total
+            .saturating_sub(self.errors)
+            .saturating_sub(self.infected)
+            .saturating_sub(self.blocked)
+            .saturating_sub(self.over_quota)
     }
 }
 
@@ -209,6 +400,7 @@ impl DeleteResult {
 /// Processes a multipart upload and inserts all valid images directly into the database.
 ///
 /// This function is shared by the web form handler and the API endpoint.
+#[allow(clippy::too_many_arguments)]
 pub async fn raw_upload_file(
     state: AppState,
     account: Account,
@@ -217,31 +409,167 @@ pub async fn raw_upload_file(
     api: bool,
     expires_at: Option<OffsetDateTime>,
     guild_id: Option<String>,
+    on_duplicate: DuplicatePolicy,
+    keep_metadata: Keep,
+    protection: Protection,
 ) -> Result<UploadResult, ApiError> {
     let max_bytes = state.config().effective_max_upload_bytes();
-    let (files, skipped) = collect_fields(multipart, max_bytes).await;
+    let (files, skipped) = collect_fields(multipart, max_bytes, keep_metadata).await;
+    store_uploads(
+        state,
+        account,
+        client_ip,
+        stream::iter(files),
+        skipped,
+        api,
+        expires_at,
+        guild_id,
+        on_duplicate,
+        protection,
+    )
+    .await
+}
 
-    if files.is_empty() {
-        return Err(ApiError::new("No valid files were provided."));
+/// Uploads one file whose bytes arrived some other way than a multipart form
+/// (a finished resumable upload), through the same validation, scanning and
+/// storage as [`raw_upload_file`]. Always counts as an API upload.
+pub async fn raw_upload_bytes(
+    state: AppState,
+    account: Account,
+    client_ip: Option<IpAddr>,
+    filename: &str,
+    bytes: Bytes,
+    expires_at: Option<OffsetDateTime>,
+    on_duplicate: DuplicatePolicy,
+) -> Result<UploadResult, ApiError> {
+    let file = validate_named(filename, bytes, Keep::default())
+        .await
+        .map_err(|e| ApiError::new(e.to_string()))?;
+    store_uploads(
+        state,
+        account,
+        client_ip,
+        stream::iter([file]),
+        0,
+        true,
+        expires_at,
+        None,
+        on_duplicate,
+        Protection::default(),
+    )
+    .await
+}
+
+/// What a presigned upload URL lets through (see `site::api::presign`).
+pub struct UploadGrant {
+    /// The largest file accepted, in bytes. Never more than the configured limit.
+    pub max_bytes: u64,
+    /// The MIME types accepted; empty accepts every type we host.
+    pub mimetypes: Vec<String>,
+    /// When the stored image expires, if ever.
+    pub expires_at: Option<OffsetDateTime>,
+    /// The guild gallery to tag the image with, if any.
+    pub guild_id: Option<String>,
+}
+
+/// Processes a multipart upload made through a presigned URL. Only the first
+/// file that fits the grant is stored; everything else counts as skipped.
+pub async fn raw_upload_presigned(
+    state: AppState,
+    account: Account,
+    client_ip: Option<IpAddr>,
+    multipart: Multipart,
+    grant: UploadGrant,
+) -> Result<UploadResult, ApiError> {
+    let max_bytes = grant.max_bytes.min(state.config().effective_max_upload_bytes());
+    let (mut files, mut skipped) = collect_fields(multipart, max_bytes, Keep::default()).await;
+    if !grant.mimetypes.is_empty() {
+        let before = files.len();
+        files.retain(|file| grant.mimetypes.contains(&file.mimetype));
+        skipped += before - files.len();
     }
+    if files.len() > 1 {
+        skipped += files.len() - 1;
+        files.truncate(1);
+    }
+    store_uploads(
+        state,
+        account,
+        client_ip,
+        stream::iter(files),
+        skipped,
+        true,
+        grant.expires_at,
+        grant.guild_id,
+        DuplicatePolicy::Warn,
+        Protection::default(),
+    )
+    .await
+}
 
-    let total = files.len();
+/// Scans, checks and stores validated files one at a time, as `files` yields
+/// them, so a caller producing them lazily (an archive import) never holds
+/// more than one in memory.
+#[allow(clippy::too_many_arguments)]
+pub(crate) async fn store_uploads(
+    state: AppState,
+    account: Account,
+    client_ip: Option<IpAddr>,
+    files: impl Stream<Item = ValidatedFile> + Send,
+    skipped: usize,
+    api: bool,
+    expires_at: Option<OffsetDateTime>,
+    guild_id: Option<String>,
+    on_duplicate: DuplicatePolicy,
+    protection: Protection,
+) -> Result<UploadResult, ApiError> {
+    let mut total = 0usize;
     let mut errors = 0usize;
     let mut infected = 0usize;
-    let mut links = Vec::with_capacity(total);
-    let mut raw_links = Vec::with_capacity(total);
+    let mut blocked = 0usize;
+    let mut over_quota = 0usize;
+    let mut exceeded = None;
+    let mut duplicates = Vec::new();
+    let mut links = Vec::new();
+    let mut raw_links = Vec::new();
+    let mut placeholders = Vec::new();
+    let mut ids = Vec::new();
+    let mut outcomes = Vec::new();
+
+    // Only pay the scanning cost when a backend is actually configured, and
+    // only up front under the `inline` policy (see `image_scan`).
+    let scan_policy = image_scan::policy(&state);
+    let mut pending = 0usize;
+
+    // Turn away files that can't fit before scanning or storing them. The
+    // insert re-checks, so a concurrent upload can't slip past this snapshot.
+    let quota = Quota::for_account(&state, &account);
+    let mut usage = if quota.is_unlimited() {
+        image_quota::Usage::default()
+    } else {
+        image_quota::usage(&state, account.id, guild_id.as_deref()).await
+    };
 
-    // Only pay the scanning cost when a backend is actually configured.
-    let scan_enabled = state.config().clamav_addr.is_some() || state.config().virustotal_api_key.is_some();
+    let mut files = std::pin::pin!(files);
+    while let Some(mut file) = files.next().await {
+        total += 1;
+        if let Err(e) = quota.check(usage, file.bytes.len() as i64) {
+            over_quota += 1;
+            outcomes.push(UploadOutcome::OverQuota);
+            exceeded = Some(e);
+            continue;
+        }
 
-    for mut file in files {
         // Malware gate: run the configured scanners (ClamAV + VirusTotal) over
         // the bytes before they ever touch the database. A definite hit on
-        // either backend ("infected") is rejected; "unknown"/"clean" pass.
-        if scan_enabled {
+        // either backend ("infected") is rejected; "unknown"/"clean" pass, and
+        // the verdict is kept with the image.
+        let mut scanned = (None, None);
+        if scan_policy == Some(UploadScan::Inline) {
             let report = crate::scan::scan_bytes(&state, &file.bytes).await;
             if report.verdict == "infected" {
                 infected += 1;
+                outcomes.push(UploadOutcome::Infected);
                 tracing::warn!(
                     sha256 = %report.sha256,
                     virus = ?report.clamav_virus,
@@ -269,35 +597,159 @@ pub async fn raw_upload_file(
                 );
                 continue;
             }
+            scanned = (
+                Some(ScanStatus::from_verdict(&report.verdict)),
+                image_scan::report_json(&report),
+            );
+        }
+
+        // Perceptual hash: refuses re-uploads of blocked images and spots
+        // near-duplicates of the uploader's own. Formats the decoder can't
+        // read (AVIF) go through unhashed.
+        let phash = crate::phash::compute(&file.bytes).await;
+        let mut duplicate = None;
+        if let Some(phash) = phash {
+            match crate::phash::blocked_by(&state, phash).await {
+                Ok(Some(block_id)) => {
+                    blocked += 1;
+                    outcomes.push(UploadOutcome::Blocked);
+                    state
+                        .audit("image.upload.rejected")
+                        .actor(&account)
+                        .target(format!("{}.{}", file.id, file.ext))
+                        .ip_opt(client_ip)
+                        .meta(serde_json::json!({
+                            "reason":   "blocked",
+                            "block_id": block_id,
+                        }))
+                        .fire();
+                    continue;
+                }
+                Ok(None) => {}
+                Err(e) => tracing::warn!(error = %e, "could not check upload against the block list"),
+            }
+
+            match crate::phash::similar(&state, account.id, phash, crate::phash::DUPLICATE_DISTANCE, None).await {
+                Ok(matches) => duplicate = matches.into_iter().next(),
+                Err(e) => tracing::warn!(error = %e, "could not look up near-duplicate uploads"),
+            }
+        }
+
+        // Under a background policy the file is stored held instead, and
+        // served once the scanner has cleared it.
+        let held = image_scan::holds(scan_policy, phash.is_some());
+        if held {
+            scanned = (Some(ScanStatus::Pending), None);
+        }
+
+        // Placeholder for listings. A deduplicated upload reports its own,
+        // which looks the same as the existing image's by definition.
+        let placeholder = crate::placeholder::compute(&file.bytes).await;
+
+        if let Some(existing) = duplicate.as_ref().filter(|_| on_duplicate == DuplicatePolicy::Dedupe) {
+            let ext = existing.mimetype.split('/').last().unwrap_or("png");
+            let dup = DuplicateUpload::of(&file, existing, false);
+            links.push(dup.existing_link.clone());
+            raw_links.push(
+                canonical_url(format!("/gallery/raw/{}.{ext}", existing.id))
+                    .unwrap_or_default()
+                    .to_string(),
+            );
+            placeholders.push(placeholder);
+            ids.push(existing.id.clone());
+            outcomes.push(UploadOutcome::Deduplicated);
+            duplicates.push(dup);
+            continue;
+        }
+
+        // Content-address the bytes: identical uploads share one stored blob
+        // (the `image_blob` triggers count the references). The blob is written
+        // before the row exists, so a reader never finds a row without bytes.
+        let hash = crate::scan::sha256_hex(&file.bytes);
+        if let Err(e) = crate::storage::store_blob(&state, &hash, &file.bytes).await {
+            tracing::error!(error = %e, store = state.blobs().name(), "could not store image blob");
+            errors += 1;
+            outcomes.push(UploadOutcome::Error);
+            continue;
         }
+        let size = file.bytes.len() as i64;
 
         // Resolve ID conflicts by appending a suffix.
-        let insert_result = state
+        let mut inserted = state
             .database()
             .execute(
-                "INSERT INTO images (id, mimetype, uploader_id, image_data, expires_at, original_name, guild_id) VALUES (?, ?, ?, ?, ?, ?, ?)",
-                (file.id.clone(), file.mimetype.clone(), account.id, file.bytes.to_vec(), expires_at, file.original_name.clone(), guild_id.clone()),
+                image_quota::INSERT_IMAGE,
+                boxed_params![
+                    file.id.clone(),
+                    file.mimetype.clone(),
+                    account.id,
+                    hash.clone(),
+                    size,
+                    expires_at,
+                    file.original_name.clone(),
+                    guild_id.clone(),
+                    phash,
+                    placeholder.as_ref().map(|p| p.blurhash.clone()),
+                    placeholder.as_ref().map(|p| p.dominant_color.clone()),
+                    quota.max_count,
+                    quota.max_bytes,
+                    scanned.0,
+                    scanned.1.clone(),
+                    protection.visibility,
+                    protection.password_hash.clone(),
+                    protection.burn_after_views,
+                ],
             )
             .await;
 
-        if let Err(ref e) = insert_result {
+        if let Err(ref e) = inserted {
             if is_unique_constraint_violation(e) {
                 // Very unlikely with random IDs, but handle it gracefully.
                 file.id = format!("{}-{}", file.id, get_new_image_id());
-                let retry = state
+                inserted = state
                     .database()
                     .execute(
-                        "INSERT INTO images (id, mimetype, uploader_id, image_data, expires_at, original_name, guild_id) VALUES (?, ?, ?, ?, ?, ?, ?)",
-                        (file.id.clone(), file.mimetype.clone(), account.id, file.bytes.to_vec(), expires_at, file.original_name.clone(), guild_id.clone()),
+                        image_quota::INSERT_IMAGE,
+                        boxed_params![
+                            file.id.clone(),
+                            file.mimetype.clone(),
+                            account.id,
+                            hash.clone(),
+                            size,
+                            expires_at,
+                            file.original_name.clone(),
+                            guild_id.clone(),
+                            phash,
+                            placeholder.as_ref().map(|p| p.blurhash.clone()),
+                            placeholder.as_ref().map(|p| p.dominant_color.clone()),
+                            quota.max_count,
+                            quota.max_bytes,
+                            scanned.0,
+                            scanned.1.clone(),
+                            protection.visibility,
+                            protection.password_hash.clone(),
+                            protection.burn_after_views,
+                        ],
                     )
                     .await;
-
-                if retry.is_err() {
-                    errors += 1;
-                    continue;
-                }
-            } else {
+            }
+        }
+        match inserted {
+            Ok(0) => {
+                // Another upload took the room since the snapshot above.
+                over_quota += 1;
+                outcomes.push(UploadOutcome::OverQuota);
+                usage = image_quota::usage(&state, account.id, guild_id.as_deref()).await;
+                exceeded = quota.check(usage, size).err().or(exceeded);
+                continue;
+            }
+            Ok(_) => {
+                usage.count += 1;
+                usage.bytes += size;
+            }
+            Err(_) => {
                 errors += 1;
+                outcomes.push(UploadOutcome::Error);
                 continue;
             }
         }
@@ -311,10 +763,39 @@ pub async fn raw_upload_file(
             .unwrap_or_default()
             .to_string();
         raw_links.push(raw_link);
+        placeholders.push(placeholder);
+
+        if let Some(existing) = duplicate {
+            duplicates.push(DuplicateUpload::of(&file, &existing, true));
+        }
+        if held {
+            pending += 1;
+            state.wake_upload_scanner();
+        } else {
+            state.pregenerate_thumbnail(&file.id);
+        }
+        ids.push(file.id);
+        outcomes.push(UploadOutcome::Stored);
+    }
+    if total == 0 {
+        return Err(ApiError::new("No valid files were provided."));
     }
 
     state.invalidate_image_caches().await;
 
+    // Nothing stored and the quota is why: an error ShareX can show, rather
+    // than a result it would report as a success with no links.
+    if let Some(exceeded) = exceeded.filter(|_| ids.is_empty()) {
+        state
+            .audit("image.upload.rejected")
+            .actor(&account)
+            .target(format!("{total} image{}", if total == 1 { "" } else { "s" }))
+            .ip_opt(client_ip)
+            .meta(serde_json::json!({ "reason": "quota", "via_api": api }))
+            .fire();
+        return Err(exceeded.into_api_error());
+    }
+
     // Audit log so the audit trail shows who uploaded what, from where, and
     // how it went. Image IDs go in meta (target stays human-readable as a
     // count); for the common case of one upload the ID is enough to
@@ -329,6 +810,10 @@ pub async fn raw_upload_file(
             "errors":    errors,
             "skipped":   skipped,
             "infected":  infected,
+            "pending":   pending,
+            "blocked":   blocked,
+            "over_quota": over_quota,
+            "duplicates": duplicates.len(),
             "via_api":   api,
             "guild_id":  guild_id,
             "links":     links,
@@ -341,12 +826,19 @@ pub async fn raw_upload_file(
         total,
         skipped,
         infected,
+        pending,
         links,
         raw_links,
+        placeholders,
+        blocked,
+        over_quota,
+        duplicates,
+        ids,
+        outcomes,
     })
 }
 
-/// Deletes a single image by ID.
+/// Moves a single image to the uploader's trash (see [`trash`]).
 ///
 /// This function is shared by the web form handler and the API endpoint.
 pub async fn delete_image(
@@ -356,7 +848,7 @@ pub async fn delete_image(
     id: String,
     api: bool,
 ) -> Result<DeleteResult, ApiError> {
-    let Some(img) = state.get_image(id.clone()).await else {
+    let Some(img) = state.get_image_meta(id.clone()).await else {
         return Err(ApiError::not_found(format!("Image `{id}` was not found")));
     };
 
@@ -364,13 +856,7 @@ pub async fn delete_image(
         return Err(ApiError::not_found(format!("Image `{id}` was not found")));
     }
 
-    let result = state
-        .database()
-        .execute(
-            "DELETE FROM images WHERE uploader_id = ? AND id = ?",
-            (account.id, id.clone()),
-        )
-        .await;
+    let result = trash::discard(&state, TrashKind::Image, account.id, &id).await;
 
     let failed = result.is_err();
 
@@ -407,25 +893,60 @@ async fn upload_file(
 ) -> Response {
     let url = referrer.map(|r| r.0).unwrap_or_else(|| "/images".to_string());
     let expires_at = expiry_from(params.expires_in);
-    match raw_upload_file(state, account, client_ip, multipart, false, expires_at, None).await {
+    let protection = match params.settings().for_upload() {
+        Ok(protection) => protection,
+        Err(e) => return flasher.add(e.error.as_ref()).bail(&url),
+    };
+    match raw_upload_file(
+        state,
+        account,
+        client_ip,
+        multipart,
+        false,
+        expires_at,
+        None,
+        params.on_duplicate,
+        params.keep_metadata,
+        protection,
+    )
+    .await
+    {
         Err(msg) => flasher.add(msg.error.as_ref()).bail(&url),
         Ok(result) => {
-            let message = if result.is_success() {
+            let message = if result.is_success() && !result.duplicates.is_empty() {
+                let n = result.duplicates.len();
+                FlashMessage::warning(format!(
+                    "Upload successful, but {n} file{} look{} like images you already uploaded.",
+                    if n == 1 { "" } else { "s" },
+                    if n == 1 { "s" } else { "" },
+                ))
+            } else if result.is_success() && result.pending > 0 {
+                let n = result.pending;
+                FlashMessage::success(format!(
+                    "Upload successful. {n} file{} will show once the malware scan clears {}.",
+                    if n == 1 { "" } else { "s" },
+                    if n == 1 { "it" } else { "them" },
+                ))
+            } else if result.is_success() {
                 FlashMessage::success("Upload successful.")
             } else if result.is_error() {
                 if result.infected > 0 && result.errors == 0 {
                     FlashMessage::error("Upload blocked: file failed a malware scan.")
+                } else if result.blocked > 0 && result.errors == 0 {
+                    FlashMessage::error("Upload blocked: this image is not allowed here.")
                 } else {
                     FlashMessage::error("Upload failed.")
                 }
             } else {
                 let ok = result.successful();
                 FlashMessage::warning(format!(
-                    "Uploaded {ok} file{}, {} skipped, {} failed, {} blocked by malware scan.",
+                    "Uploaded {ok} file{}, {} skipped, {} failed, {} blocked by malware scan, {} not allowed, {} over your quota.",
                     if ok == 1 { "" } else { "s" },
                     result.skipped,
                     result.errors,
                     result.infected,
+                    result.blocked,
+                    result.over_quota,
                 ))
             };
             flasher.add(message).bail(&url)
@@ -453,6 +974,12 @@ struct BulkFileOperationResponse {
 /// included. Only images owned by `account` are eligible; unknown or
 /// foreign IDs are silently skipped. If `requested` is empty, every image
 /// owned by the account is included.
+///
+/// Visibility, passwords and burn counts don't stand between an uploader and
+/// their own images (see [`image_lock::check`]), and downloading never counts
+/// as a view. An image whose last view has been used up is already expiring
+/// and is left out with the expired ones, and so is one held by the malware
+/// scan.
 pub async fn build_images_zip(
     state: &AppState,
     account: &Account,
@@ -492,6 +1019,15 @@ pub async fn build_images_zip(
     let mut used_names: std::collections::HashSet<String> = std::collections::HashSet::new();
     for f in &selected {
         let stem = f.id.split('.').next().unwrap_or(&f.id);
+        // Held uploads stay out, like they stay out of every other way to
+        // their bytes.
+        if state
+            .get_image_meta(stem.to_string())
+            .await
+            .is_some_and(|e| e.is_held())
+        {
+            continue;
+        }
         // The cached ImageFile may carry empty image_data because the
         // cache stores metadata only. Fetch the bytes on demand.
         let data = match state.resolve_image_data_for(stem).await {
@@ -554,18 +1090,9 @@ async fn bulk_delete_files(
     for file in payload.files {
         // Strip extension to get the bare ID.
         let id = file.split('.').next().unwrap_or(&file).to_string();
-        let result = state
-            .database()
-            .execute(
-                "DELETE FROM images WHERE uploader_id = ? AND id = ?",
-                (account.id, id.clone()),
-            )
-            .await;
-
-        if result.is_ok() {
-            success += 1;
-        } else {
-            failed += 1;
+        match trash::discard(&state, TrashKind::Image, account.id, &id).await {
+            Ok(true) => success += 1,
+            Ok(false) | Err(_) => failed += 1,
         }
     }
 
@@ -650,19 +1177,48 @@ struct ImageTemplate {
     dimensions: Option<(u32, u32)>,
     /// View count including the current visit.
     views: i64,
+    /// Whether the viewer uploaded this image, and so gets its settings form.
+    is_owner: bool,
+    /// The owner's 30-day views chart (an inline SVG), once there are views.
+    view_chart: Option<String>,
+    /// Whether the viewer uploaded this image, and so may replace its bytes.
+    is_uploader: bool,
+    /// The image's earlier versions, newest first; only loaded for its uploader.
+    revisions: Vec<ImageRevision>,
+    /// Only a public image without a password or burn count may be indexed.
+    indexable: bool,
+}
+
+/// The interstitial in front of a password-protected or burning image. It
+/// carries nothing of the image itself, not even for a link-preview crawler.
+#[derive(Template)]
+#[template(path = "images/gate.html")]
+struct ImageGateTemplate {
+    account: Option<Account>,
+    flashes: Flashes,
+    /// The canonical `{id}.{ext}`, for the form actions.
+    file: String,
+    /// `"locked"` or `"burn"`.
+    gate: &'static str,
+    /// A burning image with a password takes it in the reveal form.
+    needs_password: bool,
+    /// Reveals left before a burning image is deleted.
+    views_left: i64,
 }
 
 async fn get_image_page(
     State(state): State<AppState>,
     Path(image_id): Path<String>,
     headers: HeaderMap,
+    Extension(cookies): Extension<Vec<Cookie<'static>>>,
+    Extension(secret): Extension<SecretKey>,
     account: Option<Account>,
     flashes: Flashes,
 ) -> Result<Response, InternalError> {
     // Strip extension if present (e.g. "abc.png" → "abc").
     let id = image_id.split('.').next().unwrap_or(&image_id).to_string();
 
-    let Some(entry) = state.get_image(id.clone()).await else {
+    let Some(entry) = state.get_image_meta(id.clone()).await else {
         return Ok(Redirect::to("/").into_response());
     };
 
@@ -672,6 +1228,19 @@ async fn get_image_page(
         return Ok(Redirect::to("/").into_response());
     }
 
+    // Someone else's private image is gone as far as this viewer can tell —
+    // even the canonicalising redirect below would give it away.
+    let access = image_lock::check(&entry, account.as_ref(), &cookies, &secret);
+    if access == Access::Hidden {
+        return Ok(Redirect::to("/").into_response());
+    }
+    // So is one held by the malware scan, to all but its uploader, whose page
+    // says why it isn't showing.
+    let is_uploader = account.as_ref().is_some_and(|a| entry.uploader_id == Some(a.id));
+    if entry.is_held() && !is_uploader {
+        return Ok(Redirect::to("/").into_response());
+    }
+
     // Canonical URL is always "/gallery/{id}.{ext}". If the request came in
     // without an extension (or with the wrong one) bounce the user to the
     // canonical form via a 308 so bookmarks / shared links normalize.
@@ -690,7 +1259,30 @@ async fn get_image_page(
     // hotlinks don't inflate them.
     let accept = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok());
     if !wants_html_page(accept) {
-        return Ok(raw_image_response(entry));
+        if let Some(status) = gate_status(access).or_else(|| image_scan::held_status(&entry)) {
+            return Ok(status.into_response());
+        }
+        let Some(mut response) = serve_raw(&state, &headers, &entry).await else {
+            return Ok(Redirect::to("/").into_response());
+        };
+        // The same URL is a page or the bytes depending on `Accept`, so a
+        // shared cache must key on it.
+        response
+            .headers_mut()
+            .insert(header::VARY, header::HeaderValue::from_static("Accept"));
+        return Ok(response);
+    }
+
+    if matches!(access, Access::Locked | Access::Sealed) {
+        return Ok(ImageGateTemplate {
+            account,
+            flashes,
+            file: format!("{id}.{canonical_ext}"),
+            gate: if access == Access::Sealed { "burn" } else { "locked" },
+            needs_password: entry.is_locked(),
+            views_left: entry.burn_after_views.map_or(0, |max| (max - entry.views).max(0)),
+        }
+        .into_response());
     }
 
     let page_url = canonical_url(format!("/gallery/{}.{}", id, canonical_ext)).unwrap_or_default();
@@ -698,12 +1290,49 @@ async fn get_image_page(
 
     // Read pixel dimensions from the header only (cheap — no full decode) so
     // the info panel can show them. Best-effort: unsupported formats yield None.
-    let dimensions = image_dimensions(&entry.image_data).await;
+    let dimensions = match state.resolve_image_data_for(&id).await {
+        Some(bytes) => image_dimensions(&bytes).await,
+        None => None,
+    };
+
+    // Count this visit. Falls back to the cached value on a DB error. A
+    // burning image was counted when it was revealed, and its uploader looking
+    // at it doesn't use up a view.
+    let views = if entry.burn_after_views.is_some() {
+        entry.views
+    } else {
+        stats::record(
+            &state,
+            ViewKind::Image,
+            id.clone(),
+            stats::referrer_host(&state, &headers),
+        )
+        .await;
+        state.increment_image_views(&id).await.unwrap_or(entry.views + 1)
+    };
+
+    let is_owner = account.as_ref().is_some_and(|a| entry.owned_by(a));
+    let view_chart = if is_owner {
+        stats::load(&state, ViewKind::Image, id.clone(), stats::DEFAULT_DAYS)
+            .await
+            .ok()
+            .and_then(|s| stats::chart(&s))
+    } else {
+        None
+    };
 
-    // Count this visit. Falls back to the cached value on a DB error.
-    let views = state.increment_image_views(&id).await.unwrap_or(entry.views + 1);
+    let revisions = if is_uploader {
+        image_revision::list(&state, &id).await
+    } else {
+        Vec::new()
+    };
 
     Ok(ImageTemplate {
+        is_owner,
+        view_chart,
+        is_uploader,
+        revisions,
+        indexable: entry.visibility == Visibility::Public && entry.is_open(),
         account,
         entry,
         flashes,
@@ -718,7 +1347,7 @@ async fn get_image_page(
 /// Reads an image's pixel dimensions from its header without decoding the full
 /// pixel buffer. Runs on the blocking pool since the `image` reader is sync.
 /// Returns `None` for anything the configured decoders can't introspect.
-async fn image_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
+pub(crate) async fn image_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
     let bytes = bytes.to_vec();
     tokio::task::spawn_blocking(move || {
         image::ImageReader::new(std::io::Cursor::new(bytes))
@@ -737,40 +1366,157 @@ async fn image_dimensions(bytes: &[u8]) -> Option<(u32, u32)> {
 /// Used by the image grid to load thumbnails without a full page load.
 /// Mirrors the canonicalization behavior of [`get_image_page`]: requests
 /// for `/gallery/raw/abc` or `/gallery/raw/abc.wrong-ext` get a 308
-/// redirect to `/gallery/raw/abc.{canonical-ext}`.
-async fn get_image_raw(State(state): State<AppState>, Path(image_id): Path<String>) -> Result<Response, StatusCode> {
+/// redirect to `/gallery/raw/abc.{canonical-ext}`, query string kept.
+///
+/// `?w=`, `?h=`, `?fit=`, `?format=` and `?q=` ask for a resized or
+/// re-encoded variant instead (see [`crate::variant`]). The URL keeps the
+/// original's extension either way; `Content-Type` says what was served.
+///
+/// A private, password-protected or burning image is only served to a viewer
+/// [`image_lock::check`] lets through, and never with a shared-cacheable
+/// response.
+async fn get_image_raw(
+    State(state): State<AppState>,
+    Path(image_id): Path<String>,
+    Query(query): Query<VariantQuery>,
+    RawQuery(raw_query): RawQuery,
+    headers: HeaderMap,
+    Extension(cookies): Extension<Vec<Cookie<'static>>>,
+    Extension(secret): Extension<SecretKey>,
+    account: Option<Account>,
+) -> Result<Response, StatusCode> {
     let id = image_id.split('.').next().unwrap_or(&image_id).to_string();
 
-    let Some(entry) = state.get_image(id.clone()).await else {
+    let Some(entry) = state.get_image_meta(id.clone()).await else {
         return Err(StatusCode::NOT_FOUND);
     };
 
     if entry.is_expired() {
         return Err(StatusCode::NOT_FOUND);
     }
+    if let Some(status) = gate_status(image_lock::check(&entry, account.as_ref(), &cookies, &secret)) {
+        return Err(status);
+    }
+    // Not even the uploader gets the bytes of a held image.
+    if let Some(status) = image_scan::held_status(&entry) {
+        return Err(status);
+    }
 
     let canonical_ext = entry.ext();
     let provided_ext = image_id.rsplit_once('.').map(|(_, e)| e.to_ascii_lowercase());
     if provided_ext.as_deref() != Some(canonical_ext.as_str()) {
-        return Ok(Redirect::permanent(&format!("/gallery/raw/{}.{}", id, canonical_ext)).into_response());
+        let query = raw_query.map(|q| format!("?{q}")).unwrap_or_default();
+        return Ok(Redirect::permanent(&format!("/gallery/raw/{id}.{canonical_ext}{query}")).into_response());
     }
 
-    Ok(raw_image_response(entry))
+    let accept = headers.get(header::ACCEPT).and_then(|v| v.to_str().ok());
+    if let Some(variant) = Variant::resolve(&query, &entry.mimetype, accept, &state.config().variants) {
+        // An undecodable original (AVIF) falls through to being served as-is.
+        if let Some(rendered) = state.image_variant(&id, &variant).await {
+            return Ok(variant_response(&headers, &entry, &variant, rendered).await);
+        }
+    }
+
+    serve_raw(&state, &headers, &entry).await.ok_or(StatusCode::NOT_FOUND)
+}
+
+/// The response for a rendered variant: like [`raw_image_response`], with the
+/// download named for the variant's format and an `ETag` that covers the
+/// variant parameters.
+async fn variant_response(
+    request: &HeaderMap,
+    entry: &ImageEntry,
+    variant: &Variant,
+    rendered: ImageVariant,
+) -> Response {
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static(rendered.content_type),
+    );
+    let ext = if variant.format == "jpeg" {
+        "jpg"
+    } else {
+        variant.format
+    };
+    let name = entry.download_name();
+    let stem = name.rsplit_once('.').map(|(stem, _)| stem).unwrap_or(&name);
+    if let Ok(value) = header::HeaderValue::from_str(&inline_disposition(&format!("{stem}.{ext}"))) {
+        headers.insert(header::CONTENT_DISPOSITION, value);
+    }
+    if variant.negotiated {
+        headers.insert(header::VARY, header::HeaderValue::from_static("Accept"));
+    }
+    mark_personal(&mut headers, entry);
+    let validators = Validators::new(
+        &format!("{}-{}", rendered.source_hash, variant.key()),
+        Some(entry.modified_at()),
+    );
+    conditional::respond(request, &validators, headers, rendered.bytes.into()).await
 }
 
 /// Builds the raw-bytes response for an image: the correct `Content-Type` plus
 /// an inline `Content-Disposition` that names the download after the uploader's
 /// original filename. Shared by the explicit `/gallery/raw/:id` route and the
 /// content-negotiated `/gallery/:id` route.
-fn raw_image_response(entry: ImageEntry) -> Response {
-    (
-        [
-            (header::CONTENT_TYPE, entry.mimetype.clone()),
-            (header::CONTENT_DISPOSITION, inline_disposition(&entry.download_name())),
-        ],
-        entry.image_data,
-    )
-        .into_response()
+///
+/// The body is streamed from the blob store, so a large image is never held in
+/// memory whole. The content hash is the `ETag` and the time the bytes last
+/// changed the `Last-Modified`, so revalidation and `Range` requests work (see
+/// [`crate::conditional`]).
+async fn raw_image_response(request: &HeaderMap, entry: &ImageEntry, hash: &str, stream: BlobStream) -> Response {
+    let validators = Validators::new(hash, Some(entry.modified_at()));
+    conditional::respond(request, &validators, raw_image_headers(entry), stream.into()).await
+}
+
+/// The representation headers of an image's raw bytes.
+fn raw_image_headers(entry: &ImageEntry) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Ok(value) = header::HeaderValue::from_str(&entry.mimetype) {
+        headers.insert(header::CONTENT_TYPE, value);
+    }
+    if let Ok(value) = header::HeaderValue::from_str(&inline_disposition(&entry.download_name())) {
+        headers.insert(header::CONTENT_DISPOSITION, value);
+    }
+    mark_personal(&mut headers, entry);
+    headers
+}
+
+/// Serves the raw bytes of `entry` through [`raw_image_response`], or `None`
+/// if they can't be read. A revalidation whose validators match the stored
+/// blob's hash is answered with a 304 before the blob is opened.
+async fn serve_raw(state: &AppState, request: &HeaderMap, entry: &ImageEntry) -> Option<Response> {
+    if let Some(hash) = state.image_blob_hash(&entry.id).await {
+        let validators = Validators::new(&hash, Some(entry.modified_at()));
+        if let Some(response) = conditional::not_modified(request, &validators, raw_image_headers(entry)) {
+            return Some(response);
+        }
+    }
+    let (hash, stream) = state.image_stream(&entry.id).await?;
+    Some(raw_image_response(request, entry, &hash, stream).await)
+}
+
+/// The status to answer a request for an image's bytes with, when the viewer
+/// may not have them: 404 for someone else's private image, 401 for a locked
+/// one, 403 for a burning one that hasn't been revealed in a browser.
+fn gate_status(access: Access) -> Option<StatusCode> {
+    match access {
+        Access::Allowed => None,
+        Access::Hidden => Some(StatusCode::NOT_FOUND),
+        Access::Locked => Some(StatusCode::UNAUTHORIZED),
+        Access::Sealed => Some(StatusCode::FORBIDDEN),
+    }
+}
+
+/// Keeps caches from storing an image not everyone may see, so a CDN can't
+/// hand one viewer's unlocked copy to the next.
+fn mark_personal(headers: &mut HeaderMap, entry: &ImageEntry) {
+    if !entry.is_open() {
+        headers.insert(
+            header::CACHE_CONTROL,
+            header::HeaderValue::from_static("private, no-store"),
+        );
+    }
 }
 
 /// Decides whether a request for `/gallery/:id` wants the HTML landing page or
@@ -838,42 +1584,340 @@ fn inline_disposition(name: &str) -> String {
 ///
 /// Generates (and caches) a small JPEG/PNG so the grid doesn't download every
 /// full-resolution original. Falls back to the raw bytes for inputs the
-/// decoder can't handle (e.g. AVIF). Public, like the raw endpoint.
-async fn get_image_thumb(State(state): State<AppState>, Path(image_id): Path<String>) -> Result<Response, StatusCode> {
+/// decoder can't handle (e.g. AVIF). Gated like the raw endpoint.
+async fn get_image_thumb(
+    State(state): State<AppState>,
+    Path(image_id): Path<String>,
+    Extension(cookies): Extension<Vec<Cookie<'static>>>,
+    Extension(secret): Extension<SecretKey>,
+    account: Option<Account>,
+) -> Result<Response, StatusCode> {
     let id = image_id.split('.').next().unwrap_or(&image_id).to_string();
 
-    let Some(entry) = state.get_image(id.clone()).await else {
+    let Some(entry) = state.get_image_meta(id.clone()).await else {
         return Err(StatusCode::NOT_FOUND);
     };
     if entry.is_expired() {
         return Err(StatusCode::NOT_FOUND);
     }
+    if let Some(status) = gate_status(image_lock::check(&entry, account.as_ref(), &cookies, &secret)) {
+        return Err(status);
+    }
+    // Not even the uploader gets the bytes of a held image.
+    if let Some(status) = image_scan::held_status(&entry) {
+        return Err(status);
+    }
 
-    // Cache thumbnails aggressively in the browser: a given id's bytes never
-    // change (ids are random and never reused).
-    let cache_control = "public, max-age=604800, immutable";
-
-    match state.thumbnail_for(&id, &entry.image_data).await {
-        Some(thumb) => Ok((
+    // Cache thumbnails in the browser for a day. Not `immutable`: an owner can
+    // replace an image's bytes under the same id (see `site::image_revision`), and
+    // the grid should catch up. An image not everyone may see isn't cached at
+    // all, so it stops loading once the unlock runs out.
+    let cache_control = if entry.is_open() {
+        "public, max-age=86400"
+    } else {
+        "private, no-store"
+    };
+    let thumb_response = |thumb: Thumbnail| {
+        (
             [
                 (header::CONTENT_TYPE, thumb.content_type.to_string()),
                 (header::CACHE_CONTROL, cache_control.to_string()),
             ],
             (*thumb.bytes).clone(),
         )
-            .into_response()),
+            .into_response()
+    };
+
+    // A cached thumbnail is served without reading the original at all.
+    if let Some(thumb) = state.cached_thumbnail(&id).await {
+        return Ok(thumb_response(thumb));
+    }
+
+    let bytes = state.resolve_image_data_for(&id).await.ok_or(StatusCode::NOT_FOUND)?;
+    match state.thumbnail_for(&id, &bytes).await {
+        Some(thumb) => Ok(thumb_response(thumb)),
         // Undecodable format — serve the original so the tile still renders.
         None => Ok((
             [
                 (header::CONTENT_TYPE, entry.mimetype.clone()),
                 (header::CACHE_CONTROL, cache_control.to_string()),
             ],
-            entry.image_data,
+            bytes,
         )
             .into_response()),
     }
 }
 
+// ---------------------------------------------------------------------------
+// Image access forms
+// ---------------------------------------------------------------------------
+
+#[derive(Deserialize)]
+struct PasswordForm {
+    #[serde(default)]
+    password: String,
+}
+
+/// Loads a live image for one of the forms below, along with the URL of its
+/// page. `None` for a missing, expired or hidden image.
+async fn load_gated(state: &AppState, image_id: &str, viewer: Option<&Account>) -> Option<(ImageEntry, String)> {
+    let id = image_id.split('.').next().unwrap_or(image_id).to_string();
+    let entry = state.get_image_meta(id).await?;
+    if entry.is_expired() || !entry.visible_to(viewer) {
+        return None;
+    }
+    let page = format!("/gallery/{}.{}", entry.id, entry.ext());
+    Some((entry, page))
+}
+
+/// `POST /gallery/:id/unlock` — checks a password and hands out the unlock
+/// cookie.
+async fn unlock_image(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    Path(image_id): Path<String>,
+    Extension(secret): Extension<SecretKey>,
+    account: Option<Account>,
+    flasher: Flasher,
+    Form(form): Form<PasswordForm>,
+) -> Response {
+    let Some((entry, page)) = load_gated(&state, &image_id, account.as_ref()).await else {
+        return Redirect::to("/").into_response();
+    };
+    if !entry.is_locked() {
+        return Redirect::to(&page).into_response();
+    }
+    if !image_lock::password_matches(&entry, &form.password).await {
+        state
+            .audit("image.unlock.fail")
+            .target(entry.id.clone())
+            .ip_opt(client_ip)
+            .fire();
+        return flasher.add(FlashMessage::error("Wrong password.")).bail(&page);
+    }
+    let Some(token) = image_lock::sign_grant(&secret, &entry.id, image_lock::UNLOCK_TTL_SECS) else {
+        return flasher
+            .add(FlashMessage::error("Could not unlock the image."))
+            .bail(&page);
+    };
+
+    let mut response = Redirect::to(&page).into_response();
+    set_cookie(
+        &mut response,
+        image_lock::build_grant_cookie(&entry.id, token, image_lock::UNLOCK_TTL_SECS, state.config().production),
+    );
+    response
+}
+
+/// `POST /gallery/:id/reveal` — **the only request that counts a view of a
+/// burning image**. A `GET` shows an interstitial, so link-preview crawlers
+/// can't use the views up.
+async fn reveal_image(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    Path(image_id): Path<String>,
+    Extension(secret): Extension<SecretKey>,
+    account: Option<Account>,
+    flasher: Flasher,
+    Form(form): Form<PasswordForm>,
+) -> Response {
+    let Some((entry, page)) = load_gated(&state, &image_id, account.as_ref()).await else {
+        return Redirect::to("/").into_response();
+    };
+    if entry.burn_after_views.is_none() {
+        return Redirect::to(&page).into_response();
+    }
+    // Check the password before counting anything: a wrong one must not use
+    // up a view.
+    if !image_lock::password_matches(&entry, &form.password).await {
+        state
+            .audit("image.unlock.fail")
+            .target(entry.id.clone())
+            .ip_opt(client_ip)
+            .fire();
+        return flasher.add(FlashMessage::error("Wrong password.")).bail(&page);
+    }
+
+    let views_left = match image_lock::reveal(&state, &entry.id).await {
+        Ok(Some(left)) => {
+            stats::record(&state, ViewKind::Image, entry.id.clone(), None).await;
+            left
+        }
+        // Someone else used the last view first.
+        Ok(None) => return Redirect::to("/").into_response(),
+        Err(e) => {
+            tracing::warn!(image = %entry.id, error = %e, "could not reveal image");
+            return flasher
+                .add(FlashMessage::error("Could not open the image."))
+                .bail(&page);
+        }
+    };
+    state
+        .audit("image.reveal")
+        .target(entry.id.clone())
+        .ip_opt(client_ip)
+        .meta(serde_json::json!({ "views_left": views_left }))
+        .fire();
+
+    let Some(token) = image_lock::sign_grant(&secret, &entry.id, image_lock::REVEAL_TTL_SECS) else {
+        return flasher
+            .add(FlashMessage::error("Could not open the image."))
+            .bail(&page);
+    };
+    let mut response = Redirect::to(&page).into_response();
+    set_cookie(
+        &mut response,
+        image_lock::build_grant_cookie(&entry.id, token, image_lock::REVEAL_TTL_SECS, state.config().production),
+    );
+    response
+}
+
+/// The settings form on an image's page. Checkboxes arrive as `Some("on")` or
+/// not at all; blank fields leave the setting as it is.
+#[derive(Deserialize)]
+struct ImageSettingsForm {
+    #[serde(default)]
+    visibility: Option<String>,
+    #[serde(default)]
+    password: String,
+    #[serde(default)]
+    remove_password: Option<String>,
+    #[serde(default)]
+    burn_after_views: String,
+}
+
+/// `POST /gallery/:id/settings` — the uploader changes who sees an image.
+async fn update_image_settings(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    Path(image_id): Path<String>,
+    account: Account,
+    flasher: Flasher,
+    Form(form): Form<ImageSettingsForm>,
+) -> Response {
+    let Some((entry, page)) = load_gated(&state, &image_id, Some(&account)).await else {
+        return Redirect::to("/").into_response();
+    };
+    if entry.uploader_id != Some(account.id) {
+        return Redirect::to(&page).into_response();
+    }
+
+    let burn_after_views = match form.burn_after_views.trim() {
+        "" => None,
+        raw => match raw.parse::<i64>() {
+            Ok(views) => Some(views),
+            Err(_) => {
+                return flasher
+                    .add(FlashMessage::error("The view count must be a number."))
+                    .bail(&page)
+            }
+        },
+    };
+    let password = if form.remove_password.is_some() {
+        Some(String::new())
+    } else {
+        Some(form.password).filter(|p| !p.is_empty())
+    };
+    let settings = ImageSettings {
+        visibility: form.visibility.as_deref().map(Visibility::parse),
+        password,
+        burn_after_views,
+    };
+
+    if let Err(e) = image_lock::apply(&state, &account, &[entry.id.clone()], &settings).await {
+        return flasher.add(e.error.as_ref()).bail(&page);
+    }
+    state
+        .audit("image.settings")
+        .actor(&account)
+        .target(entry.id.clone())
+        .ip_opt(client_ip)
+        .meta(serde_json::json!({
+            "visibility":       settings.visibility,
+            "password_changed": settings.password.is_some(),
+            "burn_after_views": settings.burn_after_views,
+            "via_api":          false,
+        }))
+        .fire();
+    flasher.add(FlashMessage::success("Image settings saved.")).bail(&page)
+}
+
+/// `POST /gallery/:id/replace` — swaps the image's bytes for the uploaded
+/// file, keeping the old ones in its history (see [`image_revision`]).
+async fn replace_image(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    Path(image_id): Path<String>,
+    account: Account,
+    flasher: Flasher,
+    multipart: Multipart,
+) -> Response {
+    let Some((entry, page)) = load_gated(&state, &image_id, Some(&account)).await else {
+        return Redirect::to("/").into_response();
+    };
+    if entry.uploader_id != Some(account.id) {
+        return Redirect::to(&page).into_response();
+    }
+
+    let max_bytes = state.config().effective_max_upload_bytes();
+    let replaced = match single_file(multipart, max_bytes, Keep::default()).await {
+        Ok(file) => image_revision::replace(&state, &account, client_ip, &entry.id, file, false).await,
+        Err(e) => Err(e),
+    };
+    match replaced {
+        Ok(_) => flasher
+            .add(FlashMessage::success(
+                "Image replaced. The previous version is in its history.",
+            ))
+            .bail(&page),
+        Err(e) => flasher.add(e.error.as_ref()).bail(&page),
+    }
+}
+
+/// `GET /gallery/:id/revisions/:rev` — the bytes of an earlier version, for
+/// the uploader only.
+async fn get_image_revision(
+    State(state): State<AppState>,
+    Path((image_id, revision)): Path<(String, i64)>,
+    headers: HeaderMap,
+    account: Account,
+) -> Result<Response, StatusCode> {
+    let id = image_id.split('.').next().unwrap_or(&image_id).to_string();
+    let entry = state.get_image_meta(id.clone()).await.ok_or(StatusCode::NOT_FOUND)?;
+    if entry.uploader_id != Some(account.id) || entry.is_expired() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    let revision = image_revision::get(&state, &id, revision)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)?;
+    image_revision::respond(&state, &headers, &revision)
+        .await
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+/// `POST /gallery/:id/revisions/:rev/restore` — puts an earlier version back.
+async fn restore_image_revision(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    Path((image_id, revision)): Path<(String, i64)>,
+    account: Account,
+    flasher: Flasher,
+) -> Response {
+    let Some((entry, page)) = load_gated(&state, &image_id, Some(&account)).await else {
+        return Redirect::to("/").into_response();
+    };
+    if entry.uploader_id != Some(account.id) {
+        return Redirect::to(&page).into_response();
+    }
+
+    match image_revision::restore(&state, &account, client_ip, &entry.id, revision, false).await {
+        Ok(_) => flasher
+            .add(FlashMessage::success("Earlier version restored."))
+            .bail(&page),
+        Err(e) => flasher.add(e.error.as_ref()).bail(&page),
+    }
+}
+
 #[derive(Template)]
 #[template(path = "images/images.html")]
 struct ImagesTemplate {
@@ -908,7 +1952,14 @@ async fn get_images_page(
 /// Background reaper: deletes expired images hourly and invalidates the image
 /// caches when anything was removed. Expiry is also enforced at serve time, so
 /// this is the cleanup half — it keeps the database from accumulating dead
-/// rows rather than being the sole gate.
+/// rows rather than being the sole gate. It also purges images that have sat
+/// in the trash past the restore window (see [`trash::reap`]); short links
+/// have their own reaper ([`crate::site::links::spawn_link_reaper`]).
+///
+/// Each sweep also collects blobs no image has referenced for a while (see
+/// [`crate::storage::collect_garbage`]) — whatever deleted the last row
+/// pointing at them, whether this reaper, a user, or account deletion — and
+/// drops expired resumable uploads and spent presigned-upload records.
 pub fn spawn_expiry_reaper(state: AppState) {
     tokio::spawn(async move {
         let mut ticker = tokio::time::interval(std::time::Duration::from_secs(3600));
@@ -933,16 +1984,45 @@ pub fn spawn_expiry_reaper(state: AppState) {
                 tracing::info!(count = deleted, "reaped expired images");
                 state.invalidate_image_caches().await;
             }
+            match trash::reap(&state, TrashKind::Image).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!(count, kind = "image", "purged items from the trash"),
+                Err(e) => tracing::warn!(kind = "image", error = %e, "trash purge failed"),
+            }
+            match crate::storage::collect_garbage(&state).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!(count, "collected unreferenced image blobs"),
+                Err(e) => tracing::warn!(error = %e, "image blob collection failed"),
+            }
+            match crate::site::api::presign::reap(&state).await {
+                Ok(0) => {}
+                Ok(count) => tracing::debug!(count, "dropped expired presigned upload records"),
+                Err(e) => tracing::warn!(error = %e, "presigned upload cleanup failed"),
+            }
+            match crate::site::api::uploads::reap(&state).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!(count, "reaped expired resumable uploads"),
+                Err(e) => tracing::warn!(error = %e, "resumable upload cleanup failed"),
+            }
         }
     });
 }
 
 pub fn routes() -> Router<AppState> {
+    // Both take passwords; one limit for the two, so alternating doesn't
+    // double it. 10 a minute per IP, as for logging in.
+    let password_tries = RateLimit::default().quota(10, 60.0).build();
     Router::new()
         .route("/images", get(get_images_page))
         .route("/gallery/:id", get(get_image_page))
         .route("/gallery/raw/:id", get(get_image_raw))
         .route("/gallery/thumb/:id", get(get_image_thumb))
+        .route("/gallery/:id/unlock", post(unlock_image).layer(password_tries.clone()))
+        .route("/gallery/:id/reveal", post(reveal_image).layer(password_tries))
+        .route("/gallery/:id/settings", post(update_image_settings))
+        .route("/gallery/:id/replace", post(replace_image))
+        .route("/gallery/:id/revisions/:rev", get(get_image_revision))
+        .route("/gallery/:id/revisions/:rev/restore", post(restore_image_revision))
         .route("/images/bulk", delete(bulk_delete_files))
         .route("/images/bulk/download", post(bulk_download_files))
         .route("/images/bulk", post(upload_file).layer(RateLimit::default().build()))
@@ -0,0 +1,441 @@
+//! Bulk import: the inverse of [`build_images_zip`](crate::site::image::build_images_zip).
+//!
+//! `POST /api/v1/images/import` takes a ZIP archive and uploads every image in
+//! it as if each had been its own `file` field on `/images/upload` — the same
+//! extension allow-list, metadata stripping, malware scan, block list and
+//! quota. The response reports what became of each entry.
+//!
+//! Archives are hostile input, so unpacking is bounded before anything is
+//! decompressed and again while it is ([`Limits`]): the number of entries, the
+//! size and compression ratio of each, and the total. An archive that breaks
+//! the entry or total limit is refused outright; a single oversized or
+//! suspiciously compressible entry is skipped. Every read is capped by what it
+//! may still produce, so an entry that lies about its size in the central
+//! directory gets no further than the limit.
+
+use std::io::{Cursor, Read};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+
+use bytes::Bytes;
+use futures_util::stream::{self, StreamExt};
+use serde::Serialize;
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+use crate::error::ApiError;
+use crate::metadata::Keep;
+use crate::models::Account;
+use crate::site::image::{self, DuplicatePolicy, UploadOutcome, UploadResult};
+use crate::site::image_lock::Protection;
+use crate::AppState;
+
+/// The most entries an archive may list, directories included.
+pub const MAX_ENTRIES: usize = 250;
+/// The most an archive may unpack to, in total.
+pub const MAX_TOTAL_BYTES: u64 = 256 * 1024 * 1024;
+/// The highest compression ratio an entry may have. Images are compressed
+/// already, so real ones barely shrink in a ZIP; an entry that does shrink a
+/// hundredfold is padding, not a picture.
+pub const MAX_RATIO: u64 = 100;
+
+/// What an archive may unpack to.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    pub max_entries: usize,
+    /// The largest single entry, normally the upload size limit.
+    pub max_entry_bytes: u64,
+    pub max_total_bytes: u64,
+    pub max_ratio: u64,
+}
+
+impl Limits {
+    pub fn new(max_entry_bytes: u64) -> Self {
+        Self {
+            max_entries: MAX_ENTRIES,
+            max_entry_bytes,
+            max_total_bytes: MAX_TOTAL_BYTES,
+            max_ratio: MAX_RATIO,
+        }
+    }
+}
+
+/// One file in an archive, unpacked or passed over.
+#[derive(Debug)]
+pub struct Entry {
+    /// Its path inside the archive.
+    pub name: String,
+    /// The bytes, or why it was skipped.
+    pub data: Result<Bytes, String>,
+}
+
+/// Why a whole archive was refused.
+#[derive(Debug, PartialEq, Eq)]
+pub enum UnpackError {
+    /// It isn't a ZIP archive, or is too broken to read.
+    Invalid(String),
+    /// It lists more entries than [`Limits::max_entries`].
+    TooManyEntries(usize),
+    /// It unpacks (or claims to) to more than [`Limits::max_total_bytes`].
+    TooLarge,
+}
+
+impl std::fmt::Display for UnpackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UnpackError::Invalid(e) => write!(f, "not a readable ZIP archive: {e}"),
+            UnpackError::TooManyEntries(n) => {
+                write!(f, "the archive has {n} entries; at most {MAX_ENTRIES} are allowed")
+            }
+            UnpackError::TooLarge => write!(
+                f,
+                "the archive unpacks to more than {} MiB",
+                MAX_TOTAL_BYTES / (1024 * 1024)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for UnpackError {}
+
+/// Whether an entry is an operating system's bookkeeping rather than content:
+/// macOS resource forks (`__MACOSX/`, `._name`) and dotfiles in general.
+fn is_hidden(path: &str) -> bool {
+    path.split('/').any(|part| part == "__MACOSX" || part.starts_with('.'))
+}
+
+/// Unpacks the files in a ZIP archive within `limits`, handing each to `each`
+/// as soon as it is read, so no more than one is ever held inflated.
+/// Directories are left out; every other entry is handed over, in archive
+/// order, with its bytes or the reason it was skipped. `each` returns `false`
+/// to stop early. CPU-bound — call it from the blocking pool.
+///
+/// The entry and declared-size limits are checked before anything is handed
+/// over, so an archive refused with an error has produced nothing. One whose
+/// entries turn out larger than they claimed runs into the total as it is read
+/// instead; from there on, every entry is skipped.
+pub fn unpack(archive: &[u8], limits: &Limits, mut each: impl FnMut(Entry) -> bool) -> Result<(), UnpackError> {
+    let mut zip = zip::ZipArchive::new(Cursor::new(archive)).map_err(|e| UnpackError::Invalid(e.to_string()))?;
+    if zip.len() > limits.max_entries {
+        return Err(UnpackError::TooManyEntries(zip.len()));
+    }
+
+    // The central directory's sizes can lie, but a bomb that admits to being
+    // one is turned away before a byte is inflated.
+    let mut declared = 0u64;
+    for i in 0..zip.len() {
+        let file = zip.by_index_raw(i).map_err(|e| UnpackError::Invalid(e.to_string()))?;
+        declared = declared.saturating_add(file.size());
+    }
+    if declared > limits.max_total_bytes {
+        return Err(UnpackError::TooLarge);
+    }
+
+    let mut total = 0u64;
+    let mut exhausted = false;
+    for i in 0..zip.len() {
+        let name = zip.name_for_index(i).unwrap_or_default().to_string();
+        let data = match zip.by_index(i) {
+            Err(e) => Err(e.to_string()),
+            Ok(file) if file.is_dir() => continue,
+            Ok(_) if exhausted => Err(UnpackError::TooLarge.to_string()),
+            Ok(file) if file.enclosed_name().is_none() || is_hidden(&name) => {
+                Err("not an image: hidden or unsafe path".into())
+            }
+            Ok(file) if file.size() > limits.max_entry_bytes => Err("file exceeds the maximum upload size".into()),
+            Ok(file) if file.size() / file.compressed_size().max(1) > limits.max_ratio => {
+                Err("compressed too well to be an image".into())
+            }
+            Ok(mut file) => {
+                // Never inflate more than the entry, or the archive, may still hold.
+                let cap = limits.max_entry_bytes.min(limits.max_total_bytes.saturating_sub(total));
+                let compressed = file.compressed_size().max(1);
+                let mut buf = Vec::with_capacity(file.size().min(cap) as usize);
+                let read = (&mut file).take(cap + 1).read_to_end(&mut buf);
+                total += buf.len() as u64;
+
+                match read {
+                    Err(e) => Err(format!("could not be unpacked: {e}")),
+                    Ok(_) if buf.len() as u64 > cap && cap < limits.max_entry_bytes => {
+                        exhausted = true;
+                        Err(UnpackError::TooLarge.to_string())
+                    }
+                    Ok(_) if buf.len() as u64 > cap => Err("file exceeds the maximum upload size".into()),
+                    Ok(_) if buf.len() as u64 / compressed > limits.max_ratio => {
+                        Err("compressed too well to be an image".into())
+                    }
+                    Ok(_) => Ok(Bytes::from(buf)),
+                }
+            }
+        };
+        if !each(Entry { name, data }) {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// What became of one archive entry.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImportEntry {
+    /// The entry's path inside the archive.
+    pub name: String,
+    pub outcome: UploadOutcome,
+    /// Canonical URL of the stored image (or, when deduplicated, of the
+    /// existing one).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub link: Option<String>,
+    /// Canonical raw URL of the same.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub raw_link: Option<String>,
+    /// Why a skipped entry was skipped.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// The result of an import: the usual upload summary, plus every entry.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ImportResult {
+    #[serde(flatten)]
+    pub upload: UploadResult,
+    /// Every file in the archive, in archive order.
+    pub entries: Vec<ImportEntry>,
+}
+
+/// Imports the images in a ZIP archive for `account`, through the same
+/// validation, scanning, quota and storage as a multipart upload.
+#[allow(clippy::too_many_arguments)]
+pub async fn import(
+    state: AppState,
+    account: Account,
+    client_ip: Option<IpAddr>,
+    archive: Bytes,
+    expires_at: Option<OffsetDateTime>,
+    on_duplicate: DuplicatePolicy,
+    keep: Keep,
+    protection: Protection,
+) -> Result<ImportResult, ApiError> {
+    // The unpacker hands entries over one at a time; the channel holds one
+    // more while the last is validated and stored, and no others.
+    let limits = Limits::new(state.config().effective_max_upload_bytes());
+    let (tx, rx) = tokio::sync::mpsc::channel::<Entry>(1);
+    let unpacker =
+        tokio::task::spawn_blocking(move || unpack(&archive, &limits, |entry| tx.blocking_send(entry).is_ok()));
+
+    // Every entry goes in the report; the indexes of those handed on to be
+    // stored go in `pending` alongside it.
+    let progress = Arc::new(Mutex::new((Vec::<ImportEntry>::new(), Vec::<usize>::new())));
+    let entries = stream::unfold(rx, |mut rx| async move { rx.recv().await.map(|entry| (entry, rx)) });
+    let files = entries.filter_map(|entry| {
+        let progress = progress.clone();
+        async move {
+            let base = entry.name.rsplit('/').next().unwrap_or(&entry.name);
+            let validated = match entry.data {
+                Ok(bytes) => image::validate_named(base, bytes, keep)
+                    .await
+                    .map_err(|e| e.to_string()),
+                Err(reason) => Err(reason),
+            };
+            let mut progress = progress.lock().unwrap();
+            let (report, pending) = &mut *progress;
+            let (file, outcome, reason) = match validated {
+                Ok(file) => {
+                    pending.push(report.len());
+                    (Some(file), UploadOutcome::Error, None)
+                }
+                Err(reason) => (None, UploadOutcome::Skipped, Some(reason)),
+            };
+            report.push(ImportEntry {
+                name: entry.name,
+                outcome,
+                link: None,
+                raw_link: None,
+                reason,
+            });
+            file
+        }
+    });
+
+    // How many entries were skipped is only known once all have been read, so
+    // it is filled in afterwards rather than passed along.
+    let stored = image::store_uploads(
+        state,
+        account,
+        client_ip,
+        files,
+        0,
+        true,
+        expires_at,
+        None,
+        on_duplicate,
+        protection,
+    )
+    .await;
+
+    // An archive refused outright was refused before anything was handed on.
+    unpacker
+        .await
+        .map_err(|e| ApiError::new(e.to_string()))?
+        .map_err(|e| ApiError::new(e.to_string()))?;
+    let (mut report, pending) = std::mem::take(&mut *progress.lock().unwrap());
+    if pending.is_empty() {
+        return Err(ApiError::new("The archive contains no images that can be uploaded."));
+    }
+    let mut upload = stored?;
+    upload.skipped = report.len() - pending.len();
+
+    // The upload's outcomes are in the order its files were given, and its
+    // links in the order the stored or deduplicated ones were.
+    let mut links = upload.links.iter().zip(&upload.raw_links);
+    for (&index, &outcome) in pending.iter().zip(&upload.outcomes) {
+        let entry = &mut report[index];
+        entry.outcome = outcome;
+        if matches!(outcome, UploadOutcome::Stored | UploadOutcome::Deduplicated) {
+            if let Some((link, raw_link)) = links.next() {
+                entry.link = Some(link.clone());
+                entry.raw_link = Some(raw_link.clone());
+            }
+        }
+    }
+
+    Ok(ImportResult {
+        upload,
+        entries: report,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+    use zip::CompressionMethod;
+
+    fn archive(files: &[(&str, &[u8])], method: CompressionMethod) -> Vec<u8> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let opts = SimpleFileOptions::default().compression_method(method);
+        for (name, data) in files {
+            if name.ends_with('/') {
+                zip.add_directory(*name, opts).unwrap();
+            } else {
+                zip.start_file(*name, opts).unwrap();
+                zip.write_all(data).unwrap();
+            }
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn unpack_all(archive: &[u8], limits: &Limits) -> Result<Vec<Entry>, UnpackError> {
+        let mut entries = Vec::new();
+        unpack(archive, limits, |entry| {
+            entries.push(entry);
+            true
+        })?;
+        Ok(entries)
+    }
+
+    fn noise(len: usize) -> Vec<u8> {
+        // Incompressible enough to pass the ratio check.
+        let mut state = 0x2545_f491_u32;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unpacks_files_and_passes_over_the_rest() {
+        let picture = noise(2048);
+        let zip = archive(
+            &[
+                ("holiday/", b""),
+                ("holiday/beach.png", &picture),
+                ("notes.txt", b"not an image, but that's for validation to say"),
+                ("__MACOSX/holiday/._beach.png", b"resource fork"),
+                ("zeros.png", &[0u8; 64 * 1024]),
+            ],
+            CompressionMethod::Deflated,
+        );
+        let entries = unpack_all(&zip, &Limits::new(1024 * 1024)).unwrap();
+
+        let names: Vec<_> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "holiday/beach.png",
+                "notes.txt",
+                "__MACOSX/holiday/._beach.png",
+                "zeros.png"
+            ]
+        );
+        assert_eq!(entries[0].data.as_deref().unwrap(), &picture[..]);
+        assert!(entries[1].data.is_ok());
+        assert!(entries[2].data.is_err());
+        assert_eq!(
+            entries[3].data.as_ref().unwrap_err(),
+            "compressed too well to be an image"
+        );
+
+        let big = unpack_all(&zip, &Limits::new(1024)).unwrap();
+        assert_eq!(
+            big[0].data.as_ref().unwrap_err(),
+            "file exceeds the maximum upload size"
+        );
+    }
+
+    #[test]
+    fn oversized_archives_are_refused() {
+        let zip = archive(
+            &[("a.png", &noise(600)), ("b.png", &noise(600))],
+            CompressionMethod::Stored,
+        );
+        let limits = Limits::new(1024);
+
+        assert!(unpack_all(&zip, &limits).is_ok());
+        assert_eq!(
+            unpack_all(
+                &zip,
+                &Limits {
+                    max_entries: 1,
+                    ..limits
+                }
+            )
+            .unwrap_err(),
+            UnpackError::TooManyEntries(2)
+        );
+        assert_eq!(
+            unpack_all(
+                &zip,
+                &Limits {
+                    max_total_bytes: 1000,
+                    ..limits
+                }
+            )
+            .unwrap_err(),
+            UnpackError::TooLarge
+        );
+        assert!(matches!(
+            unpack_all(b"PK but not really", &limits),
+            Err(UnpackError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn entries_are_handed_over_one_at_a_time() {
+        let zip = archive(
+            &[("a.png", &noise(600)), ("b.png", &noise(600)), ("c.png", &noise(600))],
+            CompressionMethod::Stored,
+        );
+        let mut seen = Vec::new();
+        unpack(&zip, &Limits::new(1024), |entry| {
+            seen.push(entry.name);
+            seen.len() < 2
+        })
+        .unwrap();
+        assert_eq!(seen, ["a.png", "b.png"]);
+    }
+}
@@ -0,0 +1,460 @@
+//! Who gets to see an image: visibility, password protection and
+//! burn-after-N-views.
+//!
+//! Images follow the paste model (see [`crate::site::paste`]) with two
+//! differences. The bytes are never encrypted — thumbnails, variants and
+//! duplicate detection all need them — so a password is an Argon2 hash checked
+//! on unlock, and the unlock cookie carries no key, only the image id and an
+//! expiry. And a burning image lasts a chosen number of reveals, not one.
+//!
+//! Getting past either gate earns the same thing: a signed, `HttpOnly` cookie
+//! named for that one image and scoped to `/gallery`, so the page, the raw bytes
+//! and the thumbnail all load for that browser until it runs out. As with a
+//! burning paste, a `GET` never uses up a view — only `POST /gallery/:id/reveal`
+//! does — so a link-preview crawler can't burn an image nobody has seen. The
+//! uploader (and admins) pass every gate without counting.
+
+use cookie::Cookie;
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+use crate::error::ApiError;
+use crate::key::SecretKey;
+use crate::models::{Account, ImageEntry, Visibility};
+use crate::{boxed_params, AppState};
+
+/// How long a password unlock lasts.
+pub const UNLOCK_TTL_SECS: i64 = 30 * 60;
+/// How long a reveal of a burning image lasts. The image is deleted this long
+/// after its last view.
+pub const REVEAL_TTL_SECS: i64 = 10 * 60;
+/// The most views a burning image can be given.
+pub const MAX_BURN_VIEWS: i64 = 1000;
+/// The longest password accepted, in bytes.
+const MAX_PASSWORD_LEN: usize = 128;
+
+/// What a viewer may have of an image, right now.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Access {
+    /// Serve it.
+    Allowed,
+    /// Private, and not the viewer's. Answer as if it didn't exist.
+    Hidden,
+    /// Password-protected, and this browser hasn't unlocked it.
+    Locked,
+    /// Burn-after-views, and this browser hasn't revealed it.
+    Sealed,
+}
+
+/// Decides what `viewer` may see of `entry`, honouring the unlock cookie.
+pub fn check(entry: &ImageEntry, viewer: Option<&Account>, cookies: &[Cookie<'static>], secret: &SecretKey) -> Access {
+    if viewer.is_some_and(|a| entry.owned_by(a)) {
+        return Access::Allowed;
+    }
+    if !entry.visible_to(viewer) {
+        return Access::Hidden;
+    }
+    if !entry.is_locked() && entry.burn_after_views.is_none() {
+        return Access::Allowed;
+    }
+    let granted = grant_cookie(cookies, &entry.id).is_some_and(|token| verify_grant(secret, &entry.id, &token));
+    if granted {
+        Access::Allowed
+    } else if entry.burn_after_views.is_some() {
+        Access::Sealed
+    } else {
+        Access::Locked
+    }
+}
+
+// ─── The unlock cookie ───────────────────────────────────────────────────────
+
+/// What an image grant says it is for, so no other token signed with the
+/// site's key can pass for one.
+const GRANT_PURPOSE: &str = "image-unlock";
+
+/// The signed payload of an unlock cookie: which image, and until when.
+///
+/// A paste's unlock cookie is signed with the same key and also carries an
+/// `id` and an `exp`; the `purpose` tag and the refusal of unknown fields keep
+/// one for paste `X` from opening image `X`.
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Grant {
+    purpose: String,
+    id: String,
+    exp: i64,
+}
+
+/// The cookie name for a given image. One cookie per image, so unlocking one
+/// never unlocks another.
+pub fn cookie_name(id: &str) -> String {
+    format!("image_unlock_{id}")
+}
+
+/// Signs a grant for `id` that lasts `ttl_secs`.
+pub fn sign_grant(secret: &SecretKey, id: &str, ttl_secs: i64) -> Option<String> {
+    secret
+        .sign(&Grant {
+            purpose: GRANT_PURPOSE.to_string(),
+            id: id.to_string(),
+            exp: OffsetDateTime::now_utc().unix_timestamp() + ttl_secs,
+        })
+        .ok()
+}
+
+/// Whether `token` is a live grant for `id`. One minted for a different image,
+/// for something else, or one whose time is up, doesn't count however good its
+/// signature.
+pub fn verify_grant(secret: &SecretKey, id: &str, token: &str) -> bool {
+    secret.verify::<Grant>(token).is_some_and(|grant| {
+        grant.purpose == GRANT_PURPOSE && grant.id == id && grant.exp > OffsetDateTime::now_utc().unix_timestamp()
+    })
+}
+
+/// The signed unlock cookie for `id`, if the request carries one.
+fn grant_cookie(cookies: &[Cookie<'static>], id: &str) -> Option<String> {
+    let name = cookie_name(id);
+    cookies.iter().find(|c| c.name() == name).map(|c| c.value().to_string())
+}
+
+/// Builds the unlock cookie for an image. Scoped to `/gallery` so it covers the
+/// page, `/gallery/raw/` and `/gallery/thumb/`, and `HttpOnly`.
+pub fn build_grant_cookie(id: &str, token: String, ttl_secs: i64, production: bool) -> Cookie<'static> {
+    Cookie::build((cookie_name(id), token))
+        .path("/gallery")
+        .http_only(true)
+        .secure(production)
+        .same_site(cookie::SameSite::Lax)
+        .max_age(time::Duration::seconds(ttl_secs))
+        .build()
+}
+
+/// Checks `password` against the image's hash. An image without a password
+/// accepts anything. Argon2 is slow on purpose, so it runs on a blocking
+/// thread rather than holding up the executor.
+pub async fn password_matches(entry: &ImageEntry, password: &str) -> bool {
+    let Some(hash) = entry.password_hash.clone() else {
+        return true;
+    };
+    let password = password.to_string();
+    tokio::task::spawn_blocking(move || crate::auth::validate_password(&password, &hash).is_ok())
+        .await
+        .unwrap_or(false)
+}
+
+// ─── Burning ─────────────────────────────────────────────────────────────────
+
+/// Counts one reveal of a burning image and returns how many are left, or
+/// `None` when there were none left (or the image is gone).
+///
+/// It is one `UPDATE … RETURNING`, so two reveals of the last view can't both
+/// win. The reveal that uses up the last view also makes the image expire once
+/// the grant it hands out has run out: the viewer can still load it, and the
+/// expiry reaper deletes it afterwards.
+pub async fn reveal(state: &AppState, id: &str) -> anyhow::Result<Option<i64>> {
+    let id = id.to_string();
+    let burn_at = OffsetDateTime::now_utc() + time::Duration::seconds(REVEAL_TTL_SECS);
+    let left = state
+        .database()
+        .call(move |conn| {
+            conn.query_row(
+                "UPDATE images SET views = views + 1, \
+                     expires_at = CASE WHEN views + 1 >= burn_after_views \
+                          AND (expires_at IS NULL OR datetime(expires_at) > datetime(?2)) \
+                          THEN ?2 ELSE expires_at END \
+                 WHERE id = ?1 AND burn_after_views IS NOT NULL AND views < burn_after_views \
+                   AND (expires_at IS NULL OR datetime(expires_at) > datetime('now')) \
+                 RETURNING burn_after_views - views",
+                rusqlite::params![id, burn_at],
+                |row| row.get::<_, i64>(0),
+            )
+            .optional()
+        })
+        .await?;
+    if left.is_some() {
+        // Served metadata carries `views` and `expires_at`; both just changed.
+        state.invalidate_image_caches().await;
+    }
+    Ok(left)
+}
+
+// ─── Settings ────────────────────────────────────────────────────────────────
+
+/// What an uploader can change about who sees an image. Fields left out stay
+/// as they are.
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct ImageSettings {
+    /// `public`, `unlisted` or `private`.
+    pub visibility: Option<Visibility>,
+    /// A new password. An empty string removes the password.
+    pub password: Option<String>,
+    /// Delete the image after this many more reveals. `0` turns burning off.
+    pub burn_after_views: Option<i64>,
+}
+
+impl ImageSettings {
+    /// Whether this changes anything at all.
+    pub fn is_empty(&self) -> bool {
+        self.visibility.is_none() && self.password.is_none() && self.burn_after_views.is_none()
+    }
+
+    /// Rejects a password or burn count out of range. Upload paths call this
+    /// before storing anything, so a bad value can't leave the uploads open.
+    pub fn validate(&self) -> Result<(), ApiError> {
+        if self.password.as_ref().is_some_and(|p| p.len() > MAX_PASSWORD_LEN) {
+            return Err(ApiError::validation(
+                "password",
+                format!("the password must be at most {MAX_PASSWORD_LEN} bytes"),
+            ));
+        }
+        if self
+            .burn_after_views
+            .is_some_and(|views| !(0..=MAX_BURN_VIEWS).contains(&views))
+        {
+            return Err(ApiError::validation(
+                "burn_after_views",
+                format!("the view count must be between 0 and {MAX_BURN_VIEWS}"),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Validates these settings and turns them into the ones a new upload
+    /// starts out with, hashing the password.
+    pub fn for_upload(&self) -> Result<Protection, ApiError> {
+        self.validate()?;
+        let password_hash = match self.password.as_deref() {
+            None | Some("") => None,
+            Some(password) => Some(crate::auth::hash_password(password)?),
+        };
+        Ok(Protection {
+            visibility: self.visibility,
+            password_hash,
+            burn_after_views: self.burn_after_views.filter(|&views| views > 0),
+        })
+    }
+}
+
+/// The settings a new upload is stored with. They go in with the row (see
+/// [`crate::site::image_quota::INSERT_IMAGE`]), so a private upload is never
+/// briefly unlisted, and an upload that turns out to be a duplicate of an
+/// existing image leaves that image's settings alone.
+#[derive(Debug, Clone, Default)]
+pub struct Protection {
+    /// `None` stores the column default, `unlisted`.
+    pub visibility: Option<Visibility>,
+    pub password_hash: Option<String>,
+    /// Reveals left; a new image has no views yet.
+    pub burn_after_views: Option<i64>,
+}
+
+/// Applies `settings` to those of `ids` that `account` uploaded, and returns
+/// how many that was.
+pub async fn apply(
+    state: &AppState,
+    account: &Account,
+    ids: &[String],
+    settings: &ImageSettings,
+) -> Result<usize, ApiError> {
+    if settings.is_empty() || ids.is_empty() {
+        return Ok(0);
+    }
+    settings.validate()?;
+    // `Some(None)` clears the password, `None` leaves it alone.
+    let password_hash = match settings.password.as_deref() {
+        None => None,
+        Some("") => Some(None),
+        Some(password) => Some(Some(crate::auth::hash_password(password)?)),
+    };
+
+    let ids = ids.to_vec();
+    let account_id = account.id;
+    let visibility = settings.visibility;
+    let burn = settings.burn_after_views;
+    let changed = state
+        .database()
+        .call(move |conn| -> rusqlite::Result<usize> {
+            let tx = conn.transaction()?;
+            let mut changed = 0;
+            {
+                let mut stmt = tx.prepare_cached(
+                    "UPDATE images SET \
+                         visibility = coalesce(?3, visibility), \
+                         password_hash = CASE WHEN ?4 THEN ?5 ELSE password_hash END, \
+                         burn_after_views = CASE WHEN ?6 IS NULL THEN burn_after_views \
+                                                 WHEN ?6 = 0 THEN NULL ELSE views + ?6 END \
+                     WHERE id = ?1 AND uploader_id = ?2",
+                )?;
+                for id in ids {
+                    changed += stmt.execute(boxed_params![
+                        id,
+                        account_id,
+                        visibility,
+                        password_hash.is_some(),
+                        password_hash.clone().flatten(),
+                        burn,
+                    ])?;
+                }
+            }
+            tx.commit()?;
+            Ok(changed)
+        })
+        .await?;
+
+    if changed > 0 {
+        state.invalidate_image_caches().await;
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grants_are_image_scoped_and_expire() {
+        let secret = SecretKey::random().unwrap();
+        let token = sign_grant(&secret, "abc", 60).unwrap();
+        assert!(verify_grant(&secret, "abc", &token));
+        assert!(!verify_grant(&secret, "xyz", &token));
+        assert!(!verify_grant(&SecretKey::random().unwrap(), "abc", &token));
+
+        let expired = sign_grant(&secret, "abc", -1).unwrap();
+        assert!(!verify_grant(&secret, "abc", &expired));
+    }
+
+    #[test]
+    fn other_unlock_tokens_are_not_grants() {
+        let secret = SecretKey::random().unwrap();
+        // A paste's unlock cookie for the same id, signed with the same key.
+        let paste = crate::site::paste::crypto::sign_unlock(&secret, "abc", &[7; 32]).unwrap();
+        assert!(!verify_grant(&secret, "abc", &paste));
+        // And the other way round.
+        let image = sign_grant(&secret, "abc", 60).unwrap();
+        assert!(crate::site::paste::crypto::verify_unlock(&secret, "abc", &image).is_none());
+
+        // Same shape as a grant, but not made for images.
+        #[derive(Serialize)]
+        struct Untagged<'a> {
+            id: &'a str,
+            exp: i64,
+        }
+        let exp = OffsetDateTime::now_utc().unix_timestamp() + 60;
+        let untagged = secret.sign(&Untagged { id: "abc", exp }).unwrap();
+        assert!(!verify_grant(&secret, "abc", &untagged));
+    }
+
+    #[tokio::test]
+    async fn gates_need_the_right_cookie() {
+        let secret = SecretKey::random().unwrap();
+        let mut entry = ImageEntry::temporary("abc".into());
+        assert_eq!(check(&entry, None, &[], &secret), Access::Allowed);
+
+        entry.visibility = Visibility::Private;
+        assert_eq!(check(&entry, None, &[], &secret), Access::Hidden);
+
+        entry.visibility = Visibility::Unlisted;
+        entry.password_hash = Some(crate::auth::hash_password("hunter2").unwrap());
+        assert_eq!(check(&entry, None, &[], &secret), Access::Locked);
+        assert!(password_matches(&entry, "hunter2").await);
+        assert!(!password_matches(&entry, "hunter3").await);
+
+        let cookie = build_grant_cookie("abc", sign_grant(&secret, "abc", 60).unwrap(), 60, false);
+        assert_eq!(check(&entry, None, &[cookie.clone()], &secret), Access::Allowed);
+
+        entry.password_hash = None;
+        entry.burn_after_views = Some(1);
+        assert_eq!(check(&entry, None, &[], &secret), Access::Sealed);
+        assert_eq!(check(&entry, None, &[cookie], &secret), Access::Allowed);
+    }
+
+    #[tokio::test]
+    async fn reveals_count_down_and_the_last_one_sets_expiry() {
+        let db = crate::Database::file(":memory:")
+            .connections(1)
+            .with_init(crate::migrations::migrate)
+            .open()
+            .await
+            .unwrap();
+        db.execute(
+            "INSERT INTO images (id, image_data, mimetype, views, burn_after_views) VALUES ('i1', X'00', 'image/png', 3, 5)",
+            [],
+        )
+        .await
+        .unwrap();
+        let state = AppState::for_tests(db).await;
+
+        assert_eq!(reveal(&state, "i1").await.unwrap(), Some(1));
+        let entry = state.get_image_meta("i1".into()).await.unwrap();
+        assert!(entry.expires_at.is_none());
+
+        assert_eq!(reveal(&state, "i1").await.unwrap(), Some(0));
+        let entry = state.get_image_meta("i1".into()).await.unwrap();
+        assert!(entry.expires_at.is_some() && !entry.is_expired());
+
+        assert_eq!(reveal(&state, "i1").await.unwrap(), None);
+        assert_eq!(reveal(&state, "missing").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn uploads_are_stored_with_their_settings() {
+        let db = crate::Database::file(":memory:")
+            .connections(1)
+            .with_init(crate::migrations::migrate)
+            .open()
+            .await
+            .unwrap();
+        let state = AppState::for_tests(db).await;
+        let insert = |id: &'static str, protection: Protection| {
+            state.database().execute(
+                crate::site::image_quota::INSERT_IMAGE,
+                boxed_params![
+                    id,
+                    "image/png",
+                    None::<i64>,
+                    "hash",
+                    0_i64,
+                    None::<String>,
+                    None::<String>,
+                    None::<String>,
+                    None::<i64>,
+                    None::<String>,
+                    None::<String>,
+                    None::<i64>,
+                    None::<i64>,
+                    None::<String>,
+                    None::<String>,
+                    protection.visibility,
+                    protection.password_hash,
+                    protection.burn_after_views,
+                ],
+            )
+        };
+
+        let settings = ImageSettings {
+            visibility: Some(Visibility::Private),
+            password: Some("hunter2".into()),
+            burn_after_views: Some(3),
+        };
+        assert_eq!(insert("i1", settings.for_upload().unwrap()).await.unwrap(), 1);
+        let entry = state.get_image_meta("i1".into()).await.unwrap();
+        assert_eq!(entry.visibility, Visibility::Private);
+        assert!(password_matches(&entry, "hunter2").await);
+        assert!(!password_matches(&entry, "hunter3").await);
+        assert_eq!(entry.burn_after_views, Some(3));
+
+        // An empty password and a burn count of zero mean neither.
+        let settings = ImageSettings {
+            visibility: None,
+            password: Some(String::new()),
+            burn_after_views: Some(0),
+        };
+        assert_eq!(insert("i2", settings.for_upload().unwrap()).await.unwrap(), 1);
+        let entry = state.get_image_meta("i2".into()).await.unwrap();
+        assert_eq!(entry.visibility, Visibility::Unlisted);
+        assert!(entry.password_hash.is_none() && entry.burn_after_views.is_none());
+    }
+}
@@ -0,0 +1,258 @@
+//! Per-account image quotas: how many images, and how many bytes of them, a
+//! non-admin account may keep.
+//!
+//! The limits come from the `images` block of the config and are off unless
+//! set. Admins are exempt, as with pastes and short links. Images in the trash
+//! still count, so a restore always fits; the messages say to empty it too.
+//! Uploads check the quota up front so a full account doesn't pay for
+//! scanning, but the check that counts is the insert itself ([`INSERT_IMAGE`]),
+//! which refuses a row that would cross either limit in the same statement —
+//! two uploads racing for the last slot can't both get it.
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::error::{ApiError, ApiErrorCode};
+use crate::models::Account;
+use crate::site::account::pages::human_bytes;
+use crate::AppState;
+
+/// Inserts an image row unless it would put the uploader over a quota.
+///
+/// Parameters 1–11 are the columns; `?12` is the image limit and `?13` the byte
+/// limit, either `NULL` for none. `?14` and `?15` are the upload scan's status
+/// and JSON report (see [`crate::site::image_scan`]), which go in with the row so
+/// a held upload is never servable, even briefly. `?16`–`?18` are the
+/// uploader's visibility (`NULL` for the default), password hash and burn count
+/// (see [`crate::site::image_lock::Protection`]). Zero rows affected means a
+/// quota refused it.
+///
+/// An upload into a guild gallery (`?8` set) is counted against that guild
+/// rather than its uploader: every guild's uploads are made by the one gallery
+/// service account, so a per-account count would be one quota for all guilds.
+pub(crate) const INSERT_IMAGE: &str = "INSERT INTO images \
+     (id, mimetype, uploader_id, blob_hash, size, expires_at, original_name, guild_id, phash, blurhash, dominant_color, \
+      scan_status, scan_report, scanned_at, visibility, password_hash, burn_after_views) \
+     SELECT ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, \
+      ?14, ?15, CASE WHEN ?15 IS NOT NULL THEN strftime('%Y-%m-%dT%H:%M:%fZ', 'now') END, \
+      COALESCE(?16, 'unlisted'), ?17, ?18 \
+     WHERE (?12 IS NULL OR (SELECT COUNT(*) FROM images \
+                            WHERE (?8 IS NULL AND uploader_id = ?3) OR guild_id = ?8) < ?12) \
+       AND (?13 IS NULL OR (SELECT COALESCE(SUM(size), 0) FROM images \
+                            WHERE (?8 IS NULL AND uploader_id = ?3) OR guild_id = ?8) + ?5 <= ?13)";
+
+/// The limits that apply to one account.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quota {
+    /// The most images the account may own.
+    pub max_count: Option<i64>,
+    /// The most bytes its images may add up to.
+    pub max_bytes: Option<i64>,
+}
+
+/// What an account has stored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Usage {
+    pub count: i64,
+    pub bytes: i64,
+}
+
+/// Which limit an upload ran into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Exceeded {
+    Count(i64),
+    Bytes(i64),
+}
+
+impl Exceeded {
+    pub fn message(self) -> String {
+        match self {
+            Self::Count(limit) => {
+                format!(
+                    "You've reached the limit of {limit} images — delete one and empty it from your trash to upload another."
+                )
+            }
+            Self::Bytes(limit) => format!(
+                "You've reached your image storage limit ({}) — delete some images and empty your trash to free space.",
+                human_bytes(limit)
+            ),
+        }
+    }
+
+    pub fn into_api_error(self) -> ApiError {
+        ApiError::new(self.message()).with_code(ApiErrorCode::QuotaExceeded)
+    }
+}
+
+impl Quota {
+    /// The configured quota for `account`; none at all for admins.
+    pub fn for_account(state: &AppState, account: &Account) -> Self {
+        if account.flags.is_admin() {
+            return Self::default();
+        }
+        let config = &state.config().images;
+        Self {
+            max_count: config.account_limit.map(|n| n as i64),
+            max_bytes: config.account_max_total_bytes,
+        }
+    }
+
+    pub fn is_unlimited(&self) -> bool {
+        self.max_count.is_none() && self.max_bytes.is_none()
+    }
+
+    /// Whether one more image of `incoming` bytes fits on top of `usage`.
+    pub fn check(&self, usage: Usage, incoming: i64) -> Result<(), Exceeded> {
+        if let Some(limit) = self.max_count.filter(|&limit| usage.count >= limit) {
+            return Err(Exceeded::Count(limit));
+        }
+        if let Some(limit) = self.max_bytes.filter(|&limit| usage.bytes + incoming > limit) {
+            return Err(Exceeded::Bytes(limit));
+        }
+        Ok(())
+    }
+
+    /// What's left under each limit, `None` where there is no limit.
+    pub fn headroom(&self, usage: Usage) -> QuotaHeadroom {
+        QuotaHeadroom {
+            images: self.max_count.map(|limit| (limit - usage.count).max(0)),
+            bytes: self.max_bytes.map(|limit| (limit - usage.bytes).max(0)),
+        }
+    }
+}
+
+/// How much more an account may upload before its image quota stops it.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct QuotaHeadroom {
+    /// Images left to upload, or `null` when the count is unlimited.
+    pub images: Option<i64>,
+    /// Bytes left to store, or `null` when storage is unlimited.
+    pub bytes: Option<i64>,
+}
+
+/// The images `account_id` has stored, expired or not — or with `guild_id`,
+/// the images in that guild's gallery, which is what its uploads count against.
+pub async fn usage(state: &AppState, account_id: i64, guild_id: Option<&str>) -> Usage {
+    state
+        .database()
+        .get_row(
+            "SELECT COUNT(*), COALESCE(SUM(size), 0) FROM images \
+             WHERE (?2 IS NULL AND uploader_id = ?1) OR guild_id = ?2",
+            (account_id, guild_id.map(str::to_string)),
+            |row| {
+                Ok(Usage {
+                    count: row.get(0)?,
+                    bytes: row.get(1)?,
+                })
+            },
+        )
+        .await
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limits_are_checked_against_the_incoming_file() {
+        let quota = Quota {
+            max_count: Some(2),
+            max_bytes: Some(100),
+        };
+        assert_eq!(quota.check(Usage { count: 1, bytes: 50 }, 50), Ok(()));
+        assert_eq!(
+            quota.check(Usage { count: 1, bytes: 50 }, 51),
+            Err(Exceeded::Bytes(100))
+        );
+        assert_eq!(quota.check(Usage { count: 2, bytes: 0 }, 1), Err(Exceeded::Count(2)));
+        assert_eq!(
+            Quota::default().check(
+                Usage {
+                    count: 9999,
+                    bytes: i64::MAX / 2
+                },
+                1
+            ),
+            Ok(())
+        );
+
+        let left = quota.headroom(Usage { count: 3, bytes: 40 });
+        assert_eq!((left.images, left.bytes), (Some(0), Some(60)));
+    }
+
+    /// Runs [`INSERT_IMAGE`] for account 1.
+    async fn insert_for(
+        db: &crate::Database,
+        id: &'static str,
+        guild: Option<&'static str>,
+        size: i64,
+        count: Option<i64>,
+        bytes: Option<i64>,
+    ) -> usize {
+        db.execute(
+            INSERT_IMAGE,
+            crate::boxed_params![
+                id,
+                "image/png",
+                1_i64,
+                "hash",
+                size,
+                None::<String>,
+                None::<String>,
+                guild,
+                None::<i64>,
+                None::<String>,
+                None::<String>,
+                count,
+                bytes,
+                None::<String>,
+                None::<String>,
+                None::<crate::models::Visibility>,
+                None::<String>,
+                None::<i64>,
+            ],
+        )
+        .await
+        .unwrap()
+    }
+
+    async fn database() -> crate::Database {
+        let db = crate::Database::file(":memory:")
+            .connections(1)
+            .with_init(crate::migrations::migrate)
+            .open()
+            .await
+            .unwrap();
+        db.execute("INSERT INTO account (id, name, password) VALUES (1, 'a', 'x')", [])
+            .await
+            .unwrap();
+        db
+    }
+
+    #[tokio::test]
+    async fn the_insert_refuses_rows_over_the_quota() {
+        let db = database().await;
+        let insert = |id, size, count, bytes| insert_for(&db, id, None, size, count, bytes);
+        assert_eq!(insert("a", 60, Some(2), Some(100)).await, 1);
+        // Over the byte limit.
+        assert_eq!(insert("b", 41, Some(2), Some(100)).await, 0);
+        assert_eq!(insert("b", 40, Some(2), Some(100)).await, 1);
+        // At the count limit, however small.
+        assert_eq!(insert("c", 0, Some(2), None).await, 0);
+        // Unlimited.
+        assert_eq!(insert("c", 1000, None, None).await, 1);
+    }
+
+    #[tokio::test]
+    async fn guild_uploads_count_against_their_guild() {
+        let db = database().await;
+        // A full personal quota doesn't hold up the account's guild uploads...
+        assert_eq!(insert_for(&db, "mine", None, 10, Some(1), None).await, 1);
+        assert_eq!(insert_for(&db, "g1", Some("111"), 10, Some(1), None).await, 1);
+        // ...and one full guild doesn't hold up another.
+        assert_eq!(insert_for(&db, "g1b", Some("111"), 10, Some(1), None).await, 0);
+        assert_eq!(insert_for(&db, "g2", Some("222"), 10, Some(1), Some(10)).await, 1);
+        assert_eq!(insert_for(&db, "g2b", Some("222"), 1, None, Some(10)).await, 0);
+    }
+}
@@ -0,0 +1,568 @@
+//! Replacing an image's bytes in place, and the history that keeps.
+//!
+//! A replacement keeps the image's id, so its links, settings, views and album
+//! places all stay; only the bytes and what is derived from them (size,
+//! perceptual hash, placeholder) change. The previous version is copied into
+//! `image_revision` in the same transaction, so the owner can look at it or put
+//! it back. Putting one back is a replacement too: the version it displaces
+//! goes into the history in turn.
+//!
+//! The replacement must be the same format as the image, so the extension in
+//! its links stays right. At most [`REVISION_CAP`] revisions are kept per
+//! image; the oldest go as new ones come in. Under a byte quota, history also
+//! counts: a replacement drops the owner's oldest revisions until their images
+//! and history fit in it together. Each revision holds a reference to
+//! its blob (see `sql/13.sql`), so the old bytes stay in the store exactly as
+//! long as the history mentions them.
+//!
+//! An image held by the upload malware scan (see [`crate::site::image_scan`])
+//! can't be replaced or restored, so quarantined bytes never end up in a
+//! history they could be brought back from.
+
+use std::net::IpAddr;
+
+use axum::http::{header, HeaderMap};
+use axum::response::Response;
+
+use crate::conditional::{self, Validators};
+use crate::error::ApiError;
+use crate::models::{Account, ImageEntry, ImageRevision, ScanStatus};
+use crate::site::image::ValidatedFile;
+use crate::site::image_quota::{Exceeded, Quota};
+use crate::site::image_scan;
+use crate::{boxed_params, AppState};
+
+/// How many earlier versions to keep per image.
+pub const REVISION_CAP: i64 = 10;
+
+/// The bytes an image can point at, and what is derived from them.
+struct Version {
+    blob_hash: String,
+    size: i64,
+    mimetype: String,
+    original_name: Option<String>,
+    phash: Option<i64>,
+    blurhash: Option<String>,
+    dominant_color: Option<String>,
+    /// The replacement's malware scan verdict and JSON report, if it was
+    /// scanned.
+    scan_status: Option<ScanStatus>,
+    scan_report: Option<String>,
+}
+
+/// How a [`swap`] went.
+#[derive(Debug, PartialEq, Eq)]
+enum Swapped {
+    Done,
+    /// The image already has these bytes; nothing was recorded.
+    Unchanged,
+    /// No such live image of the owner's.
+    Missing,
+    /// The new bytes would put the owner over their byte quota.
+    OverQuota(i64),
+}
+
+/// Points image `id` at `version`, recording the version it had in the
+/// history, and trims the history to [`REVISION_CAP`]. With `from_revision`,
+/// that revision is what is being restored, and leaves the history.
+///
+/// It is all one transaction, and the quota is checked inside it, like
+/// [`crate::site::image_quota::INSERT_IMAGE`] does for uploads. The new bytes
+/// only have to fit next to the owner's images; history that would then take
+/// the owner over `max_bytes` is dropped, the owner's oldest revisions first,
+/// so replacing images can't keep more than the quota's worth of bytes.
+async fn swap(
+    state: &AppState,
+    owner_id: i64,
+    max_bytes: Option<i64>,
+    id: &str,
+    version: Version,
+    from_revision: Option<i64>,
+) -> anyhow::Result<Swapped> {
+    let id = id.to_string();
+    let swapped = state
+        .database()
+        .call(move |conn| -> rusqlite::Result<Swapped> {
+            let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+            let current = tx
+                .query_row(
+                    "SELECT blob_hash, size FROM images \
+                     WHERE id = ?1 AND uploader_id = ?2 AND deleted_at IS NULL",
+                    rusqlite::params![id, owner_id],
+                    |row| Ok((row.get::<_, Option<String>>(0)?, row.get::<_, i64>(1)?)),
+                )
+                .map(Some)
+                .or_else(|e| match e {
+                    rusqlite::Error::QueryReturnedNoRows => Ok(None),
+                    e => Err(e),
+                })?;
+            let Some((current_hash, current_size)) = current else {
+                return Ok(Swapped::Missing);
+            };
+            if current_hash.as_deref() == Some(version.blob_hash.as_str()) {
+                return Ok(Swapped::Unchanged);
+            }
+            // What the owner's images add up to once this one is swapped.
+            let live = match max_bytes {
+                Some(limit) => {
+                    let total: i64 = tx.query_row(
+                        "SELECT COALESCE(SUM(size), 0) FROM images WHERE uploader_id = ?1",
+                        [owner_id],
+                        |row| row.get(0),
+                    )?;
+                    let live = total - current_size + version.size;
+                    if live > limit {
+                        return Ok(Swapped::OverQuota(limit));
+                    }
+                    live
+                }
+                None => 0,
+            };
+
+            // A row the legacy move hasn't reached yet still has its bytes
+            // inline and no blob to keep; it just gets no history entry.
+            tx.execute(
+                "INSERT INTO image_revision \
+                 (image_id, blob_hash, size, mimetype, original_name, phash, blurhash, dominant_color) \
+                 SELECT id, blob_hash, size, mimetype, original_name, phash, blurhash, dominant_color \
+                 FROM images WHERE id = ?1 AND blob_hash IS NOT NULL",
+                [&id],
+            )?;
+            tx.execute(
+                "UPDATE images SET blob_hash = ?1, image_data = NULL, size = ?2, mimetype = ?3, \
+                 original_name = COALESCE(?4, original_name), phash = ?5, blurhash = ?6, dominant_color = ?7, \
+                 replaced_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now'), scan_status = ?9, scan_report = ?10, \
+                 scanned_at = CASE WHEN ?10 IS NOT NULL THEN strftime('%Y-%m-%dT%H:%M:%fZ', 'now') END \
+                 WHERE id = ?8",
+                rusqlite::params![
+                    version.blob_hash,
+                    version.size,
+                    version.mimetype,
+                    version.original_name,
+                    version.phash,
+                    version.blurhash,
+                    version.dominant_color,
+                    id,
+                    version.scan_status,
+                    version.scan_report
+                ],
+            )?;
+            if let Some(revision) = from_revision {
+                tx.execute(
+                    "DELETE FROM image_revision WHERE id = ?1 AND image_id = ?2",
+                    rusqlite::params![revision, id],
+                )?;
+            }
+            tx.execute(
+                "DELETE FROM image_revision WHERE image_id = ?1 AND id NOT IN ( \
+                     SELECT id FROM image_revision WHERE image_id = ?1 \
+                     ORDER BY created_at DESC, id DESC LIMIT ?2 )",
+                rusqlite::params![id, REVISION_CAP],
+            )?;
+            if let Some(limit) = max_bytes {
+                let history: Vec<(i64, i64)> = tx
+                    .prepare(
+                        "SELECT r.id, r.size FROM image_revision r JOIN images i ON i.id = r.image_id \
+                         WHERE i.uploader_id = ?1 ORDER BY r.created_at, r.id",
+                    )?
+                    .query_map([owner_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+                    .collect::<rusqlite::Result<_>>()?;
+                let mut over = live + history.iter().map(|(_, size)| size).sum::<i64>() - limit;
+                for (revision, size) in history {
+                    if over <= 0 {
+                        break;
+                    }
+                    tx.execute("DELETE FROM image_revision WHERE id = ?1", [revision])?;
+                    over -= size;
+                }
+            }
+            tx.commit()?;
+            Ok(Swapped::Done)
+        })
+        .await?;
+    Ok(swapped)
+}
+
+fn not_found(id: &str) -> ApiError {
+    ApiError::not_found(format!("Image `{id}` was not found"))
+}
+
+/// Image `id`, if it is a live image uploaded by `account`.
+async fn owned(state: &AppState, account: &Account, id: &str) -> Result<ImageEntry, ApiError> {
+    state
+        .get_image_meta(id.to_string())
+        .await
+        .filter(|entry| entry.uploader_id == Some(account.id) && !entry.is_expired())
+        .ok_or_else(|| not_found(id))
+}
+
+/// Refuses to touch the bytes of an image the malware scan is holding.
+fn check_not_held(entry: &ImageEntry) -> Result<(), ApiError> {
+    match entry.scan_status {
+        Some(ScanStatus::Pending) => Err(ApiError::new(
+            "This image is still being scanned for malware; try again once it's done.",
+        )),
+        Some(ScanStatus::Quarantined) => Err(ApiError::new(
+            "This image was quarantined by the malware scan and can only be deleted.",
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// Runs [`swap`] for `account` and turns its outcome into the refreshed image,
+/// dropping every cached copy of the old bytes.
+async fn apply(
+    state: &AppState,
+    account: &Account,
+    id: &str,
+    version: Version,
+    from_revision: Option<i64>,
+) -> Result<ImageEntry, ApiError> {
+    let max_bytes = Quota::for_account(state, account).max_bytes;
+    match swap(state, account.id, max_bytes, id, version, from_revision).await? {
+        Swapped::Done => {
+            state.invalidate_image_caches().await;
+            state.forget_renditions(id).await;
+            state.pregenerate_thumbnail(id);
+        }
+        Swapped::Unchanged => {}
+        Swapped::Missing => return Err(not_found(id)),
+        Swapped::OverQuota(limit) => return Err(Exceeded::Bytes(limit).into_api_error()),
+    }
+    owned(state, account, id).await
+}
+
+/// Refuses bytes whose perceptual hash is on the block list.
+async fn check_blocked(state: &AppState, phash: Option<i64>) -> Result<(), ApiError> {
+    let Some(phash) = phash else { return Ok(()) };
+    match crate::phash::blocked_by(state, phash).await {
+        Ok(Some(_)) => Err(ApiError::validation("file", "this image has been blocked")),
+        Ok(None) => Ok(()),
+        Err(e) => {
+            tracing::warn!(error = %e, "could not check a replacement against the block list");
+            Ok(())
+        }
+    }
+}
+
+/// Replaces the bytes of `account`'s image `id` with `file`, keeping the old
+/// ones in its history. The file goes through the same malware scan and block
+/// list as an upload.
+pub(crate) async fn replace(
+    state: &AppState,
+    account: &Account,
+    client_ip: Option<IpAddr>,
+    id: &str,
+    file: ValidatedFile,
+    api: bool,
+) -> Result<ImageEntry, ApiError> {
+    let entry = owned(state, account, id).await?;
+    check_not_held(&entry)?;
+    if file.mimetype != entry.mimetype {
+        return Err(ApiError::validation(
+            "file",
+            format!(
+                "the replacement must be {} like the original, so its links keep working",
+                entry.mimetype
+            ),
+        ));
+    }
+
+    // Always inline: the image is already live, so there's nothing to hold.
+    let mut scanned = (None, None);
+    if image_scan::policy(state).is_some() {
+        let report = crate::scan::scan_bytes(state, &file.bytes).await;
+        if report.verdict == "infected" {
+            state
+                .audit("image.replace.rejected")
+                .actor(account)
+                .target(entry.id.clone())
+                .ip_opt(client_ip)
+                .meta(serde_json::json!({
+                    "reason":       "infected",
+                    "sha256":       report.sha256,
+                    "clamav_virus": report.clamav_virus,
+                    "vt_status":    report.vt_status,
+                }))
+                .fire();
+            return Err(ApiError::validation("file", "the file failed the malware scan"));
+        }
+        scanned = (
+            Some(ScanStatus::from_verdict(&report.verdict)),
+            image_scan::report_json(&report),
+        );
+    }
+
+    let phash = crate::phash::compute(&file.bytes).await;
+    check_blocked(state, phash).await?;
+    let placeholder = crate::placeholder::compute(&file.bytes).await;
+
+    let hash = crate::scan::sha256_hex(&file.bytes);
+    if let Err(e) = crate::storage::store_blob(state, &hash, &file.bytes).await {
+        tracing::error!(error = %e, store = state.blobs().name(), "could not store image blob");
+        return Err(ApiError::new("Could not store the image."));
+    }
+    let size = file.bytes.len() as i64;
+    let version = Version {
+        blob_hash: hash,
+        size,
+        mimetype: file.mimetype,
+        original_name: Some(file.original_name),
+        phash,
+        blurhash: placeholder.as_ref().map(|p| p.blurhash.clone()),
+        dominant_color: placeholder.map(|p| p.dominant_color),
+        scan_status: scanned.0,
+        scan_report: scanned.1,
+    };
+    let replaced = apply(state, account, &entry.id, version, None).await?;
+
+    state
+        .audit("image.replace")
+        .actor(account)
+        .target(entry.id.clone())
+        .ip_opt(client_ip)
+        .meta(serde_json::json!({
+            "old_size": entry.size,
+            "new_size": size,
+            "via_api":  api,
+        }))
+        .fire();
+    Ok(replaced)
+}
+
+/// Puts revision `revision` of `account`'s image `id` back, keeping the
+/// version it displaces in the history.
+pub async fn restore(
+    state: &AppState,
+    account: &Account,
+    client_ip: Option<IpAddr>,
+    id: &str,
+    revision: i64,
+    api: bool,
+) -> Result<ImageEntry, ApiError> {
+    let entry = owned(state, account, id).await?;
+    check_not_held(&entry)?;
+    let old = get(state, &entry.id, revision)
+        .await
+        .ok_or_else(|| ApiError::not_found(format!("Image `{id}` has no revision {revision}")))?;
+    check_blocked(state, old.phash).await?;
+
+    let version = Version {
+        blob_hash: old.blob_hash,
+        size: old.size,
+        mimetype: old.mimetype,
+        original_name: old.original_name,
+        phash: old.phash,
+        blurhash: old.blurhash,
+        dominant_color: old.dominant_color,
+        scan_status: None,
+        scan_report: None,
+    };
+    let restored = apply(state, account, &entry.id, version, Some(revision)).await?;
+
+    state
+        .audit("image.revision.restore")
+        .actor(account)
+        .target(entry.id.clone())
+        .ip_opt(client_ip)
+        .meta(serde_json::json!({ "revision": revision, "via_api": api }))
+        .fire();
+    Ok(restored)
+}
+
+/// The earlier versions of image `id`, newest first.
+pub async fn list(state: &AppState, id: &str) -> Vec<ImageRevision> {
+    state
+        .database()
+        .all(
+            "SELECT * FROM image_revision WHERE image_id = ?1 ORDER BY created_at DESC, id DESC",
+            [id.to_string()],
+        )
+        .await
+        .unwrap_or_default()
+}
+
+/// Revision `revision` of image `id`.
+pub async fn get(state: &AppState, id: &str, revision: i64) -> Option<ImageRevision> {
+    state
+        .database()
+        .get(
+            "SELECT * FROM image_revision WHERE id = ?1 AND image_id = ?2",
+            boxed_params![revision, id.to_string()],
+        )
+        .await
+        .ok()
+        .flatten()
+}
+
+/// Serves the bytes of a revision, for its owner only. Never cached by a
+/// shared cache; the `ETag` is the content hash, as for the live image.
+pub async fn respond(state: &AppState, request: &HeaderMap, revision: &ImageRevision) -> Option<Response> {
+    let stream = match state.blobs().get_stream(&revision.blob_hash).await {
+        Ok(stream) => stream?,
+        Err(e) => {
+            tracing::warn!(error = %e, hash = %revision.blob_hash, "could not open image blob");
+            return None;
+        }
+    };
+    let mut headers = HeaderMap::new();
+    if let Ok(value) = header::HeaderValue::from_str(&revision.mimetype) {
+        headers.insert(header::CONTENT_TYPE, value);
+    }
+    headers.insert(header::CACHE_CONTROL, header::HeaderValue::from_static("private"));
+    let validators = Validators::new(&revision.blob_hash, Some(revision.created_at));
+    Some(conditional::respond(request, &validators, headers, stream.into()).await)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn state() -> AppState {
+        let db = crate::Database::file(":memory:")
+            .connections(1)
+            .with_init(crate::migrations::migrate)
+            .open()
+            .await
+            .unwrap();
+        db.execute("INSERT INTO account (id, name, password) VALUES (1, 'owner', 'x')", [])
+            .await
+            .unwrap();
+        db.execute(
+            "INSERT INTO images (id, mimetype, uploader_id, blob_hash, size, original_name) \
+             VALUES ('i1', 'image/png', 1, 'h0', 10, 'shot.png')",
+            [],
+        )
+        .await
+        .unwrap();
+        AppState::for_tests(db).await
+    }
+
+    fn version(hash: &str, size: i64) -> Version {
+        Version {
+            blob_hash: hash.to_string(),
+            size,
+            mimetype: "image/png".to_string(),
+            original_name: None,
+            phash: None,
+            blurhash: None,
+            dominant_color: None,
+            scan_status: None,
+            scan_report: None,
+        }
+    }
+
+    async fn refcount(state: &AppState, hash: &str) -> i64 {
+        state
+            .database()
+            .get_row(
+                "SELECT refcount FROM image_blob WHERE hash = ?1",
+                [hash.to_string()],
+                |row| row.get(0),
+            )
+            .await
+            .unwrap()
+    }
+
+    async fn current(state: &AppState) -> (String, i64) {
+        state
+            .database()
+            .get_row("SELECT blob_hash, size FROM images WHERE id = 'i1'", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn replacing_keeps_the_old_bytes_restorable() {
+        let state = state().await;
+        assert_eq!(
+            swap(&state, 1, None, "i1", version("h1", 20), None).await.unwrap(),
+            Swapped::Done
+        );
+        assert_eq!(current(&state).await, ("h1".to_string(), 20));
+        assert_eq!(
+            swap(&state, 1, None, "i1", version("h1", 20), None).await.unwrap(),
+            Swapped::Unchanged
+        );
+        // Someone else's image, and a quota the new size doesn't fit.
+        assert_eq!(
+            swap(&state, 2, None, "i1", version("h2", 5), None).await.unwrap(),
+            Swapped::Missing
+        );
+        assert_eq!(
+            swap(&state, 1, Some(25), "i1", version("h2", 30), None).await.unwrap(),
+            Swapped::OverQuota(25)
+        );
+
+        let history = list(&state, "i1").await;
+        assert_eq!(history.len(), 1);
+        assert_eq!((history[0].blob_hash.as_str(), history[0].size), ("h0", 10));
+        assert_eq!(history[0].original_name.as_deref(), Some("shot.png"));
+        assert_eq!(refcount(&state, "h0").await, 1);
+        assert_eq!(refcount(&state, "h1").await, 1);
+
+        // Restoring swaps the two: the revision leaves, the displaced bytes join.
+        let old = &history[0];
+        assert_eq!(
+            swap(&state, 1, None, "i1", version("h0", 10), Some(old.id))
+                .await
+                .unwrap(),
+            Swapped::Done
+        );
+        assert_eq!(current(&state).await, ("h0".to_string(), 10));
+        let history = list(&state, "i1").await;
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].blob_hash, "h1");
+        assert_eq!(refcount(&state, "h0").await, 1);
+        assert_eq!(refcount(&state, "h1").await, 1);
+    }
+
+    #[tokio::test]
+    async fn history_is_capped_and_goes_with_the_image() {
+        let state = state().await;
+        for n in 1..=REVISION_CAP + 2 {
+            swap(&state, 1, None, "i1", version(&format!("h{n}"), n), None)
+                .await
+                .unwrap();
+        }
+        assert_eq!(list(&state, "i1").await.len() as i64, REVISION_CAP);
+        // The two oldest versions fell off the end and let go of their blobs.
+        assert_eq!(refcount(&state, "h0").await, 0);
+        assert_eq!(refcount(&state, "h1").await, 0);
+        assert_eq!(refcount(&state, "h2").await, 1);
+
+        state
+            .database()
+            .execute("DELETE FROM images WHERE id = 'i1'", [])
+            .await
+            .unwrap();
+        assert!(list(&state, "i1").await.is_empty());
+        assert_eq!(refcount(&state, &format!("h{}", REVISION_CAP + 2)).await, 0);
+        assert_eq!(refcount(&state, "h2").await, 0);
+    }
+
+    #[tokio::test]
+    async fn history_makes_room_under_a_byte_quota() {
+        let state = state().await;
+        // 10 bytes live; each replacement pushes the last version into history.
+        for n in 1..=3 {
+            swap(&state, 1, Some(35), "i1", version(&format!("h{n}"), 10), None)
+                .await
+                .unwrap();
+        }
+        // 10 live + 20 of history: the oldest version went to make room.
+        let history = list(&state, "i1").await;
+        let hashes: Vec<_> = history.iter().map(|r| r.blob_hash.as_str()).collect();
+        assert_eq!(hashes, ["h2", "h1"]);
+        assert_eq!(refcount(&state, "h0").await, 0);
+
+        // Bigger bytes squeeze the history out entirely, but still fit.
+        assert_eq!(
+            swap(&state, 1, Some(35), "i1", version("h4", 30), None).await.unwrap(),
+            Swapped::Done
+        );
+        assert!(list(&state, "i1").await.is_empty());
+    }
+}
@@ -0,0 +1,238 @@
+//! Malware scanning of uploads, and the quarantine it can put them in.
+//!
+//! The scanners themselves (ClamAV, VirusTotal) live in [`crate::scan`]; this is
+//! the policy around uploads, picked by `upload_scan` in the config:
+//!
+//! - `inline` (the default): [`crate::site::image::store_uploads`] scans each
+//!   file before storing it and refuses what's flagged. The uploader waits.
+//! - `all`: every upload is stored straight away as `pending`, and a
+//!   background worker ([`spawn_upload_scanner`]) scans it. A pending image is
+//!   not served to anyone, its uploader included, until the scan clears it;
+//!   one that's flagged stays stored as `quarantined` and is never served. Its
+//!   uploader can still delete it, and the site alerts on the detection.
+//! - `non_images`: like `all`, but only for uploads the image decoder can't
+//!   read. Those are the ones that might be something other than a picture;
+//!   the rest are stored unscanned.
+//! - `off`: nothing is scanned.
+//!
+//! Either way the verdict and the full [`ScanReport`] are stored with the image
+//! (`scan_status`, `scan_report`, see `sql/14.sql`). A scan the scanners can't
+//! decide (an error, or a file VirusTotal has never seen) releases the image as
+//! `unknown`, as inline scanning always has.
+
+use std::time::Duration;
+
+use axum::http::StatusCode;
+
+use crate::config::UploadScan;
+use crate::models::{ImageEntry, ScanStatus};
+use crate::scan::ScanReport;
+use crate::{boxed_params, AppState};
+
+/// How long the worker sleeps between passes when nothing wakes it. Uploads
+/// wake it straight away; this picks up what a restart left pending.
+const IDLE: Duration = Duration::from_secs(300);
+
+/// Pending images read per round-trip.
+const BATCH: i64 = 16;
+
+/// The policy in effect, or `None` when uploads aren't scanned: the config
+/// says `off`, or there is no scanner to scan with.
+pub fn policy(state: &AppState) -> Option<UploadScan> {
+    let config = state.config();
+    let scanners = config.clamav_addr.is_some() || config.virustotal_api_key.is_some();
+    Some(config.upload_scan).filter(|&policy| scanners && policy != UploadScan::Off)
+}
+
+/// Whether an upload is stored held for the background scanner. `decodable`
+/// is whether the image decoder could read it.
+pub fn holds(policy: Option<UploadScan>, decodable: bool) -> bool {
+    match policy {
+        Some(UploadScan::All) => true,
+        Some(UploadScan::NonImages) => !decodable,
+        Some(UploadScan::Inline | UploadScan::Off) | None => false,
+    }
+}
+
+/// What a request for the bytes of a held image gets: `503` while the scan is
+/// still to come, and `404` once it's quarantined, as if it weren't there.
+pub fn held_status(entry: &ImageEntry) -> Option<StatusCode> {
+    match entry.scan_status {
+        Some(ScanStatus::Pending) => Some(StatusCode::SERVICE_UNAVAILABLE),
+        Some(ScanStatus::Quarantined) => Some(StatusCode::NOT_FOUND),
+        _ => None,
+    }
+}
+
+/// The report as it's stored in `scan_report`.
+pub fn report_json(report: &ScanReport) -> Option<String> {
+    serde_json::to_string(report).ok()
+}
+
+/// Starts the background scanner for held uploads. It runs whenever
+/// [`AppState::wake_upload_scanner`] is called, and every few minutes
+/// regardless, so a restart doesn't strand anything left pending.
+pub fn spawn_upload_scanner(state: AppState) {
+    tokio::spawn(async move {
+        loop {
+            match scan_pending(&state).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!(count, "scanned held uploads"),
+                Err(e) => tracing::warn!(error = %e, "scanning held uploads failed"),
+            }
+            tokio::select! {
+                _ = state.upload_scan_requested() => {}
+                _ = tokio::time::sleep(IDLE) => {}
+            }
+        }
+    });
+}
+
+/// Scans every pending image once, releasing or quarantining each. Returns
+/// how many were decided.
+pub async fn scan_pending(state: &AppState) -> anyhow::Result<usize> {
+    let mut decided = 0usize;
+    let mut cursor = String::new();
+    loop {
+        let after = cursor.clone();
+        let batch: Vec<(String, Option<i64>)> = state
+            .database()
+            .call(move |conn| {
+                let mut stmt = conn.prepare(
+                    "SELECT id, uploader_id FROM images WHERE scan_status = 'pending' AND id > ?1 ORDER BY id LIMIT ?2",
+                )?;
+                let rows: rusqlite::Result<Vec<(String, Option<i64>)>> = stmt
+                    .query_map(boxed_params![after, BATCH], |row| Ok((row.get(0)?, row.get(1)?)))?
+                    .collect();
+                rows
+            })
+            .await?;
+
+        let Some((last, _)) = batch.last() else {
+            break;
+        };
+        cursor = last.clone();
+
+        for (id, uploader_id) in batch {
+            // Still pending on the next pass if the bytes can't be read now.
+            let Some(bytes) = state.resolve_image_data_for(&id).await else {
+                continue;
+            };
+            let report = crate::scan::scan_bytes(state, &bytes).await;
+            if decide(state, &id, uploader_id, &report).await? {
+                decided += 1;
+            }
+        }
+    }
+    Ok(decided)
+}
+
+/// Records `report` for the pending image `id`. Returns `false` if it was no
+/// longer pending (deleted, or decided by another pass).
+async fn decide(state: &AppState, id: &str, uploader_id: Option<i64>, report: &ScanReport) -> anyhow::Result<bool> {
+    let status = ScanStatus::from_verdict(&report.verdict);
+    let updated = state
+        .database()
+        .execute(
+            "UPDATE images SET scan_status = ?2, scan_report = ?3, scanned_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') \
+             WHERE id = ?1 AND scan_status = 'pending'",
+            boxed_params![id.to_string(), status, report_json(report)],
+        )
+        .await?;
+    if updated == 0 {
+        return Ok(false);
+    }
+    // Straight away rather than after the pass: a cached entry would keep
+    // serving a quarantined image for as long as the rest of the batch takes.
+    state.invalidate_image_caches().await;
+
+    if status == ScanStatus::Quarantined {
+        tracing::warn!(
+            id,
+            sha256 = %report.sha256,
+            virus = ?report.clamav_virus,
+            vt = ?report.vt_status,
+            "quarantined infected upload"
+        );
+        state
+            .audit("image.quarantine")
+            .target(id.to_string())
+            .meta(serde_json::json!({
+                "uploader":     uploader_id,
+                "sha256":       report.sha256,
+                "clamav_virus": report.clamav_virus,
+                "vt_status":    report.vt_status,
+                "vt_positives": report.vt_positives,
+            }))
+            .fire();
+        state.send_alert(
+            crate::discord::Alert::error("Quarantined Infected Upload")
+                .field("Image", id)
+                .field("SHA-256", &report.sha256)
+                .field("ClamAV", report.clamav_virus.clone().unwrap_or_else(|| "—".into()))
+                .field("VirusTotal", report.vt_status.clone().unwrap_or_else(|| "—".into())),
+        );
+    } else {
+        state.pregenerate_thumbnail(id);
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_background_policies_hold_uploads() {
+        assert!(holds(Some(UploadScan::All), true));
+        assert!(holds(Some(UploadScan::NonImages), false));
+        assert!(!holds(Some(UploadScan::NonImages), true));
+        assert!(!holds(Some(UploadScan::Inline), false));
+        assert!(!holds(None, false));
+    }
+
+    #[tokio::test]
+    async fn a_flagged_upload_is_quarantined_and_a_clean_one_released() {
+        let db = crate::Database::file(":memory:")
+            .connections(1)
+            .with_init(crate::migrations::migrate)
+            .open()
+            .await
+            .unwrap();
+        db.execute(
+            "INSERT INTO images (id, mimetype, scan_status) VALUES ('bad', 'image/png', 'pending'), ('ok', 'image/png', 'pending')",
+            [],
+        )
+        .await
+        .unwrap();
+        let state = AppState::for_tests(db).await;
+
+        let report = |verdict: &str| ScanReport {
+            sha256: String::new(),
+            file_size: 0,
+            clamav_clean: Some(verdict == "clean"),
+            clamav_virus: (verdict == "infected").then(|| "Eicar-Test-Signature".into()),
+            vt_status: None,
+            vt_positives: None,
+            vt_total: None,
+            vt_url: None,
+            verdict: verdict.into(),
+        };
+        // With the listing cached, each decision is visible as soon as it's
+        // made, not once the whole pass is over.
+        drop(state.resolve_images().await);
+        assert!(decide(&state, "bad", None, &report("infected")).await.unwrap());
+        let bad = state.get_image_meta("bad".into()).await.unwrap();
+        assert_eq!(bad.scan_status, Some(ScanStatus::Quarantined));
+        assert!(decide(&state, "ok", None, &report("clean")).await.unwrap());
+        // Decided once; a second pass finds nothing pending.
+        assert!(!decide(&state, "ok", None, &report("infected")).await.unwrap());
+
+        let bad = state.get_image_meta("bad".into()).await.unwrap();
+        assert_eq!(bad.scan_status, Some(ScanStatus::Quarantined));
+        assert_eq!(held_status(&bad), Some(StatusCode::NOT_FOUND));
+        let ok = state.get_image_meta("ok".into()).await.unwrap();
+        assert_eq!(ok.scan_status, Some(ScanStatus::Clean));
+        assert_eq!(held_status(&ok), None);
+    }
+}
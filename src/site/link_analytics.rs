@@ -0,0 +1,351 @@
+//! Click analytics for short links: where the clicks came from, on what, and
+//! from which country.
+//!
+//! The per-day series and the referring sites are the link's [`crate::site::stats`]
+//! (`view_stat`); this adds the device class, guessed from the User-Agent, and
+//! the country, looked up in the local GeoIP database when one is configured
+//! (see [`crate::geoip`]). Both go into `link_click_stat` (see `sql/15.sql`),
+//! one row per link, UTC day, device class and country. Like the view counts,
+//! recording is best-effort and never holds up the redirect, and neither the
+//! address nor the User-Agent itself is kept.
+
+use std::net::IpAddr;
+
+use axum::http::{header, HeaderMap};
+use serde::Serialize;
+use time::{Duration, OffsetDateTime};
+use utoipa::ToSchema;
+
+use crate::site::api::chart::{render_svg, ChartKind, ChartRequest, ChartSeries, ChartTheme, DataPoint};
+use crate::site::stats::{self, ReferrerViews, ViewKind, ViewSeries};
+use crate::{boxed_params, AppState};
+
+/// Bars a breakdown chart shows before folding the rest into "other". One
+/// under the chart palette, to leave room for that bar.
+const CHART_BARS: usize = 6;
+/// The chart renderer refuses longer labels rather than cutting them.
+const CHART_LABEL_CHARS: usize = 48;
+
+/// What kind of client followed a link, as far as its User-Agent tells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceClass {
+    Bot,
+    Mobile,
+    Tablet,
+    Desktop,
+    Unknown,
+}
+
+impl DeviceClass {
+    /// The `link_click_stat.device` value.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DeviceClass::Bot => "bot",
+            DeviceClass::Mobile => "mobile",
+            DeviceClass::Tablet => "tablet",
+            DeviceClass::Desktop => "desktop",
+            DeviceClass::Unknown => "unknown",
+        }
+    }
+
+    /// Classifies a User-Agent. Crawlers, link-preview fetchers and HTTP
+    /// libraries are bots; anything else that names a phone or tablet
+    /// platform is one of those, and the rest is a desktop. A missing header
+    /// is unknown.
+    pub fn of(user_agent: &str) -> Self {
+        const BOTS: &[&str] = &[
+            "bot",
+            "crawl",
+            "spider",
+            "slurp",
+            "preview",
+            "facebookexternalhit",
+            "headless",
+            "curl/",
+            "wget/",
+            "python-",
+            "go-http-client",
+            "okhttp",
+            "java/",
+            "libwww",
+            "httpclient",
+        ];
+        let ua = user_agent.trim().to_ascii_lowercase();
+        if ua.is_empty() {
+            DeviceClass::Unknown
+        } else if BOTS.iter().any(|b| ua.contains(b)) {
+            DeviceClass::Bot
+        } else if ua.contains("ipad") || ua.contains("tablet") || (ua.contains("android") && !ua.contains("mobile")) {
+            DeviceClass::Tablet
+        } else if ua.contains("mobi") || ua.contains("iphone") || ua.contains("ipod") || ua.contains("android") {
+            DeviceClass::Mobile
+        } else {
+            DeviceClass::Desktop
+        }
+    }
+}
+
+/// Adds one click on link `link_id` to today's bucket for the client's device
+/// class and country.
+pub async fn record(state: &AppState, link_id: i64, headers: &HeaderMap, client_ip: Option<IpAddr>) {
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    let device = DeviceClass::of(user_agent);
+    let country = match (state.geoip(), client_ip) {
+        (Some(geoip), Some(ip)) => geoip.country(ip).unwrap_or_default(),
+        _ => String::new(),
+    };
+    let result = state
+        .database()
+        .execute(
+            "INSERT INTO link_click_stat (link_id, day, device, country, clicks) \
+             VALUES (?1, strftime('%Y-%m-%d', 'now'), ?2, ?3, 1) \
+             ON CONFLICT (link_id, day, device, country) DO UPDATE SET clicks = clicks + 1",
+            boxed_params![link_id, device.as_str(), country],
+        )
+        .await;
+    if let Err(e) = result {
+        tracing::warn!(error = %e, link = link_id, "failed to record a link click");
+    }
+}
+
+/// Clicks from one device class or country.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ClickShare {
+    /// The device class (`bot`, `mobile`, `tablet`, `desktop`, `unknown`), or
+    /// the country's ISO 3166-1 alpha-2 code (`unknown` when it couldn't be
+    /// told).
+    pub name: String,
+    /// Clicks from it within the window.
+    pub clicks: i64,
+}
+
+/// Click analytics for one short link.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LinkAnalytics {
+    /// Clicks within the window. The all-time total is the link's `clicks`.
+    pub total: i64,
+    /// Clicks per day, ready to plot.
+    pub series: ViewSeries,
+    /// The busiest referring sites within the window (at most 10).
+    pub referrers: Vec<ReferrerViews>,
+    /// Clicks per device class, most first.
+    pub devices: Vec<ClickShare>,
+    /// Clicks per country, most first.
+    pub countries: Vec<ClickShare>,
+}
+
+/// Loads the last `days` days of analytics for link `link_id`.
+pub async fn load(state: &AppState, link_id: i64, days: i64) -> anyhow::Result<LinkAnalytics> {
+    let clicks = stats::load(state, ViewKind::Link, link_id.to_string(), days).await?;
+    let since = (OffsetDateTime::now_utc().date() - Duration::days(days - 1)).to_string();
+
+    let (devices, countries) = state
+        .database()
+        .call(move |conn| -> rusqlite::Result<_> {
+            let breakdown = |column: &str| -> rusqlite::Result<Vec<ClickShare>> {
+                let mut stmt = conn.prepare_cached(&format!(
+                    "SELECT {column}, SUM(clicks) AS total FROM link_click_stat \
+                     WHERE link_id = ?1 AND day >= ?2 GROUP BY {column} ORDER BY total DESC, {column}"
+                ))?;
+                let rows = stmt.query_map(rusqlite::params![link_id, since], |row| {
+                    let name: String = row.get(0)?;
+                    Ok(ClickShare {
+                        name: if name.is_empty() { "unknown".to_string() } else { name },
+                        clicks: row.get(1)?,
+                    })
+                })?;
+                rows.collect()
+            };
+            Ok((breakdown("device")?, breakdown("country")?))
+        })
+        .await?;
+
+    Ok(LinkAnalytics {
+        total: clicks.total,
+        series: clicks.series,
+        referrers: clicks.referrers,
+        devices,
+        countries,
+    })
+}
+
+/// The SVG charts of a link's analytics page. A chart with nothing to show
+/// is `None`.
+pub struct Charts {
+    pub clicks: Option<String>,
+    pub referrers: Option<String>,
+    pub devices: Option<String>,
+    pub countries: Option<String>,
+}
+
+/// Draws `analytics`: clicks over time as an area, the device classes as a
+/// donut, and the referrers and countries as bars.
+pub fn charts(analytics: &LinkAnalytics) -> Charts {
+    let referrers: Vec<(String, i64)> = analytics
+        .referrers
+        .iter()
+        .map(|r| (r.referrer.clone(), r.views))
+        .collect();
+    let share =
+        |shares: &[ClickShare]| -> Vec<(String, i64)> { shares.iter().map(|s| (s.name.clone(), s.clicks)).collect() };
+    Charts {
+        clicks: clicks_chart(analytics),
+        referrers: breakdown_chart(ChartKind::Bar, "Referrers", referrers),
+        devices: breakdown_chart(ChartKind::Donut, "Devices", share(&analytics.devices)),
+        countries: breakdown_chart(ChartKind::Bar, "Countries", share(&analytics.countries)),
+    }
+}
+
+fn clicks_chart(analytics: &LinkAnalytics) -> Option<String> {
+    if analytics.total == 0 {
+        return None;
+    }
+    let request = ChartRequest {
+        kind: ChartKind::Area,
+        title: Some("Clicks".into()),
+        series: vec![ChartSeries {
+            label: "Clicks".into(),
+            data: analytics.series.views.iter().map(|&v| DataPoint::Y(v as f64)).collect(),
+        }],
+        // `MM-DD` is enough to read the axis and keeps the labels from colliding.
+        labels: analytics
+            .series
+            .days
+            .iter()
+            .map(|d| d.get(5..).unwrap_or(d).to_string())
+            .collect(),
+        theme: ChartTheme::Dark,
+        width: Some(640),
+        height: Some(240),
+        y_label: None,
+        x_label: None,
+    };
+    render_svg(&request).ok()
+}
+
+fn breakdown_chart(kind: ChartKind, title: &str, items: Vec<(String, i64)>) -> Option<String> {
+    let items = fold(items, CHART_BARS);
+    if items.iter().all(|(_, n)| *n == 0) {
+        return None;
+    }
+    let request = ChartRequest {
+        kind,
+        title: Some(title.into()),
+        series: vec![ChartSeries {
+            label: "Clicks".into(),
+            data: items.iter().map(|(_, n)| DataPoint::Y(*n as f64)).collect(),
+        }],
+        labels: items
+            .into_iter()
+            .map(|(label, _)| match label.char_indices().nth(CHART_LABEL_CHARS - 1) {
+                Some((cut, _)) => format!("{}…", &label[..cut]),
+                None => label,
+            })
+            .collect(),
+        theme: ChartTheme::Dark,
+        width: Some(640),
+        height: Some(240),
+        y_label: None,
+        x_label: None,
+    };
+    render_svg(&request).ok()
+}
+
+/// Keeps the first `keep` of `items` (busiest first) and folds the rest into
+/// one `other` entry, merging it with any `other` already there.
+fn fold(items: Vec<(String, i64)>, keep: usize) -> Vec<(String, i64)> {
+    let mut other = 0;
+    let mut kept = Vec::with_capacity(keep + 1);
+    for (name, count) in items {
+        if name == "other" || kept.len() == keep {
+            other += count;
+        } else {
+            kept.push((name, count));
+        }
+    }
+    if other > 0 {
+        kept.push(("other".to_string(), other));
+    }
+    kept
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_agents_are_classified() {
+        let class = DeviceClass::of;
+        assert_eq!(
+            class("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 Chrome/126.0 Safari/537.36"),
+            DeviceClass::Desktop
+        );
+        assert_eq!(
+            class("Mozilla/5.0 (iPhone; CPU iPhone OS 17_5 like Mac OS X) AppleWebKit/605.1.15 Mobile/15E148"),
+            DeviceClass::Mobile
+        );
+        assert_eq!(
+            class("Mozilla/5.0 (Linux; Android 14; Pixel 8) AppleWebKit/537.36 Chrome/126.0 Mobile Safari/537.36"),
+            DeviceClass::Mobile
+        );
+        assert_eq!(
+            class("Mozilla/5.0 (Linux; Android 13; SM-X710) AppleWebKit/537.36 Chrome/126.0 Safari/537.36"),
+            DeviceClass::Tablet
+        );
+        assert_eq!(
+            class("Mozilla/5.0 (compatible; Discordbot/2.0; +https://discordapp.com)"),
+            DeviceClass::Bot
+        );
+        assert_eq!(class("curl/8.5.0"), DeviceClass::Bot);
+        assert_eq!(class(""), DeviceClass::Unknown);
+    }
+
+    #[test]
+    fn the_long_tail_is_folded_into_other() {
+        let items =
+            |names: &[(&str, i64)]| -> Vec<(String, i64)> { names.iter().map(|(n, c)| (n.to_string(), *c)).collect() };
+        assert_eq!(
+            fold(items(&[("a", 5), ("other", 1), ("b", 3), ("c", 2), ("d", 1)]), 2),
+            items(&[("a", 5), ("b", 3), ("other", 4)])
+        );
+        assert_eq!(fold(items(&[("a", 5)]), 2), items(&[("a", 5)]));
+    }
+
+    #[tokio::test]
+    async fn clicks_are_bucketed_by_device_and_country() {
+        let db = crate::Database::file(":memory:")
+            .connections(1)
+            .with_init(crate::migrations::migrate)
+            .open()
+            .await
+            .unwrap();
+        db.execute("INSERT INTO account (id, name, password) VALUES (1, 'owner', 'x')", [])
+            .await
+            .unwrap();
+        db.execute(
+            "INSERT INTO short_link (id, code, target_url, account_id) VALUES (7, 'x', 'https://example.com', 1)",
+            [],
+        )
+        .await
+        .unwrap();
+        let state = AppState::for_tests(db).await;
+
+        let mut phone = HeaderMap::new();
+        phone.insert(header::USER_AGENT, "Mozilla/5.0 (iPhone) Mobile".parse().unwrap());
+        record(&state, 7, &phone, None).await;
+        record(&state, 7, &phone, None).await;
+        record(&state, 7, &HeaderMap::new(), None).await;
+
+        let analytics = load(&state, 7, 7).await.unwrap();
+        assert_eq!(analytics.devices[0].name, "mobile");
+        assert_eq!(analytics.devices[0].clicks, 2);
+        assert_eq!(analytics.devices[1].name, "unknown");
+        // No GeoIP database: every click is from an unknown country.
+        assert_eq!(analytics.countries.len(), 1);
+        assert_eq!(analytics.countries[0].name, "unknown");
+        assert_eq!(analytics.countries[0].clicks, 3);
+    }
+}
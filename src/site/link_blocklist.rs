@@ -0,0 +1,152 @@
+//! The destinations short links may not point at.
+//!
+//! The site can be given a blocklist file (`link_blocklist_path` in the
+//! config) of phishing and malware hosts. It is plain text, one entry a line;
+//! blank lines and `#` comments are skipped:
+//!
+//! - a bare domain (`evil.example`) blocks that host and every subdomain of it;
+//! - anything with a `/` or a `*` is a pattern over the URL without its scheme
+//!   (`host/path?query`, leaving out any login, port or fragment), where `*`
+//!   matches any run of characters:
+//!   `*.example/login*`, `example.com/*/phish`. A pattern has to match the whole
+//!   of that, so a bare `evil.example/` blocks its front page only.
+//!
+//! Both are matched case-insensitively. A blocked target is refused when a link
+//! is created or edited, and a link whose target is blocked later stops
+//! redirecting (see [`crate::site::links`]). The file is read at start-up and
+//! again by the hourly link reaper, so edits apply within the hour.
+
+use std::collections::HashSet;
+use std::path::Path;
+
+use regex::Regex;
+
+/// A parsed blocklist. The default blocks nothing.
+#[derive(Debug, Default)]
+pub struct Blocklist {
+    domains: HashSet<String>,
+    patterns: Vec<Regex>,
+}
+
+impl Blocklist {
+    /// Parses a blocklist file's contents. Entries that can't be made into a
+    /// pattern are skipped with a warning.
+    pub fn parse(text: &str) -> Self {
+        let mut blocklist = Self::default();
+        for line in text.lines() {
+            let entry = line.split('#').next().unwrap_or("").trim().to_ascii_lowercase();
+            if entry.is_empty() {
+                continue;
+            }
+            if entry.contains('/') || entry.contains('*') {
+                let pattern = format!("^{}$", regex::escape(&entry).replace(r"\*", ".*"));
+                match Regex::new(&pattern) {
+                    Ok(re) => blocklist.patterns.push(re),
+                    Err(e) => tracing::warn!(entry, error = %e, "skipping a link blocklist entry"),
+                }
+            } else {
+                blocklist.domains.insert(entry.trim_end_matches('.').to_string());
+            }
+        }
+        blocklist
+    }
+
+    /// Reads the blocklist at `path`.
+    pub fn load(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        std::fs::read_to_string(path).map(|text| Self::parse(&text))
+    }
+
+    /// The blocklist the config points at, or an empty one when it doesn't
+    /// point at one. An unreadable file is logged and blocks nothing.
+    pub fn from_config(config: &crate::Config) -> Self {
+        let Some(path) = config.link_blocklist_path.as_deref() else {
+            return Self::default();
+        };
+        match Self::load(path) {
+            Ok(blocklist) => blocklist,
+            Err(e) => {
+                tracing::warn!(path, error = %e, "could not read the link blocklist, blocking nothing");
+                Self::default()
+            }
+        }
+    }
+
+    /// How many entries it has.
+    pub fn len(&self) -> usize {
+        self.domains.len() + self.patterns.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Whether `url` (an absolute http(s) URL) is blocked.
+    pub fn blocks(&self, url: &str) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        let Ok(url) = reqwest::Url::parse(url) else {
+            return false;
+        };
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+
+        // The host and each domain above it: a.b.example, b.example, example.
+        let mut domain = host.as_str();
+        loop {
+            if self.domains.contains(domain) {
+                return true;
+            }
+            match domain.split_once('.') {
+                Some((_, parent)) => domain = parent,
+                None => break,
+            }
+        }
+
+        if self.patterns.is_empty() {
+            return false;
+        }
+        // Built from its parts rather than sliced out of the URL, so a
+        // `user:pass@` or an explicit `:port` can't dodge a pattern that
+        // starts at the host.
+        let mut rest = format!("{host}{}", url.path());
+        if let Some(query) = url.query() {
+            rest.push('?');
+            rest.push_str(query);
+        }
+        let rest = rest.to_ascii_lowercase();
+        self.patterns.iter().any(|re| re.is_match(&rest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn domains_block_their_subdomains() {
+        let blocklist = Blocklist::parse("# phishing\nevil.example\n\nbad.test.  # trailing dot\n");
+        assert_eq!(blocklist.len(), 2);
+        assert!(blocklist.blocks("https://evil.example/login"));
+        assert!(blocklist.blocks("https://WWW.Evil.Example/"));
+        assert!(blocklist.blocks("http://bad.test"));
+        assert!(!blocklist.blocks("https://notevil.example/"));
+        assert!(!blocklist.blocks("https://example/"));
+    }
+
+    #[test]
+    fn patterns_match_the_whole_url_without_its_scheme() {
+        let blocklist = Blocklist::parse("*.example.com/login*\nexample.org/*/phish\n");
+        assert!(blocklist.blocks("https://accounts.example.com/login?next=/"));
+        assert!(blocklist.blocks("https://user:pw@www.example.com/LOGIN"));
+        assert!(blocklist.blocks("https://accounts.example.com:8443/login"));
+        assert!(blocklist.blocks("https://user:pw@accounts.example.com:443/login#top"));
+        assert!(!blocklist.blocks("https://example.com/login"));
+        assert!(!blocklist.blocks("https://www.example.com/about"));
+        assert!(blocklist.blocks("https://example.org/a/b/phish"));
+        assert!(!blocklist.blocks("https://example.org/phishing"));
+        assert!(!Blocklist::default().blocks("https://example.org/a/phish"));
+    }
+}
@@ -0,0 +1,153 @@
+//! What a visitor must get past before a short link redirects: a password, an
+//! interstitial, or both.
+//!
+//! A password works as it does for images (see [`crate::site::image_lock`]): an
+//! Argon2 hash checked on unlock, and a signed, `HttpOnly` cookie naming that
+//! one link and when it runs out. The cookie is scoped to the path the link was
+//! reached at (`/<code>` on the short host, or `/r/<code>`), so it unlocks
+//! nothing else. An interstitial shows the destination and waits for the
+//! visitor to continue. Unlocking doesn't skip it; it is there every time.
+//!
+//! As with a burning image, a `GET` never counts a click on a gated link. Only
+//! the `POST` that gets past the gate does, so a link-preview crawler can't use
+//! up a `max_clicks` limit, or learn where a locked link goes.
+
+use cookie::Cookie;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::key::SecretKey;
+use crate::models::ShortLink;
+
+/// How long a password unlock lasts.
+pub const UNLOCK_TTL_SECS: i64 = 30 * 60;
+/// The longest password accepted, in bytes.
+const MAX_PASSWORD_LEN: usize = 128;
+
+/// The signed payload of an unlock cookie: which link, and until when. Not the
+/// shape of an image's grant, so one can't pass for the other.
+#[derive(Serialize, Deserialize)]
+struct Grant {
+    link: i64,
+    exp: i64,
+}
+
+/// The cookie name for a given link.
+pub fn cookie_name(link_id: i64) -> String {
+    format!("link_unlock_{link_id}")
+}
+
+/// Signs a grant for `link_id` that lasts [`UNLOCK_TTL_SECS`].
+pub fn sign_grant(secret: &SecretKey, link_id: i64) -> Option<String> {
+    secret
+        .sign(&Grant {
+            link: link_id,
+            exp: OffsetDateTime::now_utc().unix_timestamp() + UNLOCK_TTL_SECS,
+        })
+        .ok()
+}
+
+/// Whether `token` is a live grant for `link_id`.
+pub fn verify_grant(secret: &SecretKey, link_id: i64, token: &str) -> bool {
+    secret
+        .verify::<Grant>(token)
+        .is_some_and(|grant| grant.link == link_id && grant.exp > OffsetDateTime::now_utc().unix_timestamp())
+}
+
+/// Whether this browser has unlocked `link`. A link without a password needs
+/// no unlocking.
+pub fn unlocked(link: &ShortLink, cookies: &[Cookie<'static>], secret: &SecretKey) -> bool {
+    if !link.is_locked() {
+        return true;
+    }
+    let name = cookie_name(link.id);
+    cookies
+        .iter()
+        .find(|c| c.name() == name)
+        .is_some_and(|c| verify_grant(secret, link.id, c.value()))
+}
+
+/// Builds the unlock cookie for a link reached at `path`. `HttpOnly`, and
+/// scoped to that path.
+pub fn build_grant_cookie(link_id: i64, token: String, path: &str, production: bool) -> Cookie<'static> {
+    Cookie::build((cookie_name(link_id), token))
+        .path(path.to_string())
+        .http_only(true)
+        .secure(production)
+        .same_site(cookie::SameSite::Lax)
+        .max_age(time::Duration::seconds(UNLOCK_TTL_SECS))
+        .build()
+}
+
+/// Checks `password` against the link's hash. A link without a password
+/// accepts anything. Argon2 is slow on purpose, so it runs on a blocking
+/// thread rather than holding up the executor.
+pub async fn password_matches(link: &ShortLink, password: &str) -> bool {
+    let Some(hash) = link.password_hash.clone() else {
+        return true;
+    };
+    let password = password.to_string();
+    tokio::task::spawn_blocking(move || crate::auth::validate_password(&password, &hash).is_ok())
+        .await
+        .unwrap_or(false)
+}
+
+/// A change to a link's gates. `None` leaves a setting as it is on an edit;
+/// on a new link it means none.
+#[derive(Debug, Default)]
+pub struct LinkGate {
+    /// The new password's hash, or `Some(None)` to remove the password.
+    pub password_hash: Option<Option<String>>,
+    pub interstitial: Option<bool>,
+}
+
+impl LinkGate {
+    /// Reads a password (an empty one removes it) and the interstitial flag,
+    /// hashing the password.
+    pub fn new(password: Option<&str>, interstitial: Option<bool>) -> Result<Self, &'static str> {
+        let password_hash = match password {
+            None => None,
+            Some("") => Some(None),
+            Some(p) if p.len() > MAX_PASSWORD_LEN => return Err("The password must be at most 128 bytes."),
+            Some(p) => match crate::auth::hash_password(p) {
+                Ok(hash) => Some(Some(hash)),
+                Err(e) => {
+                    tracing::error!(error = %e, "failed to hash a short link password");
+                    return Err("Could not set the password.");
+                }
+            },
+        };
+        Ok(Self {
+            password_hash,
+            interstitial,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn grants_are_link_scoped_and_expire() {
+        let secret = SecretKey::random().unwrap();
+        let token = sign_grant(&secret, 7).unwrap();
+        assert!(verify_grant(&secret, 7, &token));
+        assert!(!verify_grant(&secret, 8, &token));
+        assert!(!verify_grant(&SecretKey::random().unwrap(), 7, &token));
+
+        // An image's grant doesn't unlock a link.
+        let image = crate::site::image_lock::sign_grant(&secret, "7", 60).unwrap();
+        assert!(!verify_grant(&secret, 7, &image));
+    }
+
+    #[test]
+    fn passwords_are_hashed_and_an_empty_one_removes_it() {
+        let gate = LinkGate::new(Some("hunter2"), None).unwrap();
+        let hash = gate.password_hash.unwrap().unwrap();
+        assert!(crate::auth::validate_password("hunter2", &hash).is_ok());
+
+        assert_eq!(LinkGate::new(Some(""), Some(true)).unwrap().password_hash, Some(None));
+        assert!(LinkGate::new(Some(&"x".repeat(129)), None).is_err());
+    }
+}
@@ -5,24 +5,55 @@
 //! bare-subdomain form is handled by the router fallback ([`short_link_fallback`]),
 //! which only treats a request as a short link when it targets the configured
 //! short host — every other unmatched path still 404s as before.
+//!
+//! A link can be limited to a window (`active_from`, `expires_at`) and a number
+//! of clicks (`max_clicks`, see `sql/16.sql`). Outside of them it answers with a
+//! page saying it isn't active yet, or has expired, instead of redirecting. An
+//! hourly reaper ([`spawn_link_reaper`]) marks links whose time ran out as
+//! ended, and deletes them once they have been ended for as long as the trash
+//! keeps things.
+//!
+//! A link can also ask for a password, or show where it goes before sending the
+//! visitor on (see [`crate::site::link_lock`]). Its page then posts back to
+//! the short URL itself, which counts the click and redirects.
+//!
+//! Adding a `+` to a short URL shows a preview of it instead: the destination,
+//! what's there (unfurled the way `/api/unfurl` does it) and the click count.
+//! Destinations on the site's blocklist ([`crate::site::link_blocklist`]) can't
+//! be linked to, and links made before their target was blocked stop
+//! redirecting.
+
+use std::net::IpAddr;
+use std::sync::OnceLock;
 
 use askama::Template;
 use axum::{
-    extract::{Path, State},
-    http::{header, HeaderMap, StatusCode, Uri},
+    extract::{FromRequest, Path, Request, State},
+    http::{header, HeaderMap, Method, StatusCode, Uri},
     response::{IntoResponse, Redirect, Response},
     routing::{get, post},
-    Form, Router,
+    Extension, Form, Router,
 };
+use cookie::Cookie;
 use serde::Deserialize;
 use time::OffsetDateTime;
 
+use crate::cookies::set_cookie;
 use crate::database::is_unique_constraint_violation;
 use crate::filters; // used by the `isoformat` filter in links.html
 use crate::flash::{FlashMessage, Flasher, Flashes};
-use crate::models::{Account, ShortLink};
+use crate::headers::ClientIp;
+use crate::key::SecretKey;
+use crate::models::{Account, LinkStatus, ShortLink};
+use crate::ratelimit::{IpKeyExtractor, RateLimit, RateLimitLayer};
+use crate::site::api::unfurl::{unfurl_url, UnfurlResult};
+use crate::site::link_analytics::{self, Charts, LinkAnalytics};
+use crate::site::link_blocklist::Blocklist;
+use crate::site::link_lock::{self, LinkGate};
+use crate::site::stats::{self, ViewKind};
+use crate::site::trash::{self, TrashKind};
 use crate::utils::get_new_image_id;
-use crate::AppState;
+use crate::{boxed_params, AppState};
 
 /// Maximum short links a non-admin account may own.
 pub(crate) const FREE_LINK_LIMIT: usize = 10;
@@ -32,6 +63,12 @@ const MAX_CODE_LEN: usize = 64;
 const MAX_URL_LEN: usize = 2048;
 /// How many times to retry an auto-generated code on a (rare) collision.
 const AUTO_CODE_ATTEMPTS: usize = 6;
+/// The highest click limit a link may have.
+const MAX_CLICK_LIMIT: i64 = 1_000_000_000;
+
+/// Whether a short link has stopped resolving for good, as SQL over its row.
+const ENDED: &str = "((max_clicks IS NOT NULL AND clicks >= max_clicks) \
+                     OR (expires_at IS NOT NULL AND datetime(expires_at) <= datetime('now')))";
 
 /// Top-level paths a custom alias must not shadow. On the `r.` host real routes
 /// win over the fallback, so an alias matching one of these would be unreachable.
@@ -68,8 +105,8 @@ const RESERVED_CODES: &[&str] = &[
 
 /// Normalises a user-supplied destination into a stored URL. Forgiving about a
 /// missing scheme (defaults to `https://`) but rejects anything that isn't a
-/// plain http/https URL with a host.
-pub(crate) fn normalize_target(raw: &str) -> Result<String, &'static str> {
+/// plain http/https URL with a host, and anything on the `blocklist`.
+pub(crate) fn normalize_target(raw: &str, blocklist: &Blocklist) -> Result<String, &'static str> {
     let trimmed = raw.trim();
     if trimmed.is_empty() {
         return Err("Destination URL is required.");
@@ -90,6 +127,9 @@ pub(crate) fn normalize_target(raw: &str) -> Result<String, &'static str> {
     if rest.is_empty() || rest.starts_with('/') {
         return Err("Destination URL is missing a host.");
     }
+    if blocklist.blocks(&with_scheme) {
+        return Err("That destination is blocked.");
+    }
     Ok(with_scheme)
 }
 
@@ -111,6 +151,99 @@ pub(crate) fn validate_code(raw: &str) -> Result<String, &'static str> {
     Ok(code.to_string())
 }
 
+/// When a link resolves and for how many clicks. On an edit, `None` leaves a
+/// limit as it is and `Some(None)` removes it; on a new link both mean none.
+#[derive(Debug, Default)]
+pub(crate) struct LinkLimits {
+    pub active_from: Option<Option<OffsetDateTime>>,
+    pub expires_at: Option<Option<OffsetDateTime>>,
+    pub max_clicks: Option<Option<i64>>,
+}
+
+impl LinkLimits {
+    /// Reads the API's form of a click limit: `0` removes it.
+    pub(crate) fn clicks(raw: Option<i64>) -> Result<Option<Option<i64>>, &'static str> {
+        match raw {
+            None => Ok(None),
+            Some(0) => Ok(Some(None)),
+            Some(n) if (1..=MAX_CLICK_LIMIT).contains(&n) => Ok(Some(Some(n))),
+            Some(_) => Err("The click limit must be between 1 and 1000000000, or 0 for none."),
+        }
+    }
+
+    /// Reads the API's form of a time: an RFC 3339 timestamp, or an empty
+    /// string to remove it.
+    pub(crate) fn time(raw: Option<&str>) -> Result<Option<Option<OffsetDateTime>>, &'static str> {
+        match raw.map(str::trim) {
+            None => Ok(None),
+            Some("") => Ok(Some(None)),
+            Some(raw) => OffsetDateTime::parse(raw, &time::format_description::well_known::Rfc3339)
+                .map(|at| Some(Some(at)))
+                .map_err(|_| "Times must be RFC 3339 timestamps, like 2026-01-31T18:00:00Z."),
+        }
+    }
+
+    /// Checks the limits `link` (or a new link, for `None`) would end up with.
+    /// Returns the offending field with the message. An existing link may be
+    /// given an expiry that has passed, which ends it.
+    pub(crate) fn validate(&self, link: Option<&ShortLink>) -> Result<(), (&'static str, &'static str)> {
+        let active_from = self.active_from.unwrap_or_else(|| link.and_then(|l| l.active_from));
+        let expires_at = self.expires_at.unwrap_or_else(|| link.and_then(|l| l.expires_at));
+        if link.is_none() && expires_at.is_some_and(|at| at <= OffsetDateTime::now_utc()) {
+            return Err(("expires_at", "The expiry time has already passed."));
+        }
+        if let (Some(from), Some(until)) = (active_from, expires_at) {
+            if until <= from {
+                return Err(("expires_at", "The link must expire after it becomes active."));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads a `datetime-local` form value (`2026-01-31T18:00`) entered with a
+/// browser `tz_offset` minutes behind UTC, as JavaScript's
+/// `getTimezoneOffset()` counts them. Empty is `None`.
+fn form_time(raw: &str, tz_offset: i64) -> Result<Option<OffsetDateTime>, &'static str> {
+    let raw = raw.trim();
+    if raw.is_empty() {
+        return Ok(None);
+    }
+    let with_seconds = if raw.len() == "2026-01-31T18:00".len() {
+        format!("{raw}:00")
+    } else {
+        raw.to_string()
+    };
+    let format = time::macros::format_description!("[year]-[month]-[day]T[hour]:[minute]:[second]");
+    let local =
+        time::PrimitiveDateTime::parse(&with_seconds, &format).map_err(|_| "That isn't a valid date and time.")?;
+    // Real offsets are within a day; anything else isn't worth overflowing on.
+    let offset = time::Duration::minutes(tz_offset.clamp(-24 * 60, 24 * 60));
+    Ok(Some(local.assume_utc() + offset))
+}
+
+/// The limits from the create or edit form, which always sends every field:
+/// an empty one is no limit.
+fn form_limits(
+    active_from: &str,
+    expires_at: &str,
+    max_clicks: &str,
+    tz_offset: i64,
+) -> Result<LinkLimits, &'static str> {
+    let max_clicks = match max_clicks.trim() {
+        "" => None,
+        raw => match raw.parse::<i64>() {
+            Ok(n) if (1..=MAX_CLICK_LIMIT).contains(&n) => Some(n),
+            _ => return Err("The click limit must be a whole number from 1 up, or empty for none."),
+        },
+    };
+    Ok(LinkLimits {
+        active_from: Some(form_time(active_from, tz_offset)?),
+        expires_at: Some(form_time(expires_at, tz_offset)?),
+        max_clicks: Some(max_clicks),
+    })
+}
+
 // ---------------------------------------------------------------------------
 // Management page (/links)
 // ---------------------------------------------------------------------------
@@ -123,6 +256,14 @@ struct LinkView {
     target_url: String,
     clicks: i64,
     created_at: OffsetDateTime,
+    active_from: Option<OffsetDateTime>,
+    expires_at: Option<OffsetDateTime>,
+    max_clicks: Option<i64>,
+    /// `"scheduled"`, `"expired"`, `"used up"` or `"blocked"` for a link that
+    /// doesn't resolve right now.
+    inactive: Option<&'static str>,
+    locked: bool,
+    interstitial: bool,
 }
 
 #[derive(Template)]
@@ -142,7 +283,7 @@ async fn links_page(State(state): State<AppState>, account: Account, flashes: Fl
     let links: Vec<ShortLink> = state
         .database()
         .all(
-            "SELECT * FROM short_link WHERE account_id = ?1 ORDER BY created_at DESC",
+            "SELECT * FROM short_link WHERE account_id = ?1 AND deleted_at IS NULL ORDER BY created_at DESC",
             [account.id],
         )
         .await
@@ -153,11 +294,23 @@ async fn links_page(State(state): State<AppState>, account: Account, flashes: Fl
         .into_iter()
         .map(|l| LinkView {
             short_url: config.short_link_url(&l.code),
+            locked: l.is_locked(),
+            interstitial: l.interstitial,
+            inactive: match link_status(&state, &l) {
+                LinkStatus::Active => None,
+                LinkStatus::Scheduled => Some("scheduled"),
+                LinkStatus::Expired => Some("expired"),
+                LinkStatus::UsedUp => Some("used up"),
+                LinkStatus::Blocked => Some("blocked"),
+            },
             id: l.id,
             code: l.code,
             target_url: l.target_url,
             clicks: l.clicks,
             created_at: l.created_at,
+            active_from: l.active_from,
+            expires_at: l.expires_at,
+            max_clicks: l.max_clicks,
         })
         .collect();
 
@@ -181,6 +334,20 @@ struct CreateForm {
     target_url: String,
     #[serde(default)]
     code: String,
+    #[serde(default)]
+    active_from: String,
+    #[serde(default)]
+    expires_at: String,
+    #[serde(default)]
+    max_clicks: String,
+    /// The browser's `getTimezoneOffset()`, for reading the times above.
+    #[serde(default)]
+    tz_offset: i64,
+    #[serde(default)]
+    password: String,
+    /// A checkbox: present when ticked.
+    #[serde(default)]
+    interstitial: Option<String>,
 }
 
 async fn create_link(
@@ -193,16 +360,29 @@ async fn create_link(
     if !account.flags.is_admin() && count_links(&state, account.id).await >= FREE_LINK_LIMIT {
         return flasher
             .add(FlashMessage::error(format!(
-                "You've reached the limit of {FREE_LINK_LIMIT} short links — delete one to create another."
+                "You've reached the limit of {FREE_LINK_LIMIT} short links — delete one and empty it from your trash to create another."
             )))
             .bail("/links");
     }
 
-    let target = match normalize_target(&form.target_url) {
+    let target = match normalize_target(&form.target_url, &state.link_blocklist()) {
         Ok(t) => t,
         Err(e) => return flasher.add(FlashMessage::error(e)).bail("/links"),
     };
 
+    let limits = match form_limits(&form.active_from, &form.expires_at, &form.max_clicks, form.tz_offset) {
+        Ok(limits) => limits,
+        Err(e) => return flasher.add(FlashMessage::error(e)).bail("/links"),
+    };
+    if let Err((_, e)) = limits.validate(None) {
+        return flasher.add(FlashMessage::error(e)).bail("/links");
+    }
+    let password = Some(form.password.as_str()).filter(|p| !p.is_empty());
+    let gate = match LinkGate::new(password, Some(form.interstitial.is_some())) {
+        Ok(gate) => gate,
+        Err(e) => return flasher.add(FlashMessage::error(e)).bail("/links"),
+    };
+
     let custom_alias = !form.code.trim().is_empty();
     let code = if custom_alias {
         match validate_code(&form.code) {
@@ -213,7 +393,7 @@ async fn create_link(
         get_new_image_id()
     };
 
-    match insert_link(&state, code, &target, account.id, custom_alias).await {
+    match insert_link(&state, code, &target, account.id, custom_alias, &limits, &gate).await {
         Ok(_code) => flasher.add(FlashMessage::success("Short link created.")).bail("/links"),
         Err(InsertError::Taken) => flasher
             .add(FlashMessage::error(
@@ -238,21 +418,35 @@ pub(crate) enum InsertError {
 /// Inserts a link. Custom aliases get a single attempt (collision → `Taken`);
 /// auto-generated codes are retried with fresh codes on the (rare) collision.
 /// Returns the final stored code on success (which may differ from the input for
-/// auto-generated codes that hit a collision).
+/// auto-generated codes that hit a collision). `limits` must have been
+/// validated.
 pub(crate) async fn insert_link(
     state: &AppState,
     mut code: String,
     target: &str,
     account_id: i64,
     custom_alias: bool,
+    limits: &LinkLimits,
+    gate: &LinkGate,
 ) -> Result<String, InsertError> {
     let attempts = if custom_alias { 1 } else { AUTO_CODE_ATTEMPTS };
     for _ in 0..attempts {
         let result = state
             .database()
             .execute(
-                "INSERT INTO short_link (code, target_url, account_id) VALUES (?1, ?2, ?3)",
-                (code.clone(), target.to_string(), account_id),
+                "INSERT INTO short_link (code, target_url, account_id, active_from, expires_at, max_clicks, \
+                                         password_hash, interstitial) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                boxed_params![
+                    code.clone(),
+                    target.to_string(),
+                    account_id,
+                    limits.active_from.flatten(),
+                    limits.expires_at.flatten(),
+                    limits.max_clicks.flatten(),
+                    gate.password_hash.clone().flatten(),
+                    gate.interstitial.unwrap_or(false)
+                ],
             )
             .await;
         match result {
@@ -289,6 +483,21 @@ pub(crate) async fn count_links(state: &AppState, account_id: i64) -> usize {
 struct EditForm {
     target_url: String,
     code: String,
+    #[serde(default)]
+    active_from: String,
+    #[serde(default)]
+    expires_at: String,
+    #[serde(default)]
+    max_clicks: String,
+    #[serde(default)]
+    tz_offset: i64,
+    /// A new password; empty keeps the current one.
+    #[serde(default)]
+    password: String,
+    #[serde(default)]
+    remove_password: Option<String>,
+    #[serde(default)]
+    interstitial: Option<String>,
 }
 
 async fn edit_link(
@@ -304,7 +513,7 @@ async fn edit_link(
             .bail("/links");
     };
 
-    let target = match normalize_target(&form.target_url) {
+    let target = match normalize_target(&form.target_url, &state.link_blocklist()) {
         Ok(t) => t,
         Err(e) => return flasher.add(FlashMessage::error(e)).bail("/links"),
     };
@@ -312,30 +521,94 @@ async fn edit_link(
         Ok(c) => c,
         Err(e) => return flasher.add(FlashMessage::error(e)).bail("/links"),
     };
+    let limits = match form_limits(&form.active_from, &form.expires_at, &form.max_clicks, form.tz_offset) {
+        Ok(limits) => limits,
+        Err(e) => return flasher.add(FlashMessage::error(e)).bail("/links"),
+    };
+    if let Err((_, e)) = limits.validate(Some(&link)) {
+        return flasher.add(FlashMessage::error(e)).bail("/links");
+    }
+    let password = match (form.remove_password.is_some(), form.password.as_str()) {
+        (true, _) => Some(""),
+        (false, "") => None,
+        (false, password) => Some(password),
+    };
+    let gate = match LinkGate::new(password, Some(form.interstitial.is_some())) {
+        Ok(gate) => gate,
+        Err(e) => return flasher.add(FlashMessage::error(e)).bail("/links"),
+    };
+
+    match update_link(&state, link.id, Some(target), Some(code), &limits, &gate).await {
+        Ok(()) => flasher.add(FlashMessage::success("Short link updated.")).bail("/links"),
+        Err(InsertError::Taken) => flasher
+            .add(FlashMessage::error(
+                "That alias is already taken — please pick another.",
+            ))
+            .bail("/links"),
+        Err(InsertError::Db) => flasher
+            .add(FlashMessage::error("Could not update the short link."))
+            .bail("/links"),
+    }
+}
 
+/// Repoints link `id` at `target` and renames it to `code`, each if given,
+/// and applies `limits`, which must have been validated, and `gate`. Limits
+/// that let an ended link work again revive it; limits it has already run out
+/// of end it.
+pub(crate) async fn update_link(
+    state: &AppState,
+    id: i64,
+    target: Option<String>,
+    code: Option<String>,
+    limits: &LinkLimits,
+    gate: &LinkGate,
+) -> Result<(), InsertError> {
     let result = state
         .database()
         .execute(
-            "UPDATE short_link SET target_url = ?1, code = ?2, \
-             updated_at = strftime('%Y-%m-%dT%H:%M:%fZ','now') WHERE id = ?3",
-            (target, code, link.id),
+            "UPDATE short_link SET target_url = coalesce(?2, target_url), code = coalesce(?3, code), \
+                 active_from = CASE WHEN ?4 THEN ?5 ELSE active_from END, \
+                 expires_at = CASE WHEN ?6 THEN ?7 ELSE expires_at END, \
+                 max_clicks = CASE WHEN ?8 THEN ?9 ELSE max_clicks END, \
+                 password_hash = CASE WHEN ?10 THEN ?11 ELSE password_hash END, \
+                 interstitial = coalesce(?12, interstitial), \
+                 updated_at = strftime('%Y-%m-%dT%H:%M:%fZ','now') \
+             WHERE id = ?1",
+            boxed_params![
+                id,
+                target,
+                code,
+                limits.active_from.is_some(),
+                limits.active_from.flatten(),
+                limits.expires_at.is_some(),
+                limits.expires_at.flatten(),
+                limits.max_clicks.is_some(),
+                limits.max_clicks.flatten(),
+                gate.password_hash.is_some(),
+                gate.password_hash.clone().flatten(),
+                gate.interstitial
+            ],
         )
         .await;
-
     match result {
-        Ok(_) => flasher.add(FlashMessage::success("Short link updated.")).bail("/links"),
-        Err(e) if is_unique_constraint_violation(&e) => flasher
-            .add(FlashMessage::error(
-                "That alias is already taken — please pick another.",
-            ))
-            .bail("/links"),
+        Ok(_) => {}
+        Err(e) if is_unique_constraint_violation(&e) => return Err(InsertError::Taken),
         Err(e) => {
-            tracing::error!(error = %e, "failed to update short link");
-            flasher
-                .add(FlashMessage::error("Could not update the short link."))
-                .bail("/links")
+            tracing::error!(link = id, error = %e, "failed to update short link");
+            return Err(InsertError::Db);
         }
     }
+
+    let sql = format!(
+        "UPDATE short_link SET ended_at = CASE WHEN {ENDED} \
+             THEN coalesce(ended_at, strftime('%Y-%m-%dT%H:%M:%fZ','now')) END \
+         WHERE id = ?1"
+    );
+    if let Err(e) = state.database().call(move |conn| conn.execute(&sql, [id])).await {
+        tracing::error!(link = id, error = %e, "failed to re-evaluate short link expiry");
+        return Err(InsertError::Db);
+    }
+    Ok(())
 }
 
 async fn delete_link(
@@ -349,18 +622,93 @@ async fn delete_link(
             .add(FlashMessage::error("Short link not found, or not yours to delete."))
             .bail("/links");
     };
-    let _ = state
-        .database()
-        .execute("DELETE FROM short_link WHERE id = ?1", [link.id])
-        .await;
-    flasher.add(FlashMessage::success("Short link deleted.")).bail("/links")
+    if let Err(e) = trash::discard(&state, TrashKind::Link, link.account_id, &link.code).await {
+        tracing::warn!(link = link.id, error = %e, "failed to move short link to the trash");
+        return flasher
+            .add(FlashMessage::error("Could not delete the short link."))
+            .bail("/links");
+    }
+    flasher
+        .add(FlashMessage::success("Short link moved to the trash."))
+        .bail("/links")
+}
+
+/// `GET /links/:id/stats.svg` — the link's clicks over the last 30 days, as
+/// a bare chart. The clicks column on `/links` opens the fuller analytics page.
+async fn link_stats_chart(State(state): State<AppState>, account: Account, Path(id): Path<i64>) -> Response {
+    let Some(link) = owned_link(&state, id, &account).await else {
+        return not_found();
+    };
+    let chart = match stats::load(&state, ViewKind::Link, link.id.to_string(), stats::DEFAULT_DAYS).await {
+        Ok(s) => stats::chart(&s),
+        Err(e) => {
+            tracing::warn!(link = link.id, error = %e, "failed to load link stats");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let Some(svg) = chart else {
+        return (StatusCode::NOT_FOUND, "No clicks in the last 30 days.").into_response();
+    };
+    (
+        [
+            (header::CONTENT_TYPE, "image/svg+xml"),
+            (header::CACHE_CONTROL, "private, no-store"),
+        ],
+        svg,
+    )
+        .into_response()
+}
+
+#[derive(Template)]
+#[template(path = "links/analytics.html")]
+struct AnalyticsTemplate {
+    account: Option<Account>,
+    flashes: Flashes,
+    short_host: String,
+    link: ShortLink,
+    analytics: LinkAnalytics,
+    charts: Charts,
 }
 
-/// Loads a link by id only if the account owns it (or is an admin).
+/// `GET /links/:id/analytics` — the link's clicks over the last 30 days, with
+/// where they came from, on what, and from which countries.
+async fn link_analytics_page(
+    State(state): State<AppState>,
+    account: Account,
+    flashes: Flashes,
+    flasher: Flasher,
+    Path(id): Path<i64>,
+) -> Response {
+    let Some(link) = owned_link(&state, id, &account).await else {
+        return flasher
+            .add(FlashMessage::error("Short link not found, or not yours to look at."))
+            .bail("/links");
+    };
+    let analytics = match link_analytics::load(&state, link.id, stats::DEFAULT_DAYS).await {
+        Ok(analytics) => analytics,
+        Err(e) => {
+            tracing::warn!(link = link.id, error = %e, "failed to load link analytics");
+            return flasher
+                .add(FlashMessage::error("Could not load the link's analytics."))
+                .bail("/links");
+        }
+    };
+    AnalyticsTemplate {
+        short_host: state.config().short_domain(),
+        charts: link_analytics::charts(&analytics),
+        account: Some(account),
+        flashes,
+        link,
+        analytics,
+    }
+    .into_response()
+}
+
+/// Loads a live link by id only if the account owns it (or is an admin).
 async fn owned_link(state: &AppState, id: i64, account: &Account) -> Option<ShortLink> {
     let link: ShortLink = state
         .database()
-        .get("SELECT * FROM short_link WHERE id = ?1", [id])
+        .get("SELECT * FROM short_link WHERE id = ?1 AND deleted_at IS NULL", [id])
         .await
         .ok()
         .flatten()?;
@@ -371,37 +719,398 @@ async fn owned_link(state: &AppState, id: i64, account: &Account) -> Option<Shor
 // Resolution (redirect)
 // ---------------------------------------------------------------------------
 
-/// Resolves a code to its destination, counting the click, and returns a
-/// redirect — or a 404 when the code is unknown.
-async fn resolve_and_redirect(state: &AppState, code: &str) -> Response {
-    let link: Option<ShortLink> = state
+/// The link `code` names, unless it is unknown or in the trash. It may still
+/// be outside of its limits; see [`live_link`].
+async fn find_link(state: &AppState, code: &str) -> Option<ShortLink> {
+    state
         .database()
-        .get("SELECT * FROM short_link WHERE code = ?1", [code.to_string()])
+        .get(
+            "SELECT * FROM short_link WHERE code = ?1 AND deleted_at IS NULL",
+            [code.to_string()],
+        )
+        .await
+        .unwrap_or(None)
+}
+
+/// Where `link` stands at the moment, including whether its destination has
+/// been put on the blocklist since it was made. A blocked link is `Blocked`
+/// whatever its limits say.
+pub(crate) fn link_status(state: &AppState, link: &ShortLink) -> LinkStatus {
+    if state.link_blocklist().blocks(&link.target_url) {
+        return LinkStatus::Blocked;
+    }
+    link.status()
+}
+
+/// The link `code` resolves to, unless it is unknown, in the trash, or not
+/// active right now.
+pub(crate) async fn live_link(state: &AppState, code: &str) -> Option<ShortLink> {
+    find_link(state, code)
         .await
-        .unwrap_or(None);
-
-    match link {
-        Some(link) => {
-            // Best-effort click count; never block the redirect on it.
-            let _ = state
-                .database()
-                .execute("UPDATE short_link SET clicks = clicks + 1 WHERE id = ?1", [link.id])
-                .await;
-            Redirect::temporary(&link.target_url).into_response()
+        .filter(|link| link_status(state, link) == LinkStatus::Active)
+}
+
+#[derive(Template)]
+#[template(path = "links/unavailable.html")]
+struct UnavailableTemplate {
+    account: Option<Account>,
+    code: String,
+    status: LinkStatus,
+    active_from: Option<OffsetDateTime>,
+}
+
+/// The page a link outside of its limits answers with: `410 Gone` once it has
+/// expired or been used up, `404` while it is still to start, and `403` while
+/// its destination is blocked.
+fn unavailable(link: ShortLink, status: LinkStatus) -> Response {
+    let code = match status {
+        LinkStatus::Scheduled => StatusCode::NOT_FOUND,
+        LinkStatus::Blocked => StatusCode::FORBIDDEN,
+        _ => StatusCode::GONE,
+    };
+    let page = UnavailableTemplate {
+        account: None,
+        code: link.code,
+        status,
+        active_from: link.active_from,
+    };
+    (code, [(header::CACHE_CONTROL, "no-store")], page).into_response()
+}
+
+/// Counts a click on `link`, ending it on the last one its `max_clicks`
+/// allows. `false` if it had none left, which another request may have taken
+/// since the link was loaded.
+async fn take_click(state: &AppState, link: &ShortLink) -> bool {
+    let taken = state
+        .database()
+        .execute(
+            "UPDATE short_link SET clicks = clicks + 1, \
+                 ended_at = CASE WHEN max_clicks IS NOT NULL AND clicks + 1 >= max_clicks \
+                     THEN strftime('%Y-%m-%dT%H:%M:%fZ','now') ELSE ended_at END \
+             WHERE id = ?1 AND (max_clicks IS NULL OR clicks < max_clicks)",
+            [link.id],
+        )
+        .await;
+    match taken {
+        Ok(changed) => changed > 0,
+        // Best-effort click count; never block the redirect on it.
+        Err(e) => {
+            tracing::warn!(link = link.id, error = %e, "failed to count a short link click");
+            true
         }
-        None => not_found(),
     }
 }
 
+/// What resolving a link looks at in the request.
+struct Visit<'a> {
+    /// The path the link was reached at: `/<code>` on the short host, or
+    /// `/r/<code>`. Its gate page posts back here, and its unlock cookie is
+    /// scoped to it.
+    path: String,
+    headers: &'a HeaderMap,
+    client_ip: Option<IpAddr>,
+    cookies: &'a [Cookie<'static>],
+    secret: &'a SecretKey,
+}
+
+#[derive(Template)]
+#[template(path = "links/gate.html")]
+struct GateTemplate {
+    account: Option<Account>,
+    code: String,
+    path: String,
+    /// Asks for the password when set; otherwise shows the destination.
+    locked: bool,
+    target_url: String,
+    target_host: String,
+    error: Option<&'static str>,
+}
+
+/// The page a gated link answers a `GET` with: the password form while this
+/// browser hasn't unlocked it, and then (for an interstitial) the destination.
+fn gate_page(link: &ShortLink, visit: &Visit<'_>, locked: bool, error: Option<&'static str>) -> Response {
+    let page = GateTemplate {
+        account: None,
+        code: link.code.clone(),
+        path: visit.path.clone(),
+        locked,
+        // Where a locked link goes is part of what the password protects.
+        target_url: if locked { String::new() } else { link.target_url.clone() },
+        target_host: if locked {
+            String::new()
+        } else {
+            reqwest::Url::parse(&link.target_url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string))
+                .unwrap_or_default()
+        },
+        error,
+    };
+    let status = if error.is_some() {
+        StatusCode::UNAUTHORIZED
+    } else {
+        StatusCode::OK
+    };
+    (status, [(header::CACHE_CONTROL, "no-store")], page).into_response()
+}
+
+/// Loads the link `code` for a visit, or the response to give instead: a 404
+/// when it's unknown, and the unavailable page outside of its limits or when
+/// its destination is blocked.
+async fn visitable(state: &AppState, code: &str) -> Result<ShortLink, Response> {
+    let Some(link) = find_link(state, code).await else {
+        return Err(not_found());
+    };
+    match link_status(state, &link) {
+        LinkStatus::Active => Ok(link),
+        status => Err(unavailable(link, status)),
+    }
+}
+
+/// Counts the click and records where it came from. `false` when the link
+/// turned out to be used up.
+async fn follow(state: &AppState, link: &ShortLink, visit: &Visit<'_>) -> bool {
+    if !take_click(state, link).await {
+        return false;
+    }
+    stats::record(
+        state,
+        ViewKind::Link,
+        link.id.to_string(),
+        stats::referrer_host(state, visit.headers),
+    )
+    .await;
+    link_analytics::record(state, link.id, visit.headers, visit.client_ip).await;
+    true
+}
+
+/// Resolves a code to its destination, counting the click, and returns a
+/// redirect — or a 404 when the code is unknown or in the trash, a page saying
+/// so when the link is outside of its limits, and the gate page for a link
+/// with a password or an interstitial.
+async fn resolve_and_redirect(state: &AppState, code: &str, visit: &Visit<'_>) -> Response {
+    let link = match visitable(state, code).await {
+        Ok(link) => link,
+        Err(response) => return response,
+    };
+    if !link_lock::unlocked(&link, visit.cookies, visit.secret) {
+        return gate_page(&link, visit, true, None);
+    }
+    if link.interstitial {
+        return gate_page(&link, visit, false, None);
+    }
+    if !follow(state, &link, visit).await {
+        return unavailable(link, LinkStatus::UsedUp);
+    }
+    Redirect::temporary(&link.target_url).into_response()
+}
+
+/// The limit on a gate's `POST`s, which try passwords: 10 a minute per IP, as
+/// for logging in. It is one limit whether the link is reached at `/r/<code>`
+/// or on the short host, so using both doesn't double it.
+fn gate_limit() -> RateLimitLayer<IpKeyExtractor> {
+    static LIMIT: OnceLock<RateLimitLayer<IpKeyExtractor>> = OnceLock::new();
+    LIMIT
+        .get_or_init(|| RateLimit::default().quota(10, 60.0).build())
+        .clone()
+}
+
+#[derive(Deserialize, Default)]
+struct GateForm {
+    #[serde(default)]
+    password: String,
+}
+
+/// The gate page's `POST`: checks the password, if the link has one and this
+/// browser hasn't unlocked it, and then counts the click and redirects. A
+/// link that has an interstitial as well goes back to it once unlocked.
+async fn pass_gate(state: &AppState, code: &str, visit: &Visit<'_>, form: GateForm) -> Response {
+    let link = match visitable(state, code).await {
+        Ok(link) => link,
+        Err(response) => return response,
+    };
+
+    let mut grant = None;
+    if !link_lock::unlocked(&link, visit.cookies, visit.secret) {
+        if !link_lock::password_matches(&link, &form.password).await {
+            state
+                .audit("link.unlock.fail")
+                .target(link.code.clone())
+                .ip_opt(visit.client_ip)
+                .fire();
+            return gate_page(&link, visit, true, Some("Wrong password."));
+        }
+        let Some(token) = link_lock::sign_grant(visit.secret, link.id) else {
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        };
+        grant = Some(link_lock::build_grant_cookie(
+            link.id,
+            token,
+            &visit.path,
+            state.config().production,
+        ));
+    }
+
+    let mut response = if grant.is_some() && link.interstitial {
+        Redirect::to(&visit.path).into_response()
+    } else if follow(state, &link, visit).await {
+        Redirect::to(&link.target_url).into_response()
+    } else {
+        unavailable(link, LinkStatus::UsedUp)
+    };
+    if let Some(cookie) = grant {
+        set_cookie(&mut response, cookie);
+    }
+    response
+}
+
+#[derive(Template)]
+#[template(path = "links/preview.html")]
+struct PreviewTemplate {
+    account: Option<Account>,
+    code: String,
+    short_url: String,
+    /// Hides the destination, which is part of what a password protects.
+    locked: bool,
+    target_url: String,
+    target_host: String,
+    /// "followed 3 times", with the limit when there is one.
+    clicks: String,
+    /// Why the link doesn't redirect right now, if it doesn't.
+    note: Option<&'static str>,
+    redirects: bool,
+    unfurl: Option<UnfurlResult>,
+}
+
+/// The destination's preview metadata, fetched once an hour at most per
+/// target. `None` when the page couldn't be fetched or isn't HTML.
+async fn preview_of(state: &AppState, target: &str) -> Option<UnfurlResult> {
+    if let Some(preview) = state.link_preview(target) {
+        return preview.unfurl;
+    }
+    let unfurl = match unfurl_url(target).await {
+        Ok(mut unfurl) => {
+            // The page is ours to render; only let it pull in web images.
+            unfurl.image = unfurl
+                .image
+                .filter(|image| image.starts_with("https://") || image.starts_with("http://"));
+            Some(unfurl)
+        }
+        Err(e) => {
+            tracing::debug!(url = target, error = ?e, "could not unfurl a short link target");
+            None
+        }
+    };
+    state.store_link_preview(target, unfurl.clone());
+    unfurl
+}
+
+/// `<code>+`: shows where a link goes, what's there and how often it has been
+/// followed, without following it. Links with a password keep their target
+/// to themselves, and blocked targets aren't fetched.
+async fn preview_page(state: &AppState, code: &str) -> Response {
+    let Some(link) = find_link(state, code).await else {
+        return not_found();
+    };
+    let status = link_status(state, &link);
+    let locked = link.is_locked();
+    let unfurl = if locked || status == LinkStatus::Blocked {
+        None
+    } else {
+        preview_of(state, &link.target_url).await
+    };
+
+    let plural = if link.clicks == 1 { "" } else { "s" };
+    let clicks = match link.max_clicks {
+        Some(max) => format!("followed {} time{plural} of {max}", link.clicks),
+        None => format!("followed {} time{plural}", link.clicks),
+    };
+    let page = PreviewTemplate {
+        account: None,
+        short_url: state.config().short_link_url(&link.code),
+        locked,
+        target_url: if locked { String::new() } else { link.target_url.clone() },
+        target_host: if locked {
+            String::new()
+        } else {
+            reqwest::Url::parse(&link.target_url)
+                .ok()
+                .and_then(|url| url.host_str().map(str::to_string))
+                .unwrap_or_default()
+        },
+        clicks,
+        note: match status {
+            LinkStatus::Active => None,
+            LinkStatus::Scheduled => Some("note: it isn't active yet"),
+            LinkStatus::Expired => Some("note: it has expired"),
+            LinkStatus::UsedUp => Some("note: it has been followed as often as it may be"),
+            LinkStatus::Blocked => {
+                Some("warning: its destination is on this site's blocklist, so it no longer redirects")
+            }
+        },
+        redirects: status == LinkStatus::Active,
+        unfurl,
+        code: link.code,
+    };
+    ([(header::CACHE_CONTROL, "no-store")], page).into_response()
+}
+
 /// `GET /r/:code` — path-based resolution that works on any host (used in dev,
 /// and as a fallback before the `r.` subdomain is wired up).
-async fn resolve_path(State(state): State<AppState>, Path(code): Path<String>) -> Response {
-    resolve_and_redirect(&state, &code).await
+async fn resolve_path(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    Extension(cookies): Extension<Vec<Cookie<'static>>>,
+    Extension(secret): Extension<SecretKey>,
+    Path(code): Path<String>,
+    headers: HeaderMap,
+) -> Response {
+    if let Some(code) = code.strip_suffix('+') {
+        return preview_page(&state, code).await;
+    }
+    let visit = Visit {
+        path: format!("/r/{code}"),
+        headers: &headers,
+        client_ip,
+        cookies: &cookies,
+        secret: &secret,
+    };
+    resolve_and_redirect(&state, &code, &visit).await
+}
+
+/// `POST /r/:code` — the gate page's form.
+async fn pass_gate_path(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    Extension(cookies): Extension<Vec<Cookie<'static>>>,
+    Extension(secret): Extension<SecretKey>,
+    Path(code): Path<String>,
+    headers: HeaderMap,
+    Form(form): Form<GateForm>,
+) -> Response {
+    let visit = Visit {
+        path: format!("/r/{code}"),
+        headers: &headers,
+        client_ip,
+        cookies: &cookies,
+        secret: &secret,
+    };
+    pass_gate(&state, &code, &visit, form).await
 }
 
-/// Router fallback: resolves bare `r.<domain>/<code>` requests, and 404s
-/// everything else (preserving the previous default-404 behaviour).
-pub async fn short_link_fallback(State(state): State<AppState>, headers: HeaderMap, uri: Uri) -> Response {
+/// Router fallback: resolves bare `r.<domain>/<code>` requests (and takes
+/// their gate page's `POST`), and 404s everything else (preserving the
+/// previous default-404 behaviour).
+pub async fn short_link_fallback(
+    State(state): State<AppState>,
+    ClientIp(client_ip): ClientIp,
+    Extension(cookies): Extension<Vec<Cookie<'static>>>,
+    Extension(secret): Extension<SecretKey>,
+    method: Method,
+    headers: HeaderMap,
+    uri: Uri,
+    request: Request,
+) -> Response {
     let config = state.config();
     // Bare-path resolution is only meaningful in production on the real short
     // host. In dev every request is to localhost, so resolution goes through
@@ -415,7 +1124,32 @@ pub async fn short_link_fallback(State(state): State<AppState>, headers: HeaderM
         if host_eq(host, &config.short_domain()) {
             let code = uri.path().trim_matches('/');
             if !code.is_empty() && !code.contains('/') {
-                return resolve_and_redirect(&state, code).await;
+                let visit = Visit {
+                    path: format!("/{code}"),
+                    headers: &headers,
+                    client_ip,
+                    cookies: &cookies,
+                    secret: &secret,
+                };
+                return match method {
+                    Method::GET | Method::HEAD => match code.strip_suffix('+') {
+                        Some(code) => preview_page(&state, code).await,
+                        None => resolve_and_redirect(&state, code, &visit).await,
+                    },
+                    Method::POST => {
+                        if let Err(limited) = gate_limit().check(&request) {
+                            return limited;
+                        }
+                        // Extracted here rather than in the signature, where a
+                        // `Form` would turn away requests this should 404.
+                        let form = Form::<GateForm>::from_request(request, &state)
+                            .await
+                            .map(|Form(form)| form)
+                            .unwrap_or_default();
+                        pass_gate(&state, code, &visit, form).await
+                    }
+                    _ => StatusCode::METHOD_NOT_ALLOWED.into_response(),
+                };
             }
         }
     }
@@ -423,7 +1157,7 @@ pub async fn short_link_fallback(State(state): State<AppState>, headers: HeaderM
 }
 
 /// Compares two `host` strings ignoring any `:port` suffix and ASCII case.
-fn host_eq(a: &str, b: &str) -> bool {
+pub(crate) fn host_eq(a: &str, b: &str) -> bool {
     let strip = |s: &str| s.split(':').next().unwrap_or(s).to_ascii_lowercase();
     strip(a) == strip(b)
 }
@@ -432,10 +1166,249 @@ fn not_found() -> Response {
     StatusCode::NOT_FOUND.into_response()
 }
 
+/// Starts the hourly short-link reaper: it re-reads the destination blocklist,
+/// ends links whose expiry has passed, deletes links that have been ended for
+/// longer than the trash keeps things, and empties trashed links whose
+/// restore window is over.
+pub fn spawn_link_reaper(state: AppState) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(std::time::Duration::from_secs(3600));
+        loop {
+            ticker.tick().await;
+            state.reload_link_blocklist();
+            match reap_links(&state).await {
+                Ok((0, 0)) => {}
+                Ok((ended, deleted)) => tracing::info!(ended, deleted, "reaped dead short links"),
+                Err(e) => tracing::warn!(error = %e, "failed to reap dead short links"),
+            }
+            match trash::reap(&state, TrashKind::Link).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!(count, "purged short links from the trash"),
+                Err(e) => tracing::warn!(error = %e, "failed to purge short links from the trash"),
+            }
+        }
+    });
+}
+
+/// Ends the links that have run out of time and deletes the ones that have
+/// been ended for the trash's retention window. Returns how many of each.
+async fn reap_links(state: &AppState) -> anyhow::Result<(usize, usize)> {
+    let end = format!(
+        "UPDATE short_link SET ended_at = strftime('%Y-%m-%dT%H:%M:%fZ','now') \
+         WHERE ended_at IS NULL AND {ENDED}"
+    );
+    let cutoff = format!("-{} days", trash::retention_days(state));
+    let reaped = state
+        .database()
+        .call(move |conn| -> rusqlite::Result<(usize, usize)> {
+            let ended = conn.execute(&end, [])?;
+            let deleted = conn.execute(
+                "DELETE FROM short_link WHERE ended_at IS NOT NULL \
+                 AND datetime(ended_at) <= datetime('now', ?1)",
+                [cutoff],
+            )?;
+            Ok((ended, deleted))
+        })
+        .await?;
+    Ok(reaped)
+}
+
 pub fn routes() -> Router<AppState> {
     Router::new()
         .route("/links", get(links_page).post(create_link))
         .route("/links/:id/edit", post(edit_link))
         .route("/links/:id/delete", post(delete_link))
+        .route("/links/:id/stats.svg", get(link_stats_chart))
+        .route("/links/:id/analytics", get(link_analytics_page))
         .route("/r/:code", get(resolve_path))
+        .route("/r/:code", post(pass_gate_path).layer(gate_limit()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    async fn state() -> AppState {
+        let db = crate::Database::file(":memory:")
+            .connections(1)
+            .with_init(crate::migrations::migrate)
+            .open()
+            .await
+            .unwrap();
+        db.execute("INSERT INTO account (id, name, password) VALUES (1, 'owner', 'x')", [])
+            .await
+            .unwrap();
+        AppState::for_tests(db).await
+    }
+
+    #[test]
+    fn form_times_are_read_in_the_browsers_zone() {
+        // UTC+2 reports an offset of -120.
+        assert_eq!(
+            form_time("2026-01-31T18:00", -120).unwrap(),
+            Some(datetime!(2026-01-31 16:00 UTC))
+        );
+        assert_eq!(form_time(" ", 0).unwrap(), None);
+        assert!(form_time("tomorrow", 0).is_err());
+    }
+
+    #[test]
+    fn blocked_destinations_are_refused() {
+        let blocklist = Blocklist::parse("evil.example\n");
+        assert_eq!(
+            normalize_target("login.evil.example/account", &blocklist),
+            Err("That destination is blocked.")
+        );
+        assert_eq!(
+            normalize_target("example.com", &blocklist).as_deref(),
+            Ok("https://example.com")
+        );
+    }
+
+    #[test]
+    fn limits_must_make_sense() {
+        assert_eq!(LinkLimits::clicks(Some(0)), Ok(Some(None)));
+        assert_eq!(LinkLimits::clicks(Some(5)), Ok(Some(Some(5))));
+        assert!(LinkLimits::clicks(Some(-1)).is_err());
+        assert_eq!(LinkLimits::time(Some("")), Ok(Some(None)));
+        assert!(LinkLimits::time(Some("2026-01-31")).is_err());
+
+        let past = LinkLimits {
+            expires_at: Some(Some(datetime!(2020-01-01 0:00 UTC))),
+            ..LinkLimits::default()
+        };
+        assert_eq!(past.validate(None).unwrap_err().0, "expires_at");
+
+        let backwards = LinkLimits {
+            active_from: Some(Some(datetime!(2099-02-01 0:00 UTC))),
+            expires_at: Some(Some(datetime!(2099-01-01 0:00 UTC))),
+            ..LinkLimits::default()
+        };
+        assert_eq!(backwards.validate(None).unwrap_err().0, "expires_at");
+    }
+
+    #[tokio::test]
+    async fn the_last_click_ends_a_limited_link() {
+        let state = state().await;
+        let limits = LinkLimits {
+            max_clicks: Some(Some(2)),
+            ..LinkLimits::default()
+        };
+        insert_link(
+            &state,
+            "twice".into(),
+            "https://example.com",
+            1,
+            true,
+            &limits,
+            &LinkGate::default(),
+        )
+        .await
+        .ok()
+        .unwrap();
+
+        let link = find_link(&state, "twice").await.unwrap();
+        assert!(take_click(&state, &link).await);
+        assert!(take_click(&state, &link).await);
+        // A request that loaded the link before the last click doesn't get a third.
+        assert!(!take_click(&state, &link).await);
+
+        let link = find_link(&state, "twice").await.unwrap();
+        assert_eq!(link.clicks, 2);
+        assert_eq!(link.status(), LinkStatus::UsedUp);
+        assert!(link.ended_at.is_some());
+        assert!(live_link(&state, "twice").await.is_none());
+
+        // Raising the limit revives it.
+        let raised = LinkLimits {
+            max_clicks: Some(Some(3)),
+            ..LinkLimits::default()
+        };
+        update_link(&state, link.id, None, None, &raised, &LinkGate::default())
+            .await
+            .ok()
+            .unwrap();
+        let link = live_link(&state, "twice").await.unwrap();
+        assert_eq!(link.ended_at, None);
+    }
+
+    #[tokio::test]
+    async fn a_locked_link_redirects_only_after_the_password() {
+        let state = state().await;
+        let gate = LinkGate::new(Some("hunter2"), None).unwrap();
+        insert_link(
+            &state,
+            "docs".into(),
+            "https://example.com",
+            1,
+            true,
+            &LinkLimits::default(),
+            &gate,
+        )
+        .await
+        .ok()
+        .unwrap();
+
+        let secret = SecretKey::random().unwrap();
+        let headers = HeaderMap::new();
+        let visit = Visit {
+            path: "/r/docs".into(),
+            headers: &headers,
+            client_ip: None,
+            cookies: &[],
+            secret: &secret,
+        };
+        let password = |p: &str| GateForm { password: p.into() };
+
+        // Asked for the password, without counting a click.
+        assert_eq!(
+            resolve_and_redirect(&state, "docs", &visit).await.status(),
+            StatusCode::OK
+        );
+        let wrong = pass_gate(&state, "docs", &visit, password("hunter3")).await;
+        assert_eq!(wrong.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(find_link(&state, "docs").await.unwrap().clicks, 0);
+
+        let right = pass_gate(&state, "docs", &visit, password("hunter2")).await;
+        assert_eq!(right.status(), StatusCode::SEE_OTHER);
+        assert!(right.headers().contains_key(header::SET_COOKIE));
+        assert_eq!(find_link(&state, "docs").await.unwrap().clicks, 1);
+
+        // The cookie lets the next visit straight through.
+        let link = find_link(&state, "docs").await.unwrap();
+        let cookies = [link_lock::build_grant_cookie(
+            link.id,
+            link_lock::sign_grant(&secret, link.id).unwrap(),
+            "/r/docs",
+            false,
+        )];
+        let visit = Visit {
+            cookies: &cookies,
+            ..visit
+        };
+        let response = resolve_and_redirect(&state, "docs", &visit).await;
+        assert_eq!(response.status(), StatusCode::TEMPORARY_REDIRECT);
+    }
+
+    #[tokio::test]
+    async fn the_reaper_ends_expired_links_and_deletes_long_dead_ones() {
+        let state = state().await;
+        for sql in [
+            "INSERT INTO short_link (code, target_url, account_id, expires_at) \
+             VALUES ('gone', 'https://example.com', 1, '2020-01-01T00:00:00Z')",
+            "INSERT INTO short_link (code, target_url, account_id, expires_at, ended_at) \
+             VALUES ('long-gone', 'https://example.com', 1, '2020-01-01T00:00:00Z', '2020-01-01T00:00:00Z')",
+            "INSERT INTO short_link (code, target_url, account_id, expires_at) \
+             VALUES ('later', 'https://example.com', 1, '2099-01-01T00:00:00Z')",
+        ] {
+            state.database().execute(sql, []).await.unwrap();
+        }
+
+        assert_eq!(reap_links(&state).await.unwrap(), (1, 1));
+        assert_eq!(find_link(&state, "gone").await.unwrap().status(), LinkStatus::Expired);
+        assert!(find_link(&state, "long-gone").await.is_none());
+        assert!(live_link(&state, "later").await.is_some());
+        assert_eq!(reap_links(&state).await.unwrap(), (0, 0));
+    }
 }
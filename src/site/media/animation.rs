@@ -0,0 +1,377 @@
+//! Frame-by-frame processing of animated GIF and WebP images.
+//!
+//! `image`'s plain `decode()` returns only the first frame, which is how
+//! effects and thumbnails used to flatten animations into stills. [`decode`]
+//! reads every frame instead — composited onto the full canvas, with its
+//! delay — so an operation can be applied to each in turn with
+//! [`Animation::map`] and the result written back out with
+//! [`Animation::encode`].
+//!
+//! A small file can expand into hundreds of full-size RGBA frames, so every
+//! decode runs against a [`Budget`]: the canvas is checked before anything is
+//! decoded, and decoding stops at the first frame past the frame or pixel
+//! limit.
+//!
+//! `image` can write animated GIFs but not animated WebPs, so the WebP muxing
+//! is done here: each frame is encoded as a lossless still and its bitstream
+//! wrapped in an `ANMF` chunk.
+
+use std::io::Cursor;
+
+use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+use image::codecs::webp::{WebPDecoder, WebPEncoder};
+use image::{AnimationDecoder, DynamicImage, ExtendedColorType, Frame, ImageDecoder, ImageError, ImageFormat};
+
+/// Encoder speed for GIF output (1–30). Lower spends longer on the palette.
+const GIF_SPEED: i32 = 10;
+
+/// The animated formats we can read and write.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimatedFormat {
+    Gif,
+    WebP,
+}
+
+impl AnimatedFormat {
+    /// The format for a file extension or `to=` value, if it can animate.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "gif" => Some(Self::Gif),
+            "webp" => Some(Self::WebP),
+            _ => None,
+        }
+    }
+
+    pub fn mime(self) -> &'static str {
+        match self {
+            Self::Gif => "image/gif",
+            Self::WebP => "image/webp",
+        }
+    }
+
+    pub fn ext(self) -> &'static str {
+        match self {
+            Self::Gif => "gif",
+            Self::WebP => "webp",
+        }
+    }
+}
+
+/// Limits on how much an animation may expand to when decoded.
+#[derive(Debug, Clone, Copy)]
+pub struct Budget {
+    /// The most frames decoded.
+    pub max_frames: usize,
+    /// The most pixels across all decoded frames. At four bytes a pixel, the
+    /// default holds decoding to about 128 MiB.
+    pub max_pixels: u64,
+}
+
+impl Default for Budget {
+    fn default() -> Self {
+        Self {
+            max_frames: 500,
+            max_pixels: 32_000_000,
+        }
+    }
+}
+
+/// Why an animation couldn't be processed.
+#[derive(Debug)]
+pub enum AnimationError {
+    /// More frames than the [`Budget`] allows.
+    TooManyFrames(usize),
+    /// More pixels, across all frames, than the [`Budget`] allows.
+    TooLarge(u64),
+    /// The decoder or encoder failed.
+    Image(ImageError),
+}
+
+impl std::fmt::Display for AnimationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::TooManyFrames(limit) => write!(f, "animation has more than {limit} frames"),
+            Self::TooLarge(limit) => write!(f, "animation is larger than {limit} pixels across all frames"),
+            Self::Image(e) => write!(f, "could not process animation: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for AnimationError {}
+
+impl From<ImageError> for AnimationError {
+    fn from(e: ImageError) -> Self {
+        Self::Image(e)
+    }
+}
+
+/// The frames of an animated image, each a full canvas.
+pub struct Animation {
+    /// The format it was decoded from.
+    pub format: AnimatedFormat,
+    frames: Vec<Frame>,
+}
+
+/// Decodes every frame of an animated GIF or WebP. `Ok(None)` for anything
+/// else, including a GIF or WebP with a single frame: the still-image path
+/// handles those.
+pub fn decode(bytes: &[u8], budget: Budget) -> Result<Option<Animation>, AnimationError> {
+    let (format, frames) = match image::guess_format(bytes) {
+        Ok(ImageFormat::Gif) => {
+            let decoder = GifDecoder::new(Cursor::new(bytes))?;
+            budget.check_canvas(decoder.dimensions())?;
+            (AnimatedFormat::Gif, collect(decoder.into_frames(), budget)?)
+        }
+        Ok(ImageFormat::WebP) => {
+            // Only the extended format can animate; its VP8X header says so.
+            if bytes.get(12..16) != Some(&b"VP8X"[..]) || !bytes.get(20).is_some_and(|f| f & VP8X_ANIMATION != 0) {
+                return Ok(None);
+            }
+            let decoder = WebPDecoder::new(Cursor::new(bytes))?;
+            budget.check_canvas(decoder.dimensions())?;
+            (AnimatedFormat::WebP, collect(decoder.into_frames(), budget)?)
+        }
+        _ => return Ok(None),
+    };
+    if frames.len() < 2 {
+        return Ok(None);
+    }
+    Ok(Some(Animation { format, frames }))
+}
+
+impl Budget {
+    /// Refuses a canvas that couldn't hold even one frame within the budget.
+    fn check_canvas(&self, (width, height): (u32, u32)) -> Result<(), AnimationError> {
+        if width as u64 * height as u64 > self.max_pixels {
+            return Err(AnimationError::TooLarge(self.max_pixels));
+        }
+        Ok(())
+    }
+}
+
+/// Pulls frames until the decoder runs out or the budget does. At most one
+/// frame (already bounded by the canvas check) is decoded past the budget.
+fn collect(frames: image::Frames<'_>, budget: Budget) -> Result<Vec<Frame>, AnimationError> {
+    let mut out = Vec::new();
+    let mut pixels = 0u64;
+    for frame in frames {
+        let frame = frame?;
+        if out.len() == budget.max_frames {
+            return Err(AnimationError::TooManyFrames(budget.max_frames));
+        }
+        let (width, height) = frame.buffer().dimensions();
+        pixels += width as u64 * height as u64;
+        if pixels > budget.max_pixels {
+            return Err(AnimationError::TooLarge(budget.max_pixels));
+        }
+        out.push(frame);
+    }
+    Ok(out)
+}
+
+impl Animation {
+    pub fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// The canvas size.
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.frames[0].buffer().dimensions()
+    }
+
+    /// Applies `op` to every frame, keeping each frame's delay. `op` should
+    /// give every frame the same size.
+    pub fn map<E>(self, mut op: impl FnMut(DynamicImage) -> Result<DynamicImage, E>) -> Result<Self, E> {
+        let frames = self
+            .frames
+            .into_iter()
+            .map(|frame| {
+                let delay = frame.delay();
+                let img = op(DynamicImage::ImageRgba8(frame.into_buffer()))?;
+                Ok(Frame::from_parts(img.to_rgba8(), 0, 0, delay))
+            })
+            .collect::<Result<_, E>>()?;
+        Ok(Self {
+            format: self.format,
+            frames,
+        })
+    }
+
+    /// Encodes the frames as an endlessly looping animation in `format`.
+    pub fn encode(self, format: AnimatedFormat) -> Result<Vec<u8>, AnimationError> {
+        match format {
+            AnimatedFormat::Gif => encode_gif(self.frames),
+            AnimatedFormat::WebP => encode_webp(self.frames),
+        }
+    }
+}
+
+fn encode_gif(frames: Vec<Frame>) -> Result<Vec<u8>, AnimationError> {
+    let mut out = Vec::new();
+    {
+        let mut encoder = GifEncoder::new_with_speed(&mut out, GIF_SPEED);
+        encoder.set_repeat(Repeat::Infinite)?;
+        encoder.encode_frames(frames)?;
+    }
+    Ok(out)
+}
+
+const VP8X_ALPHA: u8 = 0x10;
+const VP8X_ANIMATION: u8 = 0x02;
+/// `ANMF` flag: draw the frame over the canvas without blending. Our frames
+/// are whole canvases, so there is nothing underneath worth keeping.
+const ANMF_NO_BLEND: u8 = 0x02;
+
+/// Builds an extended-format WebP: a `VP8X` header, an `ANIM` chunk, and one
+/// `ANMF` chunk per frame holding a lossless still's bitstream.
+fn encode_webp(frames: Vec<Frame>) -> Result<Vec<u8>, AnimationError> {
+    let (width, height) = frames.first().map_or((1, 1), |f| f.buffer().dimensions());
+    let mut body = b"WEBP".to_vec();
+
+    let mut vp8x = vec![VP8X_ANIMATION | VP8X_ALPHA, 0, 0, 0];
+    vp8x.extend_from_slice(&u24(width - 1));
+    vp8x.extend_from_slice(&u24(height - 1));
+    push_chunk(&mut body, b"VP8X", &vp8x);
+    // A transparent background colour (BGRA), then the loop count: 0 is forever.
+    push_chunk(&mut body, b"ANIM", &[0, 0, 0, 0, 0, 0]);
+
+    for frame in frames {
+        let (numer, denom) = frame.delay().numer_denom_ms();
+        let delay_ms = numer / denom.max(1);
+        let buffer = frame.into_buffer();
+        let (w, h) = buffer.dimensions();
+
+        let mut still = Vec::new();
+        WebPEncoder::new_lossless(&mut still).encode(buffer.as_raw(), w, h, ExtendedColorType::Rgba8)?;
+
+        let mut anmf = Vec::with_capacity(still.len() + 16);
+        anmf.extend_from_slice(&u24(0)); // x offset / 2
+        anmf.extend_from_slice(&u24(0)); // y offset / 2
+        anmf.extend_from_slice(&u24(w - 1));
+        anmf.extend_from_slice(&u24(h - 1));
+        anmf.extend_from_slice(&u24(delay_ms.min(0xFF_FFFF)));
+        anmf.push(ANMF_NO_BLEND);
+        anmf.extend_from_slice(frame_bitstream(&still));
+        push_chunk(&mut body, b"ANMF", &anmf);
+    }
+
+    let mut out = b"RIFF".to_vec();
+    out.extend_from_slice(&(body.len() as u32).to_le_bytes());
+    out.extend_from_slice(&body);
+    Ok(out)
+}
+
+/// The image chunks (`ALPH`, `VP8 `, `VP8L`) of a still WebP, which is what
+/// an `ANMF` chunk carries after its header.
+fn frame_bitstream(still: &[u8]) -> &[u8] {
+    let mut i = 12;
+    let mut start = None;
+    while i + 8 <= still.len() {
+        let kind = &still[i..i + 4];
+        let len = u32::from_le_bytes([still[i + 4], still[i + 5], still[i + 6], still[i + 7]]) as usize;
+        let end = (i + 8 + len + (len & 1)).min(still.len());
+        match kind {
+            b"ALPH" => start = start.or(Some(i)),
+            b"VP8 " | b"VP8L" => return &still[start.unwrap_or(i)..end],
+            _ => {}
+        }
+        i = end;
+    }
+    &still[still.len().min(12)..]
+}
+
+fn push_chunk(out: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    out.extend_from_slice(kind);
+    out.extend_from_slice(&(data.len() as u32).to_le_bytes());
+    out.extend_from_slice(data);
+    if data.len() % 2 == 1 {
+        out.push(0);
+    }
+}
+
+/// A little-endian 24-bit field.
+fn u24(value: u32) -> [u8; 3] {
+    let [a, b, c, _] = value.to_le_bytes();
+    [a, b, c]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Delay, Rgba, RgbaImage};
+
+    fn frames(count: u8) -> Vec<Frame> {
+        (0..count)
+            .map(|n| {
+                let img = RgbaImage::from_pixel(8, 6, Rgba([n * 40, 0, 255 - n * 40, 255]));
+                Frame::from_parts(img, 0, 0, Delay::from_numer_denom_ms(100, 1))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn frames_survive_a_round_trip() {
+        for format in [AnimatedFormat::Gif, AnimatedFormat::WebP] {
+            let bytes = Animation {
+                format,
+                frames: frames(3),
+            }
+            .encode(format)
+            .unwrap();
+            let anim = decode(&bytes, Budget::default()).unwrap().expect("animated");
+            assert_eq!(anim.format, format);
+            assert_eq!(anim.frame_count(), 3);
+            assert_eq!(anim.dimensions(), (8, 6));
+
+            let halved = anim
+                .map(|img| Ok::<_, ()>(img.thumbnail(4, 3)))
+                .unwrap()
+                .encode(format)
+                .unwrap();
+            let anim = decode(&halved, Budget::default()).unwrap().expect("still animated");
+            assert_eq!((anim.frame_count(), anim.dimensions()), (3, (4, 3)));
+            assert_eq!(anim.frames[1].delay().numer_denom_ms().0, 100);
+        }
+    }
+
+    #[test]
+    fn budgets_are_enforced() {
+        let gif = Animation {
+            format: AnimatedFormat::Gif,
+            frames: frames(4),
+        }
+        .encode(AnimatedFormat::Gif)
+        .unwrap();
+
+        let few_frames = Budget {
+            max_frames: 3,
+            ..Budget::default()
+        };
+        assert!(matches!(
+            decode(&gif, few_frames),
+            Err(AnimationError::TooManyFrames(3))
+        ));
+        let few_pixels = Budget {
+            max_pixels: 8 * 6 * 3,
+            ..Budget::default()
+        };
+        assert!(matches!(decode(&gif, few_pixels), Err(AnimationError::TooLarge(_))));
+        let tiny_canvas = Budget {
+            max_pixels: 10,
+            ..Budget::default()
+        };
+        assert!(matches!(decode(&gif, tiny_canvas), Err(AnimationError::TooLarge(10))));
+    }
+
+    #[test]
+    fn stills_are_not_animations() {
+        let gif = Animation {
+            format: AnimatedFormat::Gif,
+            frames: frames(1),
+        }
+        .encode(AnimatedFormat::Gif)
+        .unwrap();
+        assert!(decode(&gif, Budget::default()).unwrap().is_none());
+        assert!(decode(b"not an image", Budget::default()).unwrap().is_none());
+    }
+}
@@ -1,49 +1,684 @@
-//! Strips privacy-sensitive metadata (EXIF, XMP, text chunks) from uploaded
-//! images.
+//! Reads and strips image metadata (EXIF, XMP, ICC profiles, text chunks).
 //!
-//! This works at the container level — removing metadata segments/chunks
+//! Stripping works at the container level — removing metadata segments/chunks
 //! without touching the pixel data — rather than decoding and re-encoding.
 //! That keeps the image byte-for-byte identical apart from the removed
 //! metadata, preserves quality (no JPEG recompression), and crucially keeps
-//! animation intact for APNG (re-encoding through the `image` crate would
-//! flatten it to a single frame).
+//! animation intact for APNG, animated WebP and GIF (re-encoding through the
+//! `image` crate would flatten them to a single frame).
+//!
+//! The one exception is a JPEG whose EXIF orientation says it is stored
+//! sideways: [`auto_orient`] decodes, turns and re-encodes it before the tag
+//! is stripped, since dropping the tag alone would leave the photo lying on
+//! its side.
+//!
+//! [`inspect`] is the read side, parsing the same blocks into a
+//! [`MetadataReport`] for `POST /api/v1/metadata/exif`.
 //!
 //! Anything we don't understand is returned unchanged — stripping is a
 //! best-effort privacy measure, never a correctness requirement.
 
-/// Removes metadata from `data` based on the file extension. Unknown or
-/// malformed inputs are returned unchanged.
-pub fn strip(ext: &str, data: &[u8]) -> Vec<u8> {
+use std::ops::Range;
+
+use image::codecs::jpeg::JpegEncoder;
+use image::ImageFormat;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Which metadata survives [`strip`].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Keep {
+    /// Remove everything, the colour profile and orientation included.
+    #[default]
+    Nothing,
+    /// Keep the ICC colour profile and the EXIF orientation; drop everything
+    /// else (GPS, camera, timestamps, comments).
+    Color,
+}
+
+/// Removes metadata from `data` based on the file extension, keeping what
+/// `keep` asks for. Unknown or malformed inputs are returned unchanged.
+pub fn strip(ext: &str, data: &[u8], keep: Keep) -> Vec<u8> {
     let stripped = match ext.to_ascii_lowercase().as_str() {
-        "jpg" | "jpeg" => strip_jpeg(data),
-        "png" | "apng" => strip_png(data),
-        // GIF/AVIF rarely carry GPS EXIF and need format-specific handling we
-        // don't do here; leave them untouched.
+        "jpg" | "jpeg" => strip_jpeg(data, keep),
+        "png" | "apng" => strip_png(data, keep),
+        "webp" => strip_webp(data, keep),
+        "gif" => strip_gif(data, keep),
+        "tif" | "tiff" => strip_tiff(data, keep),
+        // AVIF (ISOBMFF) would need a box rewriter; leave it untouched.
         _ => None,
     };
     stripped.unwrap_or_else(|| data.to_vec())
 }
 
-/// Drops EXIF (APP1), XMP (APP1), IPTC/Photoshop (APP13), and comment (COM)
-/// segments from a JPEG, preserving JFIF (APP0), ICC (APP2), Adobe (APP14),
-/// and the compressed scan data. Returns `None` on anything unexpected.
-fn strip_jpeg(data: &[u8]) -> Option<Vec<u8>> {
+/// Turns a JPEG upright according to its EXIF orientation by decoding,
+/// rotating and re-encoding it. The ICC profile is carried over; the EXIF
+/// block is not, so the orientation can't be applied twice.
+///
+/// `None` when there is nothing to do: not a JPEG, already upright, or not
+/// decodable.
+pub fn auto_orient(ext: &str, data: &[u8]) -> Option<Vec<u8>> {
+    if !matches!(ext.to_ascii_lowercase().as_str(), "jpg" | "jpeg") {
+        return None;
+    }
+    let (segments, _) = jpeg_segments(data)?;
+    let orientation = segments.iter().find_map(|(marker, range)| {
+        let payload = &data[range.start + 4..range.end];
+        (*marker == 0xE1)
+            .then(|| payload.strip_prefix(EXIF_HEADER))
+            .flatten()
+            .and_then(exif_orientation)
+    })?;
+
+    let img = image::load_from_memory_with_format(data, ImageFormat::Jpeg).ok()?;
+    let img = match orientation {
+        2 => img.fliph(),
+        3 => img.rotate180(),
+        4 => img.flipv(),
+        5 => img.rotate90().fliph(),
+        6 => img.rotate90(),
+        7 => img.rotate270().fliph(),
+        8 => img.rotate270(),
+        _ => return None,
+    };
+    let mut out = Vec::new();
+    JpegEncoder::new_with_quality(&mut out, REORIENT_QUALITY)
+        .encode_image(&img.to_rgb8())
+        .ok()?;
+
+    // The encoder writes SOI and a JFIF APP0; the profile goes after them.
+    let icc: Vec<u8> = segments
+        .iter()
+        .filter(|(marker, range)| *marker == 0xE2 && data[range.start + 4..range.end].starts_with(ICC_HEADER))
+        .flat_map(|(_, range)| data[range.clone()].iter().copied())
+        .collect();
+    if !icc.is_empty() {
+        let at = jpeg_segments(&out)
+            .and_then(|(segments, _)| {
+                segments
+                    .first()
+                    .filter(|(marker, _)| *marker == 0xE0)
+                    .map(|(_, r)| r.end)
+            })
+            .unwrap_or(2);
+        out.splice(at..at, icc);
+    }
+    Some(out)
+}
+
+// ─── Reports ───────────────────────────────────────────────────────────────────
+
+/// Everything [`inspect`] found in an image.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct MetadataReport {
+    /// The container format: `jpeg`, `png`, `webp`, `gif` or `tiff`.
+    pub format: String,
+    /// Parsed EXIF fields, if the image has an EXIF block.
+    pub exif: Option<ExifData>,
+    /// The raw XMP packet, if any (cut off after 64 KiB).
+    pub xmp: Option<String>,
+    /// The embedded ICC colour profile, if any.
+    pub icc: Option<IccProfile>,
+    /// Text chunks and comments, in file order.
+    pub text: Vec<TextEntry>,
+}
+
+/// The EXIF fields worth showing. Absent tags are `null`.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct ExifData {
+    pub make: Option<String>,
+    pub model: Option<String>,
+    pub lens_model: Option<String>,
+    /// The camera body's serial number.
+    pub serial_number: Option<String>,
+    pub software: Option<String>,
+    pub artist: Option<String>,
+    pub copyright: Option<String>,
+    /// When the photo was taken (`DateTimeOriginal`), as written by the camera:
+    /// `YYYY:MM:DD HH:MM:SS`, local time.
+    pub taken_at: Option<String>,
+    /// When the file was last changed (`DateTime`), in the same form.
+    pub modified_at: Option<String>,
+    /// The EXIF orientation, 1 (upright) to 8.
+    pub orientation: Option<u16>,
+    /// Exposure time in seconds.
+    pub exposure_time: Option<f64>,
+    /// The aperture's f-number.
+    pub f_number: Option<f64>,
+    pub iso: Option<u32>,
+    /// Focal length in millimetres.
+    pub focal_length: Option<f64>,
+    /// Where the photo was taken.
+    pub gps: Option<GpsPosition>,
+}
+
+/// A GPS position in decimal degrees.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct GpsPosition {
+    /// Positive north, negative south.
+    pub latitude: f64,
+    /// Positive east, negative west.
+    pub longitude: f64,
+    /// Metres above sea level (negative below), if recorded.
+    pub altitude: Option<f64>,
+}
+
+/// An embedded colour profile.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct IccProfile {
+    /// The profile's size in bytes as stored (compressed, in a PNG).
+    pub size: usize,
+    /// The profile's description, e.g. `Display P3`. For PNG, the name the
+    /// file gives it.
+    pub description: Option<String>,
+}
+
+/// One text chunk or comment.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct TextEntry {
+    /// The chunk's keyword (PNG), or `Comment` for JPEG and GIF comments.
+    pub key: String,
+    /// The text, or `null` when it is compressed (PNG `zTXt`, compressed
+    /// `iTXt`). Cut off after 4 KiB.
+    pub value: Option<String>,
+}
+
+impl MetadataReport {
+    fn push_text(&mut self, key: &str, value: Option<&[u8]>) {
+        if self.text.len() < MAX_TEXT_ENTRIES {
+            self.text.push(TextEntry {
+                key: key.to_string(),
+                value: value.map(|v| lossy(v, MAX_TEXT_LEN)),
+            });
+        }
+    }
+}
+
+/// Reads the metadata of a JPEG, PNG, WebP, GIF or TIFF image. `None` for
+/// anything else; a malformed file reports whatever could be read before the
+/// damage.
+pub fn inspect(data: &[u8]) -> Option<MetadataReport> {
+    let format = sniff(data)?;
+    let mut report = MetadataReport {
+        format: format.to_string(),
+        ..Default::default()
+    };
+    let _ = match format {
+        "jpeg" => inspect_jpeg(data, &mut report),
+        "png" => inspect_png(data, &mut report),
+        "webp" => inspect_webp(data, &mut report),
+        "gif" => inspect_gif(data, &mut report),
+        _ => inspect_tiff(data, &mut report),
+    };
+    Some(report)
+}
+
+fn sniff(data: &[u8]) -> Option<&'static str> {
+    if data.starts_with(&[0xFF, 0xD8]) {
+        Some("jpeg")
+    } else if data.starts_with(&PNG_SIG) {
+        Some("png")
+    } else if data.len() >= 12 && &data[0..4] == b"RIFF" && &data[8..12] == b"WEBP" {
+        Some("webp")
+    } else if data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a") {
+        Some("gif")
+    } else if data.starts_with(b"II*\0") || data.starts_with(b"MM\0*") {
+        Some("tiff")
+    } else {
+        None
+    }
+}
+
+/// Quality used when [`auto_orient`] has to re-encode.
+const REORIENT_QUALITY: u8 = 92;
+/// Text entries listed per image; the rest are skipped.
+const MAX_TEXT_ENTRIES: usize = 64;
+/// Bytes of each text entry kept in a report.
+const MAX_TEXT_LEN: usize = 4 * 1024;
+/// Bytes of an XMP packet kept in a report.
+const MAX_XMP_LEN: usize = 64 * 1024;
+
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+const XMP_HEADER: &[u8] = b"http://ns.adobe.com/xap/1.0/\0";
+const ICC_HEADER: &[u8] = b"ICC_PROFILE\0";
+
+/// Decodes text leniently, cut off after `max` bytes.
+fn lossy(bytes: &[u8], max: usize) -> String {
+    let bytes = &bytes[..bytes.len().min(max)];
+    String::from_utf8_lossy(bytes).trim_end_matches('\0').to_string()
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|w| w == needle)
+}
+
+/// Pulls the `<x:xmpmeta>` element out of whatever an XMP packet is wrapped
+/// in.
+fn xmp_packet(bytes: &[u8]) -> Option<String> {
+    let start = find(bytes, b"<x:xmpmeta")?;
+    let rest = &bytes[start..];
+    let end = find(rest, b"</x:xmpmeta>").map_or(rest.len(), |at| at + b"</x:xmpmeta>".len());
+    Some(lossy(&rest[..end], MAX_XMP_LEN))
+}
+
+fn icc_profile(profile: &[u8]) -> IccProfile {
+    IccProfile {
+        size: profile.len(),
+        description: icc_description(profile),
+    }
+}
+
+/// The profile's `desc` tag, in either the v2 (`desc`) or v4 (`mluc`) form.
+fn icc_description(profile: &[u8]) -> Option<String> {
+    let be32 = |bytes: &[u8], at: usize| -> Option<usize> {
+        Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?) as usize)
+    };
+    let tags = be32(profile, 128)?;
+    let entry = (0..tags.min(256))
+        .map(|n| 132 + n * 12)
+        .find(|&at| profile.get(at..at + 4) == Some(&b"desc"[..]))?;
+    let (offset, size) = (be32(profile, entry + 4)?, be32(profile, entry + 8)?);
+    let tag = profile.get(offset..offset.checked_add(size)?)?;
+    let text = match tag.get(0..4)? {
+        b"desc" => {
+            let len = be32(tag, 8)?;
+            lossy(tag.get(12..12usize.checked_add(len)?)?, MAX_TEXT_LEN)
+        }
+        b"mluc" => {
+            // The first record: language, country, length, offset (from the
+            // start of the tag) of a UTF-16BE string.
+            let (len, at) = (be32(tag, 20)?, be32(tag, 24)?);
+            let units = tag
+                .get(at..at.checked_add(len)?)?
+                .chunks_exact(2)
+                .map(|pair| u16::from_be_bytes([pair[0], pair[1]]));
+            char::decode_utf16(units)
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect()
+        }
+        _ => return None,
+    };
+    let text = text.trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+// ─── EXIF / TIFF ───────────────────────────────────────────────────────────────
+
+const TAG_ORIENTATION: u16 = 0x0112;
+const TAG_EXIF_IFD: u16 = 0x8769;
+const TAG_GPS_IFD: u16 = 0x8825;
+const TAG_XMP: u16 = 0x02BC;
+const TAG_ICC: u16 = 0x8773;
+
+/// Tags [`strip_tiff`] removes: descriptive text, the camera and its owner,
+/// timestamps, and the EXIF, GPS, XMP, IPTC and Photoshop blocks.
+const TIFF_PRIVATE_TAGS: &[u16] = &[
+    0x010D, // DocumentName
+    0x010E, // ImageDescription
+    0x010F, // Make
+    0x0110, // Model
+    0x0131, // Software
+    0x0132, // DateTime
+    0x013B, // Artist
+    0x013C, // HostComputer
+    TAG_XMP,
+    0x8298, // Copyright
+    0x83BB, // IPTC
+    0x8649, // Photoshop
+    TAG_EXIF_IFD,
+    TAG_GPS_IFD,
+    0xC4A5, // PrintIM
+];
+
+/// More entries than any real IFD has; a count above it means garbage.
+const MAX_IFD_ENTRIES: usize = 1024;
+/// IFDs followed in a TIFF's chain before giving up on a loop.
+const MAX_IFDS: usize = 64;
+
+/// One IFD entry, with its value located.
+#[derive(Debug, Clone, Copy)]
+struct Entry {
+    /// Where the 12-byte entry itself sits.
+    at: usize,
+    tag: u16,
+    ty: u16,
+    count: usize,
+    /// Where the value's bytes sit: inside the entry when they fit in four
+    /// bytes, elsewhere in the file otherwise.
+    value: usize,
+    size: usize,
+}
+
+impl Entry {
+    fn is_out_of_line(&self) -> bool {
+        self.size > 4
+    }
+}
+
+/// A TIFF structure — a TIFF file, or the body of an EXIF block — read in its
+/// own byte order. Offsets are from the start of `data`.
+struct Tiff<'a> {
+    data: &'a [u8],
+    little_endian: bool,
+}
+
+impl<'a> Tiff<'a> {
+    fn new(data: &'a [u8]) -> Option<Self> {
+        let little_endian = match data.get(0..4)? {
+            b"II*\0" => true,
+            b"MM\0*" => false,
+            _ => return None,
+        };
+        Some(Self { data, little_endian })
+    }
+
+    fn read_u16(&self, at: usize) -> Option<u16> {
+        let bytes: [u8; 2] = self.data.get(at..at + 2)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        })
+    }
+
+    fn read_u32(&self, at: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(at..at + 4)?.try_into().ok()?;
+        Some(if self.little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    }
+
+    fn encode_u16(&self, value: u16) -> [u8; 2] {
+        if self.little_endian {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        }
+    }
+
+    fn first_ifd(&self) -> Option<usize> {
+        self.read_u32(4).map(|offset| offset as usize)
+    }
+
+    fn ifd(&self, offset: usize) -> Option<Vec<Entry>> {
+        let count = self.read_u16(offset)? as usize;
+        if count > MAX_IFD_ENTRIES {
+            return None;
+        }
+        (0..count)
+            .map(|n| {
+                let at = offset + 2 + n * 12;
+                let ty = self.read_u16(at + 2)?;
+                let count = self.read_u32(at + 4)? as usize;
+                let size = type_size(ty).checked_mul(count)?;
+                let value = if size <= 4 {
+                    at + 8
+                } else {
+                    self.read_u32(at + 8)? as usize
+                };
+                Some(Entry {
+                    at,
+                    tag: self.read_u16(at)?,
+                    ty,
+                    count,
+                    value,
+                    size,
+                })
+            })
+            .collect()
+    }
+
+    /// The offset of the IFD after the one at `offset`, if there is one.
+    fn next_ifd(&self, offset: usize) -> Option<usize> {
+        let count = self.read_u16(offset)? as usize;
+        let next = self.read_u32(offset + 2 + count * 12)? as usize;
+        (next != 0).then_some(next)
+    }
+
+    fn bytes(&self, entry: &Entry) -> Option<&'a [u8]> {
+        self.data.get(entry.value..entry.value.checked_add(entry.size)?)
+    }
+
+    fn ascii(&self, entry: &Entry) -> Option<String> {
+        if entry.ty != 2 {
+            return None;
+        }
+        let raw = self.bytes(entry)?;
+        let text = lossy(raw.split(|&b| b == 0).next()?, MAX_TEXT_LEN);
+        let text = text.trim();
+        (!text.is_empty()).then(|| text.to_string())
+    }
+
+    fn unsigned(&self, entry: &Entry) -> Option<u32> {
+        match entry.ty {
+            1 => self.data.get(entry.value).map(|&b| b as u32),
+            3 => self.read_u16(entry.value).map(u32::from),
+            4 => self.read_u32(entry.value),
+            _ => None,
+        }
+    }
+
+    /// The `n`th rational of a (signed or unsigned) RATIONAL entry.
+    fn rational(&self, entry: &Entry, n: usize) -> Option<f64> {
+        if !matches!(entry.ty, 5 | 10) || n >= entry.count {
+            return None;
+        }
+        let at = entry.value + n * 8;
+        let (num, den) = (self.read_u32(at)?, self.read_u32(at + 4)?);
+        if den == 0 {
+            return None;
+        }
+        Some(if entry.ty == 10 {
+            num as i32 as f64 / den as i32 as f64
+        } else {
+            num as f64 / den as f64
+        })
+    }
+}
+
+/// The size in bytes of one value of a TIFF field type.
+fn type_size(ty: u16) -> usize {
+    match ty {
+        1 | 2 | 6 | 7 => 1,
+        3 | 8 => 2,
+        4 | 9 | 11 => 4,
+        5 | 10 | 12 => 8,
+        _ => 0,
+    }
+}
+
+fn read_exif(data: &[u8]) -> Option<ExifData> {
+    let tiff = Tiff::new(data)?;
+    let mut exif = ExifData::default();
+    let (mut exif_ifd, mut gps_ifd) = (None, None);
+
+    for entry in tiff.ifd(tiff.first_ifd()?)? {
+        let e = &entry;
+        match entry.tag {
+            0x010F => exif.make = tiff.ascii(e),
+            0x0110 => exif.model = tiff.ascii(e),
+            TAG_ORIENTATION => exif.orientation = tiff.unsigned(e).and_then(|o| u16::try_from(o).ok()),
+            0x0131 => exif.software = tiff.ascii(e),
+            0x0132 => exif.modified_at = tiff.ascii(e),
+            0x013B => exif.artist = tiff.ascii(e),
+            0x8298 => exif.copyright = tiff.ascii(e),
+            TAG_EXIF_IFD => exif_ifd = tiff.unsigned(e),
+            TAG_GPS_IFD => gps_ifd = tiff.unsigned(e),
+            _ => {}
+        }
+    }
+
+    if let Some(entries) = exif_ifd.and_then(|offset| tiff.ifd(offset as usize)) {
+        for entry in entries {
+            let e = &entry;
+            match entry.tag {
+                0x829A => exif.exposure_time = tiff.rational(e, 0),
+                0x829D => exif.f_number = tiff.rational(e, 0),
+                0x8827 => exif.iso = tiff.unsigned(e),
+                0x9003 => exif.taken_at = tiff.ascii(e),
+                0x920A => exif.focal_length = tiff.rational(e, 0),
+                0xA431 => exif.serial_number = tiff.ascii(e),
+                0xA434 => exif.lens_model = tiff.ascii(e),
+                _ => {}
+            }
+        }
+    }
+
+    exif.gps = gps_ifd
+        .and_then(|offset| tiff.ifd(offset as usize))
+        .and_then(|entries| read_gps(&tiff, &entries));
+    Some(exif)
+}
+
+fn read_gps(tiff: &Tiff, entries: &[Entry]) -> Option<GpsPosition> {
+    let find = |tag: u16| entries.iter().find(|e| e.tag == tag);
+    let reference = |tag: u16| find(tag).and_then(|e| tiff.bytes(e)).and_then(|b| b.first().copied());
+    let coordinate = |value: u16, hemisphere: u16, negative: u8| -> Option<f64> {
+        let e = find(value)?;
+        let degrees = tiff.rational(e, 0)?
+            + tiff.rational(e, 1).unwrap_or(0.0) / 60.0
+            + tiff.rational(e, 2).unwrap_or(0.0) / 3600.0;
+        Some(if reference(hemisphere) == Some(negative) {
+            -degrees
+        } else {
+            degrees
+        })
+    };
+
+    Some(GpsPosition {
+        latitude: coordinate(2, 1, b'S')?,
+        longitude: coordinate(4, 3, b'W')?,
+        altitude: find(6)
+            .and_then(|e| tiff.rational(e, 0))
+            .map(|alt| if reference(5) == Some(1) { -alt } else { alt }),
+    })
+}
+
+/// The orientation recorded in an EXIF block, if it is a valid one.
+fn exif_orientation(exif: &[u8]) -> Option<u16> {
+    let tiff = Tiff::new(exif)?;
+    let entries = tiff.ifd(tiff.first_ifd()?)?;
+    let entry = entries.iter().find(|e| e.tag == TAG_ORIENTATION)?;
+    let value = u16::try_from(tiff.unsigned(entry)?).ok()?;
+    (1..=8).contains(&value).then_some(value)
+}
+
+/// The EXIF block to keep in [`Keep::Color`] mode: just the orientation, or
+/// nothing if the image is upright anyway.
+fn orientation_only(exif: &[u8]) -> Option<Vec<u8>> {
+    exif_orientation(exif).filter(|&o| o != 1).map(minimal_exif)
+}
+
+/// A big-endian EXIF block with one tag, the orientation.
+fn minimal_exif(orientation: u16) -> Vec<u8> {
+    let mut tiff = b"MM\0*".to_vec();
+    tiff.extend_from_slice(&8u32.to_be_bytes()); // IFD0 right after the header
+    tiff.extend_from_slice(&1u16.to_be_bytes()); // one entry
+    tiff.extend_from_slice(&TAG_ORIENTATION.to_be_bytes());
+    tiff.extend_from_slice(&3u16.to_be_bytes()); // SHORT
+    tiff.extend_from_slice(&1u32.to_be_bytes());
+    tiff.extend_from_slice(&orientation.to_be_bytes());
+    tiff.extend_from_slice(&[0, 0]); // the rest of the 4-byte value field
+    tiff.extend_from_slice(&0u32.to_be_bytes()); // no next IFD
+    tiff
+}
+
+fn inspect_tiff(data: &[u8], report: &mut MetadataReport) -> Option<()> {
+    report.exif = read_exif(data);
+    let tiff = Tiff::new(data)?;
+    for entry in tiff.ifd(tiff.first_ifd()?)? {
+        match entry.tag {
+            TAG_XMP => report.xmp = tiff.bytes(&entry).and_then(xmp_packet),
+            TAG_ICC => report.icc = tiff.bytes(&entry).map(icc_profile),
+            0x010E => report.push_text("ImageDescription", tiff.ascii(&entry).as_deref().map(str::as_bytes)),
+            _ => {}
+        }
+    }
+    Some(())
+}
+
+/// Drops identifying tags from every IFD in a TIFF, in place: each IFD is
+/// rewritten with the remaining entries (it only shrinks, so nothing else
+/// moves) and the removed values and sub-IFDs are zeroed out. The file keeps
+/// its size. Returns `None` on anything unexpected.
+fn strip_tiff(data: &[u8], keep: Keep) -> Option<Vec<u8>> {
+    let tiff = Tiff::new(data)?;
+    let drops = |tag: u16| {
+        TIFF_PRIVATE_TAGS.contains(&tag) || (keep == Keep::Nothing && matches!(tag, TAG_ORIENTATION | TAG_ICC))
+    };
+    let mut out = data.to_vec();
+    let mut next = tiff.first_ifd().filter(|&offset| offset != 0);
+
+    for _ in 0..MAX_IFDS {
+        let Some(offset) = next else { break };
+        let entries = tiff.ifd(offset)?;
+        next = tiff.next_ifd(offset);
+
+        let (dropped, kept): (Vec<Entry>, Vec<Entry>) = entries.iter().partition(|e| drops(e.tag));
+        if dropped.is_empty() {
+            continue;
+        }
+        for entry in &dropped {
+            if entry.is_out_of_line() {
+                zero(&mut out, entry.value..entry.value + entry.size);
+            }
+            if matches!(entry.tag, TAG_EXIF_IFD | TAG_GPS_IFD) {
+                if let Some(sub) = tiff.unsigned(entry) {
+                    wipe_ifd(&tiff, &mut out, sub as usize);
+                }
+            }
+        }
+
+        let end = offset + 2 + entries.len() * 12 + 4;
+        let mut ifd = tiff.encode_u16(kept.len() as u16).to_vec();
+        for entry in &kept {
+            ifd.extend_from_slice(data.get(entry.at..entry.at + 12)?);
+        }
+        ifd.extend_from_slice(data.get(end - 4..end)?); // the next-IFD link
+        ifd.resize(end - offset, 0);
+        out.get_mut(offset..end)?.copy_from_slice(&ifd);
+    }
+    Some(out)
+}
+
+/// Zeroes out an IFD and the values it points to.
+fn wipe_ifd(tiff: &Tiff, out: &mut [u8], offset: usize) {
+    let Some(entries) = tiff.ifd(offset) else { return };
+    for entry in entries.iter().filter(|e| e.is_out_of_line()) {
+        zero(out, entry.value..entry.value + entry.size);
+    }
+    zero(out, offset..offset + 2 + entries.len() * 12 + 4);
+}
+
+fn zero(out: &mut [u8], range: Range<usize>) {
+    if let Some(bytes) = out.get_mut(range) {
+        bytes.fill(0);
+    }
+}
+
+// ─── JPEG ──────────────────────────────────────────────────────────────────────
+
+/// Walks a JPEG's marker segments up to the scan data, returning each
+/// segment's marker and byte range (marker included) and where the rest of
+/// the file — the entropy-coded data, which has no length prefix to walk —
+/// begins. `None` on anything unexpected.
+fn jpeg_segments(data: &[u8]) -> Option<(Vec<(u8, Range<usize>)>, usize)> {
     if data.len() < 2 || data[0] != 0xFF || data[1] != 0xD8 {
         return None;
     }
-    let mut out = Vec::with_capacity(data.len());
-    out.extend_from_slice(&data[0..2]); // SOI
+    let mut segments = Vec::new();
     let mut i = 2;
     while i + 1 < data.len() {
         if data[i] != 0xFF {
             return None; // not aligned on a marker — bail, keep original
         }
         let marker = data[i + 1];
-        // Start of scan or end of image: copy the remainder verbatim (the
-        // entropy-coded data after SOS has no length prefix to walk).
+        // Start of scan or end of image.
         if marker == 0xDA || marker == 0xD9 {
-            out.extend_from_slice(&data[i..]);
-            return Some(out);
+            break;
         }
         if i + 3 >= data.len() {
             return None;
@@ -52,80 +687,428 @@ fn strip_jpeg(data: &[u8]) -> Option<Vec<u8>> {
         if len < 2 || i + 2 + len > data.len() {
             return None;
         }
-        let segment = &data[i..i + 2 + len];
-        let drop = matches!(marker, 0xE1 | 0xED | 0xFE); // APP1, APP13, COM
-        if !drop {
-            out.extend_from_slice(segment);
-        }
+        segments.push((marker, i..i + 2 + len));
         i += 2 + len;
     }
+    Some((segments, i))
+}
+
+/// Drops EXIF (APP1), XMP (APP1), IPTC/Photoshop (APP13), and comment (COM)
+/// segments from a JPEG, preserving JFIF (APP0), Adobe (APP14), and the
+/// compressed scan data. The ICC profile (APP2) and a minimal EXIF block with
+/// the orientation survive in [`Keep::Color`] mode. Returns `None` on anything
+/// unexpected.
+fn strip_jpeg(data: &[u8], keep: Keep) -> Option<Vec<u8>> {
+    let (segments, scan) = jpeg_segments(data)?;
+    let mut out = Vec::with_capacity(data.len());
+    out.extend_from_slice(&data[0..2]); // SOI
+    for (marker, range) in segments {
+        let payload = &data[range.start + 4..range.end];
+        match marker {
+            0xE1 if keep == Keep::Color => {
+                if let Some(exif) = payload.strip_prefix(EXIF_HEADER).and_then(orientation_only) {
+                    push_jpeg_segment(&mut out, 0xE1, &[EXIF_HEADER, &exif[..]].concat());
+                }
+            }
+            0xE1 | 0xED | 0xFE => {} // APP1, APP13, COM
+            0xE2 if keep == Keep::Nothing && payload.starts_with(ICC_HEADER) => {}
+            _ => out.extend_from_slice(&data[range]),
+        }
+    }
+    out.extend_from_slice(&data[scan..]);
     Some(out)
 }
 
+fn push_jpeg_segment(out: &mut Vec<u8>, marker: u8, payload: &[u8]) {
+    out.extend_from_slice(&[0xFF, marker]);
+    out.extend_from_slice(&((payload.len() + 2) as u16).to_be_bytes());
+    out.extend_from_slice(payload);
+}
+
+fn inspect_jpeg(data: &[u8], report: &mut MetadataReport) -> Option<()> {
+    let (segments, _) = jpeg_segments(data)?;
+    let mut icc = Vec::new();
+    for (marker, range) in segments {
+        let payload = &data[range.start + 4..range.end];
+        match marker {
+            0xE1 => {
+                if let Some(exif) = payload.strip_prefix(EXIF_HEADER) {
+                    report.exif = report.exif.take().or_else(|| read_exif(exif));
+                } else if payload.starts_with(XMP_HEADER) {
+                    report.xmp = xmp_packet(payload);
+                }
+            }
+            // A profile can span several APP2 segments, each led by a
+            // sequence number and the segment count.
+            0xE2 => {
+                if let Some(part) = payload.strip_prefix(ICC_HEADER) {
+                    icc.extend_from_slice(part.get(2..).unwrap_or_default());
+                }
+            }
+            0xFE => report.push_text("Comment", Some(payload)),
+            _ => {}
+        }
+    }
+    if !icc.is_empty() {
+        report.icc = Some(icc_profile(&icc));
+    }
+    Some(())
+}
+
+// ─── PNG & WebP ────────────────────────────────────────────────────────────────
+
 const PNG_SIG: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
-/// Drops textual/EXIF/time metadata chunks from a PNG/APNG, preserving every
-/// critical and animation chunk (IHDR, PLTE, IDAT, IEND, acTL, fcTL, fdAT) as
-/// well as the ICC colour profile (iCCP). Returns `None` on anything
+/// A PNG or RIFF (WebP) chunk.
+struct Chunk {
+    kind: [u8; 4],
+    /// The chunk's data.
+    data: Range<usize>,
+    /// The whole chunk: header, data, and CRC (PNG) or padding (WebP).
+    whole: Range<usize>,
+}
+
+/// Walks a PNG's chunks up to and including IEND. `None` on anything
 /// unexpected.
-fn strip_png(data: &[u8]) -> Option<Vec<u8>> {
+fn png_chunks(data: &[u8]) -> Option<Vec<Chunk>> {
     if data.len() < 8 || data[0..8] != PNG_SIG {
         return None;
     }
-    let mut out = Vec::with_capacity(data.len());
-    out.extend_from_slice(&data[0..8]);
+    let mut chunks = Vec::new();
     let mut i = 8;
     while i + 8 <= data.len() {
         let len = u32::from_be_bytes([data[i], data[i + 1], data[i + 2], data[i + 3]]) as usize;
-        let ctype = &data[i + 4..i + 8];
+        let kind: [u8; 4] = data[i + 4..i + 8].try_into().ok()?;
         // length(4) + type(4) + data(len) + crc(4)
         let chunk_end = i.checked_add(12)?.checked_add(len)?;
         if chunk_end > data.len() {
             return None;
         }
-        let drop = matches!(ctype, b"eXIf" | b"tEXt" | b"zTXt" | b"iTXt" | b"tIME");
-        if !drop {
-            out.extend_from_slice(&data[i..chunk_end]);
-        }
-        let is_end = ctype == b"IEND";
+        chunks.push(Chunk {
+            kind,
+            data: i + 8..i + 8 + len,
+            whole: i..chunk_end,
+        });
         i = chunk_end;
-        if is_end {
+        if &kind == b"IEND" {
             break;
         }
     }
+    Some(chunks)
+}
+
+/// Drops textual/EXIF/time metadata chunks from a PNG/APNG, preserving every
+/// critical and animation chunk (IHDR, PLTE, IDAT, IEND, acTL, fcTL, fdAT).
+/// The ICC colour profile (iCCP) and a minimal eXIf with the orientation
+/// survive in [`Keep::Color`] mode. Returns `None` on anything unexpected.
+fn strip_png(data: &[u8], keep: Keep) -> Option<Vec<u8>> {
+    let chunks = png_chunks(data)?;
+    let mut out = Vec::with_capacity(data.len());
+    out.extend_from_slice(&data[0..8]);
+    for chunk in chunks {
+        match &chunk.kind {
+            b"eXIf" => {
+                if let Some(exif) = (keep == Keep::Color)
+                    .then(|| orientation_only(&data[chunk.data.clone()]))
+                    .flatten()
+                {
+                    out.extend(png_chunk(b"eXIf", &exif));
+                }
+            }
+            b"tEXt" | b"zTXt" | b"iTXt" | b"tIME" => {}
+            b"iCCP" if keep == Keep::Nothing => {}
+            _ => out.extend_from_slice(&data[chunk.whole]),
+        }
+    }
     Some(out)
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+fn png_chunk(ty: &[u8; 4], data: &[u8]) -> Vec<u8> {
+    let mut v = Vec::with_capacity(data.len() + 12);
+    v.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    v.extend_from_slice(ty);
+    v.extend_from_slice(data);
+    let mut crc_input = Vec::from(&ty[..]);
+    crc_input.extend_from_slice(data);
+    v.extend_from_slice(&crc32(&crc_input).to_be_bytes());
+    v
+}
 
-    fn crc32(bytes: &[u8]) -> u32 {
-        // Minimal CRC-32 (IEEE) so tests can build valid-enough PNG chunks.
-        let mut crc: u32 = 0xFFFF_FFFF;
-        for &b in bytes {
-            crc ^= b as u32;
-            for _ in 0..8 {
-                crc = if crc & 1 != 0 {
-                    (crc >> 1) ^ 0xEDB8_8320
+/// CRC-32 (IEEE), for the PNG chunks we write.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc: u32 = 0xFFFF_FFFF;
+    for &b in bytes {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn inspect_png(data: &[u8], report: &mut MetadataReport) -> Option<()> {
+    for chunk in png_chunks(data)? {
+        let body = &data[chunk.data];
+        // tEXt, zTXt, iTXt and iCCP all open with a NUL-terminated keyword.
+        let split = body.iter().position(|&b| b == 0);
+        let keyword = split.map(|at| lossy(&body[..at], 80)).unwrap_or_default();
+        let rest = split.map_or(&[][..], |at| &body[at + 1..]);
+        match &chunk.kind {
+            b"eXIf" => report.exif = read_exif(body),
+            b"tEXt" => report.push_text(&keyword, Some(rest)),
+            b"zTXt" => report.push_text(&keyword, None),
+            b"iTXt" => {
+                // Compression flag and method, then language and translated
+                // keyword (both NUL-terminated), then the text.
+                let compressed = rest.first() != Some(&0);
+                let text = rest
+                    .get(2..)
+                    .and_then(|r| r.splitn(3, |&b| b == 0).nth(2))
+                    .filter(|_| !compressed);
+                if keyword == "XML:com.adobe.xmp" {
+                    report.xmp = text.and_then(xmp_packet);
                 } else {
-                    crc >> 1
-                };
+                    report.push_text(&keyword, text);
+                }
+            }
+            b"iCCP" => {
+                report.icc = Some(IccProfile {
+                    size: rest.len().saturating_sub(1), // after the compression method
+                    description: Some(keyword).filter(|k| !k.is_empty()),
+                });
+            }
+            b"tIME" if body.len() == 7 => {
+                let year = u16::from_be_bytes([body[0], body[1]]);
+                let stamp = format!(
+                    "{year:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+                    body[2], body[3], body[4], body[5], body[6]
+                );
+                report.push_text("tIME", Some(stamp.as_bytes()));
+            }
+            _ => {}
+        }
+    }
+    Some(())
+}
+
+const VP8X_ICC: u8 = 0x20;
+const VP8X_EXIF: u8 = 0x08;
+const VP8X_XMP: u8 = 0x04;
+
+/// Walks the chunks of a WebP file. `None` on anything unexpected.
+fn webp_chunks(data: &[u8]) -> Option<Vec<Chunk>> {
+    if sniff(data) != Some("webp") {
+        return None;
+    }
+    let riff_size = u32::from_le_bytes(data[4..8].try_into().ok()?) as usize;
+    let end = riff_size.checked_add(8)?.min(data.len());
+    let mut chunks = Vec::new();
+    let mut i = 12;
+    while i + 8 <= end {
+        let kind: [u8; 4] = data[i..i + 4].try_into().ok()?;
+        let len = u32::from_le_bytes(data[i + 4..i + 8].try_into().ok()?) as usize;
+        let data_end = (i + 8).checked_add(len)?;
+        if data_end > end {
+            return None;
+        }
+        // Odd-sized chunks are padded to an even length.
+        let chunk_end = (data_end + (len & 1)).min(end);
+        chunks.push(Chunk {
+            kind,
+            data: i + 8..data_end,
+            whole: i..chunk_end,
+        });
+        i = chunk_end;
+    }
+    Some(chunks)
+}
+
+/// Drops the EXIF and XMP chunks from a WebP (the ICC profile too, unless
+/// keeping colour) and updates the VP8X header's flags and the RIFF size to
+/// match. Animation frames are untouched. Returns `None` on anything
+/// unexpected.
+fn strip_webp(data: &[u8], keep: Keep) -> Option<Vec<u8>> {
+    let chunks = webp_chunks(data)?;
+    let mut out = Vec::with_capacity(data.len());
+    out.extend_from_slice(&data[0..12]);
+    let mut vp8x = None;
+    let mut flags = 0u8;
+    for chunk in chunks {
+        match &chunk.kind {
+            b"EXIF" => {
+                let body = &data[chunk.data.clone()];
+                let body = body.strip_prefix(EXIF_HEADER).unwrap_or(body);
+                if let Some(exif) = (keep == Keep::Color).then(|| orientation_only(body)).flatten() {
+                    out.extend_from_slice(b"EXIF");
+                    out.extend_from_slice(&(exif.len() as u32).to_le_bytes());
+                    out.extend_from_slice(&exif); // always even-sized
+                    flags |= VP8X_EXIF;
+                }
+            }
+            b"XMP " => {}
+            b"ICCP" if keep == Keep::Nothing => {}
+            kind => {
+                match kind {
+                    b"VP8X" => vp8x = Some(out.len() + 8),
+                    b"ICCP" => flags |= VP8X_ICC,
+                    _ => {}
+                }
+                out.extend_from_slice(&data[chunk.whole]);
+            }
+        }
+    }
+    // VP8X advertises which optional chunks follow; it has to agree with what's left.
+    if let Some(byte) = vp8x.and_then(|at| out.get_mut(at)) {
+        *byte = (*byte & !(VP8X_ICC | VP8X_EXIF | VP8X_XMP)) | flags;
+    }
+    let riff_size = (out.len() - 8) as u32;
+    out[4..8].copy_from_slice(&riff_size.to_le_bytes());
+    Some(out)
+}
+
+fn inspect_webp(data: &[u8], report: &mut MetadataReport) -> Option<()> {
+    for chunk in webp_chunks(data)? {
+        let body = &data[chunk.data];
+        match &chunk.kind {
+            b"EXIF" => report.exif = read_exif(body.strip_prefix(EXIF_HEADER).unwrap_or(body)),
+            b"XMP " => report.xmp = xmp_packet(body),
+            b"ICCP" => report.icc = Some(icc_profile(body)),
+            _ => {}
+        }
+    }
+    Some(())
+}
+
+// ─── GIF ───────────────────────────────────────────────────────────────────────
+
+const GIF_EXTENSION: u8 = 0x21;
+const GIF_COMMENT: u8 = 0xFE;
+const GIF_APPLICATION: u8 = 0xFF;
+
+/// Walks a GIF's blocks: the header (with the screen descriptor and global
+/// colour table), then each extension, image and finally the trailer. Each
+/// comes with its extension label, or 0 for everything that isn't an
+/// extension. `None` on anything unexpected, including a missing trailer.
+fn gif_blocks(data: &[u8]) -> Option<Vec<(u8, Range<usize>)>> {
+    if sniff(data) != Some("gif") || data.len() < 13 {
+        return None;
+    }
+    let color_table = |packed: u8| -> usize {
+        if packed & 0x80 != 0 {
+            3 << ((packed & 7) + 1)
+        } else {
+            0
+        }
+    };
+    let mut i = 13 + color_table(data[10]);
+    let mut blocks = vec![(0, 0..i)];
+    loop {
+        match *data.get(i)? {
+            GIF_EXTENSION => {
+                let label = *data.get(i + 1)?;
+                let end = skip_sub_blocks(data, i + 2)?;
+                blocks.push((label, i..end));
+                i = end;
+            }
+            // Image descriptor, local colour table, LZW code size, image data.
+            0x2C => {
+                let start = i + 10 + color_table(*data.get(i + 9)?) + 1;
+                let end = skip_sub_blocks(data, start)?;
+                blocks.push((0, i..end));
+                i = end;
+            }
+            0x3B => {
+                blocks.push((0, i..data.len()));
+                return Some(blocks);
             }
+            _ => return None,
+        }
+    }
+}
+
+/// Skips a run of length-prefixed sub-blocks, returning where its terminator
+/// ends.
+fn skip_sub_blocks(data: &[u8], mut i: usize) -> Option<usize> {
+    loop {
+        let len = *data.get(i)? as usize;
+        i += 1 + len;
+        if len == 0 {
+            return Some(i);
         }
-        !crc
     }
+}
+
+/// Concatenates the sub-blocks starting at `i`.
+fn sub_block_data(data: &[u8], mut i: usize) -> Vec<u8> {
+    let mut out = Vec::new();
+    while let Some(&len) = data.get(i).filter(|&&len| len != 0) {
+        let Some(block) = data.get(i + 1..i + 1 + len as usize) else {
+            break;
+        };
+        out.extend_from_slice(block);
+        i += 1 + len as usize;
+    }
+    out
+}
+
+/// The application identifier of an application extension block.
+fn gif_application(data: &[u8], block: &Range<usize>) -> &'static str {
+    match data.get(block.start + 3..block.start + 14) {
+        Some(b"NETSCAPE2.0") | Some(b"ANIMEXTS1.0") => "loop",
+        Some(b"ICCRGBG1012") => "icc",
+        Some(b"XMP DataXMP") => "xmp",
+        _ => "other",
+    }
+}
 
-    fn png_chunk(ty: &[u8; 4], data: &[u8]) -> Vec<u8> {
-        let mut v = Vec::new();
-        v.extend_from_slice(&(data.len() as u32).to_be_bytes());
-        v.extend_from_slice(ty);
-        v.extend_from_slice(data);
-        let mut crc_input = Vec::from(&ty[..]);
-        crc_input.extend_from_slice(data);
-        v.extend_from_slice(&crc32(&crc_input).to_be_bytes());
-        v
+/// Drops comment extensions and application extensions other than the
+/// looping one (and the ICC profile, in [`Keep::Color`] mode) from a GIF.
+/// Frames, their timing and plain-text extensions are kept. Returns `None` on
+/// anything unexpected.
+fn strip_gif(data: &[u8], keep: Keep) -> Option<Vec<u8>> {
+    let blocks = gif_blocks(data)?;
+    let mut out = Vec::with_capacity(data.len());
+    for (label, range) in blocks {
+        let drop = match label {
+            GIF_COMMENT => true,
+            GIF_APPLICATION => match gif_application(data, &range) {
+                "loop" => false,
+                "icc" => keep == Keep::Nothing,
+                _ => true,
+            },
+            _ => false,
+        };
+        if !drop {
+            out.extend_from_slice(&data[range]);
+        }
     }
+    Some(out)
+}
+
+fn inspect_gif(data: &[u8], report: &mut MetadataReport) -> Option<()> {
+    for (label, range) in gif_blocks(data)? {
+        match label {
+            GIF_COMMENT => report.push_text("Comment", Some(&sub_block_data(data, range.start + 2))),
+            // The identifier is the first sub-block; the payload follows it.
+            GIF_APPLICATION => match gif_application(data, &range) {
+                // XMP is stored raw, its bytes doubling as sub-block lengths.
+                "xmp" => report.xmp = xmp_packet(&data[range]),
+                "icc" => report.icc = Some(icc_profile(&sub_block_data(data, range.start + 14))),
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
     #[test]
     fn png_text_and_exif_chunks_removed_others_kept() {
@@ -137,7 +1120,7 @@ mod tests {
         png.extend(png_chunk(b"IDAT", b"pixels"));
         png.extend(png_chunk(b"IEND", b""));
 
-        let out = strip("png", &png);
+        let out = strip("png", &png, Keep::Color);
         assert!(out.len() < png.len());
         // metadata gone
         assert!(!contains(&out, b"tEXt"));
@@ -148,6 +1131,11 @@ mod tests {
         assert!(contains(&out, b"iCCP"));
         assert!(contains(&out, b"IDAT"));
         assert!(contains(&out, b"IEND"));
+
+        // and by default the colour profile goes too
+        let out = strip("png", &png, Keep::Nothing);
+        assert!(!contains(&out, b"iCCP"));
+        assert!(contains(&out, b"IDAT"));
     }
 
     #[test]
@@ -164,7 +1152,7 @@ mod tests {
         // SOS + scan data (keep verbatim)
         jpg.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02, 0x11, 0x22, 0x33]);
 
-        let out = strip("jpeg", &jpg);
+        let out = strip("jpeg", &jpg, Keep::Nothing);
         assert!(!contains(&out, b"GPS-here"));
         assert!(contains(&out, b"JFI"));
         assert!(contains(&out, &[0xFF, 0xDA])); // scan preserved
@@ -173,10 +1161,211 @@ mod tests {
     #[test]
     fn malformed_input_returned_unchanged() {
         let junk = vec![1u8, 2, 3, 4, 5];
-        assert_eq!(strip("png", &junk), junk);
-        assert_eq!(strip("jpg", &junk), junk);
+        assert_eq!(strip("png", &junk, Keep::Nothing), junk);
+        assert_eq!(strip("jpg", &junk, Keep::Nothing), junk);
+        assert_eq!(strip("gif", &junk, Keep::Nothing), junk);
+        assert_eq!(strip("webp", &junk, Keep::Nothing), junk);
         // unknown extension passes through
-        assert_eq!(strip("gif", &junk), junk);
+        assert_eq!(strip("avif", &junk, Keep::Nothing), junk);
+    }
+
+    type Field<'a> = (u16, u16, u32, &'a [u8]);
+
+    fn write_ifd(out: &mut Vec<u8>, tail: &mut Vec<u8>, tail_at: usize, fields: &[Field]) {
+        out.extend((fields.len() as u16).to_be_bytes());
+        for &(tag, ty, count, value) in fields {
+            out.extend(tag.to_be_bytes());
+            out.extend(ty.to_be_bytes());
+            out.extend(count.to_be_bytes());
+            if value.len() <= 4 {
+                let mut inline = value.to_vec();
+                inline.resize(4, 0);
+                out.extend(inline);
+            } else {
+                out.extend(((tail_at + tail.len()) as u32).to_be_bytes());
+                tail.extend_from_slice(value);
+            }
+        }
+        out.extend([0; 4]);
+    }
+
+    /// A big-endian TIFF with `ifd0` and a GPS IFD holding `gps`.
+    fn tiff(ifd0: &[Field], gps: &[Field]) -> Vec<u8> {
+        let ifd_len = |n: usize| 2 + 12 * n + 4;
+        let gps_at = (8 + ifd_len(ifd0.len() + 1)) as u32;
+        let tail_at = gps_at as usize + ifd_len(gps.len());
+        let pointer = gps_at.to_be_bytes();
+        let mut fields = ifd0.to_vec();
+        fields.push((TAG_GPS_IFD, 4, 1, &pointer));
+
+        let (mut out, mut tail) = (b"MM\0*\0\0\0\x08".to_vec(), Vec::new());
+        write_ifd(&mut out, &mut tail, tail_at, &fields);
+        write_ifd(&mut out, &mut tail, tail_at, gps);
+        out.extend(tail);
+        out
+    }
+
+    fn rationals(values: &[(u32, u32)]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|(n, d)| n.to_be_bytes().into_iter().chain(d.to_be_bytes()))
+            .collect()
+    }
+
+    /// A camera-ish EXIF block: make, orientation 6 and a position in Sydney.
+    fn camera_exif() -> Vec<u8> {
+        let lat = rationals(&[(33, 1), (52, 1), (1, 2)]);
+        let lon = rationals(&[(151, 1), (12, 1), (0, 1)]);
+        tiff(
+            &[(0x010F, 2, 6, b"Canon\0"), (TAG_ORIENTATION, 3, 1, &6u16.to_be_bytes())],
+            &[(1, 2, 2, b"S\0"), (2, 5, 3, &lat), (3, 2, 2, b"E\0"), (4, 5, 3, &lon)],
+        )
+    }
+
+    fn jpeg_with(segments: &[(u8, Vec<u8>)]) -> Vec<u8> {
+        let mut jpg = vec![0xFF, 0xD8];
+        jpg.extend_from_slice(&[0xFF, 0xE0, 0x00, 0x05, b'J', b'F', b'I']);
+        for (marker, payload) in segments {
+            push_jpeg_segment(&mut jpg, *marker, payload);
+        }
+        jpg.extend_from_slice(&[0xFF, 0xDA, 0x00, 0x02, 0x11, 0x22, 0x33]);
+        jpg
+    }
+
+    #[test]
+    fn exif_gps_and_comments_are_read() {
+        let jpg = jpeg_with(&[
+            (0xE1, [EXIF_HEADER, &camera_exif()[..]].concat()),
+            (0xFE, b"hello".to_vec()),
+        ]);
+        let report = inspect(&jpg).unwrap();
+        assert_eq!(report.format, "jpeg");
+        assert_eq!(report.text[0].key, "Comment");
+        assert_eq!(report.text[0].value.as_deref(), Some("hello"));
+
+        let exif = report.exif.unwrap();
+        assert_eq!(exif.make.as_deref(), Some("Canon"));
+        assert_eq!(exif.orientation, Some(6));
+        let gps = exif.gps.unwrap();
+        assert!((gps.latitude + (33.0 + 52.0 / 60.0 + 0.5 / 3600.0)).abs() < 1e-9);
+        assert!((gps.longitude - 151.2).abs() < 1e-9);
+        assert_eq!(gps.altitude, None);
+
+        assert!(inspect(b"not an image").is_none());
+    }
+
+    #[test]
+    fn keeping_color_leaves_the_profile_and_orientation() {
+        let jpg = jpeg_with(&[
+            (0xE1, [EXIF_HEADER, &camera_exif()[..]].concat()),
+            (0xE2, [ICC_HEADER, &b"\x01\x01profile"[..]].concat()),
+        ]);
+
+        let kept = inspect(&strip("jpg", &jpg, Keep::Color)).unwrap();
+        let exif = kept.exif.unwrap();
+        assert_eq!(exif.orientation, Some(6));
+        assert!(exif.make.is_none() && exif.gps.is_none());
+        assert_eq!(kept.icc.unwrap().size, b"profile".len());
+
+        let stripped = strip("jpg", &jpg, Keep::Nothing);
+        assert!(!contains(&stripped, b"Canon"));
+        let stripped = inspect(&stripped).unwrap();
+        assert!(stripped.exif.is_none() && stripped.icc.is_none());
+    }
+
+    #[test]
+    fn webp_chunks_removed_and_header_fixed() {
+        let chunk = |kind: &[u8; 4], body: &[u8]| {
+            let mut v = kind.to_vec();
+            v.extend((body.len() as u32).to_le_bytes());
+            v.extend_from_slice(body);
+            if body.len() % 2 == 1 {
+                v.push(0);
+            }
+            v
+        };
+        let mut body = b"WEBP".to_vec();
+        body.extend(chunk(
+            b"VP8X",
+            &[VP8X_ICC | VP8X_EXIF | VP8X_XMP | 0x02, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        ));
+        body.extend(chunk(b"ICCP", b"profile"));
+        body.extend(chunk(b"ANIM", b"frames"));
+        body.extend(chunk(b"EXIF", &camera_exif()));
+        body.extend(chunk(b"XMP ", b"<x:xmpmeta>secret</x:xmpmeta>"));
+        let mut webp = b"RIFF".to_vec();
+        webp.extend((body.len() as u32).to_le_bytes());
+        webp.extend(body);
+
+        assert_eq!(
+            inspect(&webp).unwrap().xmp.as_deref(),
+            Some("<x:xmpmeta>secret</x:xmpmeta>")
+        );
+
+        let out = strip("webp", &webp, Keep::Nothing);
+        assert!(!contains(&out, b"EXIF") && !contains(&out, b"XMP ") && !contains(&out, b"ICCP"));
+        assert!(contains(&out, b"ANIM"));
+        assert_eq!(out[20], 0x02); // only the animation flag is left
+        assert_eq!(
+            u32::from_le_bytes(out[4..8].try_into().unwrap()) as usize,
+            out.len() - 8
+        );
+
+        let out = strip("webp", &webp, Keep::Color);
+        assert_eq!(out[20], VP8X_ICC | VP8X_EXIF | 0x02);
+        assert_eq!(inspect(&out).unwrap().exif.unwrap().orientation, Some(6));
+    }
+
+    #[test]
+    fn gif_comments_removed_looping_kept() {
+        let mut gif = b"GIF89a".to_vec();
+        gif.extend_from_slice(&[1, 0, 1, 0, 0, 0, 0]); // 1x1, no global colour table
+        gif.extend_from_slice(&[0x21, 0xFF, 11]);
+        gif.extend_from_slice(b"NETSCAPE2.0");
+        gif.extend_from_slice(&[3, 1, 0, 0, 0]);
+        gif.extend_from_slice(&[0x21, 0xFE, 6]);
+        gif.extend_from_slice(b"secret");
+        gif.push(0);
+        gif.extend_from_slice(&[0x2C, 0, 0, 0, 0, 1, 0, 1, 0, 0, 2, 2, 0x44, 0x01, 0]);
+        gif.push(0x3B);
+
+        assert_eq!(inspect(&gif).unwrap().text[0].value.as_deref(), Some("secret"));
+        let out = strip("gif", &gif, Keep::Nothing);
+        assert!(!contains(&out, b"secret"));
+        assert!(contains(&out, b"NETSCAPE2.0"));
+        assert!(out.ends_with(&[0x44, 0x01, 0, 0x3B]));
+    }
+
+    #[test]
+    fn tiff_tags_removed_in_place() {
+        let tif = camera_exif();
+        let out = strip("tiff", &tif, Keep::Nothing);
+        assert_eq!(out.len(), tif.len());
+        assert!(!contains(&out, b"Canon"));
+        let exif = inspect(&out).unwrap().exif.unwrap();
+        assert!(exif.make.is_none() && exif.gps.is_none() && exif.orientation.is_none());
+
+        let exif = inspect(&strip("tiff", &tif, Keep::Color)).unwrap().exif.unwrap();
+        assert_eq!(exif.orientation, Some(6));
+        assert!(exif.gps.is_none());
+    }
+
+    #[test]
+    fn sideways_jpegs_are_turned_upright() {
+        let img = image::RgbImage::new(4, 2);
+        let mut jpg = Vec::new();
+        JpegEncoder::new(&mut jpg).encode_image(&img).unwrap();
+        let mut sideways = jpg[..2].to_vec();
+        push_jpeg_segment(&mut sideways, 0xE1, &[EXIF_HEADER, &minimal_exif(6)[..]].concat());
+        sideways.extend_from_slice(&jpg[2..]);
+
+        let upright = auto_orient("jpg", &sideways).unwrap();
+        let decoded = image::load_from_memory(&upright).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (2, 4));
+        assert_eq!(inspect(&upright).unwrap().exif.and_then(|e| e.orientation), None);
+
+        assert!(auto_orient("jpg", &jpg).is_none());
+        assert!(auto_orient("png", &sideways).is_none());
     }
 
     fn contains(haystack: &[u8], needle: &[u8]) -> bool {
@@ -1,7 +1,13 @@
-//! Media processing: code-to-image rendering, file scanning, and metadata
-//! extraction.
+//! Media processing: code-to-image rendering, animation, file scanning,
+//! placeholders, metadata extraction, and the cache of rendered thumbnails and
+//! variants.
 
+pub mod animation;
 pub mod codeimage;
 pub mod metadata;
+pub mod phash;
+pub mod placeholder;
+pub mod renditions;
 pub mod scan;
 pub mod thumbnail;
+pub mod variant;
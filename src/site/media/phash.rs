@@ -0,0 +1,262 @@
+//! Perceptual hashing for near-duplicate detection.
+//!
+//! Each upload gets a 64-bit difference hash (dHash): the image is shrunk to a
+//! 9×8 grayscale grid and every bit records whether a pixel is darker than its
+//! right-hand neighbour. Re-encoding, rescaling or a slightly different crop of
+//! the same screenshot flips only a handful of bits, so two hashes a small
+//! Hamming distance apart are almost certainly the same picture.
+//!
+//! The hash is stored in `images.phash` (as the signed bit pattern SQLite can
+//! hold). SQLite has no popcount, so searches pull the candidate hashes and
+//! compare them here — cheap at the size of one account's gallery, and the
+//! site-wide sweeps (blocking) are admin-only.
+
+use std::io::Cursor;
+
+use image::imageops::FilterType;
+
+use crate::{boxed_params, AppState};
+
+/// The distance at or under which an upload counts as a near-duplicate of one
+/// of the uploader's existing images.
+pub const DUPLICATE_DISTANCE: u32 = 6;
+
+/// The distance at or under which an upload matches a blocked image. A little
+/// looser than [`DUPLICATE_DISTANCE`]: a banned image is usually re-posted
+/// deliberately altered.
+pub const BLOCK_DISTANCE: u32 = 8;
+
+/// The largest distance the "similar images" search accepts. Past this the
+/// matches are mostly unrelated images that share a rough layout.
+pub const MAX_SEARCH_DISTANCE: u32 = 20;
+
+/// Rows hashed per round-trip by [`backfill`].
+const BACKFILL_BATCH: i64 = 32;
+
+/// Computes the dHash of an encoded image, or `None` if it can't be decoded.
+/// Animated images are hashed by their first frame. CPU-bound — call it from
+/// the blocking pool.
+pub fn dhash(bytes: &[u8]) -> Option<u64> {
+    let img = image::ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()?
+        .decode()
+        .ok()?;
+    let small = image::imageops::resize(&img.to_luma8(), 9, 8, FilterType::Triangle);
+
+    let mut hash = 0u64;
+    for y in 0..8 {
+        for x in 0..8 {
+            let left = small.get_pixel(x, y).0[0];
+            let right = small.get_pixel(x + 1, y).0[0];
+            hash = (hash << 1) | u64::from(left < right);
+        }
+    }
+    Some(hash)
+}
+
+/// [`dhash`] on the blocking pool, in the column's storage form.
+pub async fn compute(bytes: &[u8]) -> Option<i64> {
+    let bytes = bytes.to_vec();
+    tokio::task::spawn_blocking(move || dhash(&bytes))
+        .await
+        .ok()
+        .flatten()
+        .map(|hash| hash as i64)
+}
+
+/// The number of bits two stored hashes differ in.
+pub fn distance(a: i64, b: i64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Whether a hash says too little about its picture to block by: one within
+/// [`BLOCK_DISTANCE`] of all zeroes or all ones, as blank, flat and plain
+/// gradient images hash. A block on it would take every such image with it.
+pub fn is_degenerate(hash: i64) -> bool {
+    let ones = hash.count_ones();
+    ones <= BLOCK_DISTANCE || ones >= 64 - BLOCK_DISTANCE
+}
+
+/// An existing image whose hash is near a probe hash.
+#[derive(Debug, Clone)]
+pub struct Match {
+    pub id: String,
+    pub mimetype: String,
+    pub distance: u32,
+}
+
+/// The account's live images within `max_distance` of `hash`, closest first.
+/// `exclude` leaves one image (usually the probe itself) out of the results.
+pub async fn similar(
+    state: &AppState,
+    account_id: i64,
+    hash: i64,
+    max_distance: u32,
+    exclude: Option<&str>,
+) -> anyhow::Result<Vec<Match>> {
+    let exclude = exclude.map(str::to_string);
+    let candidates: Vec<(String, String, i64)> = state
+        .database()
+        .call(move |conn| {
+            let mut stmt = conn.prepare_cached(
+                "SELECT id, mimetype, phash FROM images \
+                 WHERE uploader_id = ?1 AND phash IS NOT NULL AND id IS NOT ?2 AND deleted_at IS NULL \
+                   AND (expires_at IS NULL OR datetime(expires_at) > datetime('now'))",
+            )?;
+            let rows: rusqlite::Result<Vec<_>> = stmt
+                .query_map(boxed_params![account_id, exclude], |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+                })?
+                .collect();
+            rows
+        })
+        .await?;
+
+    let mut matches: Vec<Match> = candidates
+        .into_iter()
+        .filter_map(|(id, mimetype, other)| {
+            let distance = distance(hash, other);
+            (distance <= max_distance).then_some(Match { id, mimetype, distance })
+        })
+        .collect();
+    matches.sort_by(|a, b| a.distance.cmp(&b.distance).then_with(|| a.id.cmp(&b.id)));
+    Ok(matches)
+}
+
+/// The id of the block entry `hash` falls within [`BLOCK_DISTANCE`] of, if any.
+pub async fn blocked_by(state: &AppState, hash: i64) -> anyhow::Result<Option<i64>> {
+    let blocks: Vec<(i64, i64)> = state
+        .database()
+        .call(|conn| {
+            let mut stmt = conn.prepare_cached("SELECT id, phash FROM image_block")?;
+            let rows: rusqlite::Result<Vec<_>> = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect();
+            rows
+        })
+        .await?;
+    Ok(blocks
+        .into_iter()
+        .find(|&(_, blocked)| distance(hash, blocked) <= BLOCK_DISTANCE)
+        .map(|(id, _)| id))
+}
+
+/// Every live image on the site within [`BLOCK_DISTANCE`] of `hash`,
+/// regardless of owner. Used when an admin blocks an image.
+pub async fn near_duplicates_sitewide(state: &AppState, hash: i64) -> anyhow::Result<Vec<String>> {
+    let candidates: Vec<(String, i64)> = state
+        .database()
+        .call(|conn| {
+            let mut stmt = conn.prepare("SELECT id, phash FROM images WHERE phash IS NOT NULL")?;
+            let rows: rusqlite::Result<Vec<_>> = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?.collect();
+            rows
+        })
+        .await?;
+    Ok(candidates
+        .into_iter()
+        .filter(|&(_, other)| distance(hash, other) <= BLOCK_DISTANCE)
+        .map(|(id, _)| id)
+        .collect())
+}
+
+/// Hashes images uploaded before perceptual hashing existed, returning how
+/// many were hashed.
+///
+/// Walks `images` in id order from a cursor, so images that can't be decoded
+/// (AVIF in this build) are stepped over rather than retried forever; they are
+/// looked at again on the next start. Safe to run alongside serving.
+pub async fn backfill(state: &AppState) -> anyhow::Result<usize> {
+    let mut hashed = 0usize;
+    let mut cursor = String::new();
+    loop {
+        let after = cursor.clone();
+        let batch: Vec<String> = state
+            .database()
+            .call(move |conn| {
+                let mut stmt =
+                    conn.prepare("SELECT id FROM images WHERE phash IS NULL AND id > ?1 ORDER BY id LIMIT ?2")?;
+                let rows: rusqlite::Result<Vec<String>> = stmt
+                    .query_map(boxed_params![after, BACKFILL_BATCH], |row| row.get(0))?
+                    .collect();
+                rows
+            })
+            .await?;
+
+        let Some(last) = batch.last() else {
+            break;
+        };
+        cursor = last.clone();
+
+        for id in batch {
+            let Some(bytes) = state.resolve_image_data_for(&id).await else {
+                continue;
+            };
+            let Some(hash) = compute(&bytes).await else {
+                continue;
+            };
+            state
+                .database()
+                .execute("UPDATE images SET phash = ?1 WHERE id = ?2", boxed_params![hash, id])
+                .await?;
+            hashed += 1;
+        }
+    }
+
+    if hashed > 0 {
+        tracing::info!(count = hashed, "computed perceptual hashes for existing images");
+    }
+    Ok(hashed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, GrayImage, Luma};
+
+    fn gradient(width: u32, height: u32, flip: bool) -> Vec<u8> {
+        let img = GrayImage::from_fn(width, height, |x, y| {
+            let v = (x * 200 / width + y % 7) as u8;
+            Luma([if flip { 255 - v } else { v }])
+        });
+        let mut buf = Cursor::new(Vec::new());
+        DynamicImage::ImageLuma8(img)
+            .write_to(&mut buf, image::ImageFormat::Png)
+            .unwrap();
+        buf.into_inner()
+    }
+
+    #[test]
+    fn rescaled_copies_hash_close_and_different_images_far_apart() {
+        let original = dhash(&gradient(640, 480, false)).unwrap();
+        let rescaled = dhash(&gradient(320, 240, false)).unwrap();
+        let inverted = dhash(&gradient(640, 480, true)).unwrap();
+
+        assert!(distance(original as i64, rescaled as i64) <= DUPLICATE_DISTANCE);
+        assert!(distance(original as i64, inverted as i64) > MAX_SEARCH_DISTANCE);
+    }
+
+    #[test]
+    fn distance_counts_differing_bits_across_the_sign_bit() {
+        assert_eq!(distance(0, 0), 0);
+        assert_eq!(distance(0, -1), 64);
+        assert_eq!(distance(i64::MIN, 0), 1);
+    }
+
+    #[test]
+    fn flat_images_are_too_plain_to_block() {
+        let blank = GrayImage::from_pixel(64, 64, Luma([128]));
+        let mut buf = Cursor::new(Vec::new());
+        DynamicImage::ImageLuma8(blank)
+            .write_to(&mut buf, image::ImageFormat::Png)
+            .unwrap();
+
+        assert!(is_degenerate(dhash(buf.get_ref()).unwrap() as i64));
+        assert!(is_degenerate(0));
+        assert!(is_degenerate(-1));
+        assert!(!is_degenerate(0x0f0f_0f0f_0f0f_0f0f));
+    }
+
+    #[test]
+    fn undecodable_input_has_no_hash() {
+        assert!(dhash(b"not an image").is_none());
+    }
+}
@@ -0,0 +1,263 @@
+//! Low-quality placeholders for gallery listings.
+//!
+//! Every upload gets a [BlurHash](https://blurha.sh) — a ~30 character string
+//! that decodes to a soft, blurred impression of the picture — and its dominant
+//! colour, taken from the same palette extraction that backs
+//! `POST /color/palette`. Listings hand both to the frontend so a grid can
+//! paint something the right shape and colour while the thumbnails load.
+//!
+//! Both are stored on the image row (`images.blurhash`,
+//! `images.dominant_color`) at upload time; [`backfill`] fills in images
+//! uploaded before placeholders existed.
+
+use std::io::Cursor;
+
+use image::{imageops::FilterType, RgbImage};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::{boxed_params, AppState};
+
+/// The longest side the image is shrunk to before encoding. The hash only
+/// keeps a dozen low-frequency components, so more pixels buy nothing.
+const SAMPLE_SIZE: u32 = 32;
+
+/// Rows processed per round-trip by [`backfill`].
+const BACKFILL_BATCH: i64 = 32;
+
+const BASE83: &[u8; 83] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~";
+
+/// A placeholder for an image that hasn't loaded yet.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, ToSchema)]
+pub struct Placeholder {
+    /// The image's BlurHash.
+    #[schema(example = "LEHV6nWB2yk8pyo0adR*.7kCMdnj")]
+    pub blurhash: String,
+    /// The image's most prominent colour as `#rrggbb`.
+    #[schema(example = "#4f6d8a")]
+    pub dominant_color: String,
+}
+
+/// Computes the placeholder of an encoded image, or `None` if it can't be
+/// decoded. Animated images use their first frame. CPU-bound — call it from the
+/// blocking pool.
+pub fn generate(bytes: &[u8]) -> Option<Placeholder> {
+    let img = image::ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()?
+        .decode()
+        .ok()?;
+
+    let small = img.resize(SAMPLE_SIZE, SAMPLE_SIZE, FilterType::Triangle);
+    let (x_components, y_components) = if small.width() >= small.height() {
+        (4, 3)
+    } else {
+        (3, 4)
+    };
+    let blurhash = encode(&small.to_rgb8(), x_components, y_components);
+
+    // A fully transparent image has no palette; fall back to the hash's
+    // average colour so there is always something to paint.
+    let dominant_color = crate::site::api::media::extract_palette(&small, 1)
+        .colors
+        .into_iter()
+        .next()
+        .map(|color| color.hex)
+        .unwrap_or_else(|| format!("#{:06x}", decode_base83(&blurhash[2..6])));
+
+    Some(Placeholder {
+        blurhash,
+        dominant_color,
+    })
+}
+
+/// [`generate`] on the blocking pool.
+pub async fn compute(bytes: &[u8]) -> Option<Placeholder> {
+    let bytes = bytes.to_vec();
+    tokio::task::spawn_blocking(move || generate(&bytes))
+        .await
+        .ok()
+        .flatten()
+}
+
+/// Encodes `img` as a BlurHash with the given number of horizontal and
+/// vertical components (each 1–9).
+pub fn encode(img: &RgbImage, x_components: u32, y_components: u32) -> String {
+    let (width, height) = img.dimensions();
+    let linear: Vec<[f64; 3]> = img
+        .pixels()
+        .map(|p| [srgb_to_linear(p.0[0]), srgb_to_linear(p.0[1]), srgb_to_linear(p.0[2])])
+        .collect();
+
+    let mut factors = Vec::with_capacity((x_components * y_components) as usize);
+    for j in 0..y_components {
+        for i in 0..x_components {
+            let normalisation = if i == 0 && j == 0 { 1.0 } else { 2.0 };
+            let mut sum = [0.0f64; 3];
+            for y in 0..height {
+                let basis_y = (std::f64::consts::PI * j as f64 * y as f64 / height as f64).cos();
+                for x in 0..width {
+                    let basis = basis_y * (std::f64::consts::PI * i as f64 * x as f64 / width as f64).cos();
+                    let pixel = linear[(y * width + x) as usize];
+                    sum[0] += basis * pixel[0];
+                    sum[1] += basis * pixel[1];
+                    sum[2] += basis * pixel[2];
+                }
+            }
+            let scale = normalisation / (width * height).max(1) as f64;
+            factors.push(sum.map(|c| c * scale));
+        }
+    }
+
+    let mut hash = String::with_capacity(4 + 2 * factors.len());
+    push_base83(&mut hash, (x_components - 1) + (y_components - 1) * 9, 1);
+
+    let (dc, ac) = factors.split_first().expect("at least one component");
+    let max_ac = ac.iter().flatten().fold(0.0f64, |max, c| max.max(c.abs()));
+    let maximum = if ac.is_empty() {
+        push_base83(&mut hash, 0, 1);
+        1.0
+    } else {
+        let quantised = ((max_ac * 166.0 - 0.5).floor()).clamp(0.0, 82.0) as u32;
+        push_base83(&mut hash, quantised, 1);
+        (quantised + 1) as f64 / 166.0
+    };
+
+    let [r, g, b] = dc.map(linear_to_srgb);
+    push_base83(&mut hash, (r << 16) | (g << 8) | b, 4);
+
+    for component in ac {
+        let [r, g, b] = component.map(|c| {
+            let v = c / maximum;
+            (v.signum() * v.abs().sqrt() * 9.0 + 9.5).floor().clamp(0.0, 18.0) as u32
+        });
+        push_base83(&mut hash, r * 19 * 19 + g * 19 + b, 2);
+    }
+    hash
+}
+
+fn srgb_to_linear(value: u8) -> f64 {
+    let v = value as f64 / 255.0;
+    if v <= 0.04045 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f64) -> u32 {
+    let v = value.clamp(0.0, 1.0);
+    let srgb = if v <= 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+    (srgb * 255.0 + 0.5) as u32
+}
+
+fn push_base83(out: &mut String, value: u32, length: u32) {
+    for i in (0..length).rev() {
+        let digit = (value / 83u32.pow(i)) % 83;
+        out.push(BASE83[digit as usize] as char);
+    }
+}
+
+fn decode_base83(digits: &str) -> u32 {
+    digits.bytes().fold(0, |value, c| {
+        let digit = BASE83.iter().position(|&d| d == c).unwrap_or(0) as u32;
+        value * 83 + digit
+    })
+}
+
+/// Computes placeholders for images uploaded before they existed, returning
+/// how many were filled in.
+///
+/// Walks `images` in id order from a cursor, the same way
+/// [`crate::phash::backfill`] does, so undecodable images are stepped over
+/// rather than retried forever. Safe to run alongside serving.
+pub async fn backfill(state: &AppState) -> anyhow::Result<usize> {
+    let mut filled = 0usize;
+    let mut cursor = String::new();
+    loop {
+        let after = cursor.clone();
+        let batch: Vec<String> = state
+            .database()
+            .call(move |conn| {
+                let mut stmt =
+                    conn.prepare("SELECT id FROM images WHERE blurhash IS NULL AND id > ?1 ORDER BY id LIMIT ?2")?;
+                let rows: rusqlite::Result<Vec<String>> = stmt
+                    .query_map(boxed_params![after, BACKFILL_BATCH], |row| row.get(0))?
+                    .collect();
+                rows
+            })
+            .await?;
+
+        let Some(last) = batch.last() else {
+            break;
+        };
+        cursor = last.clone();
+
+        for id in batch {
+            let Some(bytes) = state.resolve_image_data_for(&id).await else {
+                continue;
+            };
+            let Some(placeholder) = compute(&bytes).await else {
+                continue;
+            };
+            state
+                .database()
+                .execute(
+                    "UPDATE images SET blurhash = ?1, dominant_color = ?2 WHERE id = ?3",
+                    boxed_params![placeholder.blurhash, placeholder.dominant_color, id],
+                )
+                .await?;
+            filled += 1;
+        }
+    }
+
+    if filled > 0 {
+        tracing::info!(count = filled, "computed placeholders for existing images");
+        state.invalidate_image_caches().await;
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{DynamicImage, Rgb};
+
+    fn png(img: RgbImage) -> Vec<u8> {
+        let mut buf = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(img)
+            .write_to(&mut buf, image::ImageFormat::Png)
+            .unwrap();
+        buf.into_inner()
+    }
+
+    #[test]
+    fn solid_images_encode_to_a_flat_hash() {
+        let black = RgbImage::from_pixel(16, 16, Rgb([0, 0, 0]));
+        assert_eq!(encode(&black, 4, 3), "L00000fQfQfQfQfQfQfQfQfQfQfQ");
+
+        let placeholder = generate(&png(RgbImage::from_pixel(40, 20, Rgb([0x33, 0x66, 0x99])))).unwrap();
+        assert_eq!(placeholder.dominant_color, "#336699");
+        assert_eq!(decode_base83(&placeholder.blurhash[2..6]), 0x336699);
+    }
+
+    #[test]
+    fn portrait_images_get_more_vertical_components() {
+        let img = RgbImage::from_fn(
+            20,
+            60,
+            |_, y| if y < 30 { Rgb([250, 20, 20]) } else { Rgb([20, 20, 250]) },
+        );
+        let placeholder = generate(&png(img)).unwrap();
+
+        // 3×4 components: size flag (3-1) + (4-1)*9 = 29, 4 + 2×11 characters.
+        assert_eq!(placeholder.blurhash.as_bytes()[0], BASE83[29]);
+        assert_eq!(placeholder.blurhash.len(), 28);
+        assert!(!placeholder.blurhash.ends_with("fQfQ"));
+        assert!(generate(b"not an image").is_none());
+    }
+}
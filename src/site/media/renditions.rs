@@ -0,0 +1,493 @@
+//! The on-disk cache of gallery thumbnails and image variants.
+//!
+//! Thumbnails and variants cost a full decode, a resize and an encode each,
+//! and used to live only in memory, so every restart paid for the whole
+//! gallery again. [`RenditionCache`] keeps them as files under the data
+//! directory (`renditions/` next to `main.db`, unless `renditions.path` says
+//! otherwise), behind the in-memory caches in [`AppState`]: a miss there reads
+//! the file, and only a miss here renders.
+//!
+//! The cache is bounded by `renditions.max_bytes`. A file's modification time
+//! is its last use, so the least recently used go first, across restarts too:
+//! once the total passes the bound, the oldest are removed until it is back
+//! under nine tenths of it.
+//!
+//! Files live at `<root>/<image id>/<name>`, so forgetting an image whose
+//! bytes were replaced (see [`crate::site::image_revision`]) is one directory.
+//! Each starts with a one-line header, the content type and the SHA-256 of the
+//! original it was rendered from, followed by the bytes. A rendition whose
+//! source isn't the image's current blob is a miss, so one whose render
+//! finished after its image was replaced is never served.
+//! Entries of deleted images aren't chased down: nothing serves them, and they
+//! age out like any other.
+//!
+//! New uploads get their thumbnail from a background worker ([`spawn_worker`])
+//! so the first gallery view doesn't wait on it. [`rebuild`] remakes every
+//! thumbnail; it runs from `klappstuhl_me thumbnails` or, on a live server,
+//! `POST /api/v1/images/thumbnails/rebuild`.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use anyhow::Context;
+
+use crate::{boxed_params, AppState, Config};
+
+/// Thumbnails the upload path may queue for the worker before it starts
+/// dropping them. A dropped one is made on its first view instead.
+const QUEUE: usize = 1024;
+
+/// Images read per round-trip by [`rebuild`].
+const REBUILD_BATCH: i64 = 64;
+
+/// The content types a rendition can have. A header naming anything else is
+/// treated as a miss.
+const CONTENT_TYPES: [&str; 4] = ["image/jpeg", "image/png", "image/webp", "image/gif"];
+
+/// Scratch directory for writes in flight. Image ids never start with a dot,
+/// so it can't collide with one.
+const TMP: &str = ".tmp";
+
+/// Set while a [`rebuild`] started through [`start_rebuild`] is running.
+static REBUILDING: AtomicBool = AtomicBool::new(false);
+
+/// A rendition read back from disk.
+pub struct Rendition {
+    pub bytes: Vec<u8>,
+    pub content_type: &'static str,
+    /// SHA-256 of the original it was rendered from.
+    pub source_hash: String,
+}
+
+struct Entry {
+    size: u64,
+    used: u64,
+}
+
+/// What is on disk, by `<id>/<name>`, with a logical clock for recency.
+#[derive(Default)]
+struct Index {
+    entries: HashMap<String, Entry>,
+    total: u64,
+    clock: u64,
+}
+
+impl Index {
+    fn insert(&mut self, key: String, size: u64) {
+        self.clock += 1;
+        let used = self.clock;
+        if let Some(old) = self.entries.insert(key, Entry { size, used }) {
+            self.total = self.total.saturating_sub(old.size);
+        }
+        self.total += size;
+    }
+
+    fn touch(&mut self, key: &str) {
+        self.clock += 1;
+        if let Some(entry) = self.entries.get_mut(key) {
+            entry.used = self.clock;
+        }
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(old) = self.entries.remove(key) {
+            self.total = self.total.saturating_sub(old.size);
+        }
+    }
+
+    fn remove_image(&mut self, id: &str) {
+        let prefix = format!("{id}/");
+        let mut freed = 0;
+        self.entries.retain(|key, entry| {
+            let keep = !key.starts_with(&prefix);
+            if !keep {
+                freed += entry.size;
+            }
+            keep
+        });
+        self.total = self.total.saturating_sub(freed);
+    }
+
+    /// Once the total is over `max`, drops the least recently used entries
+    /// until it is under nine tenths of it, returning their keys.
+    fn evict(&mut self, max: u64) -> Vec<String> {
+        if self.total <= max {
+            return Vec::new();
+        }
+        let target = max / 10 * 9;
+        let mut by_use: Vec<(u64, String)> = self.entries.iter().map(|(key, e)| (e.used, key.clone())).collect();
+        by_use.sort_unstable();
+        let mut evicted = Vec::new();
+        for (_, key) in by_use {
+            if self.total <= target {
+                break;
+            }
+            self.remove(&key);
+            evicted.push(key);
+        }
+        evicted
+    }
+}
+
+/// A size-bounded LRU of rendered images on the local disk.
+pub struct RenditionCache {
+    root: PathBuf,
+    max_bytes: u64,
+    index: Mutex<Index>,
+}
+
+impl RenditionCache {
+    /// Opens (and creates, if needed) a cache rooted at `root`, indexing what
+    /// an earlier run left there.
+    pub fn open(root: impl Into<PathBuf>, max_bytes: u64) -> anyhow::Result<Self> {
+        let root = root.into();
+        // Half-written files from a crash are of no use to anyone.
+        let _ = std::fs::remove_dir_all(root.join(TMP));
+        std::fs::create_dir_all(root.join(TMP))
+            .with_context(|| format!("could not create rendition directory {}", root.display()))?;
+
+        let mut found = Vec::new();
+        for dir in std::fs::read_dir(&root)?.flatten() {
+            let Some(id) = dir.file_name().to_str().filter(|id| is_segment(id)).map(str::to_owned) else {
+                continue;
+            };
+            let Ok(files) = std::fs::read_dir(dir.path()) else {
+                continue;
+            };
+            for file in files.flatten() {
+                let (Some(name), Ok(meta)) = (file.file_name().to_str().map(str::to_owned), file.metadata()) else {
+                    continue;
+                };
+                let used = meta.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+                found.push((used, format!("{id}/{name}"), meta.len()));
+            }
+        }
+        found.sort_unstable();
+
+        let mut index = Index::default();
+        for (_, key, size) in found {
+            index.insert(key, size);
+        }
+        remove_files(&root, index.evict(max_bytes));
+        Ok(Self {
+            root,
+            max_bytes,
+            index: Mutex::new(index),
+        })
+    }
+
+    /// How many renditions are cached, and their total size in bytes.
+    pub fn usage(&self) -> (usize, u64) {
+        let index = self.index.lock().unwrap();
+        (index.entries.len(), index.total)
+    }
+
+    fn path_for(&self, id: &str, name: &str) -> Option<PathBuf> {
+        (is_segment(id) && is_segment(name)).then(|| self.root.join(id).join(name))
+    }
+
+    /// Reads rendition `name` of image `id`, or `None` on a miss.
+    pub async fn get(&self, id: &str, name: &str) -> Option<Rendition> {
+        let path = self.path_for(id, name)?;
+        let key = format!("{id}/{name}");
+        let data = tokio::task::spawn_blocking(move || {
+            let data = std::fs::read(&path).ok()?;
+            // The modification time is the last use a restart sees.
+            if let Ok(file) = std::fs::File::options().write(true).open(&path) {
+                let _ = file.set_modified(SystemTime::now());
+            }
+            Some(data)
+        })
+        .await
+        .ok()?;
+
+        let mut index = self.index.lock().unwrap();
+        match data.and_then(decode) {
+            Some(rendition) => {
+                index.touch(&key);
+                Some(rendition)
+            }
+            None => {
+                index.remove(&key);
+                None
+            }
+        }
+    }
+
+    /// Stores rendition `name` of image `id`, evicting old ones if the cache
+    /// grows past its bound. Failures are logged and otherwise ignored: the
+    /// rendition is simply made again next time.
+    pub async fn put(&self, id: &str, name: &str, content_type: &'static str, source_hash: &str, bytes: &[u8]) {
+        let Some(path) = self.path_for(id, name) else {
+            return;
+        };
+        let mut data = format!("{content_type} {source_hash}\n").into_bytes();
+        data.extend_from_slice(bytes);
+        let size = data.len() as u64;
+        if size > self.max_bytes {
+            return;
+        }
+
+        let tmp = self.root.join(TMP).join(nanoid::nanoid!(12));
+        let written = tokio::task::spawn_blocking(move || -> std::io::Result<()> {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&tmp, &data)?;
+            std::fs::rename(&tmp, &path).inspect_err(|_| {
+                let _ = std::fs::remove_file(&tmp);
+            })
+        })
+        .await;
+        match written {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => {
+                tracing::warn!(error = %e, id, name, "could not write a rendition to disk");
+                return;
+            }
+            Err(_) => return,
+        }
+
+        let evicted = {
+            let mut index = self.index.lock().unwrap();
+            index.insert(format!("{id}/{name}"), size);
+            index.evict(self.max_bytes)
+        };
+        if !evicted.is_empty() {
+            tracing::debug!(count = evicted.len(), "evicted renditions from the disk cache");
+            let root = self.root.clone();
+            let _ = tokio::task::spawn_blocking(move || remove_files(&root, evicted)).await;
+        }
+    }
+
+    /// Drops every rendition of image `id`.
+    pub async fn forget(&self, id: &str) {
+        if !is_segment(id) {
+            return;
+        }
+        self.index.lock().unwrap().remove_image(id);
+        if let Err(e) = tokio::fs::remove_dir_all(self.root.join(id)).await {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                tracing::warn!(error = %e, id, "could not remove cached renditions");
+            }
+        }
+    }
+}
+
+/// Whether `s` is safe to use as one path component: no separators, no dot
+/// files, nothing that could climb out of the root.
+fn is_segment(s: &str) -> bool {
+    !s.is_empty()
+        && s.len() <= 128
+        && !s.starts_with('.')
+        && s.bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+}
+
+/// Splits a file back into its header and bytes.
+fn decode(mut data: Vec<u8>) -> Option<Rendition> {
+    let end = data.iter().take(256).position(|&b| b == b'\n')?;
+    let header = std::str::from_utf8(&data[..end]).ok()?;
+    let (content_type, source_hash) = header.split_once(' ')?;
+    let content_type = CONTENT_TYPES.into_iter().find(|&known| known == content_type)?;
+    let source_hash = source_hash.to_string();
+    data.drain(..=end);
+    Some(Rendition {
+        bytes: data,
+        content_type,
+        source_hash,
+    })
+}
+
+fn remove_files(root: &Path, keys: Vec<String>) {
+    for key in keys {
+        let path = root.join(&key);
+        let _ = std::fs::remove_file(&path);
+        // Only succeeds once the image's directory is empty.
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::remove_dir(parent);
+        }
+    }
+}
+
+/// Opens the cache configured by `renditions`, or `None` when it is turned
+/// off (`max_bytes` of 0).
+pub fn from_config(config: &Config) -> anyhow::Result<Option<RenditionCache>> {
+    if config.renditions.max_bytes == 0 {
+        return Ok(None);
+    }
+    let root = match &config.renditions.path {
+        Some(path) => path.clone(),
+        None => default_directory()?,
+    };
+    RenditionCache::open(root, config.renditions.max_bytes).map(Some)
+}
+
+/// The default cache directory: `renditions/` next to `main.db`.
+pub fn default_directory() -> anyhow::Result<PathBuf> {
+    Ok(crate::database::directory()?.with_file_name("renditions"))
+}
+
+/// Starts the worker that makes thumbnails for new uploads in the background
+/// (see [`AppState::pregenerate_thumbnail`]). One image at a time, so a large
+/// import doesn't take every core away from serving.
+pub fn spawn_worker(state: AppState) {
+    let (tx, mut rx) = tokio::sync::mpsc::channel::<String>(QUEUE);
+    if !state.attach_thumbnail_queue(tx) {
+        return;
+    }
+    tokio::spawn(async move {
+        while let Some(id) = rx.recv().await {
+            if state.cached_thumbnail(&id).await.is_some() {
+                continue;
+            }
+            if let Some(bytes) = state.resolve_image_data_for(&id).await {
+                state.thumbnail_for(&id, &bytes).await;
+            }
+        }
+    });
+}
+
+/// Remakes the thumbnail of every image not in the trash, dropping its cached
+/// variants along the way (they are rendered again when next asked for).
+/// Returns how many thumbnails were made; images the decoder can't read
+/// (AVIF) have none.
+pub async fn rebuild(state: &AppState) -> anyhow::Result<usize> {
+    let mut made = 0usize;
+    let mut cursor = String::new();
+    loop {
+        let after = cursor.clone();
+        let batch: Vec<String> = state
+            .database()
+            .call(move |conn| {
+                let mut stmt =
+                    conn.prepare("SELECT id FROM images WHERE deleted_at IS NULL AND id > ?1 ORDER BY id LIMIT ?2")?;
+                let rows: rusqlite::Result<Vec<String>> = stmt
+                    .query_map(boxed_params![after, REBUILD_BATCH], |row| row.get(0))?
+                    .collect();
+                rows
+            })
+            .await?;
+
+        let Some(last) = batch.last() else {
+            break;
+        };
+        cursor = last.clone();
+
+        for id in batch {
+            state.forget_renditions(&id).await;
+            let Some(bytes) = state.resolve_image_data_for(&id).await else {
+                continue;
+            };
+            if state.thumbnail_for(&id, &bytes).await.is_some() {
+                made += 1;
+            }
+        }
+    }
+    tracing::info!(count = made, "rebuilt image thumbnails");
+    Ok(made)
+}
+
+/// Runs [`rebuild`] in the background, unless one already is. Returns whether
+/// it started.
+pub fn start_rebuild(state: AppState) -> bool {
+    if REBUILDING.swap(true, Ordering::AcqRel) {
+        return false;
+    }
+    tokio::spawn(async move {
+        if let Err(e) = rebuild(&state).await {
+            tracing::error!(error = %e, "rebuilding image thumbnails failed");
+        }
+        REBUILDING.store(false, Ordering::Release);
+    });
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch() -> PathBuf {
+        std::env::temp_dir().join(format!("klappstuhl-test-renditions-{}", nanoid::nanoid!(8)))
+    }
+
+    #[tokio::test]
+    async fn renditions_survive_reopening_and_can_be_forgotten() {
+        let root = scratch();
+        let cache = RenditionCache::open(&root, 1 << 20).unwrap();
+        cache.put("abc", "thumb", "image/png", "", b"png bytes").await;
+        cache
+            .put("abc", "v-256x-contain-q80.webp", "image/webp", "f00d", b"webp")
+            .await;
+
+        let cache = RenditionCache::open(&root, 1 << 20).unwrap();
+        assert_eq!(cache.usage().0, 2);
+        let thumb = cache.get("abc", "thumb").await.unwrap();
+        assert_eq!(thumb.bytes, b"png bytes");
+        assert_eq!(thumb.content_type, "image/png");
+        let variant = cache.get("abc", "v-256x-contain-q80.webp").await.unwrap();
+        assert_eq!(variant.source_hash, "f00d");
+
+        cache.forget("abc").await;
+        assert!(cache.get("abc", "thumb").await.is_none());
+        assert_eq!(cache.usage(), (0, 0));
+        assert!(cache.get("../abc", "thumb").await.is_none());
+        let _ = std::fs::remove_dir_all(root);
+    }
+
+    #[test]
+    fn eviction_drops_the_least_recently_used() {
+        let mut index = Index::default();
+        index.insert("a/thumb".into(), 40);
+        index.insert("b/thumb".into(), 40);
+        index.touch("a/thumb");
+        index.insert("c/thumb".into(), 40);
+        assert_eq!(index.evict(100), vec!["b/thumb".to_string()]);
+        assert_eq!(index.total, 80);
+        assert!(index.evict(100).is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_thumbnail_of_replaced_bytes_is_not_kept() {
+        let db = crate::Database::file(":memory:")
+            .connections(1)
+            .with_init(crate::migrations::migrate)
+            .open()
+            .await
+            .unwrap();
+        let state = AppState::for_tests(db).await;
+
+        let mut png = std::io::Cursor::new(Vec::new());
+        image::DynamicImage::new_rgb8(32, 32)
+            .write_to(&mut png, image::ImageFormat::Png)
+            .unwrap();
+        let png = png.into_inner();
+        let hash = crate::scan::sha256_hex(&png);
+        state
+            .database()
+            .execute(
+                "INSERT INTO images (id, blob_hash, size, mimetype) VALUES ('i1', ?1, 1, 'image/png')",
+                boxed_params![hash],
+            )
+            .await
+            .unwrap();
+
+        // Rendered from the current bytes: kept.
+        assert!(state.thumbnail_for("i1", &png).await.is_some());
+        assert!(state.cached_thumbnail("i1").await.is_some());
+
+        // The bytes are replaced while a render of the old ones is running;
+        // it still answers its caller but isn't cached.
+        state
+            .database()
+            .execute("UPDATE images SET blob_hash = 'replaced' WHERE id = 'i1'", [])
+            .await
+            .unwrap();
+        state.forget_renditions("i1").await;
+        assert!(state.thumbnail_for("i1", &png).await.is_some());
+        assert!(state.cached_thumbnail("i1").await.is_none());
+    }
+}
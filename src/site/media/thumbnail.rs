@@ -3,13 +3,18 @@
 //! Decodes an uploaded image, downscales it to fit a small box, and re-encodes
 //! it. Photos (no alpha) become JPEG to keep the bytes tiny; images with
 //! transparency stay PNG so the checkerboard doesn't turn black. Animated
-//! inputs collapse to their first frame — fine for a static grid preview.
+//! inputs collapse to their first frame — fine for a static grid preview —
+//! unless animated thumbnails are switched on (`images.animated_thumbnails`),
+//! in which case [`generate_animated`] shrinks every frame and keeps the GIF or
+//! WebP moving.
 //!
 //! Anything the configured decoders can't read (e.g. AVIF in this build)
 //! returns `None`, and the caller falls back to serving the original bytes.
 
 use std::io::Cursor;
 
+use crate::animation::{self, Budget};
+
 /// Longest edge of a generated thumbnail, in pixels. Images already smaller
 /// than this in both dimensions are re-encoded but never upscaled.
 const MAX_DIM: u32 = 400;
@@ -29,13 +34,7 @@ pub fn generate(bytes: &[u8]) -> Option<(Vec<u8>, &'static str)> {
         .decode()
         .ok()?;
 
-    // `thumbnail` preserves aspect ratio and only ever fits *within* the box,
-    // so portrait/landscape both end up with their longest edge at MAX_DIM.
-    let thumb = if img.width() > MAX_DIM || img.height() > MAX_DIM {
-        img.thumbnail(MAX_DIM, MAX_DIM)
-    } else {
-        img
-    };
+    let thumb = fit_within(img, MAX_DIM, MAX_DIM);
 
     let mut out = Cursor::new(Vec::new());
     if thumb.color().has_alpha() {
@@ -48,6 +47,41 @@ pub fn generate(bytes: &[u8]) -> Option<(Vec<u8>, &'static str)> {
     }
 }
 
+/// Like [`generate`], but an animated GIF or WebP stays animated, in its own
+/// format. Stills, and animations over the decoding budget, get the usual
+/// still thumbnail.
+pub fn generate_animated(bytes: &[u8]) -> Option<(Vec<u8>, &'static str)> {
+    let animated = match animation::decode(bytes, Budget::default()) {
+        Ok(Some(anim)) => anim,
+        Ok(None) => return generate(bytes),
+        Err(e) => {
+            tracing::debug!(error = %e, "falling back to a still thumbnail");
+            return generate(bytes);
+        }
+    };
+    let format = animated.format;
+    let thumb = animated
+        .map(|frame| Ok::<_, ()>(fit_within(frame, MAX_DIM, MAX_DIM)))
+        .ok()?;
+    match thumb.encode(format) {
+        Ok(out) => Some((out, format.mime())),
+        Err(_) => generate(bytes),
+    }
+}
+
+/// Downscales `img` to fit inside a `max_width` × `max_height` box, keeping
+/// its aspect ratio. An image already inside the box is returned untouched —
+/// never upscaled.
+pub fn fit_within(img: image::DynamicImage, max_width: u32, max_height: u32) -> image::DynamicImage {
+    // `thumbnail` preserves aspect ratio and only ever fits *within* the box,
+    // so portrait/landscape both end up with their longest edge on the box.
+    if img.width() > max_width || img.height() > max_height {
+        img.thumbnail(max_width, max_height)
+    } else {
+        img
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -92,6 +126,31 @@ mod tests {
         assert_eq!(dims, (100, 50));
     }
 
+    #[test]
+    fn animated_thumbnails_keep_their_frames() {
+        use crate::animation::AnimatedFormat;
+        use image::{Delay, Frame};
+
+        let frame = |n: u8| {
+            let img = RgbaImage::from_pixel(800, 600, image::Rgba([n, n, n, 255]));
+            Frame::from_parts(img, 0, 0, Delay::from_numer_denom_ms(50, 1))
+        };
+        let mut gif = Vec::new();
+        {
+            let mut encoder = image::codecs::gif::GifEncoder::new(&mut gif);
+            encoder.encode_frames(vec![frame(0), frame(200)]).unwrap();
+        }
+
+        let (bytes, ct) = generate_animated(&gif).expect("should generate");
+        assert_eq!(ct, AnimatedFormat::Gif.mime());
+        let anim = animation::decode(&bytes, Budget::default()).unwrap().expect("animated");
+        assert_eq!((anim.frame_count(), anim.dimensions()), (2, (MAX_DIM, 300)));
+
+        // Stills fall through to the normal path.
+        let png = encode_png(DynamicImage::ImageRgb8(RgbImage::new(100, 50)));
+        assert_eq!(generate_animated(&png).unwrap().1, "image/jpeg");
+    }
+
     #[test]
     fn undecodable_input_returns_none() {
         assert!(generate(b"not an image at all").is_none());
@@ -0,0 +1,353 @@
+//! Resized and re-encoded renditions of gallery images.
+//!
+//! `/gallery/raw/<id>` takes `?w=`, `?h=`, `?fit=`, `?format=` and `?q=` so an
+//! embedding site can ask for the size and format it needs. [`VariantQuery`]
+//! is the raw query; [`Variant::resolve`] snaps it onto the sizes and
+//! qualities the config allows, and [`render`] does the work. Rendered
+//! variants are cached by image id plus the resolved parameters (see
+//! [`crate::AppState::image_variant`]), which is why the snapping matters: it
+//! bounds how many distinct variants one image can have.
+//!
+//! Like [`crate::thumbnail`], images are only ever scaled down, animated inputs
+//! collapse to their first frame, and an undecodable input (AVIF in this
+//! build) yields `None` so the caller serves the original.
+
+use std::io::Cursor;
+
+use image::imageops::FilterType;
+use image::DynamicImage;
+use serde::Deserialize;
+
+use crate::config::VariantConfig;
+
+/// JPEG quality when `?q=` isn't given, before snapping.
+const DEFAULT_QUALITY: u8 = 80;
+
+/// How a variant is fitted into the requested `w` × `h` box.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Fit {
+    /// Scale to fill the box, cropping the overflow (centred).
+    Cover,
+    /// Scale to fit inside the box, keeping the aspect ratio.
+    #[default]
+    Contain,
+    /// Stretch to exactly the box, ignoring the aspect ratio.
+    Fill,
+}
+
+impl Fit {
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Cover => "cover",
+            Self::Contain => "contain",
+            Self::Fill => "fill",
+        }
+    }
+}
+
+/// The output format a request asks for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RequestedFormat {
+    Webp,
+    #[serde(alias = "jpg")]
+    Jpeg,
+    Png,
+    /// WebP if the client's `Accept` allows it, otherwise the original format.
+    Auto,
+}
+
+/// The variant query parameters of `/gallery/raw/<id>`.
+#[derive(Debug, Default, Deserialize)]
+pub struct VariantQuery {
+    pub w: Option<u32>,
+    pub h: Option<u32>,
+    pub fit: Option<Fit>,
+    pub format: Option<RequestedFormat>,
+    pub q: Option<u8>,
+}
+
+impl VariantQuery {
+    /// Whether any variant parameter was given at all.
+    pub fn is_empty(&self) -> bool {
+        self.w.is_none() && self.h.is_none() && self.fit.is_none() && self.format.is_none() && self.q.is_none()
+    }
+}
+
+/// A fully resolved variant: allowed sizes, a concrete format.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variant {
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    pub fit: Fit,
+    /// The encoder name [`crate::site::api::media::encode_to`] takes.
+    pub format: &'static str,
+    pub quality: u8,
+    /// Whether the format was picked from `Accept`, so the response must vary
+    /// on it.
+    pub negotiated: bool,
+}
+
+impl Variant {
+    /// Resolves `query` for an image of type `mimetype`, or `None` when the
+    /// query asks for nothing the original doesn't already satisfy.
+    pub fn resolve(query: &VariantQuery, mimetype: &str, accept: Option<&str>, config: &VariantConfig) -> Option<Self> {
+        if query.is_empty() {
+            return None;
+        }
+
+        let width = query.w.and_then(|w| snap_size(w, &config.sizes));
+        let height = query.h.and_then(|h| snap_size(h, &config.sizes));
+        let original = match mimetype {
+            "image/jpeg" => "jpeg",
+            "image/webp" => "webp",
+            // Everything else the decoder reads is re-encoded losslessly.
+            _ => "png",
+        };
+        let (format, negotiated) = match query.format {
+            Some(RequestedFormat::Webp) => ("webp", false),
+            Some(RequestedFormat::Jpeg) => ("jpeg", false),
+            Some(RequestedFormat::Png) => ("png", false),
+            Some(RequestedFormat::Auto) => (if accepts_webp(accept) { "webp" } else { original }, true),
+            None => (original, false),
+        };
+        // Only JPEG has a quality knob; pinning it elsewhere keeps one cache
+        // entry per size for the lossless formats.
+        let quality = if format == "jpeg" {
+            snap_quality(query.q.unwrap_or(DEFAULT_QUALITY), &config.qualities)
+        } else {
+            100
+        };
+
+        let variant = Self {
+            width,
+            height,
+            fit: query.fit.unwrap_or_default(),
+            format,
+            quality,
+            negotiated,
+        };
+        let resizes = variant.width.is_some() || variant.height.is_some();
+        let converts = format_mime(format) != mimetype || (query.q.is_some() && format == "jpeg");
+        (resizes || converts).then_some(variant)
+    }
+
+    /// A string unique to this variant, for cache keys and `ETag`s.
+    pub fn key(&self) -> String {
+        let dim = |d: Option<u32>| d.map(|d| d.to_string()).unwrap_or_default();
+        format!(
+            "{}x{}-{}-q{}.{}",
+            dim(self.width),
+            dim(self.height),
+            self.fit.as_str(),
+            self.quality,
+            self.format
+        )
+    }
+}
+
+fn format_mime(format: &str) -> &'static str {
+    match format {
+        "jpeg" => "image/jpeg",
+        "webp" => "image/webp",
+        _ => "image/png",
+    }
+}
+
+/// Whether an `Accept` header lists `image/webp` with a non-zero `q`. A bare
+/// `*/*` doesn't count: plenty of clients send it without decoding WebP.
+fn accepts_webp(accept: Option<&str>) -> bool {
+    let Some(accept) = accept else { return false };
+    accept.split(',').any(|part| {
+        let mut segments = part.split(';').map(str::trim);
+        let media = segments.next().map(str::to_ascii_lowercase);
+        if !matches!(media.as_deref(), Some("image/webp")) {
+            return false;
+        }
+        !segments.any(|p| {
+            p.strip_prefix("q=")
+                .and_then(|q| q.trim().parse::<f32>().ok())
+                .is_some_and(|q| q <= 0.0)
+        })
+    })
+}
+
+/// Rounds `size` up to the next allowed size, or down to the largest.
+fn snap_size(size: u32, allowed: &[u32]) -> Option<u32> {
+    if size == 0 {
+        return None;
+    }
+    allowed
+        .iter()
+        .copied()
+        .filter(|&s| s >= size)
+        .min()
+        .or_else(|| allowed.iter().copied().max())
+}
+
+/// The allowed quality nearest to `quality`.
+fn snap_quality(quality: u8, allowed: &[u8]) -> u8 {
+    allowed
+        .iter()
+        .copied()
+        .filter(|q| (1..=100).contains(q))
+        .min_by_key(|&q| (q as i16 - quality as i16).abs())
+        .unwrap_or(DEFAULT_QUALITY)
+}
+
+/// Scales `img` for `variant`. Never upscales: a box larger than the image is
+/// shrunk (keeping its proportions) until it fits.
+fn resize(img: DynamicImage, variant: &Variant) -> DynamicImage {
+    let (src_w, src_h) = (img.width(), img.height());
+    let (w, h) = match (variant.width, variant.height) {
+        (None, None) => return img,
+        (Some(w), None) => (w, u32::MAX),
+        (None, Some(h)) => (u32::MAX, h),
+        (Some(w), Some(h)) => (w, h),
+    };
+
+    match variant.fit {
+        Fit::Contain => crate::thumbnail::fit_within(img, w, h),
+        // With only one side given there's no box to fill: fit instead.
+        _ if w == u32::MAX || h == u32::MAX => crate::thumbnail::fit_within(img, w, h),
+        fit => {
+            let scale = (src_w as f64 / w as f64).min(src_h as f64 / h as f64).min(1.0);
+            let w = ((w as f64 * scale).round() as u32).max(1);
+            let h = ((h as f64 * scale).round() as u32).max(1);
+            if fit == Fit::Cover {
+                img.resize_to_fill(w, h, FilterType::Lanczos3)
+            } else {
+                img.resize_exact(w, h, FilterType::Lanczos3)
+            }
+        }
+    }
+}
+
+/// Renders `variant` from the original image bytes, returning the encoded
+/// bytes and their MIME type, or `None` if the original can't be decoded.
+/// CPU-bound — call it from the blocking pool.
+pub fn render(bytes: &[u8], variant: &Variant) -> Option<(Vec<u8>, &'static str)> {
+    let img = image::ImageReader::new(Cursor::new(bytes))
+        .with_guessed_format()
+        .ok()?
+        .decode()
+        .ok()?;
+    let img = resize(img, variant);
+    let (out, mime, _) = crate::site::api::media::encode_to(&img, variant.format, variant.quality).ok()?;
+    Some((out, mime))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::RgbImage;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut buf = Cursor::new(Vec::new());
+        DynamicImage::ImageRgb8(RgbImage::new(width, height))
+            .write_to(&mut buf, image::ImageFormat::Png)
+            .unwrap();
+        buf.into_inner()
+    }
+
+    fn dims(bytes: &[u8]) -> (u32, u32) {
+        image::ImageReader::new(Cursor::new(bytes))
+            .with_guessed_format()
+            .unwrap()
+            .into_dimensions()
+            .unwrap()
+    }
+
+    fn query(w: Option<u32>, h: Option<u32>, fit: Option<Fit>) -> VariantQuery {
+        VariantQuery {
+            w,
+            h,
+            fit,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn sizes_and_qualities_snap_to_the_allowed_lists() {
+        assert_eq!(snap_size(300, &[128, 320, 640]), Some(320));
+        assert_eq!(snap_size(5000, &[128, 320, 640]), Some(640));
+        assert_eq!(snap_size(300, &[]), None);
+        assert_eq!(snap_quality(72, &[50, 65, 80, 90]), 65);
+        assert_eq!(snap_quality(99, &[50, 65, 80, 90]), 90);
+    }
+
+    #[test]
+    fn resolve_negotiates_and_skips_no_ops() {
+        let config = VariantConfig::default();
+        assert!(Variant::resolve(&VariantQuery::default(), "image/png", None, &config).is_none());
+        // Asking for the format the image already has is not a variant.
+        let same = VariantQuery {
+            format: Some(RequestedFormat::Png),
+            ..Default::default()
+        };
+        assert!(Variant::resolve(&same, "image/png", None, &config).is_none());
+
+        let auto = VariantQuery {
+            w: Some(300),
+            format: Some(RequestedFormat::Auto),
+            ..Default::default()
+        };
+        let webp = Variant::resolve(&auto, "image/jpeg", Some("image/avif,image/webp,*/*"), &config).unwrap();
+        assert_eq!((webp.format, webp.width, webp.negotiated), ("webp", Some(320), true));
+        let jpeg = Variant::resolve(&auto, "image/jpeg", Some("image/png,*/*"), &config).unwrap();
+        assert_eq!((jpeg.format, jpeg.quality), ("jpeg", 80));
+        assert_ne!(webp.key(), jpeg.key());
+    }
+
+    #[test]
+    fn fits_never_upscale() {
+        let config = VariantConfig::default();
+        let original = png(1000, 500);
+
+        let contain = Variant::resolve(&query(Some(256), Some(256), None), "image/png", None, &config).unwrap();
+        assert_eq!(dims(&render(&original, &contain).unwrap().0), (256, 128));
+
+        let cover = Variant::resolve(
+            &query(Some(256), Some(256), Some(Fit::Cover)),
+            "image/png",
+            None,
+            &config,
+        )
+        .unwrap();
+        assert_eq!(dims(&render(&original, &cover).unwrap().0), (256, 256));
+
+        let fill = Variant::resolve(
+            &query(Some(640), Some(128), Some(Fit::Fill)),
+            "image/png",
+            None,
+            &config,
+        )
+        .unwrap();
+        assert_eq!(dims(&render(&original, &fill).unwrap().0), (640, 128));
+
+        // A box bigger than the image shrinks to fit it, keeping its shape.
+        let big = Variant::resolve(
+            &query(Some(2560), Some(2560), Some(Fit::Cover)),
+            "image/png",
+            None,
+            &config,
+        )
+        .unwrap();
+        assert_eq!(dims(&render(&original, &big).unwrap().0), (500, 500));
+        let small = png(100, 50);
+        assert_eq!(dims(&render(&small, &contain).unwrap().0), (100, 50));
+    }
+
+    #[test]
+    fn undecodable_input_has_no_variant() {
+        let variant = Variant::resolve(
+            &query(Some(64), None, None),
+            "image/avif",
+            None,
+            &VariantConfig::default(),
+        )
+        .unwrap();
+        assert!(render(b"not an image", &variant).is_none());
+    }
+}
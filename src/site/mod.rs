@@ -1,5 +1,5 @@
-//! The public website surface at klappstuhl.me: the homepage, the image hoster,
-//! short links, the code screenshot tools,
+//! The public website surface at klappstuhl.me: the homepage, the image hoster
+//! and its albums, short links, the code screenshot tools,
 //! account/login pages, the public changelog, and the documented public JSON API
 //! (`site::api`). Each
 //! feature owns its handlers here; [`routes`] assembles them into the public
@@ -16,13 +16,26 @@ use axum::{
 };
 
 pub mod account;
+pub mod album;
 pub mod api;
 pub mod changelog;
 pub mod discord_oauth;
 pub mod image;
+pub mod image_import;
+pub mod image_lock;
+pub mod image_quota;
+pub mod image_revision;
+pub mod image_scan;
+pub mod link_analytics;
+pub mod link_blocklist;
+pub mod link_lock;
 pub mod links;
 pub mod media;
+pub mod oembed;
 pub mod paste;
+pub mod search;
+pub mod stats;
+pub mod trash;
 
 #[derive(Template)]
 #[template(path = "index.html")]
@@ -82,7 +95,9 @@ pub fn routes() -> Router<AppState> {
         .route("/projects", get(projects))
         .route("/percy", get(percy_redirect))
         .route("/m/:id", get(api::serve_media))
+        .route("/oembed", get(oembed::oembed))
         .merge(account::routes())
+        .merge(album::routes())
         .merge(changelog::routes())
         .merge(discord_oauth::routes())
         .merge(image::routes())
@@ -0,0 +1,290 @@
+//! The oEmbed provider (<https://oembed.com>) at `GET /oembed`.
+//!
+//! Chat and CMS tools that speak oEmbed hand us the URL someone pasted and get
+//! back how to embed it:
+//!
+//! - `/gallery/<id>` (and `/gallery/raw/<id>`) and shared media at `/m/<id>`
+//!   are `photo`s, pointing straight at the bytes;
+//! - `/p/<id>` is `rich`: an iframe of the bare `/p/<id>/embed` view;
+//! - a short link (`r.<domain>/<code>`, or `/r/<code>`) is a plain `link`.
+//!
+//! The consumer fetches this server-side, with no cookies, so it gets exactly
+//! what an anonymous visitor would: a private image is unknown (404), and one
+//! behind a password or a burn count, like an encrypted, burning or private
+//! paste or a short link with a password, is refused (401) rather than
+//! described. An upload held by the malware scan is unknown too. Only
+//! `format=json` is offered; asking for XML is a 501, as the spec says.
+//!
+//! The image and paste pages advertise this endpoint with a
+//! `<link rel="alternate" type="application/json+oembed">` tag.
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::models::Visibility;
+use crate::site::image::image_dimensions;
+use crate::site::links::{host_eq, live_link};
+use crate::site::paste::{pages::display_title, service};
+use crate::AppState;
+
+/// The iframe size a paste embed gets when the consumer doesn't say.
+const PASTE_WIDTH: u32 = 640;
+const PASTE_HEIGHT: u32 = 400;
+
+#[derive(Deserialize)]
+pub struct OembedQuery {
+    url: String,
+    #[serde(default)]
+    format: Option<String>,
+    #[serde(default)]
+    maxwidth: Option<u32>,
+    #[serde(default)]
+    maxheight: Option<u32>,
+}
+
+/// An oEmbed response. Which of the optional fields are set depends on `type`.
+#[derive(Debug, Serialize)]
+struct Oembed {
+    version: &'static str,
+    #[serde(rename = "type")]
+    kind: &'static str,
+    provider_name: &'static str,
+    provider_url: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    html: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    height: Option<u32>,
+}
+
+impl Oembed {
+    fn new(state: &AppState, kind: &'static str) -> Self {
+        Self {
+            version: "1.0",
+            kind,
+            provider_name: "klappstuhl.me",
+            provider_url: state.config().canonical_url(),
+            title: None,
+            author_name: None,
+            author_url: None,
+            url: None,
+            html: None,
+            width: None,
+            height: None,
+        }
+    }
+
+    /// Credits the account `account_id`, linking its public profile.
+    async fn by(mut self, state: &AppState, account_id: Option<i64>) -> Self {
+        if let Some(account) = match account_id {
+            Some(id) => state.get_account(id).await,
+            None => None,
+        } {
+            self.author_url = Some(state.config().url_to(format!("/user/{}", account.name)));
+            self.author_name = Some(account.name);
+        }
+        self
+    }
+
+    /// A `photo` of `url`, scaled down to the consumer's bounds, or a plain
+    /// `link` when the dimensions can't be read (the spec requires them).
+    fn photo(mut self, url: String, dimensions: Option<(u32, u32)>, query: &OembedQuery) -> Self {
+        match dimensions {
+            Some((width, height)) => {
+                let (width, height) = fit(width, height, query.maxwidth, query.maxheight);
+                self.kind = "photo";
+                self.url = Some(url);
+                self.width = Some(width);
+                self.height = Some(height);
+            }
+            None => self.kind = "link",
+        }
+        self
+    }
+}
+
+/// What an oEmbed URL points at.
+#[derive(Debug, PartialEq, Eq)]
+enum Resource {
+    Image(String),
+    Paste(String),
+    Media(String),
+    Link(String),
+}
+
+/// The resource at `path` on the main site, from its non-empty segments.
+/// Extensions (`abc.png`, `abc.txt`) are dropped; ids never contain a dot.
+fn route(segments: &[&str]) -> Option<Resource> {
+    let bare = |s: &str| s.split('.').next().unwrap_or(s).to_string();
+    match segments {
+        ["gallery", file] | ["gallery", "raw", file] => Some(Resource::Image(bare(file))),
+        ["p", id] | ["p", id, "embed"] => Some(Resource::Paste(bare(id))),
+        ["m", id] => Some(Resource::Media(id.to_string())),
+        ["r", code] => Some(Resource::Link(code.to_string())),
+        _ => None,
+    }
+}
+
+/// Parses a URL the consumer asked about, if it is one of ours.
+fn resource(state: &AppState, raw: &str) -> Option<Resource> {
+    let config = state.config();
+    let url = reqwest::Url::parse(raw).ok()?;
+    let host = url.host_str()?;
+    let segments: Vec<&str> = url.path_segments()?.filter(|s| !s.is_empty()).collect();
+
+    if config.production && host_eq(host, &config.short_domain()) {
+        return match segments[..] {
+            [code] => Some(Resource::Link(code.to_string())),
+            _ => None,
+        };
+    }
+    if !config.is_valid_host(host) {
+        return None;
+    }
+    route(&segments)
+}
+
+/// Scales `width` × `height` down to fit the consumer's `maxwidth` and
+/// `maxheight`, keeping the aspect ratio. Never scales up.
+fn fit(width: u32, height: u32, max_width: Option<u32>, max_height: Option<u32>) -> (u32, u32) {
+    let mut scale = 1.0f64;
+    if let Some(max) = max_width.filter(|&max| max > 0 && max < width) {
+        scale = scale.min(max as f64 / width as f64);
+    }
+    if let Some(max) = max_height.filter(|&max| max > 0 && max < height) {
+        scale = scale.min(max as f64 / height as f64);
+    }
+    let scaled = |n: u32| ((n as f64 * scale).round() as u32).max(1);
+    (scaled(width), scaled(height))
+}
+
+fn refuse(status: StatusCode) -> Response {
+    status.into_response()
+}
+
+/// `GET /oembed?url=…&format=json`
+pub async fn oembed(State(state): State<AppState>, Query(query): Query<OembedQuery>) -> Response {
+    match query.format.as_deref() {
+        None | Some("json") => {}
+        Some(_) => return refuse(StatusCode::NOT_IMPLEMENTED),
+    }
+    let Some(resource) = resource(&state, &query.url) else {
+        return refuse(StatusCode::NOT_FOUND);
+    };
+
+    let embed = match resource {
+        Resource::Image(id) => {
+            let Some(entry) = state.get_image_meta(id.clone()).await.filter(|e| !e.is_expired()) else {
+                return refuse(StatusCode::NOT_FOUND);
+            };
+            if entry.visibility == Visibility::Private || entry.is_held() {
+                return refuse(StatusCode::NOT_FOUND);
+            }
+            if !entry.is_open() {
+                return refuse(StatusCode::UNAUTHORIZED);
+            }
+            let dimensions = match state.resolve_image_data_for(&id).await {
+                Some(bytes) => image_dimensions(&bytes).await,
+                None => None,
+            };
+            let raw = state.config().url_to(format!("/gallery/raw/{id}.{}", entry.ext()));
+            let mut embed = Oembed::new(&state, "photo")
+                .photo(raw, dimensions, &query)
+                .by(&state, entry.uploader_id)
+                .await;
+            embed.title = Some(entry.download_name());
+            embed
+        }
+        Resource::Media(id) => {
+            let Some(media) = state.get_media(&id) else {
+                return refuse(StatusCode::NOT_FOUND);
+            };
+            let dimensions = image_dimensions(&media.bytes).await;
+            Oembed::new(&state, "photo").photo(state.config().url_to(format!("/m/{id}")), dimensions, &query)
+        }
+        Resource::Paste(id) => {
+            let Some(paste) = service::load(&state, &id).await else {
+                return refuse(StatusCode::NOT_FOUND);
+            };
+            // The embed view would refuse these too; a private paste is
+            // reachable by link but kept out of anything that lists it.
+            if paste.is_encrypted() || paste.burn_after_read || paste.visibility == Visibility::Private {
+                return refuse(StatusCode::UNAUTHORIZED);
+            }
+            let width = query
+                .maxwidth
+                .filter(|&w| w > 0)
+                .map_or(PASTE_WIDTH, |w| w.min(PASTE_WIDTH));
+            let height = query
+                .maxheight
+                .filter(|&h| h > 0)
+                .map_or(PASTE_HEIGHT, |h| h.min(PASTE_HEIGHT));
+            let src = state.config().url_to(format!("/p/{}/embed", paste.id));
+            let mut embed = Oembed::new(&state, "rich").by(&state, paste.account_id).await;
+            embed.title = Some(display_title(&paste));
+            embed.html = Some(format!(
+                r#"<iframe src="{src}" width="{width}" height="{height}" style="border:0" loading="lazy"></iframe>"#
+            ));
+            embed.width = Some(width);
+            embed.height = Some(height);
+            embed
+        }
+        Resource::Link(code) => {
+            let Some(link) = live_link(&state, &code).await else {
+                return refuse(StatusCode::NOT_FOUND);
+            };
+            if link.is_locked() {
+                return refuse(StatusCode::UNAUTHORIZED);
+            }
+            let mut embed = Oembed::new(&state, "link");
+            embed.title = Some(link.target_url);
+            embed
+        }
+    };
+    Json(embed).into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_our_urls_to_their_resources() {
+        assert_eq!(route(&["gallery", "abc.png"]), Some(Resource::Image("abc".into())));
+        assert_eq!(
+            route(&["gallery", "raw", "abc.png"]),
+            Some(Resource::Image("abc".into()))
+        );
+        assert_eq!(route(&["p", "xyz"]), Some(Resource::Paste("xyz".into())));
+        assert_eq!(route(&["p", "xyz.txt"]), Some(Resource::Paste("xyz".into())));
+        assert_eq!(route(&["p", "xyz", "embed"]), Some(Resource::Paste("xyz".into())));
+        assert_eq!(route(&["m", "m1"]), Some(Resource::Media("m1".into())));
+        assert_eq!(route(&["r", "docs"]), Some(Resource::Link("docs".into())));
+        assert_eq!(route(&["p", "xyz", "raw"]), None);
+        assert_eq!(route(&["account"]), None);
+        assert_eq!(route(&[]), None);
+    }
+
+    #[test]
+    fn fit_scales_down_and_keeps_the_aspect_ratio() {
+        assert_eq!(fit(1600, 900, None, None), (1600, 900));
+        assert_eq!(fit(1600, 900, Some(800), None), (800, 450));
+        assert_eq!(fit(1600, 900, Some(800), Some(300)), (533, 300));
+        // Never up, and a zero bound is ignored.
+        assert_eq!(fit(400, 300, Some(800), Some(0)), (400, 300));
+    }
+}
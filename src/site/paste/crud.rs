@@ -292,7 +292,12 @@ pub async fn delete(
             if json {
                 Json(serde_json::json!({ "ok": true, "redirect": destination })).into_response()
             } else {
-                flasher.add(FlashMessage::success("Paste deleted.")).bail(destination)
+                let message = if paste.account_id.is_some() {
+                    "Paste moved to the trash."
+                } else {
+                    "Paste deleted."
+                };
+                flasher.add(FlashMessage::success(message)).bail(destination)
             }
         }
         Err(error) => error_response(error, json, &flasher, &format!("/p/{id}")),
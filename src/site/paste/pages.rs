@@ -16,10 +16,12 @@ use cookie::Cookie;
 use serde::{Deserialize, Serialize};
 use time::OffsetDateTime;
 
+use crate::conditional::{self, Validators};
 use crate::filters; // the `isoformat` filter, used by the templates
 use crate::flash::Flashes;
 use crate::key::SecretKey;
 use crate::models::{Account, Paste, Visibility};
+use crate::site::stats::{self, ViewKind};
 use crate::AppState;
 
 use super::render;
@@ -136,6 +138,8 @@ struct ViewTemplate {
     visibility: &'static str,
     indexable: bool,
     is_owner: bool,
+    /// The owner's 30-day views chart (an inline SVG), once there are views.
+    view_chart: Option<String>,
     encrypted: bool,
     burn: bool,
     forked_from: Option<String>,
@@ -437,9 +441,10 @@ pub async fn view(
     Extension(secret): Extension<SecretKey>,
     account: Option<Account>,
     flashes: Flashes,
+    request: HeaderMap,
 ) -> Response {
     if let Some(bare) = id.strip_suffix(".txt") {
-        return raw_body(&state, bare, &cookies, &secret, false).await;
+        return raw_body(&state, bare, &cookies, &secret, &request, false).await;
     }
 
     let Some(paste) = service::load(&state, &id).await else {
@@ -450,7 +455,7 @@ pub async fn view(
     // A locked or sealed paste is never counted as read — the view count would
     // otherwise tick up for every link-preview crawler.
     if matches!(body, Body::Plain(_)) {
-        service::count_view(&state, &id).await;
+        service::count_view(&state, &id, stats::referrer_host(&state, &request)).await;
     }
 
     let config = state.config();
@@ -499,6 +504,14 @@ pub async fn view(
 
     let author = author_name(&state, paste.account_id).await;
     let revisions = service::revisions(&state, &paste.id).await.len();
+    let view_chart = if is_owner {
+        stats::load(&state, ViewKind::Paste, paste.id.clone(), stats::DEFAULT_DAYS)
+            .await
+            .ok()
+            .and_then(|s| stats::chart(&s))
+    } else {
+        None
+    };
 
     // The chip reads "Auto · Rust" when a language was inferred, plain "Auto"
     // when nothing was, or the picked label when it wasn't Auto at all.
@@ -521,6 +534,7 @@ pub async fn view(
         // private, anonymous, encrypted, burning — carries `noindex`.
         indexable: paste.visibility == Visibility::Public && !paste.burn_after_read && !paste.is_encrypted(),
         is_owner,
+        view_chart,
         encrypted: paste.is_encrypted(),
         burn: paste.burn_after_read,
         // A locked-and-burning paste takes the password in the reveal form: once
@@ -552,19 +566,23 @@ pub async fn raw(
     Path(id): Path<String>,
     Extension(cookies): Extension<Vec<Cookie<'static>>>,
     Extension(secret): Extension<SecretKey>,
+    request: HeaderMap,
 ) -> Response {
-    raw_body(&state, &id, &cookies, &secret, true).await
+    raw_body(&state, &id, &cookies, &secret, &request, true).await
 }
 
 /// The shared body of `/p/<id>.txt` (inline) and `/p/<id>/raw` (attachment).
 ///
 /// A locked paste 401s and a burning one 403s rather than serving anything: the
-/// raw path must never become the way around the gate the viewer puts up.
+/// raw path must never become the way around the gate the viewer puts up. The
+/// gate is checked before any validator, so a `304` is only ever an answer to
+/// a client that could have had the body.
 async fn raw_body(
     state: &AppState,
     id: &str,
     cookies: &[Cookie<'static>],
     secret: &SecretKey,
+    request: &HeaderMap,
     download: bool,
 ) -> Response {
     let Some(paste) = service::load(state, id).await else {
@@ -581,7 +599,11 @@ async fn raw_body(
                     headers.insert(header::CONTENT_DISPOSITION, value);
                 }
             }
-            (headers, text).into_response()
+            let validators = Validators::new(
+                &crate::scan::sha256_hex(text.as_bytes()),
+                Some(paste.updated_at.unwrap_or(paste.created_at)),
+            );
+            conditional::respond(request, &validators, headers, text.into()).await
         }
         Body::Locked | Body::Undecodable => {
             (StatusCode::UNAUTHORIZED, "this paste is password-protected").into_response()
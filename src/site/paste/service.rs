@@ -17,6 +17,8 @@ use rusqlite::OptionalExtension;
 use time::{Duration, OffsetDateTime};
 
 use crate::models::{Account, Paste, PasteRevision, Visibility};
+use crate::site::stats::{self, ViewKind};
+use crate::site::trash::{self, TrashKind};
 use crate::utils::get_new_image_id;
 use crate::AppState;
 
@@ -74,10 +76,10 @@ impl PasteError {
             Self::TitleTooLong => format!("Title is too long (max {MAX_TITLE_LEN} characters)."),
             Self::AnonymousDisabled => "Anonymous pastes are disabled — sign in to create one.".to_string(),
             Self::QuotaCount(limit) => {
-                format!("You've reached the limit of {limit} pastes — delete one to create another.")
+                format!("You've reached the limit of {limit} pastes — delete one and empty it from your trash to create another.")
             }
             Self::QuotaBytes(limit) => format!(
-                "You've reached your paste storage limit ({}) — delete one to free space.",
+                "You've reached your paste storage limit ({}) — delete one and empty your trash to free space.",
                 human_bytes(*limit)
             ),
             Self::SecretsFound(rules) | Self::SecretsRefused(rules) => {
@@ -450,7 +452,9 @@ pub async fn edit(
 
 // ─── Delete ──────────────────────────────────────────────────────────────────
 
-/// Deletes a paste. Revisions go with it via `ON DELETE CASCADE`.
+/// Deletes a paste. A paste with an owner goes to their trash (see
+/// [`crate::site::trash`]); an anonymous one is deleted outright, revisions
+/// going with it via `ON DELETE CASCADE`.
 pub async fn delete(
     state: &AppState,
     paste: &Paste,
@@ -461,14 +465,21 @@ pub async fn delete(
         return Err(PasteError::NotFound);
     }
 
-    state
-        .database()
-        .execute("DELETE FROM paste WHERE id = ?1", [paste.id.clone()])
-        .await
-        .map_err(|e| {
-            tracing::error!(error = %e, "failed to delete paste");
-            PasteError::Db
-        })?;
+    let result = match paste.account_id {
+        Some(owner) => trash::discard(state, TrashKind::Paste, owner, &paste.id)
+            .await
+            .map(drop),
+        None => state
+            .database()
+            .execute("DELETE FROM paste WHERE id = ?1", [paste.id.clone()])
+            .await
+            .map(drop)
+            .map_err(Into::into),
+    };
+    result.map_err(|e| {
+        tracing::error!(error = %e, "failed to delete paste");
+        PasteError::Db
+    })?;
 
     let mut audit = state.audit("paste.delete").target(paste.id.clone()).ip_opt(client_ip);
     if let Some(account) = actor.account {
@@ -517,12 +528,13 @@ pub async fn fork(
 
 // ─── Reads ───────────────────────────────────────────────────────────────────
 
-/// Loads a paste by id, unless it has expired. Every read path starts here.
+/// Loads a paste by id, unless it has expired or is in the trash. Every read
+/// path starts here.
 pub async fn load(state: &AppState, id: &str) -> Option<Paste> {
     state
         .database()
         .get(
-            "SELECT * FROM paste WHERE id = ?1 \
+            "SELECT * FROM paste WHERE id = ?1 AND deleted_at IS NULL \
              AND (expires_at IS NULL OR datetime(expires_at) > datetime('now'))",
             [id.to_string()],
         )
@@ -547,7 +559,7 @@ pub async fn list_for_account(state: &AppState, account_id: i64) -> Vec<Paste> {
     state
         .database()
         .all(
-            "SELECT * FROM paste WHERE account_id = ?1 \
+            "SELECT * FROM paste WHERE account_id = ?1 AND deleted_at IS NULL \
              AND (expires_at IS NULL OR datetime(expires_at) > datetime('now')) \
              ORDER BY created_at DESC",
             [account_id],
@@ -561,7 +573,7 @@ pub async fn list_public(state: &AppState, account_id: i64, limit: i64) -> Vec<P
     state
         .database()
         .all(
-            "SELECT * FROM paste WHERE account_id = ?1 AND visibility = 'public' \
+            "SELECT * FROM paste WHERE account_id = ?1 AND visibility = 'public' AND deleted_at IS NULL \
              AND (expires_at IS NULL OR datetime(expires_at) > datetime('now')) \
              ORDER BY created_at DESC LIMIT ?2",
             (account_id, limit),
@@ -582,12 +594,14 @@ pub async fn revisions(state: &AppState, id: &str) -> Vec<PasteRevision> {
         .unwrap_or_default()
 }
 
-/// Counts a view. Best-effort — a read is never blocked on it.
-pub async fn count_view(state: &AppState, id: &str) {
+/// Counts a view, and adds it to the day's stats under `referrer` (a host, or
+/// `None` for a direct visit). Best-effort — a read is never blocked on it.
+pub async fn count_view(state: &AppState, id: &str, referrer: Option<String>) {
     let _ = state
         .database()
         .execute("UPDATE paste SET views = views + 1 WHERE id = ?1", [id.to_string()])
         .await;
+    stats::record(state, ViewKind::Paste, id, referrer).await;
 }
 
 // ─── Burn-after-read ─────────────────────────────────────────────────────────
@@ -606,7 +620,7 @@ pub async fn burn(state: &AppState, id: &str) -> Option<Paste> {
         .database()
         .call(move |conn| {
             conn.query_row(
-                "DELETE FROM paste WHERE id = ?1 \
+                "DELETE FROM paste WHERE id = ?1 AND deleted_at IS NULL \
                  AND (expires_at IS NULL OR datetime(expires_at) > datetime('now')) \
                  RETURNING *",
                 [id],
@@ -713,7 +727,8 @@ fn dedupe(mut rules: Vec<String>) -> Vec<String> {
 
 // ─── The reaper ──────────────────────────────────────────────────────────────
 
-/// Hourly housekeeping: delete expired pastes, prune revision history past
+/// Hourly housekeeping: delete expired pastes and trashed ones past the
+/// restore window, prune revision history past
 /// [`REVISION_CAP`], and forget the `creator_ip` of anonymous pastes once they
 /// are older than the anonymous retention window (no indefinite IP retention).
 pub fn spawn_paste_reaper(state: AppState) {
@@ -737,6 +752,12 @@ pub fn spawn_paste_reaper(state: AppState) {
                 tracing::info!(count = deleted, "reaped expired pastes");
             }
 
+            match trash::reap(&state, TrashKind::Paste).await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!(count, "purged pastes from the trash"),
+                Err(e) => tracing::warn!(error = %e, "failed to purge pastes from the trash"),
+            }
+
             let _ = state
                 .database()
                 .execute(
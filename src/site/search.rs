@@ -0,0 +1,278 @@
+//! Full-text search over an account's pastes, images and short links.
+//!
+//! The index is SQLite FTS5 (see `sql/8.sql`) and is maintained entirely by
+//! triggers, so nothing that writes pastes, images or links has to know it
+//! exists. This module turns what someone typed into a query FTS5 can't choke
+//! on and reads the hits back — only ever from the caller's own documents.
+//! Encrypted paste bodies are never indexed; their titles are.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::{boxed_params, AppState};
+
+/// The longest query accepted, in bytes.
+pub const MAX_QUERY_LEN: usize = 200;
+/// Words past this many are ignored.
+const MAX_TERMS: usize = 16;
+/// Hits returned when the caller doesn't say.
+pub const DEFAULT_LIMIT: u32 = 25;
+/// The most hits one search returns.
+pub const MAX_LIMIT: u32 = 100;
+
+/// What a search hit is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SearchKind {
+    Image,
+    Paste,
+    Link,
+}
+
+impl SearchKind {
+    pub const ALL: [SearchKind; 3] = [SearchKind::Image, SearchKind::Paste, SearchKind::Link];
+
+    /// The `search_document.kind` value.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SearchKind::Image => "image",
+            SearchKind::Paste => "paste",
+            SearchKind::Link => "link",
+        }
+    }
+
+    fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.as_str() == s)
+    }
+}
+
+/// One search result.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Hit {
+    pub kind: SearchKind,
+    /// The image id, paste id or link code.
+    pub id: String,
+    /// The paste title, image file name or link code.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// The stretch of text around the match.
+    pub snippet: String,
+    /// Where the thing lives: the image page, the paste, or the short link.
+    pub url: String,
+}
+
+/// Turns free text into an FTS5 query: each word becomes a quoted prefix term
+/// and every one of them has to match. Quoting makes FTS5 take operators and
+/// punctuation in the input literally instead of parsing them, so no input can
+/// make the `MATCH` fail. `None` when nothing searchable is left.
+pub fn fts_query(input: &str) -> Option<String> {
+    let terms: Vec<String> = input
+        .split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphanumeric))
+        .take(MAX_TERMS)
+        .map(|word| format!("\"{}\"*", word.replace('"', "\"\"")))
+        .collect();
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Searches `account_id`'s documents of the given kinds, best match first.
+/// Expired images and pastes that haven't been reaped yet are left out.
+pub async fn search(
+    state: &AppState,
+    account_id: i64,
+    input: &str,
+    kinds: &[SearchKind],
+    limit: u32,
+) -> anyhow::Result<Vec<Hit>> {
+    let Some(query) = fts_query(input) else {
+        return Ok(Vec::new());
+    };
+    if kinds.is_empty() {
+        return Ok(Vec::new());
+    }
+    let kinds = kinds.iter().map(|kind| kind.as_str()).collect::<Vec<_>>().join(",");
+    let limit = limit.clamp(1, MAX_LIMIT);
+
+    let rows: Vec<(String, String, Option<String>, String)> = state
+        .database()
+        .call(move |conn| {
+            let mut stmt = conn.prepare_cached(
+                "SELECT d.kind, CASE d.kind WHEN 'link' THEN l.code ELSE d.ref END, d.title, snippet(search_fts, -1, '', '', '…', 16) \
+                 FROM search_fts \
+                 JOIN search_document d ON d.id = search_fts.rowid \
+                 LEFT JOIN images i ON d.kind = 'image' AND i.id = d.ref \
+                 LEFT JOIN paste p ON d.kind = 'paste' AND p.id = d.ref \
+                 LEFT JOIN short_link l ON d.kind = 'link' AND l.id = d.ref \
+                 WHERE search_fts MATCH ?1 AND d.account_id = ?2 \
+                   AND instr(',' || ?3 || ',', ',' || d.kind || ',') > 0 \
+                   AND i.deleted_at IS NULL AND p.deleted_at IS NULL AND l.deleted_at IS NULL \
+                   AND (i.expires_at IS NULL OR datetime(i.expires_at) > datetime('now')) \
+                   AND (p.expires_at IS NULL OR datetime(p.expires_at) > datetime('now')) \
+                 ORDER BY rank LIMIT ?4",
+            )?;
+            let rows: rusqlite::Result<Vec<_>> = stmt
+                .query_map(boxed_params![query, account_id, kinds, limit], |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+                })?
+                .collect();
+            rows
+        })
+        .await?;
+
+    let config = state.config();
+    Ok(rows
+        .into_iter()
+        .filter_map(|(kind, id, title, snippet)| {
+            let kind = SearchKind::from_str(&kind)?;
+            let url = match kind {
+                SearchKind::Image => config.url_to(format!("/gallery/{id}")),
+                SearchKind::Paste => config.url_to(format!("/p/{id}")),
+                SearchKind::Link => config.short_link_url(&id),
+            };
+            Some(Hit {
+                kind,
+                id,
+                title,
+                snippet,
+                url,
+            })
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn queries_become_quoted_prefix_terms() {
+        assert_eq!(
+            fts_query("holiday  photos").as_deref(),
+            Some("\"holiday\"* \"photos\"*")
+        );
+        assert_eq!(fts_query("say \"hi\"").as_deref(), Some("\"say\"* \"\"\"hi\"\"\"*"));
+        assert_eq!(fts_query("NOT OR AND"), Some("\"NOT\"* \"OR\"* \"AND\"*".to_string()));
+        assert_eq!(fts_query("  - * ( "), None);
+        assert_eq!(fts_query(""), None);
+    }
+
+    async fn state() -> AppState {
+        let db = crate::Database::file(":memory:")
+            .connections(1)
+            .with_init(crate::migrations::migrate)
+            .open()
+            .await
+            .unwrap();
+        for (id, name) in [(1, "owner"), (2, "other")] {
+            db.execute(
+                "INSERT INTO account (id, name, password) VALUES (?1, ?2, 'x')",
+                boxed_params![id, name],
+            )
+            .await
+            .unwrap();
+        }
+        AppState::for_tests(db).await
+    }
+
+    async fn ids(state: &AppState, account_id: i64, input: &str) -> Vec<String> {
+        let mut ids: Vec<String> = search(state, account_id, input, &SearchKind::ALL, MAX_LIMIT)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|hit| hit.id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[tokio::test]
+    async fn finds_only_the_callers_own_documents() {
+        let state = state().await;
+        let db = state.database();
+        db.execute(
+            "INSERT INTO paste (id, account_id, title, content) VALUES ('p1', 1, 'Deploy notes', CAST('restart the ingest worker' AS BLOB))",
+            [],
+        )
+        .await
+        .unwrap();
+        db.execute(
+            "INSERT INTO paste (id, account_id, title, content) VALUES ('p2', 2, 'Deploy notes', CAST('ingest' AS BLOB))",
+            [],
+        )
+        .await
+        .unwrap();
+        db.execute(
+            "INSERT INTO images (id, mimetype, uploader_id, original_name) VALUES ('i1', 'image/png', 1, 'ingest-diagram.png')",
+            [],
+        )
+        .await
+        .unwrap();
+        db.execute(
+            "INSERT INTO short_link (code, target_url, account_id) VALUES ('docs', 'https://example.com/ingest', 1)",
+            [],
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(ids(&state, 1, "ingest").await, ["docs", "i1", "p1"]);
+        assert_eq!(ids(&state, 1, "deploy ingest").await, ["p1"]);
+        assert_eq!(ids(&state, 2, "ingest").await, ["p2"]);
+        assert_eq!(ids(&state, 1, "diag").await, ["i1"]);
+
+        let pastes_only = search(&state, 1, "ingest", &[SearchKind::Paste], 10).await.unwrap();
+        assert_eq!(pastes_only.len(), 1);
+        assert_eq!(pastes_only[0].kind, SearchKind::Paste);
+        assert!(pastes_only[0].url.ends_with("/p/p1"));
+    }
+
+    #[tokio::test]
+    async fn the_index_follows_edits_deletes_and_encryption() {
+        let state = state().await;
+        let db = state.database();
+        db.execute(
+            "INSERT INTO paste (id, account_id, title, content) VALUES ('p1', 1, 'Recipe', CAST('sourdough starter' AS BLOB))",
+            [],
+        )
+        .await
+        .unwrap();
+        assert_eq!(ids(&state, 1, "sourdough").await, ["p1"]);
+
+        db.execute(
+            "UPDATE paste SET content = CAST('focaccia' AS BLOB) WHERE id = 'p1'",
+            [],
+        )
+        .await
+        .unwrap();
+        assert!(ids(&state, 1, "sourdough").await.is_empty());
+        assert_eq!(ids(&state, 1, "focaccia").await, ["p1"]);
+
+        // Sealing the body takes it out of the index; the title stays.
+        db.execute(
+            "UPDATE paste SET content = X'00FF', enc_salt = X'01', enc_nonce = X'02' WHERE id = 'p1'",
+            [],
+        )
+        .await
+        .unwrap();
+        assert!(ids(&state, 1, "focaccia").await.is_empty());
+        assert_eq!(ids(&state, 1, "recipe").await, ["p1"]);
+
+        db.execute("DELETE FROM paste WHERE id = 'p1'", []).await.unwrap();
+        assert!(ids(&state, 1, "recipe").await.is_empty());
+
+        // A link is found under its current code after a rename, and only once.
+        db.execute(
+            "INSERT INTO short_link (code, target_url, account_id) VALUES ('menu', 'https://example.com/bakery', 1)",
+            [],
+        )
+        .await
+        .unwrap();
+        db.execute("UPDATE short_link SET code = 'bakery-menu' WHERE code = 'menu'", [])
+            .await
+            .unwrap();
+        assert_eq!(ids(&state, 1, "bakery").await, ["bakery-menu"]);
+        db.execute("DELETE FROM short_link WHERE code = 'bakery-menu'", [])
+            .await
+            .unwrap();
+        assert!(ids(&state, 1, "bakery").await.is_empty());
+    }
+}
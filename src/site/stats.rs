@@ -0,0 +1,307 @@
+//! Per-day view statistics for images, pastes and short links.
+//!
+//! The `views`/`clicks` columns are running totals and say nothing about when
+//! or from where. Every counted view is also added to `view_stat` (see
+//! `sql/10.sql`), bucketed by UTC day and referring host, and this module
+//! reads the buckets back as a zero-filled series — the same shape
+//! `/api/v1/me/usage` returns, so it drops straight into `POST /render/chart`.
+//!
+//! Recording is best-effort, like the counters it sits next to: a failed
+//! insert is logged and the view goes on. Only the host of a referrer is ever
+//! stored, never the full URL.
+
+use std::collections::HashMap;
+
+use axum::http::{header, HeaderMap};
+use serde::{Deserialize, Serialize};
+use time::{Date, Duration, OffsetDateTime};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::site::account::insights::{host_of, is_own_host};
+use crate::site::api::chart::{render_svg, ChartKind, ChartRequest, ChartSeries, ChartTheme, DataPoint};
+use crate::{boxed_params, AppState};
+
+/// Days of history returned when the caller doesn't say.
+pub const DEFAULT_DAYS: i64 = 30;
+/// The longest window a caller may ask for.
+pub const MAX_DAYS: i64 = 90;
+/// Distinct referring hosts kept per thing per day. Views from further hosts
+/// that day are pooled under [`OTHER`], so a flood of junk Referer headers
+/// can't grow the table without bound.
+const MAX_REFERRERS_PER_DAY: i64 = 50;
+/// Referrers listed in a [`ViewStats`], busiest first.
+const TOP_REFERRERS: i64 = 10;
+
+/// The stored referrer for direct visits and internal navigation.
+const DIRECT: &str = "";
+/// The stored referrer for the overflow past [`MAX_REFERRERS_PER_DAY`].
+const OTHER: &str = "*";
+
+/// What was viewed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ViewKind {
+    Image,
+    Paste,
+    Link,
+}
+
+impl ViewKind {
+    /// The `view_stat.kind` value.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ViewKind::Image => "image",
+            ViewKind::Paste => "paste",
+            ViewKind::Link => "link",
+        }
+    }
+}
+
+/// The referring host of a request, if it came from another site.
+///
+/// Missing or unparsable headers and links from our own domains all count as
+/// direct (`None`).
+pub fn referrer_host(state: &AppState, headers: &HeaderMap) -> Option<String> {
+    let referrer = headers.get(header::REFERER)?.to_str().ok()?;
+    let host = host_of(referrer)?;
+    (!is_own_host(&host, &state.config().domains)).then_some(host)
+}
+
+/// Adds one view of `id` to today's bucket for `referrer`.
+pub async fn record(state: &AppState, kind: ViewKind, id: impl Into<String>, referrer: Option<String>) {
+    let id = id.into();
+    let referrer = referrer.unwrap_or_default();
+    // The host is only stored as itself while the day has room for it (or
+    // already has it); otherwise the view joins the overflow bucket.
+    let result = state
+        .database()
+        .execute(
+            "INSERT INTO view_stat (kind, ref, day, referrer, views) \
+             VALUES (?1, ?2, strftime('%Y-%m-%d', 'now'), \
+                     CASE WHEN ?3 = '' \
+                            OR EXISTS (SELECT 1 FROM view_stat WHERE kind = ?1 AND ref = ?2 \
+                                       AND day = strftime('%Y-%m-%d', 'now') AND referrer = ?3) \
+                            OR (SELECT COUNT(*) FROM view_stat WHERE kind = ?1 AND ref = ?2 \
+                                AND day = strftime('%Y-%m-%d', 'now')) < ?4 \
+                          THEN ?3 ELSE ?5 END, \
+                     1) \
+             ON CONFLICT (kind, ref, day, referrer) DO UPDATE SET views = views + 1",
+            boxed_params![kind.as_str(), id, referrer, MAX_REFERRERS_PER_DAY, OTHER],
+        )
+        .await;
+    if let Err(e) = result {
+        tracing::warn!(error = %e, kind = kind.as_str(), "failed to record a view");
+    }
+}
+
+/// Query parameters for the `/stats` endpoints.
+#[derive(Debug, Deserialize, IntoParams)]
+pub struct StatsQuery {
+    /// How many days of history to return, 1 to 90. Defaults to 30.
+    pub days: Option<i64>,
+}
+
+impl StatsQuery {
+    /// The requested window, or an error message naming the allowed range.
+    pub fn days(&self) -> Result<i64, String> {
+        match self.days {
+            None => Ok(DEFAULT_DAYS),
+            Some(days) if (1..=MAX_DAYS).contains(&days) => Ok(days),
+            Some(_) => Err(format!("must be between 1 and {MAX_DAYS}")),
+        }
+    }
+}
+
+/// A zero-filled per-day view series (oldest first), chart-ready: `days` are
+/// the x labels, `views` is the series.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ViewSeries {
+    /// The day of each bucket (`YYYY-MM-DD`, UTC), oldest first.
+    pub days: Vec<String>,
+    /// Views on each day.
+    pub views: Vec<i64>,
+}
+
+/// Views from one referring site.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ReferrerViews {
+    /// The referring host, `direct` for visits without a usable referrer, or
+    /// `other` for the long tail on busy days.
+    pub referrer: String,
+    /// Views from it within the window.
+    pub views: i64,
+}
+
+/// View statistics for one image, paste or short link.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ViewStats {
+    /// Views within the window. The all-time total is the resource's own
+    /// `views` / `clicks` count.
+    pub total: i64,
+    /// Views per day, ready to plot.
+    pub series: ViewSeries,
+    /// The busiest referrers within the window (at most 10).
+    pub referrers: Vec<ReferrerViews>,
+}
+
+/// Loads the last `days` days of statistics for `id`.
+pub async fn load(state: &AppState, kind: ViewKind, id: impl Into<String>, days: i64) -> anyhow::Result<ViewStats> {
+    let id = id.into();
+    let today = OffsetDateTime::now_utc().date();
+    let since = (today - Duration::days(days - 1)).to_string();
+
+    let (buckets, referrers) = state
+        .database()
+        .call(move |conn| -> rusqlite::Result<_> {
+            let mut stmt = conn.prepare_cached(
+                "SELECT day, SUM(views) FROM view_stat \
+                 WHERE kind = ?1 AND ref = ?2 AND day >= ?3 GROUP BY day",
+            )?;
+            let buckets: HashMap<String, i64> = stmt
+                .query_map(rusqlite::params![kind.as_str(), id, since], |row| {
+                    Ok((row.get(0)?, row.get(1)?))
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+
+            let mut stmt = conn.prepare_cached(
+                "SELECT referrer, SUM(views) AS total FROM view_stat \
+                 WHERE kind = ?1 AND ref = ?2 AND day >= ?3 \
+                 GROUP BY referrer ORDER BY total DESC, referrer LIMIT ?4",
+            )?;
+            let referrers: Vec<ReferrerViews> = stmt
+                .query_map(rusqlite::params![kind.as_str(), id, since, TOP_REFERRERS], |row| {
+                    let referrer: String = row.get(0)?;
+                    Ok(ReferrerViews {
+                        referrer: match referrer.as_str() {
+                            DIRECT => "direct".to_string(),
+                            OTHER => "other".to_string(),
+                            _ => referrer,
+                        },
+                        views: row.get(1)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+
+            Ok((buckets, referrers))
+        })
+        .await?;
+
+    let series = zero_filled(today, days, &buckets);
+    Ok(ViewStats {
+        total: series.views.iter().sum(),
+        series,
+        referrers,
+    })
+}
+
+/// The `days` days ending on `today`, with the buckets' counts filled in.
+fn zero_filled(today: Date, days: i64, buckets: &HashMap<String, i64>) -> ViewSeries {
+    let mut series = ViewSeries {
+        days: Vec::with_capacity(days as usize),
+        views: Vec::with_capacity(days as usize),
+    };
+    for offset in (0..days).rev() {
+        let day = (today - Duration::days(offset)).to_string();
+        series.views.push(buckets.get(&day).copied().unwrap_or(0));
+        series.days.push(day);
+    }
+    series
+}
+
+/// A small SVG area chart of the series, for an owner's page. `None` when
+/// there is nothing to draw.
+pub fn chart(stats: &ViewStats) -> Option<String> {
+    if stats.total == 0 {
+        return None;
+    }
+    let request = ChartRequest {
+        kind: ChartKind::Area,
+        title: None,
+        series: vec![ChartSeries {
+            label: "Views".into(),
+            data: stats.series.views.iter().map(|&v| DataPoint::Y(v as f64)).collect(),
+        }],
+        // `MM-DD` is enough to read the axis and keeps the labels from colliding.
+        labels: stats
+            .series
+            .days
+            .iter()
+            .map(|d| d.get(5..).unwrap_or(d).to_string())
+            .collect(),
+        theme: ChartTheme::Dark,
+        width: Some(640),
+        height: Some(240),
+        y_label: None,
+        x_label: None,
+    };
+    render_svg(&request).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn state() -> AppState {
+        let db = crate::Database::file(":memory:")
+            .connections(1)
+            .with_init(crate::migrations::migrate)
+            .open()
+            .await
+            .unwrap();
+        AppState::for_tests(db).await
+    }
+
+    #[tokio::test]
+    async fn views_are_bucketed_by_referrer() {
+        let state = state().await;
+        record(&state, ViewKind::Paste, "abc", None).await;
+        record(&state, ViewKind::Paste, "abc", Some("news.ycombinator.com".into())).await;
+        record(&state, ViewKind::Paste, "abc", Some("news.ycombinator.com".into())).await;
+        // Same id, different kind: counted separately.
+        record(&state, ViewKind::Image, "abc", None).await;
+
+        let stats = load(&state, ViewKind::Paste, "abc", 7).await.unwrap();
+        assert_eq!(stats.total, 3);
+        assert_eq!(stats.series.days.len(), 7);
+        assert_eq!(stats.series.views[..6], [0; 6]);
+        assert_eq!(stats.series.views[6], 3);
+        assert_eq!(stats.referrers[0].referrer, "news.ycombinator.com");
+        assert_eq!(stats.referrers[0].views, 2);
+        assert_eq!(stats.referrers[1].referrer, "direct");
+    }
+
+    #[tokio::test]
+    async fn referrers_past_the_daily_cap_are_pooled() {
+        let state = state().await;
+        for i in 0..MAX_REFERRERS_PER_DAY + 5 {
+            record(&state, ViewKind::Link, "1", Some(format!("site{i}.example"))).await;
+        }
+        // A host seen before the cap was hit keeps its own bucket.
+        record(&state, ViewKind::Link, "1", Some("site0.example".into())).await;
+
+        let rows: i64 = state
+            .database()
+            .get_row(
+                "SELECT COUNT(*) FROM view_stat WHERE kind = 'link' AND ref = '1'",
+                [],
+                |row| row.get(0),
+            )
+            .await
+            .unwrap();
+        assert_eq!(rows, MAX_REFERRERS_PER_DAY + 1);
+
+        let stats = load(&state, ViewKind::Link, "1", 1).await.unwrap();
+        assert_eq!(stats.total, MAX_REFERRERS_PER_DAY + 6);
+        assert_eq!(stats.referrers[0].referrer, "other");
+        assert_eq!(stats.referrers[0].views, 5);
+        assert_eq!(stats.referrers[1].referrer, "site0.example");
+        assert_eq!(stats.referrers[1].views, 2);
+    }
+
+    #[test]
+    fn window_is_validated() {
+        assert_eq!(StatsQuery { days: None }.days(), Ok(DEFAULT_DAYS));
+        assert_eq!(StatsQuery { days: Some(90) }.days(), Ok(90));
+        assert!(StatsQuery { days: Some(0) }.days().is_err());
+        assert!(StatsQuery { days: Some(91) }.days().is_err());
+    }
+}
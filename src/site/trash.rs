@@ -0,0 +1,346 @@
+//! The trash: deleted images, pastes and short links, restorable for a while.
+//!
+//! When an owner deletes one of these it is only marked — `deleted_at` is set
+//! on the row — and every read path skips marked rows, so to everyone else it
+//! is gone at once. For `trash.retention_days` the owner can restore it from
+//! `/account/trash` or `/api/v1/trash`, or purge it early; after that the
+//! hourly reapers ([`reap`]) delete it for real, and only then do the delete
+//! triggers (blob references, search documents, view stats) run.
+//!
+//! Anonymous pastes have no owner to restore them, so deleting one is still
+//! immediate. So is deleting a whole account, and an admin block.
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+use utoipa::ToSchema;
+
+use crate::{boxed_params, AppState};
+
+/// What is in the trash.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TrashKind {
+    Image,
+    Paste,
+    Link,
+}
+
+impl TrashKind {
+    pub const ALL: [TrashKind; 3] = [TrashKind::Image, TrashKind::Paste, TrashKind::Link];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TrashKind::Image => "image",
+            TrashKind::Paste => "paste",
+            TrashKind::Link => "link",
+        }
+    }
+
+    fn from_str(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|kind| kind.as_str() == s)
+    }
+
+    /// The table, its owner column, and the column items are addressed by.
+    /// Links go by code, as they do everywhere else in the API.
+    fn columns(self) -> (&'static str, &'static str, &'static str) {
+        match self {
+            TrashKind::Image => ("images", "uploader_id", "id"),
+            TrashKind::Paste => ("paste", "account_id", "id"),
+            TrashKind::Link => ("short_link", "account_id", "code"),
+        }
+    }
+}
+
+/// One item in an account's trash.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TrashItem {
+    pub kind: TrashKind,
+    /// The image id, paste id or link code.
+    pub id: String,
+    /// The image's file name, the paste's title or the link's target, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    /// When it was deleted.
+    #[serde(with = "time::serde::rfc3339")]
+    pub deleted_at: OffsetDateTime,
+    /// When it will be removed for good.
+    #[serde(with = "time::serde::rfc3339")]
+    pub purge_at: OffsetDateTime,
+}
+
+/// Days a deleted item stays restorable. Never less than one.
+pub fn retention_days(state: &AppState) -> i64 {
+    state.config().trash.retention_days.max(1)
+}
+
+/// Moves an item owned by `owner_id` to the trash. `false` if there was no such
+/// live item.
+pub async fn discard(state: &AppState, kind: TrashKind, owner_id: i64, id: &str) -> anyhow::Result<bool> {
+    let (table, owner, key) = kind.columns();
+    let sql = format!(
+        "UPDATE {table} SET deleted_at = strftime('%Y-%m-%dT%H:%M:%fZ', 'now') \
+         WHERE {owner} = ?1 AND {key} = ?2 AND deleted_at IS NULL"
+    );
+    let id = id.to_string();
+    let changed = state
+        .database()
+        .call(move |conn| conn.execute(&sql, rusqlite::params![owner_id, id]))
+        .await?;
+    Ok(changed > 0)
+}
+
+/// The account's trash, most recently deleted first, limited to `kinds`.
+pub async fn list(state: &AppState, account_id: i64, kinds: &[TrashKind]) -> anyhow::Result<Vec<TrashItem>> {
+    if kinds.is_empty() {
+        return Ok(Vec::new());
+    }
+    let kinds = kinds.iter().map(|kind| kind.as_str()).collect::<Vec<_>>().join(",");
+    let rows: Vec<(String, String, Option<String>, OffsetDateTime)> = state
+        .database()
+        .call(move |conn| {
+            let mut stmt = conn.prepare_cached(
+                "SELECT kind, id, title, deleted_at FROM ( \
+                     SELECT 'image' AS kind, id, original_name AS title, deleted_at FROM images \
+                      WHERE uploader_id = ?1 AND deleted_at IS NOT NULL \
+                     UNION ALL \
+                     SELECT 'paste', id, NULLIF(title, ''), deleted_at FROM paste \
+                      WHERE account_id = ?1 AND deleted_at IS NOT NULL \
+                     UNION ALL \
+                     SELECT 'link', code, target_url, deleted_at FROM short_link \
+                      WHERE account_id = ?1 AND deleted_at IS NOT NULL \
+                 ) \
+                 WHERE instr(',' || ?2 || ',', ',' || kind || ',') > 0 \
+                 ORDER BY deleted_at DESC, id",
+            )?;
+            let rows: rusqlite::Result<Vec<_>> = stmt
+                .query_map(boxed_params![account_id, kinds], |row| {
+                    Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+                })?
+                .collect();
+            rows
+        })
+        .await?;
+
+    let window = time::Duration::days(retention_days(state));
+    Ok(rows
+        .into_iter()
+        .filter_map(|(kind, id, title, deleted_at)| {
+            Some(TrashItem {
+                kind: TrashKind::from_str(&kind)?,
+                id,
+                title,
+                deleted_at,
+                purge_at: deleted_at + window,
+            })
+        })
+        .collect())
+}
+
+/// Takes an item back out of the account's trash. `false` if it isn't there.
+pub async fn restore(state: &AppState, kind: TrashKind, account_id: i64, id: &str) -> anyhow::Result<bool> {
+    let (table, owner, key) = kind.columns();
+    let sql =
+        format!("UPDATE {table} SET deleted_at = NULL WHERE {owner} = ?1 AND {key} = ?2 AND deleted_at IS NOT NULL");
+    let id = id.to_string();
+    let changed = state
+        .database()
+        .call(move |conn| conn.execute(&sql, rusqlite::params![account_id, id]))
+        .await?;
+    if changed > 0 && kind == TrashKind::Image {
+        state.invalidate_image_caches().await;
+    }
+    Ok(changed > 0)
+}
+
+/// Deletes an item in the account's trash for good. `false` if it isn't there.
+pub async fn purge(state: &AppState, kind: TrashKind, account_id: i64, id: &str) -> anyhow::Result<bool> {
+    let (table, owner, key) = kind.columns();
+    let sql = format!("DELETE FROM {table} WHERE {owner} = ?1 AND {key} = ?2 AND deleted_at IS NOT NULL");
+    let id = id.to_string();
+    let changed = state
+        .database()
+        .call(move |conn| conn.execute(&sql, rusqlite::params![account_id, id]))
+        .await?;
+    Ok(changed > 0)
+}
+
+/// Purges everything of `kinds` in the account's trash, returning how many
+/// items went.
+pub async fn empty(state: &AppState, account_id: i64, kinds: &[TrashKind]) -> anyhow::Result<usize> {
+    let statements: Vec<String> = kinds
+        .iter()
+        .map(|kind| {
+            let (table, owner, _) = kind.columns();
+            format!("DELETE FROM {table} WHERE {owner} = ?1 AND deleted_at IS NOT NULL")
+        })
+        .collect();
+    let purged = state
+        .database()
+        .call(move |conn| -> rusqlite::Result<usize> {
+            let tx = conn.transaction()?;
+            let mut purged = 0;
+            for sql in &statements {
+                purged += tx.execute(sql, [account_id])?;
+            }
+            tx.commit()?;
+            Ok(purged)
+        })
+        .await?;
+    Ok(purged)
+}
+
+/// Deletes every trashed item of `kind` whose restore window has passed,
+/// returning how many went. Run by the hourly reapers.
+pub async fn reap(state: &AppState, kind: TrashKind) -> anyhow::Result<usize> {
+    let (table, _, _) = kind.columns();
+    let sql = format!(
+        "DELETE FROM {table} WHERE deleted_at IS NOT NULL \
+         AND datetime(deleted_at) <= datetime('now', ?1)"
+    );
+    let cutoff = format!("-{} days", retention_days(state));
+    let reaped = state.database().call(move |conn| conn.execute(&sql, [cutoff])).await?;
+    Ok(reaped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn state() -> AppState {
+        let db = crate::Database::file(":memory:")
+            .connections(1)
+            .with_init(crate::migrations::migrate)
+            .open()
+            .await
+            .unwrap();
+        for (id, name) in [(1, "owner"), (2, "other")] {
+            db.execute(
+                "INSERT INTO account (id, name, password) VALUES (?1, ?2, 'x')",
+                boxed_params![id, name],
+            )
+            .await
+            .unwrap();
+        }
+        for sql in [
+            "INSERT INTO images (id, mimetype, uploader_id, original_name) VALUES ('i1', 'image/png', 1, 'cat.png')",
+            "INSERT INTO paste (id, account_id, title, content) VALUES ('p1', 1, 'Notes', CAST('hi' AS BLOB))",
+            "INSERT INTO short_link (code, target_url, account_id) VALUES ('docs', 'https://example.com', 1)",
+        ] {
+            db.execute(sql, []).await.unwrap();
+        }
+        AppState::for_tests(db).await
+    }
+
+    fn ids(items: &[TrashItem]) -> Vec<(TrashKind, &str)> {
+        let mut ids: Vec<_> = items.iter().map(|item| (item.kind, item.id.as_str())).collect();
+        ids.sort_by_key(|(kind, id)| (kind.as_str(), id.to_string()));
+        ids
+    }
+
+    #[tokio::test]
+    async fn discarded_items_are_hidden_until_restored() {
+        let state = state().await;
+        assert!(discard(&state, TrashKind::Image, 1, "i1").await.unwrap());
+        assert!(discard(&state, TrashKind::Paste, 1, "p1").await.unwrap());
+        assert!(discard(&state, TrashKind::Link, 1, "docs").await.unwrap());
+        // Already in the trash, or not theirs.
+        assert!(!discard(&state, TrashKind::Image, 1, "i1").await.unwrap());
+        assert!(!discard(&state, TrashKind::Paste, 2, "p1").await.unwrap());
+
+        let items = list(&state, 1, &TrashKind::ALL).await.unwrap();
+        assert_eq!(
+            ids(&items),
+            [
+                (TrashKind::Image, "i1"),
+                (TrashKind::Link, "docs"),
+                (TrashKind::Paste, "p1")
+            ]
+        );
+        assert!(items
+            .iter()
+            .all(|item| item.purge_at - item.deleted_at == time::Duration::days(30)));
+        assert!(list(&state, 2, &TrashKind::ALL).await.unwrap().is_empty());
+        assert_eq!(list(&state, 1, &[TrashKind::Link]).await.unwrap().len(), 1);
+
+        assert!(crate::site::paste::service::load(&state, "p1").await.is_none());
+        assert!(state.get_image_meta("i1".into()).await.is_none());
+
+        assert!(!restore(&state, TrashKind::Paste, 2, "p1").await.unwrap());
+        assert!(restore(&state, TrashKind::Paste, 1, "p1").await.unwrap());
+        assert!(restore(&state, TrashKind::Image, 1, "i1").await.unwrap());
+        assert!(crate::site::paste::service::load(&state, "p1").await.is_some());
+        assert!(state.get_image_meta("i1".into()).await.is_some());
+        assert_eq!(
+            ids(&list(&state, 1, &TrashKind::ALL).await.unwrap()),
+            [(TrashKind::Link, "docs")]
+        );
+    }
+
+    #[tokio::test]
+    async fn purging_and_reaping_delete_for_good() {
+        let state = state().await;
+        let db = state.database();
+        for kind in TrashKind::ALL {
+            let id = match kind {
+                TrashKind::Image => "i1",
+                TrashKind::Paste => "p1",
+                TrashKind::Link => "docs",
+            };
+            assert!(discard(&state, kind, 1, id).await.unwrap());
+        }
+
+        assert!(purge(&state, TrashKind::Image, 1, "i1").await.unwrap());
+        assert!(!restore(&state, TrashKind::Image, 1, "i1").await.unwrap());
+
+        // Nothing is old enough yet.
+        assert_eq!(reap(&state, TrashKind::Paste).await.unwrap(), 0);
+        db.execute("UPDATE paste SET deleted_at = '2000-01-01T00:00:00.000Z'", [])
+            .await
+            .unwrap();
+        assert_eq!(reap(&state, TrashKind::Paste).await.unwrap(), 1);
+        assert_eq!(reap(&state, TrashKind::Link).await.unwrap(), 0);
+
+        assert_eq!(empty(&state, 1, &TrashKind::ALL).await.unwrap(), 1);
+        assert!(list(&state, 1, &TrashKind::ALL).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn a_trashed_image_holds_its_quota_slot_until_it_is_purged() {
+        let state = state().await;
+        // `i1` fills a quota of one image.
+        let upload = |id: &'static str| {
+            state.database().execute(
+                crate::site::image_quota::INSERT_IMAGE,
+                crate::boxed_params![
+                    id,
+                    "image/png",
+                    1_i64,
+                    "hash",
+                    0_i64,
+                    None::<String>,
+                    None::<String>,
+                    None::<String>,
+                    None::<i64>,
+                    None::<String>,
+                    None::<String>,
+                    Some(1_i64),
+                    None::<i64>,
+                    None::<String>,
+                    None::<String>,
+                    None::<crate::models::Visibility>,
+                    None::<String>,
+                    None::<i64>,
+                ],
+            )
+        };
+        assert_eq!(upload("i2").await.unwrap(), 0);
+
+        assert!(discard(&state, TrashKind::Image, 1, "i1").await.unwrap());
+        assert_eq!(upload("i2").await.unwrap(), 0);
+        let message = crate::site::image_quota::Exceeded::Count(1).message();
+        assert!(message.contains("trash"), "{message}");
+
+        assert!(purge(&state, TrashKind::Image, 1, "i1").await.unwrap());
+        assert_eq!(upload("i2").await.unwrap(), 1);
+    }
+}
@@ -205,6 +205,14 @@ document.querySelector('#api-section button[type=submit][name="new"]')?.addEvent
   }
 });
 
+/* ── Trash ───────────────────────────────────────────────────── */
+
+document.querySelectorAll('form[data-confirm]').forEach((form) => {
+  form.addEventListener('submit', (event) => {
+    if (!window.confirm(form.dataset.confirm)) event.preventDefault();
+  });
+});
+
 /* ── Danger zone ─────────────────────────────────────────────── */
 
 (() => {
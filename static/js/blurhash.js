@@ -0,0 +1,111 @@
+/* This file is licensed under AGPL-3.0 */
+// Paints gallery placeholders from the BlurHash the server stores per image
+// (see `site::media::placeholder`). Elements carrying `data-blurhash` get the
+// decoded hash as their background until the `<img>` inside them has loaded.
+
+const BASE83 = '0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz#$%*+,-.:;=?@[]^_{|}~';
+const PLACEHOLDER_SIZE = 32;
+
+function decode83(str) {
+    let value = 0;
+    for (const c of str) {
+        const digit = BASE83.indexOf(c);
+        if (digit === -1) return NaN;
+        value = value * 83 + digit;
+    }
+    return value;
+}
+
+function srgbToLinear(value) {
+    const v = value / 255;
+    return v <= 0.04045 ? v / 12.92 : Math.pow((v + 0.055) / 1.055, 2.4);
+}
+
+function linearToSrgb(value) {
+    const v = Math.max(0, Math.min(1, value));
+    const srgb = v <= 0.0031308 ? v * 12.92 : 1.055 * Math.pow(v, 1 / 2.4) - 0.055;
+    return Math.round(srgb * 255);
+}
+
+function signPow(value, exp) {
+    return Math.sign(value) * Math.pow(Math.abs(value), exp);
+}
+
+/**
+ * Decodes a BlurHash into RGBA pixels, or returns null if it's malformed.
+ * @param {string} hash
+ * @param {number} width
+ * @param {number} height
+ * @returns {Uint8ClampedArray | null}
+ */
+function decodeBlurHash(hash, width, height) {
+    if (!hash || hash.length < 6) return null;
+    const sizeFlag = decode83(hash[0]);
+    const nx = (sizeFlag % 9) + 1;
+    const ny = Math.floor(sizeFlag / 9) + 1;
+    if (hash.length !== 4 + 2 * nx * ny) return null;
+
+    const maximum = (decode83(hash[1]) + 1) / 166;
+    const colors = new Array(nx * ny);
+    const dc = decode83(hash.substring(2, 6));
+    colors[0] = [srgbToLinear(dc >> 16), srgbToLinear((dc >> 8) & 255), srgbToLinear(dc & 255)];
+    for (let i = 1; i < colors.length; i++) {
+        const value = decode83(hash.substring(4 + i * 2, 6 + i * 2));
+        const quant = [Math.floor(value / (19 * 19)), Math.floor(value / 19) % 19, value % 19];
+        colors[i] = quant.map((q) => signPow((q - 9) / 9, 2) * maximum);
+    }
+    if (colors.some((c) => c.some(Number.isNaN))) return null;
+
+    const pixels = new Uint8ClampedArray(width * height * 4);
+    for (let y = 0; y < height; y++) {
+        for (let x = 0; x < width; x++) {
+            let r = 0, g = 0, b = 0;
+            for (let j = 0; j < ny; j++) {
+                for (let i = 0; i < nx; i++) {
+                    const basis = Math.cos(Math.PI * x * i / width) * Math.cos(Math.PI * y * j / height);
+                    const color = colors[i + j * nx];
+                    r += color[0] * basis;
+                    g += color[1] * basis;
+                    b += color[2] * basis;
+                }
+            }
+            const offset = 4 * (x + y * width);
+            pixels[offset] = linearToSrgb(r);
+            pixels[offset + 1] = linearToSrgb(g);
+            pixels[offset + 2] = linearToSrgb(b);
+            pixels[offset + 3] = 255;
+        }
+    }
+    return pixels;
+}
+
+function blurHashToDataURL(hash) {
+    const pixels = decodeBlurHash(hash, PLACEHOLDER_SIZE, PLACEHOLDER_SIZE);
+    if (pixels === null) return null;
+    const canvas = document.createElement('canvas');
+    canvas.width = PLACEHOLDER_SIZE;
+    canvas.height = PLACEHOLDER_SIZE;
+    const ctx = canvas.getContext('2d');
+    ctx.putImageData(new ImageData(pixels, PLACEHOLDER_SIZE, PLACEHOLDER_SIZE), 0, 0);
+    return canvas.toDataURL();
+}
+
+function clearPlaceholder(el) {
+    el.style.backgroundImage = '';
+    el.style.backgroundColor = '';
+    el.classList.remove('has-placeholder');
+}
+
+document.querySelectorAll('[data-blurhash]').forEach((el) => {
+    const img = el.querySelector('img');
+    if (img?.complete && img.naturalWidth > 0) {
+        clearPlaceholder(el);
+        return;
+    }
+    const url = blurHashToDataURL(el.dataset.blurhash);
+    if (url !== null) {
+        el.style.backgroundImage = `url(${url})`;
+        el.classList.add('has-placeholder');
+    }
+    img?.addEventListener('load', () => clearPlaceholder(el), {once: true});
+});
@@ -218,7 +218,7 @@ class BulkFilesOperations {
         }
 
         let total = js.success + js.failed;
-        showAlert({level: 'success', content: `Successfully deleted ${js.success}/${total} file${total === 1 ? "" : "s"}`});
+        showAlert({level: 'success', content: `Moved ${js.success}/${total} file${total === 1 ? "" : "s"} to the trash`});
 
         this.deleteModal.close();
         this.removeCheckedFiles();
@@ -284,11 +284,17 @@ uploadInput?.addEventListener('change', () => {
     if (valid.length !== uploadInput.files.length) {
         uploadInput.files = valid;
     }
-    // Carry the chosen TTL through as a query param so the shared upload
-    // handler picks it up (the form body is multipart and reserved for files).
+    // Carry the chosen TTL and visibility through as query params so the
+    // shared upload handler picks them up (the form body is multipart and
+    // reserved for files).
+    const params = new URLSearchParams();
     const expiry = document.getElementById('upload-expiry');
     const secs = expiry ? parseInt(expiry.value, 10) : 0;
-    uploadForm.action = secs > 0 ? `/images/bulk?expires_in=${secs}` : '/images/bulk';
+    if (secs > 0) params.set('expires_in', String(secs));
+    const visibility = document.getElementById('upload-visibility');
+    if (visibility && visibility.value !== 'unlisted') params.set('visibility', visibility.value);
+    const query = params.toString();
+    uploadForm.action = query ? `/images/bulk?${query}` : '/images/bulk';
     uploadForm.submit();
 });
 
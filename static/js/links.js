@@ -1,5 +1,6 @@
 // Behaviour for the URL-shortener management page (/links):
-// copy-to-clipboard, the edit modal, and delete confirmation.
+// copy-to-clipboard, the edit modal, link limits in local time, and delete
+// confirmation.
 (() => {
     "use strict";
 
@@ -23,11 +24,35 @@
         });
     });
 
+    // --- Limits in local time ----------------------------------------------
+    // datetime-local inputs carry no zone; the server reads them with this
+    // offset (minutes behind UTC, as getTimezoneOffset() counts them).
+    document.querySelectorAll(".js-tz-offset").forEach((input) => {
+        input.value = String(new Date().getTimezoneOffset());
+    });
+
+    // An RFC 3339 timestamp as a datetime-local value, in local time.
+    const toLocalInput = (iso) => {
+        if (!iso) return "";
+        const at = new Date(iso);
+        if (Number.isNaN(at.getTime())) return "";
+        const pad = (n) => String(n).padStart(2, "0");
+        return `${at.getFullYear()}-${pad(at.getMonth() + 1)}-${pad(at.getDate())}` +
+            `T${pad(at.getHours())}:${pad(at.getMinutes())}`;
+    };
+
     // --- Edit modal ---------------------------------------------------------
     const modal = document.getElementById("edit-link-modal");
     const form = document.getElementById("edit-link-form");
     const targetInput = document.getElementById("edit-target");
     const aliasInput = document.getElementById("edit-alias");
+    const activeFromInput = document.getElementById("edit-active-from");
+    const expiresAtInput = document.getElementById("edit-expires-at");
+    const maxClicksInput = document.getElementById("edit-max-clicks");
+    const passwordInput = document.getElementById("edit-password");
+    const removePasswordInput = document.getElementById("edit-remove-password");
+    const removePasswordLabel = document.getElementById("edit-remove-password-label");
+    const interstitialInput = document.getElementById("edit-interstitial");
 
     if (modal && form) {
         document.querySelectorAll(".link-edit").forEach((btn) => {
@@ -37,6 +62,13 @@
                 form.action = `/links/${row.dataset.id}/edit`;
                 targetInput.value = row.dataset.target || "";
                 aliasInput.value = row.dataset.code || "";
+                activeFromInput.value = toLocalInput(row.dataset.activeFrom);
+                expiresAtInput.value = toLocalInput(row.dataset.expiresAt);
+                maxClicksInput.value = row.dataset.maxClicks || "";
+                passwordInput.value = "";
+                removePasswordInput.checked = false;
+                removePasswordLabel.hidden = !("locked" in row.dataset);
+                interstitialInput.checked = "interstitial" in row.dataset;
                 modal.showModal();
             });
         });
@@ -50,7 +82,7 @@
         deleteForm.addEventListener("submit", (e) => {
             const row = deleteForm.closest(".links-row");
             const code = row ? row.dataset.code : "this link";
-            if (!confirm(`Delete short link "${code}"? This can't be undone.`)) {
+            if (!confirm(`Move short link "${code}" to the trash?`)) {
                 e.preventDefault();
             }
         });